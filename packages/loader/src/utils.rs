@@ -30,43 +30,43 @@ impl ImageHandle {
         let img = load_image_from_array_buffer(buffer).unwrap();
         let width = img.width() as usize;
         let height = img.height() as usize;
-        return ImageHandle {
+        ImageHandle {
             buffer: img,
             width,
             height,
-        };
+        }
     }
 
     #[wasm_bindgen(getter, js_name=rgb8)]
     pub fn load_image_by_rgb8(&self) -> Vec<u8> {
-        return self
+        self
             .buffer
             .clone()
             .into_rgba8()
             .iter()
-            .map(|x| *x)
-            .collect();
+            .copied()
+            .collect()
     }
 
     #[wasm_bindgen(getter, js_name=rgba16)]
     pub fn load_image_by_rgba16(&self) -> Vec<u16> {
-        return self
+        self
             .buffer
             .clone()
             .into_rgba16()
             .iter()
-            .map(|x| *x)
-            .collect();
+            .copied()
+            .collect()
     }
 
     #[wasm_bindgen(getter, js_name=luma16)]
     pub fn load_image_by_luma16(&self) -> Vec<u16> {
-        return self
+        self
             .buffer
             .clone()
             .into_luma16()
             .iter()
-            .map(|x| *x)
-            .collect();
+            .copied()
+            .collect()
     }
 }
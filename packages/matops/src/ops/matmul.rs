@@ -38,11 +38,22 @@ pub fn matmul_f64(a: &[f64], am: JsValue, b: &[f64], bm: JsValue) -> Vec<f64> {
     matmul(a, am, b, bm)
 }
 
+/// Accumulates in `i8`, so sums outside `-128..=127` wrap around; use
+/// `matmul_i8_i32` for quantized data.
 #[wasm_bindgen]
 pub fn matmul_i8(a: &[i8], am: JsValue, b: &[i8], bm: JsValue) -> Vec<i8> {
     matmul(a, am, b, bm)
 }
 
+/// `i8` matmul accumulating in `i32`, which can't overflow for inner
+/// dimensions below 2^17.
+#[wasm_bindgen]
+pub fn matmul_i8_i32(a: &[i8], am: JsValue, b: &[i8], bm: JsValue) -> Vec<i32> {
+    let a: Vec<i32> = a.iter().map(|x| *x as i32).collect();
+    let b: Vec<i32> = b.iter().map(|x| *x as i32).collect();
+    matmul(&a, am, &b, bm)
+}
+
 #[wasm_bindgen]
 pub fn matmul_i16(a: &[i16], am: JsValue, b: &[i16], bm: JsValue) -> Vec<i16> {
     matmul(a, am, b, bm)
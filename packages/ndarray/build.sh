@@ -17,9 +17,24 @@ if [ -d "pkg-node" ]; then
     rm -rf pkg-node
 fi
 
+# SIMD=1 builds the simd128 kernels, which need a runtime with wasm SIMD
+if [ -n "$SIMD" ]; then
+    export RUSTFLAGS="$RUSTFLAGS -C target-feature=+simd128"
+    CARGO_ARGS="-- --features simd"
+fi
+
+# The simd128 kernels aren't compiled by native builds, so check them
+# when the wasm32 target is available
+if rustup target list --installed 2>/dev/null | grep -q wasm32-unknown-unknown; then
+    RUSTFLAGS="$RUSTFLAGS -C target-feature=+simd128" \
+        cargo check --target wasm32-unknown-unknown --features simd
+else
+    echo "wasm32-unknown-unknown is not installed, skipping the simd128 check" >& 2
+fi
+
 # Build for both targets
-wasm-pack build -t nodejs -d pkg-node
-wasm-pack build -t browser -d pkg-browser
+wasm-pack build -t nodejs -d pkg-node $CARGO_ARGS
+wasm-pack build -t browser -d pkg-browser $CARGO_ARGS
 
 rm pkg-node/package.json
 rm pkg-browser/package.json
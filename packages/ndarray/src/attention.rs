@@ -0,0 +1,780 @@
+//! Scaled dot-product and multi-head attention, with backward passes.
+//!
+//! Attention inputs are `[..., T, D]` arrays, usually `[B, H, T, D]`;
+//! every leading axis is treated as a batch axis. The forward passes save
+//! what their backward passes need in the returned output, along with the
+//! input shapes, which the backward passes check their inputs against.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::dropout::dropout;
+use crate::error::{Error, JsResult, Result};
+#[cfg(test)]
+use crate::gradcheck::assert_grad;
+use crate::ndarray::NdArray;
+use crate::ops::{bmm, broadcast_with};
+use crate::random::Generator;
+
+/// Optional arguments of the attention kernels.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, Default)]
+pub struct AttentionOptions {
+    pub(crate) mask: Option<NdArray>,
+    pub(crate) causal: bool,
+    pub(crate) dropout_p: f32,
+    pub(crate) scale: Option<f32>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl AttentionOptions {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> AttentionOptions {
+        AttentionOptions::default()
+    }
+
+    /// Additive mask broadcast against the `[..., Tq, Tk]` scores; use
+    /// `-Infinity` to block a position.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = setMask))]
+    pub fn set_mask(&mut self, mask: &NdArray) {
+        self.mask = Some(mask.clone());
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = clearMask))]
+    pub fn clear_mask(&mut self) {
+        self.mask = None;
+    }
+
+    /// Blocks every key after the query's own position.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn causal(&self) -> bool {
+        self.causal
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(setter))]
+    pub fn set_causal(&mut self, value: bool) {
+        self.causal = value;
+    }
+
+    /// Dropout probability applied to the attention weights.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = dropoutP))]
+    pub fn dropout_p(&self) -> f32 {
+        self.dropout_p
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(setter = dropoutP))]
+    pub fn set_dropout_p(&mut self, value: f32) {
+        self.dropout_p = value;
+    }
+
+    /// Factor the scores are multiplied by; `1 / sqrt(D)` when unset.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn scale(&self) -> Option<f32> {
+        self.scale
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(setter))]
+    pub fn set_scale(&mut self, value: Option<f32>) {
+        self.scale = value;
+    }
+}
+
+/// Result of `scaled_dot_product_attention`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct AttentionOutput {
+    output: NdArray,
+    weights: NdArray,
+    dropout_mask: Option<NdArray>,
+    scale: f32,
+    inputs: [Vec<usize>; 3],
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl AttentionOutput {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn output(&self) -> NdArray {
+        self.output.clone()
+    }
+
+    /// Softmax-normalized `[..., Tq, Tk]` weights, before dropout.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn weights(&self) -> NdArray {
+        self.weights.clone()
+    }
+}
+
+/// Gradients of attention with respect to its query, key and value.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct AttentionGrads {
+    query: NdArray,
+    key: NdArray,
+    value: NdArray,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl AttentionGrads {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn query(&self) -> NdArray {
+        self.query.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn key(&self) -> NdArray {
+        self.key.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn value(&self) -> NdArray {
+        self.value.clone()
+    }
+}
+
+/// Sizes of an attention call: `(batch, tq, tk, d, dv)`.
+fn attention_dims(
+    q: &NdArray,
+    k: &NdArray,
+    v: &NdArray,
+) -> Result<(usize, usize, usize, usize, usize)> {
+    let r = q.shape.len();
+    if r < 2
+        || k.shape.len() != r
+        || v.shape.len() != r
+        || k.shape[..r - 2] != q.shape[..r - 2]
+        || v.shape[..r - 1] != k.shape[..r - 1]
+        || k.shape[r - 1] != q.shape[r - 1]
+    {
+        return Err(Error::ShapeMismatch(format!(
+            "incompatible attention shapes {:?}, {:?} and {:?}",
+            q.shape, k.shape, v.shape
+        )));
+    }
+    let batch = q.shape[..r - 2].iter().product();
+    Ok((
+        batch,
+        q.shape[r - 2],
+        k.shape[r - 2],
+        q.shape[r - 1],
+        v.shape[r - 1],
+    ))
+}
+
+/// Checks the inputs and output grad of a backward pass against the
+/// shapes its forward pass saw.
+fn check_saved(
+    inputs: [&NdArray; 3],
+    saved: &[Vec<usize>; 3],
+    grad: &NdArray,
+    output: &NdArray,
+) -> Result<()> {
+    for ((name, x), shape) in ["query", "key", "value"].iter().zip(&inputs).zip(saved) {
+        if x.shape != *shape {
+            return Err(Error::ShapeMismatch(format!(
+                "{} of shape {:?} doesn't match the forward pass's {:?}",
+                name, x.shape, shape
+            )));
+        }
+    }
+    if grad.shape != output.shape {
+        return Err(Error::ShapeMismatch(format!(
+            "grad of shape {:?} doesn't match the output of shape {:?}",
+            grad.shape, output.shape
+        )));
+    }
+    Ok(())
+}
+
+/// Softmax over rows of length `n`, subtracting each row's maximum first.
+/// Rows that are entirely `-inf` become zeros instead of NaN.
+fn stable_softmax_rows(buffer: &mut [f32], n: usize) {
+    for row in buffer.chunks_mut(n.max(1)) {
+        let max = row.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        if max == f32::NEG_INFINITY {
+            row.iter_mut().for_each(|x| *x = 0.);
+            continue;
+        }
+        let mut sum = 0.;
+        for x in row.iter_mut() {
+            *x = (*x - max).exp();
+            sum += *x;
+        }
+        row.iter_mut().for_each(|x| *x /= sum);
+    }
+}
+
+/// `softmax(q @ k^T * scale + mask) @ v`, with an optional causal mask and
+/// dropout on the attention weights drawn from `rng`.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = scaledDotProductAttention))]
+pub fn scaled_dot_product_attention(
+    q: &NdArray,
+    k: &NdArray,
+    v: &NdArray,
+    options: &AttentionOptions,
+    rng: &mut Generator,
+) -> JsResult<AttentionOutput> {
+    Ok(attention(q, k, v, options, rng)?)
+}
+
+fn attention(
+    q: &NdArray,
+    k: &NdArray,
+    v: &NdArray,
+    options: &AttentionOptions,
+    rng: &mut Generator,
+) -> Result<AttentionOutput> {
+    let (batch, tq, tk, d, dv) = attention_dims(q, k, v)?;
+    let scale = options.scale.unwrap_or(1. / (d as f32).sqrt());
+    let mut scores_shape = q.shape.clone();
+    *scores_shape.last_mut().unwrap() = tk;
+    let mut scores = bmm(&q.buffer, &k.buffer, batch, tq, d, tk, false, true);
+    scores.iter_mut().for_each(|s| *s *= scale);
+    let mut weights = NdArray::from(&scores, Some(scores_shape), None);
+    if let Some(mask) = &options.mask {
+        weights = match broadcast_with(&weights, mask, |s, m| s + m) {
+            Some(res) if res.shape == weights.shape => res,
+            _ => {
+                return Err(Error::ShapeMismatch(format!(
+                    "mask of shape {:?} can't be applied to scores of shape {:?}",
+                    mask.shape, weights.shape
+                )))
+            }
+        };
+    }
+    if options.causal {
+        for (i, row) in weights.buffer.chunks_mut(tk.max(1)).enumerate() {
+            row.iter_mut()
+                .skip(i % tq + 1)
+                .for_each(|s| *s = f32::NEG_INFINITY);
+        }
+    }
+    stable_softmax_rows(&mut weights.buffer, tk);
+
+    let (dropped, dropout_mask) = if options.dropout_p > 0. {
+        let res = dropout(&weights, options.dropout_p, true, rng);
+        (res.output(), Some(res.mask()))
+    } else {
+        (weights.clone(), None)
+    };
+    let mut out_shape = q.shape.clone();
+    *out_shape.last_mut().unwrap() = dv;
+    let output = bmm(&dropped.buffer, &v.buffer, batch, tq, tk, dv, false, false);
+    Ok(AttentionOutput {
+        output: NdArray::from(&output, Some(out_shape), None),
+        weights,
+        dropout_mask,
+        scale,
+        inputs: [q.shape.clone(), k.shape.clone(), v.shape.clone()],
+    })
+}
+
+/// Gradients of `scaled_dot_product_attention` given the gradient of its
+/// output and the output it returned; `q`, `k` and `v` must have the
+/// shapes the forward pass was called with.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = scaledDotProductAttentionBackward))]
+pub fn scaled_dot_product_attention_backward(
+    grad: &NdArray,
+    q: &NdArray,
+    k: &NdArray,
+    v: &NdArray,
+    saved: &AttentionOutput,
+) -> JsResult<AttentionGrads> {
+    Ok(attention_backward(grad, q, k, v, saved)?)
+}
+
+fn attention_backward(
+    grad: &NdArray,
+    q: &NdArray,
+    k: &NdArray,
+    v: &NdArray,
+    saved: &AttentionOutput,
+) -> Result<AttentionGrads> {
+    check_saved([q, k, v], &saved.inputs, grad, &saved.output)?;
+    let (batch, tq, tk, d, dv) = attention_dims(q, k, v)?;
+    let p = &saved.weights.buffer;
+    let dropped: Vec<f32> = match &saved.dropout_mask {
+        Some(m) => p.iter().zip(&m.buffer).map(|(p, m)| p * m).collect(),
+        None => p.clone(),
+    };
+    let dvalue = bmm(&dropped, &grad.buffer, batch, tk, tq, dv, true, false);
+    let mut dp = bmm(&grad.buffer, &v.buffer, batch, tq, dv, tk, false, true);
+    if let Some(m) = &saved.dropout_mask {
+        dp.iter_mut().zip(&m.buffer).for_each(|(g, m)| *g *= m);
+    }
+    // softmax backward: ds = p * (dp - sum(dp * p)), per row
+    for (dp, p) in dp.chunks_mut(tk.max(1)).zip(p.chunks(tk.max(1))) {
+        let dot: f32 = dp.iter().zip(p).map(|(g, p)| g * p).sum();
+        for (g, p) in dp.iter_mut().zip(p) {
+            *g = p * (*g - dot) * saved.scale;
+        }
+    }
+    let dquery = bmm(&dp, &k.buffer, batch, tq, tk, d, false, false);
+    let dkey = bmm(&dp, &q.buffer, batch, tk, tq, d, true, false);
+    Ok(AttentionGrads {
+        query: NdArray::from(&dquery, Some(q.shape.clone()), None),
+        key: NdArray::from(&dkey, Some(k.shape.clone()), None),
+        value: NdArray::from(&dvalue, Some(v.shape.clone()), None),
+    })
+}
+
+/// `x @ w^T + b` over the last axis of `x`, for a `[out, in]` weight.
+fn linear(x: &[f32], w: &[f32], b: &[f32], input: usize) -> Vec<f32> {
+    let (rows, out) = (x.len() / input, b.len());
+    let mut res = bmm(x, w, 1, rows, input, out, false, true);
+    for row in res.chunks_mut(out) {
+        row.iter_mut().zip(b).for_each(|(y, b)| *y += b);
+    }
+    res
+}
+
+/// Gradients `(dx, dw, db)` of `linear`, for an `[out, input]` weight.
+fn linear_backward(
+    dy: &[f32],
+    x: &[f32],
+    w: &[f32],
+    input: usize,
+    out: usize,
+) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+    let rows = x.len() / input;
+    let dx = bmm(dy, w, 1, rows, out, input, false, false);
+    let dw = bmm(dy, x, 1, out, rows, input, true, false);
+    let mut db = vec![0.; out];
+    for row in dy.chunks(out) {
+        db.iter_mut().zip(row).for_each(|(b, g)| *b += g);
+    }
+    (dx, dw, db)
+}
+
+/// `[B, T, H * D]` to `[B, H, T, D]`.
+fn split_heads(x: &[f32], b: usize, t: usize, h: usize, d: usize) -> NdArray {
+    let mut res = vec![0.; x.len()];
+    for (i, chunk) in x.chunks(d).enumerate() {
+        let (bi, ti, hi) = (i / (t * h), (i / h) % t, i % h);
+        let at = ((bi * h + hi) * t + ti) * d;
+        res[at..at + d].copy_from_slice(chunk);
+    }
+    NdArray::from(&res, Some(vec![b, h, t, d]), None)
+}
+
+/// `[B, H, T, D]` to `[B, T, H * D]`.
+fn merge_heads(x: &NdArray) -> Vec<f32> {
+    let (h, t, d) = (x.shape[1], x.shape[2], x.shape[3]);
+    let mut res = vec![0.; x.buffer.len()];
+    for (i, chunk) in x.buffer.chunks(d.max(1)).enumerate() {
+        let (bi, hi, ti) = (i / (h * t), (i / t) % h, i % t);
+        let at = ((bi * t + ti) * h + hi) * d;
+        res[at..at + d].copy_from_slice(chunk);
+    }
+    res
+}
+
+/// Result of `multi_head_attention`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct MultiHeadAttentionOutput {
+    output: NdArray,
+    attention: AttentionOutput,
+    heads: [NdArray; 3],
+    context: Vec<f32>,
+    inputs: [Vec<usize>; 3],
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl MultiHeadAttentionOutput {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn output(&self) -> NdArray {
+        self.output.clone()
+    }
+
+    /// Per-head attention weights, `[B, H, Tq, Tk]`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn weights(&self) -> NdArray {
+        self.attention.weights.clone()
+    }
+}
+
+/// Gradients of `multi_head_attention` with respect to its inputs and
+/// projection parameters.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct MultiHeadAttentionGrads {
+    query: NdArray,
+    key: NdArray,
+    value: NdArray,
+    in_proj_weight: NdArray,
+    in_proj_bias: NdArray,
+    out_proj_weight: NdArray,
+    out_proj_bias: NdArray,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl MultiHeadAttentionGrads {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn query(&self) -> NdArray {
+        self.query.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn key(&self) -> NdArray {
+        self.key.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn value(&self) -> NdArray {
+        self.value.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = inProjWeight))]
+    pub fn in_proj_weight(&self) -> NdArray {
+        self.in_proj_weight.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = inProjBias))]
+    pub fn in_proj_bias(&self) -> NdArray {
+        self.in_proj_bias.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = outProjWeight))]
+    pub fn out_proj_weight(&self) -> NdArray {
+        self.out_proj_weight.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = outProjBias))]
+    pub fn out_proj_bias(&self) -> NdArray {
+        self.out_proj_bias.clone()
+    }
+}
+
+fn check_mha_shapes(
+    query: &NdArray,
+    key: &NdArray,
+    value: &NdArray,
+    in_proj_weight: &NdArray,
+    out_proj_weight: &NdArray,
+    num_heads: usize,
+) -> Result<usize> {
+    let e = query.shape.last().copied().unwrap_or(0);
+    if e == 0
+        || query.shape.len() != 3
+        || key.shape.len() != 3
+        || key.shape != value.shape
+        || key.shape[0] != query.shape[0]
+        || key.shape[2] != e
+        || in_proj_weight.shape != [3 * e, e]
+        || out_proj_weight.shape != [e, e]
+    {
+        return Err(Error::ShapeMismatch(format!(
+            "expected [B, T, E] inputs, a [3E, E] input projection and an [E, E] output \
+             projection, got {:?}, {:?}, {:?}, {:?} and {:?}",
+            query.shape, key.shape, value.shape, in_proj_weight.shape, out_proj_weight.shape
+        )));
+    }
+    if num_heads == 0 || !e.is_multiple_of(num_heads) {
+        return Err(Error::InvalidArgument(format!(
+            "embed dim {} isn't divisible by {} heads",
+            e, num_heads
+        )));
+    }
+    Ok(e)
+}
+
+fn check_mha_biases(e: usize, in_proj_bias: &NdArray, out_proj_bias: &NdArray) -> Result<()> {
+    if in_proj_bias.shape != [3 * e] || out_proj_bias.shape != [e] {
+        return Err(Error::ShapeMismatch(format!(
+            "expected a [3E] input bias and an [E] output bias with E = {}, got {:?} and {:?}",
+            e, in_proj_bias.shape, out_proj_bias.shape
+        )));
+    }
+    Ok(())
+}
+
+/// Multi-head attention over `[B, T, E]` inputs, laid out like PyTorch's
+/// `nn.MultiheadAttention`: `in_proj_weight` is `[3E, E]` with the query,
+/// key and value projections stacked, `out_proj_weight` is `[E, E]`, and
+/// every projection computes `x @ w^T + b`. `E` must be divisible by
+/// `num_heads`.
+#[allow(clippy::too_many_arguments)]
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = multiHeadAttention))]
+pub fn multi_head_attention(
+    query: &NdArray,
+    key: &NdArray,
+    value: &NdArray,
+    in_proj_weight: &NdArray,
+    in_proj_bias: &NdArray,
+    out_proj_weight: &NdArray,
+    out_proj_bias: &NdArray,
+    num_heads: usize,
+    options: &AttentionOptions,
+    rng: &mut Generator,
+) -> JsResult<MultiHeadAttentionOutput> {
+    let e = check_mha_shapes(
+        query,
+        key,
+        value,
+        in_proj_weight,
+        out_proj_weight,
+        num_heads,
+    )?;
+    check_mha_biases(e, in_proj_bias, out_proj_bias)?;
+    let (b, tq, tk) = (query.shape[0], query.shape[1], key.shape[1]);
+    let w = &in_proj_weight.buffer;
+    let bias = &in_proj_bias.buffer;
+    let project = |x: &NdArray, i: usize, t: usize| {
+        let y = linear(
+            &x.buffer,
+            &w[i * e * e..(i + 1) * e * e],
+            &bias[i * e..(i + 1) * e],
+            e,
+        );
+        split_heads(&y, b, t, num_heads, e / num_heads)
+    };
+    let heads = [
+        project(query, 0, tq),
+        project(key, 1, tk),
+        project(value, 2, tk),
+    ];
+    let attention = attention(&heads[0], &heads[1], &heads[2], options, rng)?;
+    let context = merge_heads(&attention.output);
+    let output = linear(&context, &out_proj_weight.buffer, &out_proj_bias.buffer, e);
+    Ok(MultiHeadAttentionOutput {
+        output: NdArray::from(&output, Some(vec![b, tq, e]), None),
+        attention,
+        heads,
+        context,
+        inputs: [query.shape.clone(), key.shape.clone(), value.shape.clone()],
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = multiHeadAttentionBackward))]
+pub fn multi_head_attention_backward(
+    grad: &NdArray,
+    query: &NdArray,
+    key: &NdArray,
+    value: &NdArray,
+    in_proj_weight: &NdArray,
+    out_proj_weight: &NdArray,
+    num_heads: usize,
+    saved: &MultiHeadAttentionOutput,
+) -> JsResult<MultiHeadAttentionGrads> {
+    let e = check_mha_shapes(
+        query,
+        key,
+        value,
+        in_proj_weight,
+        out_proj_weight,
+        num_heads,
+    )?;
+    check_saved([query, key, value], &saved.inputs, grad, &saved.output)?;
+    let (b, tq) = (query.shape[0], query.shape[1]);
+    let (dcontext, dout_w, dout_b) =
+        linear_backward(&grad.buffer, &saved.context, &out_proj_weight.buffer, e, e);
+    let dheads = split_heads(&dcontext, b, tq, num_heads, e / num_heads);
+    let [q, k, v] = &saved.heads;
+    let g = attention_backward(&dheads, q, k, v, &saved.attention)?;
+
+    let w = &in_proj_weight.buffer;
+    let mut din_w = Vec::with_capacity(3 * e * e);
+    let mut din_b = Vec::with_capacity(3 * e);
+    let mut dinputs = Vec::with_capacity(3);
+    for (i, &(dh, x)) in [(&g.query, query), (&g.key, key), (&g.value, value)]
+        .iter()
+        .enumerate()
+    {
+        let dy = merge_heads(dh);
+        let (dx, dw, db) = linear_backward(&dy, &x.buffer, &w[i * e * e..(i + 1) * e * e], e, e);
+        din_w.extend(dw);
+        din_b.extend(db);
+        dinputs.push(NdArray::from(&dx, Some(x.shape.clone()), None));
+    }
+    let value_grad = dinputs.pop().unwrap();
+    let key_grad = dinputs.pop().unwrap();
+    let query_grad = dinputs.pop().unwrap();
+    Ok(MultiHeadAttentionGrads {
+        query: query_grad,
+        key: key_grad,
+        value: value_grad,
+        in_proj_weight: NdArray::from(&din_w, Some(vec![3 * e, e]), None),
+        in_proj_bias: NdArray::from(&din_b, Some(vec![3 * e]), None),
+        out_proj_weight: NdArray::from(&dout_w, Some(vec![e, e]), None),
+        out_proj_bias: NdArray::from(&dout_b, Some(vec![e]), None),
+    })
+}
+
+#[test]
+fn test_scaled_dot_product_attention() {
+    let mut rng = Generator::new(Some(0));
+    let q = rng.randn(&[2, 2, 3, 4]);
+    let k = rng.randn(&[2, 2, 5, 4]);
+    let v = rng.randn(&[2, 2, 5, 3]);
+    let out = scaled_dot_product_attention(&q, &k, &v, &AttentionOptions::new(), &mut rng).unwrap();
+    assert_eq!(out.output().shape, vec![2, 2, 3, 3]);
+    let row_sums: Vec<f32> = out
+        .weights()
+        .buffer
+        .chunks(5)
+        .map(|r| r.iter().sum())
+        .collect();
+    assert!(row_sums.iter().all(|s| (s - 1.).abs() < 1e-5));
+
+    // huge scores must not overflow
+    let big = q.mul_scalar(1e4);
+    let out =
+        scaled_dot_product_attention(&big, &k, &v, &AttentionOptions::new(), &mut rng).unwrap();
+    assert!(out.output().buffer.iter().all(|x| x.is_finite()));
+
+    let mut options = AttentionOptions::new();
+    options.set_causal(true);
+    let mut mask = NdArray::zeros(&[5]);
+    mask.buffer[1] = f32::NEG_INFINITY;
+    options.set_mask(&mask);
+    let out = scaled_dot_product_attention(&q, &k, &v, &options, &mut rng).unwrap();
+    let w = out.weights();
+    // query 0 only sees key 0; query 2 sees keys 0 and 2
+    assert_eq!(&w.buffer[..5], &[1., 0., 0., 0., 0.]);
+    assert!(w.buffer[10] > 0. && w.buffer[11] == 0. && w.buffer[12] > 0. && w.buffer[13] == 0.);
+    assert_eq!(&out.output().buffer[..3], &v.buffer[..3]);
+
+    let r = rng.randn(&out.output().shape);
+    let g = scaled_dot_product_attention_backward(&r, &q, &k, &v, &out).unwrap();
+    let run = |q: &NdArray, k: &NdArray, v: &NdArray| {
+        scaled_dot_product_attention(q, k, v, &options, &mut Generator::new(None))
+            .unwrap()
+            .output()
+    };
+    assert_grad(&g.query, &q, &r, |x| run(x, &k, &v));
+    assert_grad(&g.key, &k, &r, |x| run(&q, x, &v));
+    assert_grad(&g.value, &v, &r, |x| run(&q, &k, x));
+}
+
+#[test]
+fn test_attention_dropout_is_reproducible() {
+    let mut rng = Generator::new(Some(1));
+    let (q, k, v) = (
+        rng.randn(&[1, 1, 4, 2]),
+        rng.randn(&[1, 1, 4, 2]),
+        rng.randn(&[1, 1, 4, 2]),
+    );
+    let mut options = AttentionOptions::new();
+    options.set_dropout_p(0.5);
+    let a =
+        scaled_dot_product_attention(&q, &k, &v, &options, &mut Generator::new(Some(9))).unwrap();
+    let b =
+        scaled_dot_product_attention(&q, &k, &v, &options, &mut Generator::new(Some(9))).unwrap();
+    assert_eq!(a.output().buffer, b.output().buffer);
+    let g = scaled_dot_product_attention_backward(&NdArray::ones(&[1, 1, 4, 2]), &q, &k, &v, &a)
+        .unwrap();
+    // dropped weights pass no gradient to the values
+    let dropped = a.dropout_mask.as_ref().unwrap();
+    let col_kept: Vec<bool> = (0..4)
+        .map(|j| (0..4).any(|i| dropped.buffer[i * 4 + j] != 0.))
+        .collect();
+    for (j, kept) in col_kept.iter().enumerate() {
+        if !kept {
+            assert_eq!(&g.value().buffer[j * 2..j * 2 + 2], &[0., 0.]);
+        }
+    }
+}
+
+#[test]
+fn test_multi_head_attention() {
+    let mut rng = Generator::new(Some(2));
+    let (e, heads) = (4, 2);
+    let query = rng.randn(&[2, 3, e]);
+    let key = rng.randn(&[2, 2, e]);
+    let value = rng.randn(&[2, 2, e]);
+    let in_w = rng.randn(&[3 * e, e]).mul_scalar(0.5);
+    let in_b = rng.randn(&[3 * e]);
+    let out_w = rng.randn(&[e, e]).mul_scalar(0.5);
+    let out_b = rng.randn(&[e]);
+    let options = AttentionOptions::new();
+    let run = |q: &NdArray, k: &NdArray, v: &NdArray, in_w: &NdArray, out_w: &NdArray| {
+        multi_head_attention(
+            q,
+            k,
+            v,
+            in_w,
+            &in_b,
+            out_w,
+            &out_b,
+            heads,
+            &options,
+            &mut Generator::new(None),
+        )
+        .unwrap()
+    };
+    let out = run(&query, &key, &value, &in_w, &out_w);
+    assert_eq!(out.output().shape, vec![2, 3, e]);
+    assert_eq!(out.weights().shape, vec![2, heads, 3, 2]);
+
+    let r = rng.randn(&[2, 3, e]);
+    let g = multi_head_attention_backward(&r, &query, &key, &value, &in_w, &out_w, heads, &out)
+        .unwrap();
+    assert_grad(&g.query, &query, &r, |x| {
+        run(x, &key, &value, &in_w, &out_w).output()
+    });
+    assert_grad(&g.value, &value, &r, |x| {
+        run(&query, &key, x, &in_w, &out_w).output()
+    });
+    assert_grad(&g.in_proj_weight, &in_w, &r, |x| {
+        run(&query, &key, &value, x, &out_w).output()
+    });
+    assert_grad(&g.out_proj_weight, &out_w, &r, |x| {
+        run(&query, &key, &value, &in_w, x).output()
+    });
+    let db: f32 = r.buffer.iter().step_by(e).sum();
+    assert!((g.out_proj_bias().buffer[0] - db).abs() < 1e-4);
+
+    // an empty query sequence has empty input grads and zero bias grads
+    let empty = NdArray::zeros(&[2, 0, e]);
+    let out = run(&empty, &key, &value, &in_w, &out_w);
+    assert_eq!(out.output().shape, vec![2, 0, e]);
+    let g = multi_head_attention_backward(&empty, &empty, &key, &value, &in_w, &out_w, heads, &out)
+        .unwrap();
+    assert_eq!(g.query().shape, vec![2, 0, e]);
+    assert_eq!(g.out_proj_bias().buffer, vec![0.; e]);
+}
+
+#[test]
+fn test_attention_shape_errors() {
+    let mut rng = Generator::new(Some(3));
+    let q = rng.randn(&[2, 3, 4]);
+    let k = rng.randn(&[2, 5, 4]);
+    let v = rng.randn(&[2, 5, 2]);
+    let options = AttentionOptions::new();
+    let mismatch = |r: Result<()>| matches!(r, Err(Error::ShapeMismatch(_)));
+    assert!(mismatch(
+        attention(&q, &v, &v, &options, &mut rng).map(|_| ())
+    ));
+    let mut masked = options.clone();
+    masked.set_mask(&NdArray::zeros(&[4]));
+    assert!(mismatch(
+        attention(&q, &k, &v, &masked, &mut rng).map(|_| ())
+    ));
+
+    let out = attention(&q, &k, &v, &options, &mut rng).unwrap();
+    let grad = NdArray::ones(&out.output.shape);
+    assert!(attention_backward(&grad, &q, &k, &v, &out).is_ok());
+    // consistent with each other, but not with the forward pass
+    let (k2, v2) = (rng.randn(&[2, 6, 4]), rng.randn(&[2, 6, 2]));
+    assert!(mismatch(
+        attention_backward(&grad, &q, &k2, &v2, &out).map(|_| ())
+    ));
+    let q2 = rng.randn(&[2, 3, 1, 4]);
+    assert!(mismatch(
+        attention_backward(&grad, &q2, &k, &v, &out).map(|_| ())
+    ));
+    assert!(mismatch(
+        attention_backward(&q, &q, &k, &v, &out).map(|_| ())
+    ));
+
+    let (w, ew) = (NdArray::zeros(&[12, 4]), NdArray::zeros(&[4, 4]));
+    assert!(matches!(
+        check_mha_shapes(&q, &k, &k, &w, &ew, 3),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert!(mismatch(
+        check_mha_shapes(&q, &k, &v, &w, &ew, 2).map(|_| ())
+    ));
+    assert!(mismatch(check_mha_biases(
+        4,
+        &NdArray::zeros(&[4]),
+        &NdArray::zeros(&[4])
+    )));
+}
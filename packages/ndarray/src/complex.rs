@@ -0,0 +1,318 @@
+//! Complex (`complex64`) arrays.
+//!
+//! `ComplexNdArray` stores its elements as interleaved `[re, im]` `f32`
+//! pairs, which is also how it is exchanged with JS. The layout is the same
+//! as the trailing-pair `NdArray`s used by the `fft` module, so the two
+//! convert into each other without reordering.
+
+use std::borrow::Borrow;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    dtype::DType,
+    fft::{self, Complex},
+    ndarray::NdArray,
+    utils,
+};
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct ComplexNdArray {
+    pub(super) buffer: Vec<f32>,
+    pub(super) strides: Vec<usize>,
+    pub(super) shape: Vec<usize>,
+}
+
+fn load(pair: &[f32]) -> Complex {
+    Complex::new(pair[0] as f64, pair[1] as f64)
+}
+
+fn store(pair: &mut [f32], z: Complex) {
+    pair[0] = z.re as f32;
+    pair[1] = z.im as f32;
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl ComplexNdArray {
+    /// Builds an array from interleaved `[re, im]` pairs.
+    pub fn from(buffer: &[f32], shape: Option<Vec<usize>>) -> Self {
+        assert!(
+            buffer.len().is_multiple_of(2),
+            "interleaved buffer must have an even length"
+        );
+        let shape = shape.unwrap_or(vec![buffer.len() / 2]);
+        assert_eq!(
+            shape.iter().product::<usize>() * 2,
+            buffer.len(),
+            "shape is not compatible with buffer"
+        );
+        Self {
+            buffer: buffer.to_vec(),
+            strides: utils::get_strides(shape.borrow()),
+            shape,
+        }
+    }
+
+    /// Combines real and imaginary parts of the same shape.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = fromParts))]
+    pub fn from_parts(re: &NdArray, im: &NdArray) -> Self {
+        assert_eq!(re.shape, im.shape);
+        let mut buffer = Vec::with_capacity(re.buffer.len() * 2);
+        for (r, i) in re.buffer.iter().zip(&im.buffer) {
+            buffer.push(*r);
+            buffer.push(*i);
+        }
+        Self::from(&buffer, Some(re.shape.clone()))
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = fromReal))]
+    pub fn from_real(re: &NdArray) -> Self {
+        Self::from_parts(re, &NdArray::zeros(&re.shape))
+    }
+
+    /// Builds `abs * exp(i * angle)` element-wise.
+    pub fn polar(abs: &NdArray, angle: &NdArray) -> Self {
+        assert_eq!(abs.shape, angle.shape);
+        let mut buffer = Vec::with_capacity(abs.buffer.len() * 2);
+        for (r, t) in abs.buffer.iter().zip(&angle.buffer) {
+            buffer.push(r * t.cos());
+            buffer.push(r * t.sin());
+        }
+        Self::from(&buffer, Some(abs.shape.clone()))
+    }
+
+    /// Reinterprets an `NdArray` with a trailing axis of length 2 as complex.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = viewAsComplex))]
+    pub fn view_as_complex(a: &NdArray) -> Self {
+        assert_eq!(
+            a.shape.last(),
+            Some(&2),
+            "complex arrays must have a trailing axis of length 2"
+        );
+        Self::from(&a.buffer, Some(a.shape[..a.shape.len() - 1].to_vec()))
+    }
+
+    pub fn zeros(shape: &[usize]) -> Self {
+        Self::from(
+            &vec![0.0; shape.iter().product::<usize>() * 2],
+            Some(shape.to_vec()),
+        )
+    }
+
+    /// Interleaved `[re, im]` pairs.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter, js_name = "buffer"))]
+    pub fn get_buffer(&self) -> Vec<f32> {
+        self.buffer.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter, js_name = "shape"))]
+    pub fn get_shape(&self) -> Vec<usize> {
+        self.shape.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn dtype(&self) -> DType {
+        DType::Complex64
+    }
+
+    /// The same data as an `NdArray` of shape `[...shape, 2]`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = viewAsReal))]
+    pub fn view_as_real(&self) -> NdArray {
+        let mut shape = self.shape.clone();
+        shape.push(2);
+        NdArray::from(&self.buffer, Some(shape), None)
+    }
+
+    pub fn reshape(&self, shape: &[i32]) -> Self {
+        let mut real_shape = shape.to_vec();
+        real_shape.push(2);
+        Self::view_as_complex(&self.view_as_real().reshape(&real_shape))
+    }
+
+    pub fn real(&self) -> NdArray {
+        self.map_to_real(|re, _| re)
+    }
+
+    pub fn imag(&self) -> NdArray {
+        self.map_to_real(|_, im| im)
+    }
+
+    pub fn abs(&self) -> NdArray {
+        self.map_to_real(f32::hypot)
+    }
+
+    pub fn angle(&self) -> NdArray {
+        self.map_to_real(|re, im| im.atan2(re))
+    }
+
+    pub fn conj(&self) -> Self {
+        self.map(Complex::conj)
+    }
+
+    pub fn add(&self, b: &ComplexNdArray) -> Self {
+        self.zip(b, |x, y| x + y)
+    }
+
+    pub fn sub(&self, b: &ComplexNdArray) -> Self {
+        self.zip(b, |x, y| x - y)
+    }
+
+    /// Element-wise product.
+    pub fn mul(&self, b: &ComplexNdArray) -> Self {
+        self.zip(b, |x, y| x * y)
+    }
+
+    pub fn div(&self, b: &ComplexNdArray) -> Self {
+        self.zip(b, |x, y| x / y)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = addScalar))]
+    pub fn add_scalar(&self, re: f32, im: Option<f32>) -> Self {
+        let z = Complex::new(re as f64, im.unwrap_or(0.0) as f64);
+        self.map(|x| x + z)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = mulScalar))]
+    pub fn mul_scalar(&self, re: f32, im: Option<f32>) -> Self {
+        let z = Complex::new(re as f64, im.unwrap_or(0.0) as f64);
+        self.map(|x| x * z)
+    }
+
+    pub fn matmul(&self, b: &ComplexNdArray) -> Self {
+        matmul_complex(self, b)
+    }
+
+    pub fn fft(&self, n: Option<usize>, axis: Option<usize>) -> Self {
+        Self::view_as_complex(&fft::fft(&self.view_as_real(), n, axis))
+    }
+
+    pub fn ifft(&self, n: Option<usize>, axis: Option<usize>) -> Self {
+        Self::view_as_complex(&fft::ifft(&self.view_as_real(), n, axis))
+    }
+}
+
+impl ComplexNdArray {
+    fn map_to_real<F: Fn(f32, f32) -> f32>(&self, f: F) -> NdArray {
+        NdArray::from(
+            &self
+                .buffer
+                .chunks(2)
+                .map(|c| f(c[0], c[1]))
+                .collect::<Vec<f32>>(),
+            Some(self.shape.clone()),
+            None,
+        )
+    }
+
+    fn map<F: Fn(Complex) -> Complex>(&self, f: F) -> Self {
+        let mut res = self.clone();
+        for c in res.buffer.chunks_mut(2) {
+            store(c, f(load(c)));
+        }
+        res
+    }
+
+    /// Element-wise binary op. Like `ops::add`, `b` is repeated when its
+    /// length divides the length of `self`.
+    fn zip<F: Fn(Complex, Complex) -> Complex>(&self, b: &ComplexNdArray, f: F) -> Self {
+        let len = b.buffer.len() / 2;
+        assert!(
+            len > 0 && (self.buffer.len() / 2).is_multiple_of(len),
+            "cannot broadcast shape {:?} to {:?}",
+            b.shape,
+            self.shape
+        );
+        let mut res = self.clone();
+        for (i, c) in res.buffer.chunks_mut(2).enumerate() {
+            let j = i % len;
+            store(c, f(load(c), load(&b.buffer[2 * j..2 * j + 2])));
+        }
+        res
+    }
+}
+
+impl Clone for ComplexNdArray {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer.clone(),
+            strides: self.strides.clone(),
+            shape: self.shape.clone(),
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = matmulComplex))]
+pub fn matmul_complex(a: &ComplexNdArray, b: &ComplexNdArray) -> ComplexNdArray {
+    assert!(
+        a.shape.len() == 2 && b.shape.len() == 2,
+        "matmul_complex expects 2-d operands, got {:?} and {:?}",
+        a.shape,
+        b.shape
+    );
+    assert_eq!(a.shape[1], b.shape[0], "inner dimensions do not match");
+    let (m, k, n) = (a.shape[0], a.shape[1], b.shape[1]);
+    let mut buffer = Vec::with_capacity(m * n * 2);
+    for i in 0..m {
+        for j in 0..n {
+            let mut sum = Complex::default();
+            for l in 0..k {
+                let x = load(&a.buffer[2 * (i * k + l)..]);
+                let y = load(&b.buffer[2 * (l * n + j)..]);
+                sum = sum + x * y;
+            }
+            buffer.push(sum.re as f32);
+            buffer.push(sum.im as f32);
+        }
+    }
+    ComplexNdArray::from(&buffer, Some(vec![m, n]))
+}
+
+#[test]
+fn test_complex_parts() {
+    let re = NdArray::from(&[3., 0., -1.], None, None);
+    let im = NdArray::from(&[4., 2., 0.], None, None);
+    let z = ComplexNdArray::from_parts(&re, &im);
+    assert_eq!(z.buffer, vec![3., 4., 0., 2., -1., 0.]);
+    assert_eq!(z.real().buffer, re.buffer);
+    assert_eq!(z.imag().buffer, im.buffer);
+    assert_eq!(z.abs().buffer, vec![5., 2., 1.]);
+    assert_eq!(z.conj().imag().buffer, vec![-4., -2., 0.]);
+
+    let w = ComplexNdArray::polar(&z.abs(), &z.angle());
+    for (x, y) in w.buffer.iter().zip(&z.buffer) {
+        assert!((x - y).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn test_complex_arithmetic() {
+    let a = ComplexNdArray::from(&[1., 2., 3., -1.], None);
+    let b = ComplexNdArray::from(&[0., 1., 2., 2.], None);
+    assert_eq!(a.add(&b).buffer, vec![1., 3., 5., 1.]);
+    assert_eq!(a.sub(&b).buffer, vec![1., 1., 1., -3.]);
+    assert_eq!(a.mul(&b).buffer, vec![-2., 1., 8., 4.]);
+    let c = a.mul(&b).div(&b);
+    for (x, y) in c.buffer.iter().zip(&a.buffer) {
+        assert!((x - y).abs() < 1e-6, "{:?} != {:?}", c.buffer, a.buffer);
+    }
+    assert_eq!(a.mul_scalar(0., Some(1.)).buffer, vec![-2., 1., 1., 3.]);
+}
+
+#[test]
+fn test_complex_matmul() {
+    // [[1, i], [0, 1]] @ [[1], [i]] = [[0], [i]]
+    let a = ComplexNdArray::from(&[1., 0., 0., 1., 0., 0., 1., 0.], Some(vec![2, 2]));
+    let b = ComplexNdArray::from(&[1., 0., 0., 1.], Some(vec![2, 1]));
+    let c = a.matmul(&b);
+    assert_eq!(c.shape, vec![2, 1]);
+    assert_eq!(c.buffer, vec![0., 0., 0., 1.]);
+}
+
+#[test]
+fn test_complex_fft() {
+    let z = ComplexNdArray::from(&[1., 0., 0., 0., 0., 0.], None);
+    let f = z.fft(None, None);
+    assert_eq!(f.shape, vec![3]);
+    assert_eq!(f.real().buffer, vec![1., 1., 1.]);
+    assert_eq!(z.view_as_real().shape, vec![3, 2]);
+}
@@ -0,0 +1,211 @@
+//! Dropout and related stochastic regularization.
+//!
+//! Each kernel returns the output together with a `mask` holding the factor
+//! every element was multiplied by, so the backward pass is `dy * mask`.
+//! Outside of training, or with `p == 0`, the input passes through
+//! unchanged and the mask is all ones.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::ndarray::NdArray;
+use crate::random::Generator;
+
+/// Result of a dropout forward pass.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct DropoutOutput {
+    output: NdArray,
+    mask: NdArray,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl DropoutOutput {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn output(&self) -> NdArray {
+        self.output.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn mask(&self) -> NdArray {
+        self.mask.clone()
+    }
+}
+
+fn check_p(p: f32) {
+    assert!(
+        (0. ..=1.).contains(&p),
+        "dropout probability must be in [0, 1], got {}",
+        p
+    );
+}
+
+fn identity(x: &NdArray) -> DropoutOutput {
+    DropoutOutput {
+        output: x.clone(),
+        mask: NdArray::ones(&x.shape),
+    }
+}
+
+/// `units` factors that are `0` with probability `p` and `1 / (1 - p)`
+/// otherwise.
+fn draw(p: f32, units: usize, rng: &mut Generator) -> Vec<f32> {
+    let scale = if p < 1. { 1. / (1. - p) } else { 0. };
+    (0..units)
+        .map(|_| if rng.bernoulli(p) { 0. } else { scale })
+        .collect()
+}
+
+/// Multiplies `x` by a mask with one factor per element.
+fn apply(x: &NdArray, mask: Vec<f32>) -> DropoutOutput {
+    let mask = NdArray::from(&mask, Some(x.shape.clone()), None);
+    let mut output = x.clone();
+    output.dot_(&mask);
+    DropoutOutput { output, mask }
+}
+
+/// Zeroes each element with probability `p` and scales the rest by
+/// `1 / (1 - p)`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn dropout(x: &NdArray, p: f32, training: bool, rng: &mut Generator) -> DropoutOutput {
+    check_p(p);
+    if !training || p == 0. {
+        return identity(x);
+    }
+    apply(x, draw(p, x.buffer.len(), rng))
+}
+
+/// Zeroes whole channels of a channel-last `[N, ..., C]` input: each
+/// `(n, c)` pair is dropped with probability `p` across every spatial
+/// position.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn dropout2d(x: &NdArray, p: f32, training: bool, rng: &mut Generator) -> DropoutOutput {
+    assert!(
+        x.shape.len() >= 2,
+        "expected a [N, ..., C] input, got shape {:?}",
+        x.shape
+    );
+    let (n, c) = (x.shape[0], x.shape[x.shape.len() - 1]);
+    check_p(p);
+    if !training || p == 0. {
+        return identity(x);
+    }
+    let spatial = x.buffer.len() / (n * c).max(1);
+    let drawn = draw(p, n * c, rng);
+    let mask = (0..n)
+        .flat_map(|i| {
+            let row = &drawn[i * c..(i + 1) * c];
+            std::iter::repeat_n(row, spatial).flatten().copied()
+        })
+        .collect();
+    apply(x, mask)
+}
+
+/// Dropout for self-normalizing (SELU) networks: dropped elements are set
+/// to SELU's negative saturation value and the result is shifted and
+/// scaled so the mean and variance of the input are kept. The mask holds
+/// the gradient factor, which is `0` for dropped elements.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = alphaDropout))]
+pub fn alpha_dropout(x: &NdArray, p: f32, training: bool, rng: &mut Generator) -> DropoutOutput {
+    check_p(p);
+    if !training || p == 0. {
+        return identity(x);
+    }
+    if p == 1. {
+        return DropoutOutput {
+            output: NdArray::zeros(&x.shape),
+            mask: NdArray::zeros(&x.shape),
+        };
+    }
+    // -scale * alpha of SELU
+    let alpha = -1.758_099_3_f32;
+    let a = ((1. - p) * (1. + p * alpha * alpha)).powf(-0.5);
+    let b = -a * alpha * p;
+    let mut output = x.clone();
+    let mut mask = vec![0f32; x.buffer.len()];
+    for (y, m) in output.buffer.iter_mut().zip(mask.iter_mut()) {
+        if rng.bernoulli(p) {
+            *y = a * alpha + b;
+        } else {
+            *y = a * *y + b;
+            *m = a;
+        }
+    }
+    DropoutOutput {
+        output,
+        mask: NdArray::from(&mask, Some(x.shape.clone()), None),
+    }
+}
+
+/// Stochastic depth: drops the whole residual branch of each sample along
+/// the first axis with probability `p`, scaling kept samples by
+/// `1 / (1 - p)`.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = dropPath))]
+pub fn drop_path(x: &NdArray, p: f32, training: bool, rng: &mut Generator) -> DropoutOutput {
+    check_p(p);
+    if !training || p == 0. {
+        return identity(x);
+    }
+    let n = x.shape.first().copied().unwrap_or(1);
+    let per_sample = x.buffer.len() / n.max(1);
+    let mask = draw(p, n, rng)
+        .into_iter()
+        .flat_map(|m| std::iter::repeat_n(m, per_sample))
+        .collect();
+    apply(x, mask)
+}
+
+#[test]
+fn test_dropout() {
+    let x = NdArray::ones(&[100, 100]);
+    let mut rng = Generator::new(Some(0));
+    let out = dropout(&x, 0.3, true, &mut rng);
+    let kept = out.mask().buffer.iter().filter(|m| **m != 0.).count();
+    assert!((6700..7300).contains(&kept), "{}", kept);
+    assert!(out
+        .output()
+        .buffer
+        .iter()
+        .all(|y| *y == 0. || (*y - 1. / 0.7).abs() < 1e-6));
+    assert_eq!(out.output().buffer, out.mask().buffer);
+    assert!((out.output().sum() / 10000. - 1.).abs() < 0.05);
+
+    let again = dropout(&x, 0.3, true, &mut Generator::new(Some(0)));
+    assert_eq!(again.mask().buffer, out.mask().buffer);
+
+    let eval = dropout(&x, 0.3, false, &mut rng);
+    assert_eq!(eval.output().buffer, x.buffer);
+    let all = dropout(&x, 1., true, &mut rng);
+    assert!(all.output().buffer.iter().all(|y| *y == 0.));
+}
+
+#[test]
+fn test_channel_and_path_dropout() {
+    let x = NdArray::arange(1, 49, None).reshape(&[2, 3, 2, 4]);
+    let mut rng = Generator::new(Some(3));
+    let out = dropout2d(&x, 0.5, true, &mut rng);
+    let mask = out.mask();
+    for n in 0..2 {
+        for c in 0..4 {
+            let first = mask.buffer[n * 24 + c];
+            assert!((0..6).all(|s| mask.buffer[n * 24 + s * 4 + c] == first));
+        }
+    }
+
+    let out = drop_path(&x, 0.5, true, &mut rng);
+    for n in 0..2 {
+        let sample = &out.mask().buffer[n * 24..(n + 1) * 24];
+        assert!(sample.iter().all(|m| *m == sample[0]));
+        assert!(sample[0] == 0. || sample[0] == 2.);
+    }
+}
+
+#[test]
+fn test_alpha_dropout_keeps_moments() {
+    let x = Generator::new(Some(1)).randn(&[20000]);
+    let out = alpha_dropout(&x, 0.2, true, &mut Generator::new(Some(2))).output();
+    let n = out.buffer.len() as f32;
+    let mean = out.sum() / n;
+    let var = out.buffer.iter().map(|y| (y - mean).powi(2)).sum::<f32>() / n;
+    assert!(mean.abs() < 0.05, "{}", mean);
+    assert!((var - 1.).abs() < 0.05, "{}", var);
+}
@@ -0,0 +1,71 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// Element type of an array. `Float32` is the storage of `NdArray`, the
+/// other variants belong to the specialised array types.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
+pub enum DType {
+    Float32 = 0,
+    Complex64 = 1,
+    Float16 = 2,
+    BFloat16 = 3,
+}
+
+impl DType {
+    /// Name used by numpy and by the JS `dtype` enum.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DType::Float32 => "float32",
+            DType::Complex64 => "complex64",
+            DType::Float16 => "float16",
+            DType::BFloat16 => "bfloat16",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<DType> {
+        match name {
+            "float32" => Some(DType::Float32),
+            "complex64" => Some(DType::Complex64),
+            "float16" => Some(DType::Float16),
+            "bfloat16" => Some(DType::BFloat16),
+            _ => None,
+        }
+    }
+
+    /// Size of one element in bytes.
+    pub fn item_size(&self) -> usize {
+        match self {
+            DType::Float32 => 4,
+            DType::Complex64 => 8,
+            DType::Float16 | DType::BFloat16 => 2,
+        }
+    }
+
+    /// Whether this is one of the 16-bit float storage types of
+    /// `HalfNdArray`.
+    pub fn is_half(&self) -> bool {
+        matches!(self, DType::Float16 | DType::BFloat16)
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = dtypeName))]
+pub fn dtype_name(dtype: DType) -> String {
+    dtype.name().to_string()
+}
+
+#[test]
+fn test_dtype_name() {
+    for dtype in [
+        DType::Float32,
+        DType::Complex64,
+        DType::Float16,
+        DType::BFloat16,
+    ] {
+        assert_eq!(DType::from_name(dtype.name()), Some(dtype));
+        assert_eq!(DType::try_from_primitive(u8::from(dtype)).ok(), Some(dtype));
+    }
+    assert_eq!(DType::from_name("int8"), None);
+}
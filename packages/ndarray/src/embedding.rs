@@ -0,0 +1,215 @@
+//! Embedding lookup with a row-sparse backward pass.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use std::collections::BTreeSet;
+
+use crate::ndarray::NdArray;
+
+/// Gradient touching only some rows of a `[V, D]` matrix: `values[i]` is
+/// the gradient of row `indices[i]`. Indices are sorted and unique.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct RowGrad {
+    indices: Vec<u32>,
+    values: NdArray,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl RowGrad {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn indices(&self) -> Vec<u32> {
+        self.indices.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn values(&self) -> NdArray {
+        self.values.clone()
+    }
+
+    /// The dense `[rows, D]` gradient.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = toDense))]
+    pub fn to_dense(&self, rows: usize) -> NdArray {
+        let dim = self.values.shape[1];
+        let mut res = NdArray::zeros(&[rows, dim]);
+        res.index_add_(&self.indices, &self.values, None);
+        res
+    }
+}
+
+fn row_len(a: &NdArray) -> usize {
+    a.shape[1..].iter().product()
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl NdArray {
+    /// Adds `alpha * source[i]` (default `alpha` 1) to row `indices[i]` of
+    /// `self` along the first axis. Repeated indices accumulate, so a
+    /// `RowGrad` can be applied with
+    /// `weight.index_add_(&g.indices(), &g.values(), Some(-lr))`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = indexAdd_))]
+    pub fn index_add_(&mut self, indices: &[u32], source: &NdArray, alpha: Option<f32>) {
+        let alpha = alpha.unwrap_or(1.);
+        let dim = row_len(self);
+        if source.buffer.len() != indices.len() * dim {
+            panic!(
+                "can't add shape {:?} into {} rows of shape {:?}",
+                source.shape,
+                indices.len(),
+                self.shape
+            );
+        }
+        for (i, row) in indices.iter().zip(source.buffer.chunks(dim.max(1))) {
+            let i = *i as usize;
+            assert!(i < self.shape[0], "index {} is out of bounds", i);
+            for (x, y) in self.buffer[i * dim..(i + 1) * dim].iter_mut().zip(row) {
+                *x += alpha * y;
+            }
+        }
+    }
+}
+
+/// Renormalizes in place every row of `weight` referenced by `indices`
+/// whose `norm_type`-norm (default 2) exceeds `max_norm`.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = embeddingRenorm_))]
+pub fn embedding_renorm_(
+    weight: &mut NdArray,
+    indices: &[u32],
+    max_norm: f32,
+    norm_type: Option<f32>,
+) {
+    let norm_type = norm_type.unwrap_or(2.);
+    let dim = weight.shape[1];
+    let mut rows = indices.to_vec();
+    rows.sort_unstable();
+    rows.dedup();
+    for i in rows {
+        let row = &mut weight.buffer[i as usize * dim..(i as usize + 1) * dim];
+        let norm = row
+            .iter()
+            .map(|x| x.abs().powf(norm_type))
+            .sum::<f32>()
+            .powf(1. / norm_type);
+        if norm > max_norm {
+            let scale = max_norm / (norm + 1e-7);
+            row.iter_mut().for_each(|x| *x *= scale);
+        }
+    }
+}
+
+/// Looks up rows of the `[V, D]` `weight` for integer `indices`, laid out
+/// as `index_shape` (default 1-D); the result has shape
+/// `[...index_shape, D]`.
+///
+/// With `max_norm`, the referenced rows of `weight` are first renormalized
+/// in place, as `embedding_renorm_` does.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn embedding(
+    weight: &mut NdArray,
+    indices: &[u32],
+    index_shape: Option<Vec<usize>>,
+    max_norm: Option<f32>,
+    norm_type: Option<f32>,
+) -> NdArray {
+    if weight.shape.len() != 2 {
+        panic!(
+            "embedding weight must be [V, D], got shape {:?}",
+            weight.shape
+        );
+    }
+    let index_shape = index_shape.unwrap_or_else(|| vec![indices.len()]);
+    if index_shape.iter().product::<usize>() != indices.len() {
+        panic!(
+            "can't lay out {} indices as shape {:?}",
+            indices.len(),
+            index_shape
+        );
+    }
+    let (rows, dim) = (weight.shape[0], weight.shape[1]);
+    if let Some(i) = indices.iter().find(|i| **i as usize >= rows) {
+        panic!("index {} is out of bounds for {} embeddings", i, rows);
+    }
+    if let Some(max_norm) = max_norm {
+        embedding_renorm_(weight, indices, max_norm, norm_type);
+    }
+    let mut buffer = Vec::with_capacity(indices.len() * dim);
+    for i in indices {
+        let i = *i as usize;
+        buffer.extend_from_slice(&weight.buffer[i * dim..(i + 1) * dim]);
+    }
+    let mut shape = index_shape;
+    shape.push(dim);
+    NdArray::from(&buffer, Some(shape), None)
+}
+
+/// Gradient of `embedding` w.r.t. its weight, given the gradient of its
+/// output. Only the looked-up rows are returned, with the contributions of
+/// repeated indices summed; `padding_idx` receives no gradient.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = embeddingBackward))]
+pub fn embedding_backward(grad: &NdArray, indices: &[u32], padding_idx: Option<u32>) -> RowGrad {
+    let dim = grad.shape.last().copied().unwrap_or(0);
+    if grad.buffer.len() != indices.len() * dim {
+        panic!(
+            "grad of shape {:?} doesn't match {} indices",
+            grad.shape,
+            indices.len()
+        );
+    }
+    let rows: Vec<u32> = indices
+        .iter()
+        .copied()
+        .filter(|i| Some(*i) != padding_idx)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let mut values = NdArray::zeros(&[rows.len(), dim]);
+    for (i, row) in indices.iter().zip(grad.buffer.chunks(dim.max(1))) {
+        if let Ok(slot) = rows.binary_search(i) {
+            for (x, y) in values.buffer[slot * dim..(slot + 1) * dim]
+                .iter_mut()
+                .zip(row)
+            {
+                *x += y;
+            }
+        }
+    }
+    RowGrad {
+        indices: rows,
+        values,
+    }
+}
+
+#[test]
+fn test_embedding() {
+    let mut weight = NdArray::arange(0, 12, None).reshape(&[4, 3]);
+    let out = embedding(&mut weight, &[3, 0, 3, 1], Some(vec![2, 2]), None, None);
+    assert_eq!(out.shape, vec![2, 2, 3]);
+    assert_eq!(
+        out.buffer,
+        vec![9., 10., 11., 0., 1., 2., 9., 10., 11., 3., 4., 5.]
+    );
+
+    let out = embedding(&mut weight, &[1, 2], None, Some(5.), None);
+    // row 1 = [3, 4, 5] has norm ~7.07 and is scaled down to 5
+    let norm = out.buffer[..3].iter().map(|x| x * x).sum::<f32>().sqrt();
+    assert!((norm - 5.).abs() < 1e-4);
+    assert!((weight.buffer[3] - out.buffer[0]).abs() < 1e-6);
+    assert_eq!(weight.buffer[0..3], [0., 1., 2.]);
+}
+
+#[test]
+fn test_embedding_backward() {
+    let grad = NdArray::arange(0, 8, None).reshape(&[4, 2]);
+    let g = embedding_backward(&grad, &[5, 2, 5, 0], Some(0));
+    assert_eq!(g.indices(), vec![2, 5]);
+    assert_eq!(g.values().buffer, vec![2., 3., 4., 6.]);
+    let dense = g.to_dense(6);
+    assert_eq!(dense.shape, vec![6, 2]);
+    assert_eq!(&dense.buffer[4..6], &[2., 3.]);
+    assert_eq!(&dense.buffer[10..12], &[4., 6.]);
+
+    let mut weight = NdArray::ones(&[6, 2]);
+    weight.index_add_(&g.indices(), &g.values(), Some(-0.5));
+    assert_eq!(&weight.buffer[10..12], &[-1., -2.]);
+    assert_eq!(&weight.buffer[0..2], &[1., 1.]);
+}
@@ -0,0 +1,60 @@
+use std::fmt;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// Errors raised by fallible array operations, serialization and models.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The input is malformed or truncated.
+    Format(String),
+    /// The input is well formed but uses an element type that can't be
+    /// represented by the requested array type.
+    UnsupportedDType(String),
+    /// A model uses an operator, or an operator option, that isn't
+    /// implemented.
+    UnsupportedOp(String),
+    /// A model graph is inconsistent, e.g. it refers to a missing value.
+    InvalidGraph(String),
+    /// An array's length or shape doesn't match what the operation expects.
+    ShapeMismatch(String),
+    /// An argument or input value is outside of the range the operation
+    /// accepts.
+    InvalidArgument(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Result of a function exported to JS. Errors become a thrown `Error` in
+/// JS; without the `wasm` feature the crate's own [`Error`] is returned.
+#[cfg(feature = "wasm")]
+pub type JsResult<T> = std::result::Result<T, JsValue>;
+#[cfg(not(feature = "wasm"))]
+pub type JsResult<T> = Result<T>;
+
+impl Error {
+    pub(crate) fn format<S: Into<String>>(msg: S) -> Self {
+        Error::Format(msg.into())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Format(msg) => write!(f, "invalid format: {}", msg),
+            Error::UnsupportedDType(dtype) => write!(f, "unsupported dtype: {}", dtype),
+            Error::UnsupportedOp(op) => write!(f, "unsupported operator: {}", op),
+            Error::InvalidGraph(msg) => write!(f, "invalid graph: {}", msg),
+            Error::ShapeMismatch(msg) => write!(f, "shape mismatch: {}", msg),
+            Error::InvalidArgument(msg) => write!(f, "invalid argument: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(feature = "wasm")]
+impl From<Error> for JsValue {
+    fn from(err: Error) -> JsValue {
+        JsError::new(&err.to_string()).into()
+    }
+}
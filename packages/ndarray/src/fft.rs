@@ -0,0 +1,785 @@
+//! Discrete Fourier transforms.
+//!
+//! Complex arrays are stored as real `NdArray`s whose last axis has length 2
+//! and holds `[re, im]` pairs, so a complex array of shape `[a, b]` is an
+//! `NdArray` of shape `[a, b, 2]`. Unless noted otherwise, `axis`/`axes`
+//! arguments refer to the complex (logical) axes, i.e. they never include the
+//! trailing pair axis.
+
+use std::f64::consts::PI;
+use std::ops::{Add, Div, Mul, Sub};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::ndarray::NdArray;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Complex {
+    pub(crate) re: f64,
+    pub(crate) im: f64,
+}
+
+impl Complex {
+    pub(crate) fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    fn from_polar(r: f64, theta: f64) -> Self {
+        Complex::new(r * theta.cos(), r * theta.sin())
+    }
+
+    pub(crate) fn conj(self) -> Self {
+        Complex::new(self.re, -self.im)
+    }
+
+    fn scale(self, k: f64) -> Self {
+        Complex::new(self.re * k, self.im * k)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, rhs: Complex) -> Complex {
+        let den = rhs.re * rhs.re + rhs.im * rhs.im;
+        Complex::new(
+            (self.re * rhs.re + self.im * rhs.im) / den,
+            (self.im * rhs.re - self.re * rhs.im) / den,
+        )
+    }
+}
+
+/// In-place iterative radix-2 transform. `buf.len()` must be a power of two.
+fn radix2(buf: &mut [Complex], inverse: bool) {
+    let n = buf.len();
+    if n <= 1 {
+        return;
+    }
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            buf.swap(i, j);
+        }
+    }
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let w_len = Complex::from_polar(1.0, sign * 2.0 * PI / len as f64);
+        for start in (0..n).step_by(len) {
+            let mut w = Complex::new(1.0, 0.0);
+            for k in 0..len / 2 {
+                let u = buf[start + k];
+                let v = buf[start + k + len / 2] * w;
+                buf[start + k] = u + v;
+                buf[start + k + len / 2] = u - v;
+                w = w * w_len;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Bluestein's chirp-z algorithm, used for lengths that are not a power of two.
+fn bluestein(buf: &mut [Complex], inverse: bool) {
+    let n = buf.len();
+    let m = (2 * n - 1).next_power_of_two();
+    let sign = if inverse { 1.0 } else { -1.0 };
+    // k^2 is reduced modulo 2n to keep the angle small and precise.
+    let chirp: Vec<Complex> = (0..n)
+        .map(|k| {
+            let k2 = (k * k) % (2 * n);
+            Complex::from_polar(1.0, sign * PI * k2 as f64 / n as f64)
+        })
+        .collect();
+
+    let mut a = vec![Complex::default(); m];
+    for k in 0..n {
+        a[k] = buf[k] * chirp[k];
+    }
+    let mut b = vec![Complex::default(); m];
+    b[0] = chirp[0].conj();
+    for k in 1..n {
+        b[k] = chirp[k].conj();
+        b[m - k] = chirp[k].conj();
+    }
+
+    radix2(&mut a, false);
+    radix2(&mut b, false);
+    for i in 0..m {
+        a[i] = a[i] * b[i];
+    }
+    radix2(&mut a, true);
+    let scale = 1.0 / m as f64;
+    for k in 0..n {
+        buf[k] = a[k].scale(scale) * chirp[k];
+    }
+}
+
+/// Unnormalized transform of an arbitrary-length sequence.
+fn transform(buf: &mut [Complex], inverse: bool) {
+    let n = buf.len();
+    if n <= 1 {
+        return;
+    }
+    if n.is_power_of_two() {
+        radix2(buf, inverse);
+    } else {
+        bluestein(buf, inverse);
+    }
+}
+
+fn to_complex(a: &NdArray) -> (Vec<Complex>, Vec<usize>) {
+    assert!(
+        a.shape.len() >= 2 && a.shape[a.shape.len() - 1] == 2,
+        "complex arrays must have a trailing axis of length 2"
+    );
+    let data = a
+        .buffer
+        .chunks(2)
+        .map(|c| Complex::new(c[0] as f64, c[1] as f64))
+        .collect();
+    (data, a.shape[..a.shape.len() - 1].to_vec())
+}
+
+fn real_to_complex(a: &NdArray) -> (Vec<Complex>, Vec<usize>) {
+    let data = a
+        .buffer
+        .iter()
+        .map(|x| Complex::new(*x as f64, 0.0))
+        .collect();
+    (data, a.shape.clone())
+}
+
+fn from_complex(data: &[Complex], shape: &[usize]) -> NdArray {
+    let mut buffer = Vec::with_capacity(data.len() * 2);
+    for c in data {
+        buffer.push(c.re as f32);
+        buffer.push(c.im as f32);
+    }
+    let mut shape = shape.to_vec();
+    shape.push(2);
+    NdArray::from(&buffer, Some(shape), None)
+}
+
+/// Applies `f` to every lane along `axis`. Each lane is truncated or
+/// zero-padded to `n_in` before the call, and `f` must return `n_out` values.
+fn map_lanes<F>(
+    data: &[Complex],
+    shape: &[usize],
+    axis: usize,
+    n_in: usize,
+    n_out: usize,
+    mut f: F,
+) -> (Vec<Complex>, Vec<usize>)
+where
+    F: FnMut(&mut Vec<Complex>),
+{
+    assert!(axis < shape.len(), "axis {} is out of bounds", axis);
+    let len = shape[axis];
+    let inner: usize = shape[axis + 1..].iter().product();
+    let outer: usize = shape[..axis].iter().product();
+    let mut out_shape = shape.to_vec();
+    out_shape[axis] = n_out;
+    let mut out = vec![Complex::default(); outer * n_out * inner];
+    let mut lane = Vec::with_capacity(n_in.max(n_out));
+    for o in 0..outer {
+        for i in 0..inner {
+            lane.clear();
+            for k in 0..n_in {
+                lane.push(if k < len {
+                    data[(o * len + k) * inner + i]
+                } else {
+                    Complex::default()
+                });
+            }
+            f(&mut lane);
+            assert_eq!(lane.len(), n_out);
+            for (k, v) in lane.iter().enumerate() {
+                out[(o * n_out + k) * inner + i] = *v;
+            }
+        }
+    }
+    (out, out_shape)
+}
+
+fn fft_along(
+    data: &[Complex],
+    shape: &[usize],
+    n: Option<usize>,
+    axis: usize,
+    inverse: bool,
+) -> (Vec<Complex>, Vec<usize>) {
+    assert!(axis < shape.len(), "axis {} is out of bounds", axis);
+    let n = n.unwrap_or(shape[axis]);
+    let scale = if inverse { 1.0 / n as f64 } else { 1.0 };
+    map_lanes(data, shape, axis, n, n, |lane| {
+        transform(lane, inverse);
+        if inverse {
+            lane.iter_mut().for_each(|x| *x = x.scale(scale));
+        }
+    })
+}
+
+fn fft_axes(a: &NdArray, axes: Vec<usize>, inverse: bool) -> NdArray {
+    let (mut data, mut shape) = to_complex(a);
+    for axis in axes {
+        let res = fft_along(&data, &shape, None, axis, inverse);
+        data = res.0;
+        shape = res.1;
+    }
+    from_complex(&data, &shape)
+}
+
+fn default_axis(ndim: usize, axis: Option<usize>) -> usize {
+    assert!(ndim > 0, "fft of a 0-d array");
+    axis.unwrap_or(ndim - 1)
+}
+
+/// One-dimensional discrete Fourier transform of a complex array along `axis`
+/// (default: the last complex axis). The input is cropped or zero-padded to
+/// `n` points when `n` is given.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn fft(a: &NdArray, n: Option<usize>, axis: Option<usize>) -> NdArray {
+    let (data, shape) = to_complex(a);
+    let axis = default_axis(shape.len(), axis);
+    let (data, shape) = fft_along(&data, &shape, n, axis, false);
+    from_complex(&data, &shape)
+}
+
+/// Inverse of [`fft`], normalized by `1 / n`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn ifft(a: &NdArray, n: Option<usize>, axis: Option<usize>) -> NdArray {
+    let (data, shape) = to_complex(a);
+    let axis = default_axis(shape.len(), axis);
+    let (data, shape) = fft_along(&data, &shape, n, axis, true);
+    from_complex(&data, &shape)
+}
+
+/// Transform of a real array. Only the `n / 2 + 1` non-negative frequency
+/// terms are returned, as a complex array. `n = 0` gives an empty axis.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn rfft(a: &NdArray, n: Option<usize>, axis: Option<usize>) -> NdArray {
+    let (data, shape) = real_to_complex(a);
+    let axis = default_axis(shape.len(), axis);
+    let n = n.unwrap_or(shape[axis]);
+    let half = if n == 0 { 0 } else { n / 2 + 1 };
+    let (data, shape) = map_lanes(&data, &shape, axis, n, half, |lane| {
+        transform(lane, false);
+        lane.truncate(half);
+    });
+    from_complex(&data, &shape)
+}
+
+/// Inverse of [`rfft`]. `n` is the length of the real output and defaults to
+/// `2 * (m - 1)` where `m` is the length of the input along `axis`; an output
+/// length of 0 gives an empty axis.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn irfft(a: &NdArray, n: Option<usize>, axis: Option<usize>) -> NdArray {
+    let (data, shape) = to_complex(a);
+    let axis = default_axis(shape.len(), axis);
+    let m = shape[axis];
+    let n = n.unwrap_or(2 * (m.max(1) - 1));
+    let half = if n == 0 { 0 } else { n / 2 + 1 };
+    let scale = 1.0 / n as f64;
+    let (data, shape) = map_lanes(&data, &shape, axis, half, n, |lane| {
+        if n == 0 {
+            return;
+        }
+        // rebuild the negative frequencies from Hermitian symmetry
+        lane.resize(n, Complex::default());
+        for k in half..n {
+            lane[k] = lane[n - k].conj();
+        }
+        lane[0].im = 0.0;
+        if n.is_multiple_of(2) {
+            lane[n / 2].im = 0.0;
+        }
+        transform(lane, true);
+        lane.iter_mut().for_each(|x| *x = x.scale(scale));
+    });
+    NdArray::from(
+        &data.iter().map(|c| c.re as f32).collect::<Vec<f32>>(),
+        Some(shape),
+        None,
+    )
+}
+
+fn complex_axes(a: &NdArray, axes: Option<Vec<usize>>, default_count: Option<usize>) -> Vec<usize> {
+    // the trailing [re, im] axis isn't transformed
+    assert!(!a.shape.is_empty(), "fft of a 0-d array");
+    let ndim = a.shape.len() - 1;
+    axes.unwrap_or_else(|| {
+        let count = default_count.unwrap_or(ndim).min(ndim);
+        (ndim - count..ndim).collect()
+    })
+}
+
+/// Two-dimensional transform over `axes` (default: the last two complex axes).
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn fft2(a: &NdArray, axes: Option<Vec<usize>>) -> NdArray {
+    fft_axes(a, complex_axes(a, axes, Some(2)), false)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn ifft2(a: &NdArray, axes: Option<Vec<usize>>) -> NdArray {
+    fft_axes(a, complex_axes(a, axes, Some(2)), true)
+}
+
+/// N-dimensional transform over `axes` (default: every complex axis).
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn fftn(a: &NdArray, axes: Option<Vec<usize>>) -> NdArray {
+    fft_axes(a, complex_axes(a, axes, None), false)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn ifftn(a: &NdArray, axes: Option<Vec<usize>>) -> NdArray {
+    fft_axes(a, complex_axes(a, axes, None), true)
+}
+
+fn roll_axes(a: &NdArray, axes: Option<Vec<usize>>, inverse: bool) -> NdArray {
+    let axes = axes.unwrap_or_else(|| (0..a.shape.len().saturating_sub(1)).collect());
+    let mut res = a.clone();
+    for axis in axes {
+        assert!(axis < res.shape.len(), "axis {} is out of bounds", axis);
+        let len = res.shape[axis];
+        let shift = if inverse { len - len / 2 } else { len / 2 };
+        let inner: usize = res.shape[axis + 1..].iter().product();
+        let outer: usize = res.shape[..axis].iter().product();
+        let mut buffer = vec![0.0; res.buffer.len()];
+        for o in 0..outer {
+            for k in 0..len {
+                let src = (o * len + k) * inner;
+                let dst = (o * len + (k + shift) % len) * inner;
+                buffer[dst..dst + inner].copy_from_slice(&res.buffer[src..src + inner]);
+            }
+        }
+        res.buffer = buffer;
+    }
+    res
+}
+
+/// Moves the zero-frequency term to the centre of `axes`. Unlike the
+/// transforms, `axes` index the raw array here. The default is every axis
+/// but the last, i.e. every complex axis of a `[..., 2]` array; pass the
+/// axes explicitly to shift a real array.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn fftshift(a: &NdArray, axes: Option<Vec<usize>>) -> NdArray {
+    roll_axes(a, axes, false)
+}
+
+/// Inverse of [`fftshift`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn ifftshift(a: &NdArray, axes: Option<Vec<usize>>) -> NdArray {
+    roll_axes(a, axes, true)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+fn window_buffer(window: Window, n: usize, periodic: bool) -> Vec<f32> {
+    if n <= 1 {
+        return vec![1.0; n];
+    }
+    let denom = if periodic { n } else { n - 1 } as f64;
+    (0..n)
+        .map(|i| {
+            let x = 2.0 * PI * i as f64 / denom;
+            let w = match window {
+                Window::Rectangular => 1.0,
+                Window::Hann => 0.5 - 0.5 * x.cos(),
+                Window::Hamming => 0.54 - 0.46 * x.cos(),
+                Window::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+            };
+            w as f32
+        })
+        .collect()
+}
+
+/// Builds a window of length `n`. Periodic windows (the default) are the
+/// ones to use for spectral analysis; symmetric ones suit filter design.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = getWindow))]
+pub fn get_window(window: Window, n: usize, periodic: Option<bool>) -> NdArray {
+    NdArray::from(
+        &window_buffer(window, n, periodic.unwrap_or(true)),
+        None,
+        None,
+    )
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = hannWindow))]
+pub fn hann_window(n: usize, periodic: Option<bool>) -> NdArray {
+    get_window(Window::Hann, n, periodic)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = hammingWindow))]
+pub fn hamming_window(n: usize, periodic: Option<bool>) -> NdArray {
+    get_window(Window::Hamming, n, periodic)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = blackmanWindow))]
+pub fn blackman_window(n: usize, periodic: Option<bool>) -> NdArray {
+    get_window(Window::Blackman, n, periodic)
+}
+
+/// Window of length `win_length` centred in `n_fft` zeros.
+fn padded_window(window: Window, win_length: usize, n_fft: usize) -> Vec<f64> {
+    assert!(win_length <= n_fft, "win_length must not exceed n_fft");
+    let mut res = vec![0.0; n_fft];
+    let left = (n_fft - win_length) / 2;
+    for (i, w) in window_buffer(window, win_length, true).iter().enumerate() {
+        res[left + i] = *w as f64;
+    }
+    res
+}
+
+fn reflect_pad(signal: &[f32], pad: usize) -> Vec<f32> {
+    assert!(
+        pad < signal.len(),
+        "signal is too short to be reflect-padded by {}",
+        pad
+    );
+    let mut res = Vec::with_capacity(signal.len() + 2 * pad);
+    res.extend((1..=pad).rev().map(|i| signal[i]));
+    res.extend_from_slice(signal);
+    let last = signal.len() - 1;
+    res.extend((1..=pad).map(|i| signal[last - i]));
+    res
+}
+
+/// Short-time Fourier transform of a real signal of shape `[..., L]`.
+///
+/// Returns the one-sided spectrum as a complex array of shape
+/// `[..., n_fft / 2 + 1, frames]`, i.e. an `NdArray` of shape
+/// `[..., n_fft / 2 + 1, frames, 2]`. `hop_length` defaults to `n_fft / 4`,
+/// `win_length` to `n_fft` and `window` to Hann. With `center` (the default)
+/// the signal is reflect-padded by `n_fft / 2` on both sides so that frame
+/// `t` is centred on sample `t * hop_length`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn stft(
+    x: &NdArray,
+    n_fft: usize,
+    hop_length: Option<usize>,
+    win_length: Option<usize>,
+    window: Option<Window>,
+    center: Option<bool>,
+) -> NdArray {
+    assert!(n_fft > 0, "n_fft must be positive");
+    let hop = hop_length.unwrap_or((n_fft / 4).max(1));
+    assert!(hop > 0, "hop_length must be positive");
+    let win = padded_window(
+        window.unwrap_or(Window::Hann),
+        win_length.unwrap_or(n_fft),
+        n_fft,
+    );
+    let center = center.unwrap_or(true);
+    let len = *x.shape.last().unwrap();
+    let batch_shape = &x.shape[..x.shape.len() - 1];
+    let bins = n_fft / 2 + 1;
+
+    let mut frames = 0;
+    let mut buffer = Vec::new();
+    // an empty last axis means an empty buffer, so no frames at all
+    for signal in x.buffer.chunks(len.max(1)) {
+        let signal = if center {
+            reflect_pad(signal, n_fft / 2)
+        } else {
+            signal.to_vec()
+        };
+        assert!(signal.len() >= n_fft, "signal is shorter than n_fft");
+        frames = (signal.len() - n_fft) / hop + 1;
+        let mut spec = vec![Complex::default(); bins * frames];
+        let mut frame = vec![Complex::default(); n_fft];
+        for t in 0..frames {
+            for k in 0..n_fft {
+                frame[k] = Complex::new(signal[t * hop + k] as f64 * win[k], 0.0);
+            }
+            transform(&mut frame, false);
+            for f in 0..bins {
+                spec[f * frames + t] = frame[f];
+            }
+        }
+        for c in spec {
+            buffer.push(c.re as f32);
+            buffer.push(c.im as f32);
+        }
+    }
+    let mut shape = batch_shape.to_vec();
+    shape.extend_from_slice(&[bins, frames, 2]);
+    NdArray::from(&buffer, Some(shape), None)
+}
+
+/// Inverse of [`stft`] by windowed overlap-add. The arguments must match the
+/// ones the spectrum was produced with; `length` trims or zero-pads the
+/// output to an exact number of samples.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn istft(
+    x: &NdArray,
+    n_fft: usize,
+    hop_length: Option<usize>,
+    win_length: Option<usize>,
+    window: Option<Window>,
+    center: Option<bool>,
+    length: Option<usize>,
+) -> NdArray {
+    let (data, shape) = to_complex(x);
+    assert!(
+        shape.len() >= 2,
+        "istft expects a [..., freq, frames] spectrum"
+    );
+    let bins = shape[shape.len() - 2];
+    let frames = shape[shape.len() - 1];
+    assert_eq!(bins, n_fft / 2 + 1, "spectrum does not match n_fft");
+    let hop = hop_length.unwrap_or((n_fft / 4).max(1));
+    assert!(hop > 0, "hop_length must be positive");
+    let win = padded_window(
+        window.unwrap_or(Window::Hann),
+        win_length.unwrap_or(n_fft),
+        n_fft,
+    );
+    let center = center.unwrap_or(true);
+    let full = n_fft + hop * (frames.max(1) - 1);
+    let start = if center { n_fft / 2 } else { 0 };
+    let natural = if frames == 0 {
+        0
+    } else if center {
+        full - 2 * (n_fft / 2)
+    } else {
+        full
+    };
+    let out_len = length.unwrap_or(natural);
+
+    let mut envelope = vec![0.0f64; full];
+    for t in 0..frames {
+        for k in 0..n_fft {
+            envelope[t * hop + k] += win[k] * win[k];
+        }
+    }
+
+    let mut buffer = Vec::new();
+    let mut frame = vec![Complex::default(); n_fft];
+    let batch: usize = shape[..shape.len() - 2].iter().product();
+    for b in 0..batch {
+        let spec = &data[b * bins * frames..(b + 1) * bins * frames];
+        let mut signal = vec![0.0f64; full];
+        for t in 0..frames {
+            for f in 0..bins {
+                frame[f] = spec[f * frames + t];
+            }
+            for f in bins..n_fft {
+                frame[f] = frame[n_fft - f].conj();
+            }
+            transform(&mut frame, true);
+            for k in 0..n_fft {
+                signal[t * hop + k] += frame[k].re / n_fft as f64 * win[k];
+            }
+        }
+        for i in 0..out_len {
+            let j = start + i;
+            let v = if j < full && envelope[j] > 1e-11 {
+                signal[j] / envelope[j]
+            } else {
+                0.0
+            };
+            buffer.push(v as f32);
+        }
+    }
+    let mut out_shape = shape[..shape.len() - 2].to_vec();
+    out_shape.push(out_len);
+    NdArray::from(&buffer, Some(out_shape), None)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl NdArray {
+    pub fn fft(&self, n: Option<usize>, axis: Option<usize>) -> NdArray {
+        fft(self, n, axis)
+    }
+
+    pub fn ifft(&self, n: Option<usize>, axis: Option<usize>) -> NdArray {
+        ifft(self, n, axis)
+    }
+
+    pub fn rfft(&self, n: Option<usize>, axis: Option<usize>) -> NdArray {
+        rfft(self, n, axis)
+    }
+
+    pub fn irfft(&self, n: Option<usize>, axis: Option<usize>) -> NdArray {
+        irfft(self, n, axis)
+    }
+
+    pub fn fft2(&self, axes: Option<Vec<usize>>) -> NdArray {
+        fft2(self, axes)
+    }
+
+    pub fn ifft2(&self, axes: Option<Vec<usize>>) -> NdArray {
+        ifft2(self, axes)
+    }
+
+    pub fn fftn(&self, axes: Option<Vec<usize>>) -> NdArray {
+        fftn(self, axes)
+    }
+
+    pub fn ifftn(&self, axes: Option<Vec<usize>>) -> NdArray {
+        ifftn(self, axes)
+    }
+
+    pub fn fftshift(&self, axes: Option<Vec<usize>>) -> NdArray {
+        fftshift(self, axes)
+    }
+
+    pub fn ifftshift(&self, axes: Option<Vec<usize>>) -> NdArray {
+        ifftshift(self, axes)
+    }
+}
+
+#[cfg(test)]
+fn assert_close(a: &[f32], b: &[f32], tol: f32) {
+    assert_eq!(a.len(), b.len());
+    for (x, y) in a.iter().zip(b) {
+        assert!((x - y).abs() <= tol, "{:?} != {:?}", a, b);
+    }
+}
+
+#[cfg(test)]
+fn naive_dft(x: &[f32]) -> Vec<f32> {
+    let n = x.len() / 2;
+    let mut res = Vec::new();
+    for k in 0..n {
+        let mut acc = Complex::default();
+        for t in 0..n {
+            let theta = -2.0 * PI * (k * t) as f64 / n as f64;
+            acc = acc
+                + Complex::new(x[2 * t] as f64, x[2 * t + 1] as f64)
+                    * Complex::from_polar(1.0, theta);
+        }
+        res.push(acc.re as f32);
+        res.push(acc.im as f32);
+    }
+    res
+}
+
+#[test]
+fn test_fft_arbitrary_length() {
+    for n in [1, 2, 5, 8, 12, 17] {
+        let a = NdArray::randn(&[n, 2]);
+        let b = fft(&a, None, None);
+        assert_eq!(b.shape, vec![n, 2]);
+        assert_close(&b.buffer, &naive_dft(&a.buffer), 1e-3);
+        assert_close(&ifft(&b, None, None).buffer, &a.buffer, 1e-4);
+    }
+}
+
+#[test]
+fn test_rfft() {
+    let a = NdArray::from(&[1., 2., 3., 4., 5.], None, None);
+    let b = rfft(&a, None, None);
+    assert_eq!(b.shape, vec![3, 2]);
+    assert_close(&b.buffer[..2], &[15., 0.], 1e-5);
+    assert_close(&irfft(&b, Some(5), None).buffer, &a.buffer, 1e-5);
+
+    let c = NdArray::randn(&[3, 8]);
+    let d = rfft(&c, None, Some(1));
+    assert_eq!(d.shape, vec![3, 5, 2]);
+    assert_close(&irfft(&d, None, Some(1)).buffer, &c.buffer, 1e-4);
+}
+
+#[test]
+fn test_fft2() {
+    let a = NdArray::randn(&[3, 6, 2]);
+    let b = fft2(&a, None);
+    let c = fft(&fft(&a, None, Some(0)), None, Some(1));
+    assert_close(&b.buffer, &c.buffer, 1e-4);
+    assert_close(&ifftn(&b, None).buffer, &a.buffer, 1e-4);
+}
+
+#[test]
+fn test_fftshift() {
+    let a = NdArray::arange(0, 5, None);
+    let b = fftshift(&a, Some(vec![0]));
+    assert_eq!(b.buffer, vec![3., 4., 0., 1., 2.]);
+    assert_eq!(ifftshift(&b, Some(vec![0])).buffer, a.buffer);
+
+    let c = NdArray::arange(0, 4, None).reshape(&[2, 2]);
+    assert_eq!(fftshift(&c, Some(vec![1])).buffer, vec![1., 0., 3., 2.]);
+
+    // the trailing [re, im] axis is left alone by default
+    let z = NdArray::arange(0, 6, None).reshape(&[3, 2]);
+    let shifted = fftshift(&z, None);
+    assert_eq!(shifted.buffer, vec![4., 5., 0., 1., 2., 3.]);
+    assert_eq!(ifftshift(&shifted, None).buffer, z.buffer);
+}
+
+#[test]
+fn test_fft_empty() {
+    let a = NdArray::from(&[1., 2., 3.], None, None);
+    assert_eq!(rfft(&a, Some(0), None).shape, vec![0, 2]);
+    let spec = NdArray::from(&[1., 0., 2., 0.], Some(vec![2, 2]), None);
+    assert_eq!(irfft(&spec, Some(0), None).shape, vec![0]);
+    assert_eq!(fft(&spec, Some(0), None).shape, vec![0, 2]);
+
+    let x = NdArray::from(&[], Some(vec![2, 0]), None);
+    let spec = stft(&x, 8, None, None, None, None);
+    assert_eq!(spec.shape, vec![2, 5, 0, 2]);
+    assert_eq!(
+        istft(&spec, 8, None, None, None, None, None).shape,
+        vec![2, 0]
+    );
+}
+
+#[test]
+fn test_stft_roundtrip() {
+    let x = NdArray::randn(&[2, 100]);
+    for window in [Window::Hann, Window::Hamming, Window::Blackman] {
+        let spec = stft(&x, 16, Some(4), None, Some(window), None);
+        assert_eq!(spec.shape, vec![2, 9, 26, 2]);
+        let y = istft(&spec, 16, Some(4), None, Some(window), None, Some(100));
+        assert_eq!(y.shape, vec![2, 100]);
+        assert_close(&y.buffer, &x.buffer, 1e-4);
+    }
+}
+
+#[test]
+#[should_panic(expected = "fft of a 0-d array")]
+fn test_fftn_0d() {
+    fftn(&NdArray::from(&[1.], Some(vec![]), None), None);
+}
+
+#[test]
+#[should_panic(expected = "hop_length must be positive")]
+fn test_istft_zero_hop() {
+    istft(&NdArray::zeros(&[9, 3, 2]), 16, Some(0), None, None, None, None);
+}
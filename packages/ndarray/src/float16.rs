@@ -0,0 +1,326 @@
+//! Half-precision (`float16` and `bfloat16`) arrays.
+//!
+//! `HalfNdArray` stores every element as its raw 16-bit pattern, halving
+//! the memory and download size of weights compared to `NdArray`. It is a
+//! storage type: every operation widens its inputs to `f32`, computes in
+//! `f32` and narrows the result back.
+//!
+//! Narrowing rounds to nearest, ties to even. Values beyond the largest
+//! finite half become infinities, values too small for the format become
+//! subnormals or signed zeros, and NaNs stay (quiet) NaNs. Widening is
+//! exact.
+
+use half::{bf16, f16};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    dtype::DType,
+    error::{Error, JsResult, Result},
+    ndarray::NdArray,
+    ops::broadcast_with,
+    utils,
+};
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct HalfNdArray {
+    pub(super) bits: Vec<u16>,
+    pub(super) shape: Vec<usize>,
+    pub(super) dtype: DType,
+}
+
+pub(crate) fn check_half(dtype: DType) -> Result<()> {
+    if !dtype.is_half() {
+        return Err(Error::UnsupportedDType(format!(
+            "{} is not a half-precision type",
+            dtype.name()
+        )));
+    }
+    Ok(())
+}
+
+/// Rounds `x` to the nearest `dtype` value, ties to even.
+pub(crate) fn narrow(x: f32, dtype: DType) -> u16 {
+    match dtype {
+        DType::Float16 => f16::from_f32(x).to_bits(),
+        DType::BFloat16 => bf16::from_f32(x).to_bits(),
+        _ => unreachable!(),
+    }
+}
+
+pub(crate) fn widen(bits: u16, dtype: DType) -> f32 {
+    match dtype {
+        DType::Float16 => f16::from_bits(bits).to_f32(),
+        DType::BFloat16 => bf16::from_bits(bits).to_f32(),
+        _ => unreachable!(),
+    }
+}
+
+impl HalfNdArray {
+    pub fn new(a: &NdArray, dtype: DType) -> Result<HalfNdArray> {
+        check_half(dtype)?;
+        Ok(HalfNdArray {
+            bits: a.buffer.iter().map(|x| narrow(*x, dtype)).collect(),
+            shape: a.shape.clone(),
+            dtype,
+        })
+    }
+
+    /// Takes ownership of raw 16-bit patterns.
+    pub fn from_bits(
+        bits: Vec<u16>,
+        shape: Option<Vec<usize>>,
+        dtype: DType,
+    ) -> Result<HalfNdArray> {
+        check_half(dtype)?;
+        let shape = shape.unwrap_or_else(|| vec![bits.len()]);
+        let len = shape.iter().try_fold(1usize, |n, d| n.checked_mul(*d));
+        if len != Some(bits.len()) {
+            return Err(Error::ShapeMismatch(format!(
+                "{} values can't have shape {:?}",
+                bits.len(),
+                shape
+            )));
+        }
+        Ok(HalfNdArray { bits, shape, dtype })
+    }
+
+    pub fn as_bits(&self) -> &[u16] {
+        &self.bits
+    }
+
+    /// Applies `f` to every widened element.
+    fn map(&self, f: impl Fn(f32) -> f32) -> HalfNdArray {
+        HalfNdArray {
+            bits: self
+                .bits
+                .iter()
+                .map(|b| narrow(f(widen(*b, self.dtype)), self.dtype))
+                .collect(),
+            shape: self.shape.clone(),
+            dtype: self.dtype,
+        }
+    }
+
+    /// Applies `f` to the widened arrays and narrows the result to the
+    /// inputs' dtype.
+    fn zip(&self, b: &HalfNdArray, f: impl Fn(&NdArray, &NdArray) -> NdArray) -> HalfNdArray {
+        if self.dtype != b.dtype {
+            panic!(
+                "can't combine {} and {} arrays",
+                self.dtype.name(),
+                b.dtype.name()
+            );
+        }
+        HalfNdArray::new(&f(&self.to_ndarray(), &b.to_ndarray()), self.dtype).unwrap()
+    }
+
+    fn broadcast(&self, b: &HalfNdArray, f: fn(f32, f32) -> f32) -> HalfNdArray {
+        self.zip(b, |x, y| {
+            broadcast_with(x, y, f)
+                .unwrap_or_else(|| panic!("can't broadcast shapes {:?} and {:?}", x.shape, y.shape))
+        })
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl HalfNdArray {
+    /// Narrows an `f32` array to `dtype` (`Float16` or `BFloat16`).
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = fromNdArray))]
+    pub fn from_ndarray(a: &NdArray, dtype: DType) -> JsResult<HalfNdArray> {
+        Ok(HalfNdArray::new(a, dtype)?)
+    }
+
+    /// Builds an array from the raw 16-bit patterns of a `Uint16Array`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = fromBits))]
+    pub fn from_bits_js(
+        bits: Vec<u16>,
+        shape: Option<Vec<usize>>,
+        dtype: DType,
+    ) -> JsResult<HalfNdArray> {
+        Ok(HalfNdArray::from_bits(bits, shape, dtype)?)
+    }
+
+    /// Raw 16-bit patterns of the elements.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn bits(&self) -> Vec<u16> {
+        self.bits.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn shape(&self) -> Vec<usize> {
+        self.shape.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn dtype(&self) -> DType {
+        self.dtype
+    }
+
+    /// Widens the elements to an `f32` `NdArray`, which is exact.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = toNdArray))]
+    pub fn to_ndarray(&self) -> NdArray {
+        let buffer: Vec<f32> = self.bits.iter().map(|b| widen(*b, self.dtype)).collect();
+        NdArray::from(&buffer, Some(self.shape.clone()), None)
+    }
+
+    /// Converts between `Float16` and `BFloat16`, rounding to nearest.
+    pub fn cast(&self, dtype: DType) -> JsResult<HalfNdArray> {
+        Ok(HalfNdArray::new(&self.to_ndarray(), dtype)?)
+    }
+
+    pub fn reshape(&self, shape: &[i32]) -> HalfNdArray {
+        let shape = utils::reshaped_shape(shape, self.bits.len());
+        HalfNdArray {
+            bits: self.bits.clone(),
+            shape,
+            dtype: self.dtype,
+        }
+    }
+
+    /// Element-wise sum with NumPy-style broadcasting.
+    pub fn add(&self, b: &HalfNdArray) -> HalfNdArray {
+        self.broadcast(b, |x, y| x + y)
+    }
+
+    pub fn sub(&self, b: &HalfNdArray) -> HalfNdArray {
+        self.broadcast(b, |x, y| x - y)
+    }
+
+    pub fn mul(&self, b: &HalfNdArray) -> HalfNdArray {
+        self.broadcast(b, |x, y| x * y)
+    }
+
+    pub fn div(&self, b: &HalfNdArray) -> HalfNdArray {
+        self.broadcast(b, |x, y| x / y)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = addScalar))]
+    pub fn add_scalar(&self, b: f32) -> HalfNdArray {
+        self.map(|x| x + b)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = mulScalar))]
+    pub fn mul_scalar(&self, b: f32) -> HalfNdArray {
+        self.map(|x| x * b)
+    }
+
+    pub fn neg(&self) -> HalfNdArray {
+        self.map(|x| -x)
+    }
+
+    pub fn relu(&self) -> HalfNdArray {
+        self.map(|x| x.max(0.))
+    }
+
+    pub fn exp(&self) -> HalfNdArray {
+        self.map(f32::exp)
+    }
+
+    pub fn sigmoid(&self) -> HalfNdArray {
+        self.map(|x| 1. / (1. + (-x).exp()))
+    }
+
+    pub fn tanh(&self) -> HalfNdArray {
+        self.map(f32::tanh)
+    }
+
+    /// Matrix product accumulated in `f32` and rounded once at the end.
+    pub fn matmul(&self, b: &HalfNdArray) -> HalfNdArray {
+        self.zip(b, |x, y| x.matmul(y))
+    }
+
+    /// Product with an `f32` array, e.g. activations times half-precision
+    /// weights; the result stays in `f32`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = matmulF32))]
+    pub fn matmul_f32(&self, b: &NdArray) -> NdArray {
+        self.to_ndarray().matmul(b)
+    }
+}
+
+impl NdArray {
+    pub fn to_half(&self, dtype: DType) -> Result<HalfNdArray> {
+        HalfNdArray::new(self, dtype)
+    }
+}
+
+#[test]
+fn test_half_rounding() {
+    let h = |x: f32| narrow(x, DType::Float16);
+    let b = |x: f32| narrow(x, DType::BFloat16);
+    // 1 + 2^-11 is halfway between 1 and the next f16, and rounds to even
+    assert_eq!(h(1. + 2f32.powi(-11)), 0x3c00);
+    assert_eq!(h(1. + 3. * 2f32.powi(-11)), 0x3c02);
+    assert_eq!(h(1. + 2f32.powi(-11) + 2f32.powi(-20)), 0x3c01);
+    assert_eq!(b(1. + 2f32.powi(-8)), 0x3f80);
+    assert_eq!(b(1. + 3. * 2f32.powi(-8)), 0x3f82);
+
+    // overflow, the largest finite value and infinities
+    assert_eq!(h(65504.), 0x7bff);
+    assert_eq!(h(65519.), 0x7bff);
+    assert_eq!(h(65520.), 0x7c00);
+    assert_eq!(h(f32::NEG_INFINITY), 0xfc00);
+    assert_eq!(b(f32::MAX), 0x7f80);
+    assert_eq!(widen(0x7c00, DType::Float16), f32::INFINITY);
+
+    // subnormals and underflow
+    assert_eq!(h(2f32.powi(-24)), 0x0001);
+    assert_eq!(h(-3. * 2f32.powi(-24)), 0x8003);
+    assert_eq!(h(2f32.powi(-25)), 0x0000);
+    assert_eq!(h(3. * 2f32.powi(-26)), 0x0001);
+    assert_eq!(h(-1e-10), 0x8000);
+    assert_eq!(widen(0x03ff, DType::Float16), 1023. * 2f32.powi(-24));
+    assert_eq!(b(f32::MIN_POSITIVE / 4.), 0x0020);
+
+    // NaN stays NaN
+    for dtype in [DType::Float16, DType::BFloat16] {
+        let n = narrow(f32::NAN, dtype);
+        assert!(widen(n, dtype).is_nan());
+        assert!(widen(narrow(-f32::NAN, dtype), dtype).is_nan());
+    }
+    // a NaN whose payload lives only in the low bits must not become inf
+    let payload = f32::from_bits(0x7f80_0001);
+    assert!(widen(h(payload), DType::Float16).is_nan());
+    assert!(widen(b(payload), DType::BFloat16).is_nan());
+}
+
+#[test]
+fn test_half_array() {
+    let a = NdArray::from(&[0.1, -2.5, 1000.1, 3.], Some(vec![2, 2]), None);
+    let h = a.to_half(DType::Float16).unwrap();
+    assert_eq!(h.dtype(), DType::Float16);
+    assert_eq!(h.to_ndarray().buffer, vec![0.099975586, -2.5, 1000., 3.]);
+    assert_eq!(
+        HalfNdArray::from_bits(h.bits(), Some(vec![4]), DType::Float16)
+            .unwrap()
+            .to_ndarray()
+            .buffer,
+        h.to_ndarray().buffer
+    );
+    assert!(HalfNdArray::new(&a, DType::Float32).is_err());
+    assert!(HalfNdArray::from_bits(vec![0; 3], Some(vec![2, 2]), DType::Float16).is_err());
+    // a shape whose product overflows
+    let huge = Some(vec![usize::MAX / 2 + 1, 2]);
+    assert!(HalfNdArray::from_bits(vec![], huge, DType::Float16).is_err());
+
+    // computed in f32, rounded once
+    let ones = NdArray::ones(&[2]).to_half(DType::Float16).unwrap();
+    let sum = h.add(&ones);
+    assert_eq!(sum.shape(), vec![2, 2]);
+    assert_eq!(sum.to_ndarray().buffer, vec![1.0996094, -1.5, 1001., 4.]);
+    assert_eq!(h.mul_scalar(100.).to_ndarray().buffer[2], f32::INFINITY);
+    let prod = h.matmul(&h);
+    let expected = h.to_ndarray().matmul(&h.to_ndarray());
+    assert_eq!(prod, HalfNdArray::new(&expected, DType::Float16).unwrap());
+
+    let bf = h.cast(DType::BFloat16).unwrap();
+    assert_eq!(bf.to_ndarray().buffer, vec![0.100097656, -2.5, 1000., 3.]);
+    assert_eq!(
+        bf.relu().to_ndarray().buffer,
+        vec![0.100097656, 0., 1000., 3.]
+    );
+    assert_eq!(bf.reshape(&[4, 1]).shape(), vec![4, 1]);
+    let r = bf.reshape(&[-1, 2]);
+    assert_eq!((r.shape(), r.bits()), (vec![2, 2], bf.bits()));
+}
@@ -0,0 +1,47 @@
+//! Finite-difference gradient checks shared by the tests of the layers
+//! with hand-written backward passes.
+
+use crate::ndarray::NdArray;
+
+/// Central difference of `loss` w.r.t. `x[i]`, with step `h`.
+pub(crate) fn central_diff<F: Fn(&NdArray) -> f64>(x: &NdArray, i: usize, h: f32, loss: F) -> f32 {
+    let (mut lo, mut hi) = (x.clone(), x.clone());
+    lo.buffer[i] -= h;
+    hi.buffer[i] += h;
+    ((loss(&hi) - loss(&lo)) / (2. * h as f64)) as f32
+}
+
+/// Central difference of `sum(f() * r)` w.r.t. `x[i]`.
+pub(crate) fn numeric_grad<F: Fn(&NdArray) -> NdArray>(
+    x: &NdArray,
+    i: usize,
+    r: &NdArray,
+    f: F,
+) -> f32 {
+    central_diff(x, i, 1e-2, |x| {
+        f(x).buffer
+            .iter()
+            .zip(&r.buffer)
+            .map(|(a, b)| (a * b) as f64)
+            .sum()
+    })
+}
+
+/// Asserts that `analytic` is the gradient of `sum(f(x) * r)` w.r.t. `x`.
+pub(crate) fn assert_grad(
+    analytic: &NdArray,
+    x: &NdArray,
+    r: &NdArray,
+    f: impl Fn(&NdArray) -> NdArray,
+) {
+    for i in 0..x.buffer.len() {
+        let n = numeric_grad(x, i, r, &f);
+        assert!(
+            (analytic.buffer[i] - n).abs() < 1e-2,
+            "grad[{}]: {} vs {}",
+            i,
+            analytic.buffer[i],
+            n
+        );
+    }
+}
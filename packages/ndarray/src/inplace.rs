@@ -0,0 +1,262 @@
+//! In-place variants of the element-wise ops, `*Out` variants that write
+//! into a preallocated array, and the fused `axpy` update.
+//!
+//! In-place methods carry a trailing `_` like their PyTorch counterparts and
+//! return nothing. Binary ops broadcast `b` over `a` the same way `ops::add`
+//! repeats it, so `b`'s shape, without leading 1s, must end `a`'s.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::{ndarray::NdArray, simd};
+
+fn zip_with<F: Fn(f32, f32) -> f32>(a: &mut NdArray, b: &NdArray, f: F) {
+    // repeating b is broadcasting when its shape is a suffix of a's
+    let leading = b.shape.iter().take_while(|d| **d == 1).count();
+    if !a.shape.ends_with(&b.shape[leading..]) {
+        panic!("can't broadcast shape {:?} to shape {:?}", b.shape, a.shape);
+    }
+    for (x, y) in a.buffer.iter_mut().zip(b.buffer.iter().cycle()) {
+        *x = f(*x, *y);
+    }
+}
+
+fn map<F: Fn(f32) -> f32>(a: &mut NdArray, f: F) {
+    a.buffer.iter_mut().for_each(|x| *x = f(*x));
+}
+
+/// Makes `out` hold `a`'s shape, reusing its allocation.
+fn prepare_out(out: &mut NdArray, a: &NdArray) {
+    if out.buffer.len() != a.buffer.len() {
+        panic!(
+            "out has shape {:?} but the result has shape {:?}",
+            out.shape, a.shape
+        );
+    }
+    out.buffer.copy_from_slice(&a.buffer);
+    out.shape.clone_from(&a.shape);
+    out.strides.clone_from(&a.strides);
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl NdArray {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = add_))]
+    pub fn add_(&mut self, b: &NdArray) {
+        zip_with(self, b, |x, y| x + y);
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = sub_))]
+    pub fn sub_(&mut self, b: &NdArray) {
+        zip_with(self, b, |x, y| x - y);
+    }
+
+    /// Element-wise product, in place; see `dot`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = dot_))]
+    pub fn dot_(&mut self, b: &NdArray) {
+        zip_with(self, b, |x, y| x * y);
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = div_))]
+    pub fn div_(&mut self, b: &NdArray) {
+        zip_with(self, b, |x, y| x / y);
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = addScalar_))]
+    pub fn add_scalar_(&mut self, b: f32) {
+        map(self, |x| x + b);
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = subScalar_))]
+    pub fn sub_scalar_(&mut self, b: f32) {
+        map(self, |x| x - b);
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = mulScalar_))]
+    pub fn mul_scalar_(&mut self, b: f32) {
+        map(self, |x| x * b);
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = pow_))]
+    pub fn pow_(&mut self, b: f32) {
+        map(self, |x| x.powf(b));
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = exp_))]
+    pub fn exp_(&mut self) {
+        map(self, f32::exp);
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = ln_))]
+    pub fn ln_(&mut self) {
+        map(self, f32::ln);
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = relu_))]
+    pub fn relu_(&mut self) {
+        map(self, |x| x.max(0.));
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = sigmoid_))]
+    pub fn sigmoid_(&mut self) {
+        map(self, |x| 1. / (1. + (-x).exp()));
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = tanh_))]
+    pub fn tanh_(&mut self) {
+        map(self, f32::tanh);
+    }
+
+    /// Limits every value to `[min, max]`; either bound may be omitted.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = clamp_))]
+    pub fn clamp_(&mut self, min: Option<f32>, max: Option<f32>) {
+        let (min, max) = (
+            min.unwrap_or(f32::NEG_INFINITY),
+            max.unwrap_or(f32::INFINITY),
+        );
+        map(self, |x| x.max(min).min(max));
+    }
+
+    pub fn clamp(&self, min: Option<f32>, max: Option<f32>) -> NdArray {
+        let mut res = self.clone();
+        res.clamp_(min, max);
+        res
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = fill_))]
+    pub fn fill_(&mut self, value: f32) {
+        self.buffer.iter_mut().for_each(|x| *x = value);
+    }
+
+    /// `self += alpha * x` without allocating, e.g. `p.axpy(-lr, grad)` for
+    /// an SGD step.
+    pub fn axpy(&mut self, alpha: f32, x: &NdArray) {
+        zip_with(self, x, |y, x| y + alpha * x);
+    }
+}
+
+/// Writes `a + b` into `out`, which must have as many elements as `a`.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = addOut))]
+pub fn add_out(a: &NdArray, b: &NdArray, out: &mut NdArray) {
+    prepare_out(out, a);
+    out.add_(b);
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = subOut))]
+pub fn sub_out(a: &NdArray, b: &NdArray, out: &mut NdArray) {
+    prepare_out(out, a);
+    out.sub_(b);
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = dotOut))]
+pub fn dot_out(a: &NdArray, b: &NdArray, out: &mut NdArray) {
+    prepare_out(out, a);
+    out.dot_(b);
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = mulScalarOut))]
+pub fn mul_scalar_out(a: &NdArray, b: f32, out: &mut NdArray) {
+    prepare_out(out, a);
+    out.mul_scalar_(b);
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = addScalarOut))]
+pub fn add_scalar_out(a: &NdArray, b: f32, out: &mut NdArray) {
+    prepare_out(out, a);
+    out.add_scalar_(b);
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = reluOut))]
+pub fn relu_out(a: &NdArray, out: &mut NdArray) {
+    prepare_out(out, a);
+    out.relu_();
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = sigmoidOut))]
+pub fn sigmoid_out(a: &NdArray, out: &mut NdArray) {
+    prepare_out(out, a);
+    out.sigmoid_();
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = tanhOut))]
+pub fn tanh_out(a: &NdArray, out: &mut NdArray) {
+    prepare_out(out, a);
+    out.tanh_();
+}
+
+/// Writes the 2-D product `a @ b` into `out`.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = matmulOut))]
+pub fn matmul_out(a: &NdArray, b: &NdArray, out: &mut NdArray) {
+    if a.shape.len() != 2 || b.shape.len() != 2 || a.shape[1] != b.shape[0] {
+        panic!("can't multiply shapes {:?} and {:?}", a.shape, b.shape);
+    }
+    let (m, k, n) = (a.shape[0], a.shape[1], b.shape[1]);
+    if out.buffer.len() != m * n {
+        panic!(
+            "out has shape {:?} but the result has shape {:?}",
+            out.shape,
+            [m, n]
+        );
+    }
+    out.shape = vec![m, n];
+    out.strides = vec![n, 1];
+    out.buffer.iter_mut().for_each(|x| *x = 0.);
+    // row i of the result accumulates a[i, p] * b[p, :] over p, in the same
+    // order as a dot product per element
+    for i in 0..m {
+        let row = &mut out.buffer[i * n..(i + 1) * n];
+        for (p, x) in a.buffer[i * k..(i + 1) * k].iter().enumerate() {
+            simd::axpy(row, *x, &b.buffer[p * n..(p + 1) * n]);
+        }
+    }
+}
+
+#[test]
+fn test_inplace() {
+    let mut a = NdArray::arange(0, 6, None).reshape(&[2, 3]);
+    let ptr = a.buffer.as_ptr();
+    a.add_(&NdArray::ones(&[3]));
+    a.mul_scalar_(2.);
+    a.sub_scalar_(4.);
+    assert_eq!(a.buffer, vec![-2., 0., 2., 4., 6., 8.]);
+    a.relu_();
+    a.clamp_(None, Some(5.));
+    assert_eq!(a.buffer, vec![0., 0., 2., 4., 5., 5.]);
+    assert_eq!(a.buffer.as_ptr(), ptr);
+
+    let mut p = NdArray::ones(&[4]);
+    let grad = NdArray::arange(0, 4, None);
+    p.axpy(-0.5, &grad);
+    // leading 1s in b's shape are ignored
+    p.add_(&NdArray::ones(&[1, 4]));
+    assert_eq!(p.buffer, vec![2., 1.5, 1., 0.5]);
+    assert_eq!(
+        p.buffer,
+        NdArray::ones(&[4])
+            .add(&grad.mul_scalar(-0.5))
+            .add_scalar(1.)
+            .buffer
+    );
+}
+
+#[test]
+#[should_panic(expected = "can't broadcast shape [3] to shape [3, 2]")]
+fn test_inplace_shape_mismatch() {
+    NdArray::zeros(&[3, 2]).sub_(&NdArray::ones(&[3]));
+}
+
+#[test]
+fn test_out() {
+    let a = NdArray::arange(0, 6, None).reshape(&[2, 3]);
+    let b = NdArray::arange(0, 6, None).reshape(&[3, 2]);
+    let mut out = NdArray::zeros(&[4]);
+    matmul_out(&a, &b, &mut out);
+    assert_eq!(out.shape, vec![2, 2]);
+    assert_eq!(out.buffer, crate::ops::matmul(&a, &b).buffer);
+
+    let mut out = NdArray::zeros(&[6]);
+    add_out(&a, &a, &mut out);
+    assert_eq!(out.shape, vec![2, 3]);
+    assert_eq!(out.buffer, a.mul_scalar(2.).buffer);
+    relu_out(&a.sub_scalar(3.), &mut out);
+    assert_eq!(out.buffer, vec![0., 0., 0., 0., 1., 2.]);
+}
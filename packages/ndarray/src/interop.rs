@@ -0,0 +1,133 @@
+//! Moving data between JS typed arrays and `NdArray` without extra copies.
+//!
+//! `buffer` returns a fresh copy every time it is read. For large arrays,
+//! use `bufferView` to read the data in place, `copyFrom` to overwrite it
+//! in place, and `fromVec` to hand a buffer over to a new array.
+
+#[cfg(feature = "wasm")]
+use js_sys::Float32Array;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    error::{Error, JsResult, Result},
+    ndarray::NdArray,
+    utils::get_strides,
+};
+
+impl NdArray {
+    /// Builds an array that takes ownership of `buffer`.
+    pub fn from_vec(buffer: Vec<f32>, shape: Option<Vec<usize>>) -> Result<NdArray> {
+        let shape = shape.unwrap_or_else(|| vec![buffer.len()]);
+        if shape.iter().product::<usize>() != buffer.len() {
+            return Err(Error::ShapeMismatch(format!(
+                "{} values can't have shape {:?}",
+                buffer.len(),
+                shape
+            )));
+        }
+        Ok(NdArray {
+            strides: get_strides(&shape),
+            buffer,
+            shape,
+        })
+    }
+
+    pub fn as_slice(&self) -> &[f32] {
+        &self.buffer
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [f32] {
+        &mut self.buffer
+    }
+
+    pub fn into_vec(self) -> Vec<f32> {
+        self.buffer
+    }
+
+    /// Overwrites the data in place; `src` must have as many elements as
+    /// the array.
+    pub fn copy_from_slice(&mut self, src: &[f32]) -> Result<()> {
+        check_len(src.len(), self.buffer.len())?;
+        self.buffer.copy_from_slice(src);
+        Ok(())
+    }
+}
+
+fn check_len(src: usize, len: usize) -> Result<()> {
+    if src != len {
+        return Err(Error::ShapeMismatch(format!(
+            "can't copy {} values into an array of {}",
+            src, len
+        )));
+    }
+    Ok(())
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl NdArray {
+    /// Creates an array from a `Float32Array`, which is copied into wasm
+    /// memory once and then owned by the array.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = fromVec))]
+    pub fn from_vec_js(
+        buffer: Vec<f32>,
+        shape: Option<Vec<usize>>,
+    ) -> JsResult<NdArray> {
+        Ok(NdArray::from_vec(buffer, shape)?)
+    }
+
+    /// A `Float32Array` backed directly by this array's data in wasm memory.
+    ///
+    /// The view is only valid until the next call into wasm that may
+    /// allocate, since growing the wasm memory detaches every view of it
+    /// (the view then has length 0). It must also not be used after the
+    /// array is freed or passed by value to a function. Read or copy what
+    /// you need right away; writes through the view update the array.
+    #[cfg(feature = "wasm")]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = bufferView))]
+    pub fn buffer_view(&self) -> Float32Array {
+        // Safety: the view borrows `self.buffer`; the rules above are what
+        // keep JS from observing it after the buffer moves or is freed.
+        unsafe { Float32Array::view(&self.buffer) }
+    }
+
+    /// Overwrites the data in place with the contents of `src`, which must
+    /// have exactly as many elements as the array.
+    #[cfg(feature = "wasm")]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = copyFrom))]
+    pub fn copy_from(&mut self, src: &Float32Array) -> JsResult<()> {
+        check_len(src.length() as usize, self.buffer.len())?;
+        src.copy_to(&mut self.buffer);
+        Ok(())
+    }
+
+    /// Moves the data out of the array, leaving the JS object unusable.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = intoBuffer))]
+    pub fn into_buffer(self) -> Vec<f32> {
+        self.buffer
+    }
+}
+
+#[test]
+fn test_from_vec() {
+    let buffer: Vec<f32> = (0..6).map(|x| x as f32).collect();
+    let ptr = buffer.as_ptr();
+    let a = NdArray::from_vec(buffer, Some(vec![2, 3])).unwrap();
+    assert_eq!(a.strides, vec![3, 1]);
+    assert_eq!(a.as_slice().as_ptr(), ptr);
+    let buffer = a.into_vec();
+    assert_eq!(buffer.as_ptr(), ptr);
+
+    assert_eq!(
+        NdArray::from_vec(vec![0.; 5], Some(vec![2, 3])).err(),
+        Some(Error::ShapeMismatch(
+            "5 values can't have shape [2, 3]".to_string()
+        ))
+    );
+    assert_eq!(NdArray::from_vec(vec![1.; 4], None).unwrap().shape, vec![4]);
+
+    let mut b = NdArray::zeros(&[2]);
+    b.copy_from_slice(&[1., 2.]).unwrap();
+    assert_eq!(b.buffer, vec![1., 2.]);
+    assert!(b.copy_from_slice(&[1.]).is_err());
+}
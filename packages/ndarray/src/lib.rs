@@ -1,6 +1,53 @@
-mod ndarray;
+//! N-dimensional `f32` arrays and the kernels behind mua.js.
+//!
+//! With the default `wasm` feature every type and function is also
+//! exported to JS through wasm-bindgen. Build with
+//! `default-features = false` to use the crate as a plain Rust library.
+
+// `Ok(f()?)` converts errors to `JsValue` in JS bindings, and is a no-op
+// without them.
+#![cfg_attr(not(feature = "wasm"), allow(clippy::needless_question_mark))]
+
+pub mod ndarray;
 mod utils;
-mod ops;
-mod loader;
+pub mod ops;
+pub mod loader;
+pub mod fft;
+pub mod dtype;
+pub mod complex;
+pub mod error;
+pub mod npy;
+pub mod safetensors;
+pub mod serialization;
+pub mod onnx;
+pub mod print;
+mod interop;
+pub mod inplace;
+pub mod stats;
+pub mod norm;
+pub mod random;
+pub mod dropout;
+pub mod embedding;
+pub mod attention;
+pub mod rnn;
+pub mod optim;
+pub mod lr_scheduler;
+pub mod loss;
+pub mod metrics;
+pub mod sparse;
+pub mod quant;
+pub mod float16;
+mod simd;
+mod traits;
+#[cfg(test)]
+mod gradcheck;
+
+pub use crate::{
+    complex::ComplexNdArray,
+    dtype::DType,
+    error::{Error, Result},
+    float16::HalfNdArray,
+    ndarray::NdArray,
+};
 
 // pub use wasm_bindgen_rayon::init_thread_pool;
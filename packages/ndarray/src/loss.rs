@@ -0,0 +1,753 @@
+//! Loss functions returning the loss together with its gradients.
+//!
+//! Class scores are laid out with the class axis last, `[..., C]`, and
+//! pairwise losses take `[N, D]` inputs. Every loss takes a `Reduction`
+//! (default `Mean`); its gradients are those of the reduced loss, or with
+//! `Reduction::None` those of the sum of the unreduced losses. Since each
+//! unreduced loss only depends on its own element or sample, an upstream
+//! gradient is applied by scaling the matching gradient entries.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+#[cfg(test)]
+use crate::gradcheck::assert_grad;
+use crate::ndarray::NdArray;
+
+/// How per-element or per-sample losses are combined.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reduction {
+    None,
+    Mean,
+    Sum,
+}
+
+/// A loss and its gradients, one per differentiable input in argument
+/// order.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct LossOutput {
+    loss: NdArray,
+    grads: Vec<NdArray>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl LossOutput {
+    /// Unreduced losses, or the `[1]` reduced loss.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn loss(&self) -> NdArray {
+        self.loss.clone()
+    }
+
+    /// Gradient of the first input.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn grad(&self) -> NdArray {
+        self.grads[0].clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn grads(&self) -> Vec<NdArray> {
+        self.grads.clone()
+    }
+}
+
+/// Reduces unreduced `losses` of shape `shape`; `Mean` divides the sum,
+/// and the gradients, by `count`.
+fn finish(
+    losses: Vec<f32>,
+    shape: Vec<usize>,
+    mut grads: Vec<NdArray>,
+    count: f32,
+    reduction: Option<Reduction>,
+) -> LossOutput {
+    let loss = match reduction.unwrap_or(Reduction::Mean) {
+        Reduction::None => NdArray::from(&losses, Some(shape), None),
+        Reduction::Sum => NdArray::from(&[losses.iter().sum::<f32>()], None, None),
+        Reduction::Mean => {
+            grads
+                .iter_mut()
+                .flat_map(|g| g.buffer.iter_mut())
+                .for_each(|g| *g /= count);
+            NdArray::from(&[losses.iter().sum::<f32>() / count], None, None)
+        }
+    };
+    LossOutput { loss, grads }
+}
+
+fn check_same_shape(a: &NdArray, b: &NdArray, what: &str) {
+    if a.shape != b.shape {
+        panic!(
+            "{} of shape {:?} doesn't match input of shape {:?}",
+            what, b.shape, a.shape
+        );
+    }
+}
+
+/// Entry `i` of an optional weight repeated over the leading axes.
+fn weight_at(weight: &Option<Vec<f32>>, i: usize) -> f32 {
+    weight.as_ref().map_or(1., |w| w[i % w.len()])
+}
+
+fn check_weight(weight: &Option<Vec<f32>>, len: usize, what: &str) {
+    if let Some(w) = weight {
+        if w.is_empty() || !len.is_multiple_of(w.len()) {
+            panic!(
+                "{} of length {} doesn't tile {} elements",
+                what,
+                w.len(),
+                len
+            );
+        }
+    }
+}
+
+/// Loss `f(i, x, y) -> (loss, d loss / d x)` applied to every element.
+fn elementwise<F: Fn(usize, f32, f32) -> (f32, f32)>(
+    input: &NdArray,
+    target: &NdArray,
+    reduction: Option<Reduction>,
+    f: F,
+) -> LossOutput {
+    check_same_shape(input, target, "target");
+    let (losses, grad): (Vec<f32>, Vec<f32>) = input
+        .buffer
+        .iter()
+        .zip(&target.buffer)
+        .enumerate()
+        .map(|(i, (x, y))| f(i, *x, *y))
+        .unzip();
+    let grad = NdArray::from(&grad, Some(input.shape.clone()), None);
+    let count = losses.len() as f32;
+    finish(losses, input.shape.clone(), vec![grad], count, reduction)
+}
+
+fn sign(x: f32) -> f32 {
+    if x > 0. {
+        1.
+    } else if x < 0. {
+        -1.
+    } else {
+        0.
+    }
+}
+
+/// `ln(1 + e^x)` without overflow.
+fn softplus(x: f32) -> f32 {
+    x.max(0.) + (-x.abs()).exp().ln_1p()
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1. / (1. + (-x).exp())
+}
+
+/// Mean squared error.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = mseLoss))]
+pub fn mse_loss(input: &NdArray, target: &NdArray, reduction: Option<Reduction>) -> LossOutput {
+    elementwise(input, target, reduction, |_, x, y| {
+        let d = x - y;
+        (d * d, 2. * d)
+    })
+}
+
+/// Mean absolute error.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = l1Loss))]
+pub fn l1_loss(input: &NdArray, target: &NdArray, reduction: Option<Reduction>) -> LossOutput {
+    elementwise(input, target, reduction, |_, x, y| {
+        ((x - y).abs(), sign(x - y))
+    })
+}
+
+/// Squared error scaled by `1 / beta` below `beta` (default 1), absolute
+/// error above it. `beta = 0` gives the L1 loss.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = smoothL1Loss))]
+pub fn smooth_l1_loss(
+    input: &NdArray,
+    target: &NdArray,
+    beta: Option<f32>,
+    reduction: Option<Reduction>,
+) -> LossOutput {
+    let beta = beta.unwrap_or(1.);
+    elementwise(input, target, reduction, |_, x, y| {
+        let d = x - y;
+        if d.abs() < beta {
+            (0.5 * d * d / beta, d / beta)
+        } else {
+            (d.abs() - 0.5 * beta, sign(d))
+        }
+    })
+}
+
+/// Squared error below `delta` (default 1), `delta`-scaled absolute error
+/// above it; that is `delta` times `smooth_l1_loss` with `beta = delta`.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = huberLoss))]
+pub fn huber_loss(
+    input: &NdArray,
+    target: &NdArray,
+    delta: Option<f32>,
+    reduction: Option<Reduction>,
+) -> LossOutput {
+    let delta = delta.unwrap_or(1.);
+    elementwise(input, target, reduction, |_, x, y| {
+        let d = x - y;
+        if d.abs() <= delta {
+            (0.5 * d * d, d)
+        } else {
+            (delta * (d.abs() - 0.5 * delta), delta * sign(d))
+        }
+    })
+}
+
+/// Binary cross entropy between probabilities and targets in `[0, 1]`,
+/// optionally weighted by `weight` repeated over the leading axes. Logs
+/// are clamped to `-100` like PyTorch.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = bceLoss))]
+pub fn bce_loss(
+    input: &NdArray,
+    target: &NdArray,
+    weight: Option<Vec<f32>>,
+    reduction: Option<Reduction>,
+) -> LossOutput {
+    check_weight(&weight, input.buffer.len(), "weight");
+    elementwise(input, target, reduction, |i, x, y| {
+        assert!(
+            (0. ..=1.).contains(&x),
+            "bce input {} is not a probability",
+            x
+        );
+        let w = weight_at(&weight, i);
+        let (lx, l1x) = (x.ln().max(-100.), (1. - x).ln().max(-100.));
+        let loss = -w * (y * lx + (1. - y) * l1x);
+        (loss, w * (x - y) / (x * (1. - x)).max(1e-12))
+    })
+}
+
+/// Binary cross entropy on logits, computed stably. `pos_weight` scales
+/// the positive term per class, repeated over the leading axes like
+/// `weight`.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = bceWithLogitsLoss))]
+pub fn bce_with_logits_loss(
+    input: &NdArray,
+    target: &NdArray,
+    weight: Option<Vec<f32>>,
+    pos_weight: Option<Vec<f32>>,
+    reduction: Option<Reduction>,
+) -> LossOutput {
+    check_weight(&weight, input.buffer.len(), "weight");
+    check_weight(&pos_weight, input.buffer.len(), "pos_weight");
+    elementwise(input, target, reduction, |i, x, y| {
+        let (w, p) = (weight_at(&weight, i), weight_at(&pos_weight, i));
+        // -log(sigmoid(x)) = softplus(-x), -log(1 - sigmoid(x)) = softplus(x)
+        let loss = w * (p * y * softplus(-x) + (1. - y) * softplus(x));
+        let s = sigmoid(x);
+        (loss, w * (p * y * (s - 1.) + (1. - y) * s))
+    })
+}
+
+/// `(rows, classes)` of a `[..., C]` input.
+fn class_layout(input: &NdArray) -> (usize, usize) {
+    let (&classes, rows) = input
+        .shape
+        .split_last()
+        .unwrap_or_else(|| panic!("expected a [..., C] input, got a scalar"));
+    (rows.iter().product(), classes)
+}
+
+fn check_indices(target: &[u32], rows: usize, classes: usize, ignore_index: Option<u32>) {
+    if target.len() != rows {
+        panic!("expected {} target indices, got {}", rows, target.len());
+    }
+    if let Some(t) = target
+        .iter()
+        .find(|t| Some(**t) != ignore_index && **t as usize >= classes)
+    {
+        panic!("target {} is out of bounds for {} classes", t, classes);
+    }
+}
+
+fn check_class_weight(weight: &Option<Vec<f32>>, classes: usize) {
+    if let Some(w) = weight {
+        if w.len() != classes {
+            panic!("expected {} class weights, got {}", classes, w.len());
+        }
+    }
+}
+
+/// Log-softmax of every row.
+fn log_softmax_rows(input: &NdArray, classes: usize) -> Vec<f32> {
+    let mut res = input.buffer.clone();
+    for row in res.chunks_mut(classes.max(1)) {
+        let max = row.iter().fold(f32::NEG_INFINITY, |m, x| m.max(*x));
+        let lse = max + row.iter().map(|x| (x - max).exp()).sum::<f32>().ln();
+        row.iter_mut().for_each(|x| *x -= lse);
+    }
+    res
+}
+
+/// Cross entropy of rows of logits against per-class coefficients: each
+/// row's loss is `-sum_c coef[c] * log_softmax(x)[c]`, whose gradient is
+/// `softmax(x) * sum(coef) - coef`.
+fn soft_cross_entropy(
+    input: &NdArray,
+    classes: usize,
+    coefs: &[f32],
+    count: f32,
+    reduction: Option<Reduction>,
+) -> LossOutput {
+    let logp = log_softmax_rows(input, classes);
+    let shape = input.shape[..input.shape.len() - 1].to_vec();
+    let rows = shape.iter().product();
+    let mut losses = Vec::with_capacity(rows);
+    let mut grad = NdArray::zeros(&input.shape);
+    // rows of zero classes have a zero loss
+    for r in 0..rows {
+        let span = r * classes..(r + 1) * classes;
+        let (lp, a) = (&logp[span.clone()], &coefs[span.clone()]);
+        let g = &mut grad.buffer[span];
+        losses.push(-lp.iter().zip(a).map(|(l, a)| l * a).sum::<f32>());
+        let total: f32 = a.iter().sum();
+        for ((g, l), a) in g.iter_mut().zip(lp).zip(a) {
+            *g = l.exp() * total - a;
+        }
+    }
+    finish(losses, shape, vec![grad], count, reduction)
+}
+
+/// Cross entropy between logits `[..., C]` and class indices.
+///
+/// `weight` rescales each class. Targets equal to `ignore_index`
+/// contribute no loss or gradient. With `label_smoothing` `ε`, the target
+/// distribution puts `1 - ε` on the target class and spreads `ε` uniformly
+/// over all classes. `Mean` divides by the total weight of the
+/// non-ignored targets, as PyTorch does.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = crossEntropyLoss))]
+pub fn cross_entropy_loss(
+    input: &NdArray,
+    target: &[u32],
+    weight: Option<Vec<f32>>,
+    ignore_index: Option<u32>,
+    label_smoothing: Option<f32>,
+    reduction: Option<Reduction>,
+) -> LossOutput {
+    let (rows, classes) = class_layout(input);
+    check_indices(target, rows, classes, ignore_index);
+    check_class_weight(&weight, classes);
+    let eps = label_smoothing.unwrap_or(0.);
+    let mut coefs = vec![0.; input.buffer.len()];
+    let mut count = 0.;
+    for (t, row) in target.iter().zip(coefs.chunks_mut(classes.max(1))) {
+        if Some(*t) == ignore_index {
+            continue;
+        }
+        let t = *t as usize;
+        count += weight_at(&weight, t);
+        for (c, a) in row.iter_mut().enumerate() {
+            *a = eps * weight_at(&weight, c) / classes as f32;
+        }
+        row[t] += (1. - eps) * weight_at(&weight, t);
+    }
+    soft_cross_entropy(input, classes, &coefs, count, reduction)
+}
+
+/// Cross entropy between logits and target class probabilities of the
+/// same shape, with optional class `weight` and `label_smoothing`. `Mean`
+/// divides by the number of rows.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = crossEntropyLossProbs))]
+pub fn cross_entropy_loss_probs(
+    input: &NdArray,
+    target: &NdArray,
+    weight: Option<Vec<f32>>,
+    label_smoothing: Option<f32>,
+    reduction: Option<Reduction>,
+) -> LossOutput {
+    check_same_shape(input, target, "target");
+    let (rows, classes) = class_layout(input);
+    check_class_weight(&weight, classes);
+    let eps = label_smoothing.unwrap_or(0.);
+    let coefs: Vec<f32> = target
+        .buffer
+        .iter()
+        .enumerate()
+        .map(|(i, y)| weight_at(&weight, i) * ((1. - eps) * y + eps / classes as f32))
+        .collect();
+    soft_cross_entropy(input, classes, &coefs, rows as f32, reduction)
+}
+
+/// Negative log likelihood of log-probabilities `[..., C]` at the target
+/// class indices, with class `weight` and `ignore_index` as in
+/// `cross_entropy_loss`.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = nllLoss))]
+pub fn nll_loss(
+    input: &NdArray,
+    target: &[u32],
+    weight: Option<Vec<f32>>,
+    ignore_index: Option<u32>,
+    reduction: Option<Reduction>,
+) -> LossOutput {
+    let (rows, classes) = class_layout(input);
+    check_indices(target, rows, classes, ignore_index);
+    check_class_weight(&weight, classes);
+    let mut losses = vec![0.; rows];
+    let mut grad = NdArray::zeros(&input.shape);
+    let mut count = 0.;
+    for (r, t) in target.iter().enumerate() {
+        if Some(*t) == ignore_index {
+            continue;
+        }
+        let (t, w) = (*t as usize, weight_at(&weight, *t as usize));
+        losses[r] = -w * input.buffer[r * classes + t];
+        grad.buffer[r * classes + t] = -w;
+        count += w;
+    }
+    let shape = input.shape[..input.shape.len() - 1].to_vec();
+    finish(losses, shape, vec![grad], count, reduction)
+}
+
+/// Kullback-Leibler divergence `target * (ln(target) - input)` for
+/// log-probabilities `input`. With `log_target` the target holds
+/// log-probabilities too. `Mean` averages over every element; use `Sum`
+/// and divide by the batch size for the mathematical KL divergence.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = klDivLoss))]
+pub fn kl_div_loss(
+    input: &NdArray,
+    target: &NdArray,
+    log_target: Option<bool>,
+    reduction: Option<Reduction>,
+) -> LossOutput {
+    let log_target = log_target.unwrap_or(false);
+    elementwise(input, target, reduction, |_, x, y| {
+        if log_target {
+            let p = y.exp();
+            (p * (y - x), -p)
+        } else if y > 0. {
+            (y * (y.ln() - x), -y)
+        } else {
+            (0., -y)
+        }
+    })
+}
+
+fn check_pairs(a: &NdArray, b: &NdArray) -> (usize, usize) {
+    if a.shape.len() != 2 {
+        panic!("expected an [N, D] input, got shape {:?}", a.shape);
+    }
+    check_same_shape(a, b, "second input");
+    (a.shape[0], a.shape[1])
+}
+
+fn check_labels(target: &NdArray, n: usize) {
+    if target.buffer.len() != n {
+        panic!("expected {} targets, got shape {:?}", n, target.shape);
+    }
+}
+
+/// `1 - cos(x1, x2)` for targets `1` and `max(0, cos(x1, x2) - margin)`
+/// (default margin 0) for targets `-1`, over `[N, D]` inputs.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = cosineEmbeddingLoss))]
+pub fn cosine_embedding_loss(
+    x1: &NdArray,
+    x2: &NdArray,
+    target: &NdArray,
+    margin: Option<f32>,
+    reduction: Option<Reduction>,
+) -> LossOutput {
+    let (n, d) = check_pairs(x1, x2);
+    check_labels(target, n);
+    let margin = margin.unwrap_or(0.);
+    let eps = 1e-12;
+    let mut losses = vec![0.; n];
+    let (mut g1, mut g2) = (NdArray::zeros(&x1.shape), NdArray::zeros(&x2.shape));
+    for (i, loss) in losses.iter_mut().enumerate() {
+        let (a, b) = (
+            &x1.buffer[i * d..(i + 1) * d],
+            &x2.buffer[i * d..(i + 1) * d],
+        );
+        let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+        let aa = a.iter().map(|a| a * a).sum::<f32>() + eps;
+        let bb = b.iter().map(|b| b * b).sum::<f32>() + eps;
+        let den = (aa * bb).sqrt();
+        let cos = dot / den;
+        // d loss / d cos
+        let scale = if target.buffer[i] > 0. {
+            *loss = 1. - cos;
+            -1.
+        } else if cos > margin {
+            *loss = cos - margin;
+            1.
+        } else {
+            0.
+        };
+        for k in 0..d {
+            g1.buffer[i * d + k] = scale * (b[k] / den - cos * a[k] / aa);
+            g2.buffer[i * d + k] = scale * (a[k] / den - cos * b[k] / bb);
+        }
+    }
+    finish(losses, vec![n], vec![g1, g2], n as f32, reduction)
+}
+
+/// `max(0, -y * (x1 - x2) + margin)` for targets `y` of `1` or `-1`;
+/// all three arrays share one shape.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = marginRankingLoss))]
+pub fn margin_ranking_loss(
+    x1: &NdArray,
+    x2: &NdArray,
+    target: &NdArray,
+    margin: Option<f32>,
+    reduction: Option<Reduction>,
+) -> LossOutput {
+    check_same_shape(x1, x2, "second input");
+    check_same_shape(x1, target, "target");
+    let margin = margin.unwrap_or(0.);
+    let (mut losses, mut g1) = (vec![0.; x1.buffer.len()], NdArray::zeros(&x1.shape));
+    for (i, ((a, b), y)) in x1
+        .buffer
+        .iter()
+        .zip(&x2.buffer)
+        .zip(&target.buffer)
+        .enumerate()
+    {
+        let l = -y * (a - b) + margin;
+        if l > 0. {
+            losses[i] = l;
+            g1.buffer[i] = -y;
+        }
+    }
+    let g2 = g1.mul_scalar(-1.);
+    let count = losses.len() as f32;
+    finish(losses, x1.shape.clone(), vec![g1, g2], count, reduction)
+}
+
+/// `p`-norm distance between rows of `a` and `b`, PyTorch's
+/// `pairwise_distance` with its `1e-6` offset, and its gradient w.r.t. `a`.
+fn distance(a: &[f32], b: &[f32], p: f32) -> (f32, Vec<f32>) {
+    let diff: Vec<f32> = a.iter().zip(b).map(|(a, b)| a - b + 1e-6).collect();
+    let dist = diff
+        .iter()
+        .map(|v| v.abs().powf(p))
+        .sum::<f32>()
+        .powf(1. / p);
+    let grad = diff
+        .iter()
+        .map(|v| {
+            if dist == 0. {
+                0.
+            } else {
+                sign(*v) * (v.abs() / dist).powf(p - 1.)
+            }
+        })
+        .collect();
+    (dist, grad)
+}
+
+/// `max(0, d(a, p) - d(a, n) + margin)` over `[N, D]` anchors, positives
+/// and negatives, with the `p`-norm distance (default 2) and margin
+/// (default 1). With `swap`, the positive-negative distance replaces the
+/// anchor-negative one when it is smaller.
+#[allow(clippy::too_many_arguments)]
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = tripletMarginLoss))]
+pub fn triplet_margin_loss(
+    anchor: &NdArray,
+    positive: &NdArray,
+    negative: &NdArray,
+    margin: Option<f32>,
+    p: Option<f32>,
+    swap: Option<bool>,
+    reduction: Option<Reduction>,
+) -> LossOutput {
+    let (n, d) = check_pairs(anchor, positive);
+    check_same_shape(anchor, negative, "negative");
+    let (margin, p, swap) = (margin.unwrap_or(1.), p.unwrap_or(2.), swap.unwrap_or(false));
+    let mut losses = vec![0.; n];
+    let mut grads = vec![NdArray::zeros(&anchor.shape); 3];
+    for (i, loss) in losses.iter_mut().enumerate() {
+        let row = |x: &NdArray| x.buffer[i * d..(i + 1) * d].to_vec();
+        let (a, pos, neg) = (row(anchor), row(positive), row(negative));
+        let (d_ap, g_ap) = distance(&a, &pos, p);
+        // the negative distance and the rows it is taken between
+        let (mut d_neg, mut g_neg) = distance(&a, &neg, p);
+        let mut from = 0;
+        if swap {
+            let (d_pn, g_pn) = distance(&pos, &neg, p);
+            if d_pn < d_neg {
+                d_neg = d_pn;
+                g_neg = g_pn;
+                from = 1;
+            }
+        }
+        let l = d_ap - d_neg + margin;
+        if l <= 0. {
+            continue;
+        }
+        *loss = l;
+        for k in 0..d {
+            let e = i * d + k;
+            grads[0].buffer[e] += g_ap[k];
+            grads[1].buffer[e] -= g_ap[k];
+            grads[from].buffer[e] -= g_neg[k];
+            grads[2].buffer[e] += g_neg[k];
+        }
+    }
+    finish(losses, vec![n], grads, n as f32, reduction)
+}
+
+/// Checks the gradient of the reduced loss w.r.t. `inputs[which]`.
+#[cfg(test)]
+fn check_grad<F: Fn(&[NdArray]) -> LossOutput>(inputs: &[NdArray], which: usize, f: F) {
+    let analytic = f(inputs).grads[which].clone();
+    assert_grad(&analytic, &inputs[which], &NdArray::ones(&[1]), |x| {
+        let mut inputs = inputs.to_vec();
+        inputs[which] = x.clone();
+        f(&inputs).loss
+    });
+}
+
+#[test]
+fn test_regression_losses() {
+    let x = NdArray::from(&[0., 1., 3., -2.], None, None);
+    let y = NdArray::from(&[1., 1., 0.5, 0.], None, None);
+    assert_eq!(
+        mse_loss(&x, &y, None).loss().buffer,
+        vec![(1. + 6.25 + 4.) / 4.]
+    );
+    assert_eq!(
+        l1_loss(&x, &y, Some(Reduction::Sum)).loss().buffer,
+        vec![5.5]
+    );
+    let out = smooth_l1_loss(&x, &y, Some(1.), Some(Reduction::None));
+    assert_eq!(out.loss().buffer, vec![0.5, 0., 2., 1.5]);
+    assert_eq!(out.grad().buffer, vec![-1., 0., 1., -1.]);
+    let out = huber_loss(&x, &y, Some(2.), Some(Reduction::None));
+    assert_eq!(out.loss().buffer, vec![0.5, 0., 3., 2.]);
+    assert_eq!(out.loss().shape, vec![4]);
+
+    let x = NdArray::from(&[0.3, -1.2, 2.5, 0.1, 0.7, -0.4], Some(vec![2, 3]), None);
+    let y = NdArray::from(&[0.1, -1., 1.5, 0.2, 0.6, 0.], Some(vec![2, 3]), None);
+    check_grad(&[x.clone(), y.clone()], 0, |v| mse_loss(&v[0], &v[1], None));
+    check_grad(&[x.clone(), y.clone()], 0, |v| {
+        smooth_l1_loss(&v[0], &v[1], Some(0.5), Some(Reduction::Sum))
+    });
+    check_grad(&[x, y], 0, |v| huber_loss(&v[0], &v[1], Some(0.5), None));
+}
+
+#[test]
+fn test_binary_cross_entropy() {
+    let logits = NdArray::from(&[0.5, -1., 2., -3.], Some(vec![2, 2]), None);
+    let target = NdArray::from(&[1., 0., 0.3, 1.], Some(vec![2, 2]), None);
+    let probs = logits.sigmoid();
+    let a = bce_loss(&probs, &target, None, None).loss().buffer[0];
+    let b = bce_with_logits_loss(&logits, &target, None, None, None)
+        .loss()
+        .buffer[0];
+    assert!((a - b).abs() < 1e-5);
+
+    let w = Some(vec![2., 0.5]);
+    // away from 0 and 1, where the log is too curved for the finite
+    // difference step
+    let probs = logits.mul_scalar(0.5).sigmoid();
+    check_grad(&[probs, target.clone()], 0, |v| {
+        bce_loss(&v[0], &v[1], w.clone(), None)
+    });
+    check_grad(&[logits.clone(), target.clone()], 0, |v| {
+        bce_with_logits_loss(&v[0], &v[1], w.clone(), Some(vec![3., 1.]), None)
+    });
+    // stable for large logits
+    let big = NdArray::from(&[100., -100.], None, None);
+    let t = NdArray::from(&[0., 1.], None, None);
+    let out = bce_with_logits_loss(&big, &t, None, None, Some(Reduction::None));
+    assert_eq!(out.loss().buffer, vec![100., 100.]);
+}
+
+#[test]
+fn test_cross_entropy() {
+    let x = NdArray::from(&[1., 2., 3., 1., 1., 1.], Some(vec![2, 3]), None);
+    let out = cross_entropy_loss(&x, &[2, 0], None, None, None, Some(Reduction::None));
+    let lse = (1f32.exp() + 2f32.exp() + 3f32.exp()).ln();
+    assert!((out.loss().buffer[0] - (lse - 3.)).abs() < 1e-5);
+    assert!((out.loss().buffer[1] - 3f32.ln()).abs() < 1e-5);
+    // matches nll on log-softmax
+    let logp = NdArray::from(&log_softmax_rows(&x, 3), Some(vec![2, 3]), None);
+    let nll = nll_loss(&logp, &[2, 0], None, None, None);
+    assert!((nll.loss().buffer[0] - out.loss().sum() / 2.).abs() < 1e-5);
+
+    let ignored = cross_entropy_loss(&x, &[2, 1], None, Some(1), None, None);
+    assert!((ignored.loss().buffer[0] - (lse - 3.)).abs() < 1e-5);
+    assert!(ignored.grad().buffer[3..].iter().all(|g| *g == 0.));
+
+    // one-hot probabilities give the same loss as indices
+    let onehot = NdArray::from(&[0., 0., 1., 1., 0., 0.], Some(vec![2, 3]), None);
+    let w = Some(vec![1., 2., 0.5]);
+    let a = cross_entropy_loss(
+        &x,
+        &[2, 0],
+        w.clone(),
+        None,
+        Some(0.1),
+        Some(Reduction::Sum),
+    );
+    let b = cross_entropy_loss_probs(&x, &onehot, w.clone(), Some(0.1), Some(Reduction::Sum));
+    assert!((a.loss().buffer[0] - b.loss().buffer[0]).abs() < 1e-5);
+
+    let x = NdArray::from(&[0.2, -0.5, 1.1, 0.3, 0.9, -1.3], Some(vec![2, 3]), None);
+    check_grad(std::slice::from_ref(&x), 0, |v| {
+        cross_entropy_loss(&v[0], &[1, 2], w.clone(), None, Some(0.2), None)
+    });
+    let probs = NdArray::from(&[0.2, 0.5, 0.3, 0.6, 0.1, 0.3], Some(vec![2, 3]), None);
+    check_grad(std::slice::from_ref(&x), 0, |v| {
+        cross_entropy_loss_probs(&v[0], &probs, None, None, None)
+    });
+    check_grad(&[x], 0, |v| {
+        nll_loss(&v[0], &[0, 2], w.clone(), Some(0), None)
+    });
+}
+
+#[test]
+fn test_kl_div() {
+    let input = NdArray::from(&[0.25f32.ln(), 0.75f32.ln()], None, None);
+    let target = NdArray::from(&[0.5, 0.5], None, None);
+    let out = kl_div_loss(&input, &target, None, Some(Reduction::Sum));
+    let expected = 0.5 * (0.5f32 / 0.25).ln() + 0.5 * (0.5f32 / 0.75).ln();
+    assert!((out.loss().buffer[0] - expected).abs() < 1e-6);
+    let log_target = target.ln();
+    let same = kl_div_loss(&input, &log_target, Some(true), Some(Reduction::Sum));
+    assert!((same.loss().buffer[0] - expected).abs() < 1e-6);
+    assert_eq!(out.grad().buffer, vec![-0.5, -0.5]);
+}
+
+#[test]
+fn test_pairwise_losses() {
+    let a = NdArray::from(&[1., 0.5, -0.3, 0.2, 0.8, 1.5], Some(vec![2, 3]), None);
+    let b = NdArray::from(&[0.4, -0.6, 0.9, 1.1, 0.3, -0.2], Some(vec![2, 3]), None);
+    let c = NdArray::from(&[0.1, 0.7, 0.2, -0.9, 0.5, 0.6], Some(vec![2, 3]), None);
+    let y = NdArray::from(&[1., -1.], None, None);
+    let inputs = [a.clone(), b.clone(), c.clone()];
+    for which in 0..2 {
+        check_grad(&inputs, which, |v| {
+            cosine_embedding_loss(&v[0], &v[1], &y, Some(-0.5), None)
+        });
+        check_grad(&inputs, which, |v| {
+            margin_ranking_loss(&v[0], &v[1], &NdArray::ones(&[2, 3]), Some(0.4), None)
+        });
+    }
+    for which in 0..3 {
+        check_grad(&inputs, which, |v| {
+            triplet_margin_loss(&v[0], &v[1], &v[2], Some(2.), None, None, None)
+        });
+        check_grad(&inputs, which, |v| {
+            triplet_margin_loss(&v[0], &v[1], &v[2], Some(2.), Some(1.5), Some(true), None)
+        });
+    }
+
+    let same = cosine_embedding_loss(&a, &a, &NdArray::ones(&[2]), None, Some(Reduction::None));
+    assert!(same.loss().buffer.iter().all(|l| l.abs() < 1e-6));
+    let ranked = margin_ranking_loss(&y, &y.mul_scalar(-1.), &y, None, Some(Reduction::Sum));
+    assert_eq!(ranked.loss().buffer, vec![0.]);
+}
+
+#[test]
+fn test_cross_entropy_no_classes() {
+    let x = NdArray::zeros(&[2, 0]);
+    let out = cross_entropy_loss_probs(&x, &x, None, None, Some(Reduction::None));
+    assert_eq!(out.loss().buffer, vec![0., 0.]);
+    assert_eq!(out.grad().shape, vec![2, 0]);
+    let out = cross_entropy_loss(&NdArray::zeros(&[0, 0]), &[], None, None, None, None);
+    assert!(out.loss().buffer[0].is_nan());
+}
@@ -0,0 +1,571 @@
+//! Learning-rate schedulers.
+//!
+//! Schedulers don't hold a reference to an optimizer; `step` advances the
+//! schedule and returns the new learning rate, which is then assigned to
+//! any optimizer from `optim`:
+//!
+//! ```ignore
+//! let mut sched = StepLr::new(0.1, 30, None);
+//! for _ in 0..epochs {
+//!     train_one_epoch(&mut opt);
+//!     opt.set_lr(sched.step());
+//! }
+//! ```
+//!
+//! Epochs count from 0, at which every schedule yields its starting rate.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use std::f32::consts::PI;
+
+/// Adds `lr`, `epoch` and `step` to a scheduler computing its rate in
+/// closed form with `lr_at(epoch)`.
+macro_rules! scheduler_methods {
+    ($name:ident) => {
+        #[cfg_attr(feature = "wasm", wasm_bindgen)]
+        impl $name {
+            /// Learning rate for the current epoch.
+            #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+            pub fn lr(&self) -> f32 {
+                self.lr_at(self.epoch)
+            }
+
+            #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+            pub fn epoch(&self) -> u32 {
+                self.epoch
+            }
+
+            /// Jumps to `epoch`, e.g. when resuming from a checkpoint.
+            #[cfg_attr(feature = "wasm", wasm_bindgen(setter))]
+            pub fn set_epoch(&mut self, epoch: u32) {
+                self.epoch = epoch;
+            }
+
+            /// Advances one epoch and returns its learning rate.
+            pub fn step(&mut self) -> f32 {
+                self.epoch += 1;
+                self.lr()
+            }
+        }
+    };
+}
+
+/// Decays the rate by `gamma` (default 0.1) every `step_size` epochs.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct StepLr {
+    base_lr: f32,
+    step_size: u32,
+    gamma: f32,
+    epoch: u32,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl StepLr {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(base_lr: f32, step_size: u32, gamma: Option<f32>) -> StepLr {
+        assert!(step_size > 0, "step_size must be positive");
+        StepLr {
+            base_lr,
+            step_size,
+            gamma: gamma.unwrap_or(0.1),
+            epoch: 0,
+        }
+    }
+}
+
+impl StepLr {
+    fn lr_at(&self, epoch: u32) -> f32 {
+        self.base_lr * self.gamma.powi((epoch / self.step_size) as i32)
+    }
+}
+
+scheduler_methods!(StepLr);
+
+/// Decays the rate by `gamma` (default 0.1) at each of the `milestones`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct MultiStepLr {
+    base_lr: f32,
+    milestones: Vec<u32>,
+    gamma: f32,
+    epoch: u32,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl MultiStepLr {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(base_lr: f32, milestones: &[u32], gamma: Option<f32>) -> MultiStepLr {
+        MultiStepLr {
+            base_lr,
+            milestones: milestones.to_vec(),
+            gamma: gamma.unwrap_or(0.1),
+            epoch: 0,
+        }
+    }
+}
+
+impl MultiStepLr {
+    fn lr_at(&self, epoch: u32) -> f32 {
+        let passed = self.milestones.iter().filter(|m| **m <= epoch).count();
+        self.base_lr * self.gamma.powi(passed as i32)
+    }
+}
+
+scheduler_methods!(MultiStepLr);
+
+/// Decays the rate by `gamma` every epoch.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct ExponentialLr {
+    base_lr: f32,
+    gamma: f32,
+    epoch: u32,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl ExponentialLr {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(base_lr: f32, gamma: f32) -> ExponentialLr {
+        ExponentialLr {
+            base_lr,
+            gamma,
+            epoch: 0,
+        }
+    }
+}
+
+impl ExponentialLr {
+    fn lr_at(&self, epoch: u32) -> f32 {
+        self.base_lr * self.gamma.powi(epoch as i32)
+    }
+}
+
+scheduler_methods!(ExponentialLr);
+
+/// SGDR: cosine annealing from `base_lr` to `eta_min` (default 0) over
+/// `t_0` epochs, then restarting with periods growing by `t_mult`
+/// (default 1).
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct CosineAnnealingWarmRestarts {
+    base_lr: f32,
+    t_0: u32,
+    t_mult: u32,
+    eta_min: f32,
+    epoch: u32,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CosineAnnealingWarmRestarts {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        base_lr: f32,
+        t_0: u32,
+        t_mult: Option<u32>,
+        eta_min: Option<f32>,
+    ) -> CosineAnnealingWarmRestarts {
+        let t_mult = t_mult.unwrap_or(1);
+        assert!(t_0 > 0, "t_0 must be positive");
+        assert!(t_mult > 0, "t_mult must be positive");
+        CosineAnnealingWarmRestarts {
+            base_lr,
+            t_0,
+            t_mult,
+            eta_min: eta_min.unwrap_or(0.),
+            epoch: 0,
+        }
+    }
+}
+
+impl CosineAnnealingWarmRestarts {
+    fn lr_at(&self, epoch: u32) -> f32 {
+        // position within the current period and that period's length
+        let (t_0, m, epoch) = (self.t_0 as u128, self.t_mult as u128, epoch as u128);
+        let (t_cur, t_i) = if m == 1 {
+            (epoch % t_0, t_0)
+        } else {
+            // period i starts at t_0 * (m^i - 1) / (m - 1); the float log
+            // can be off by one either way, so settle it exactly
+            let start = |i: u32| t_0 * (m.pow(i) - 1) / (m - 1);
+            let mut i = ((epoch * (m - 1) / t_0 + 1) as f64).log(m as f64) as u32;
+            while i > 0 && start(i) > epoch {
+                i -= 1;
+            }
+            while start(i + 1) <= epoch {
+                i += 1;
+            }
+            (epoch - start(i), t_0 * m.pow(i))
+        };
+        let cos = (PI * t_cur as f32 / t_i as f32).cos();
+        self.eta_min + (self.base_lr - self.eta_min) * (1. + cos) / 2.
+    }
+}
+
+scheduler_methods!(CosineAnnealingWarmRestarts);
+
+/// How `OneCycleLr` moves between two rates.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnnealStrategy {
+    Cos,
+    Linear,
+}
+
+/// The 1cycle policy, stepped once per batch: the rate rises from
+/// `max_lr / div_factor` to `max_lr` over the first `pct_start` of
+/// `total_steps`, then anneals to `max_lr / (div_factor *
+/// final_div_factor)`. With `three_phase` it first returns to the initial
+/// rate symmetrically before the final anneal. Past `total_steps` the rate
+/// stays at its final value.
+///
+/// Defaults follow `torch.optim.lr_scheduler.OneCycleLR`: `pct_start =
+/// 0.3`, cosine annealing, `div_factor = 25`, `final_div_factor = 1e4`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct OneCycleLr {
+    /// `(last step, start rate, end rate)` of every phase.
+    phases: Vec<(f32, f32, f32)>,
+    total_steps: u32,
+    anneal: AnnealStrategy,
+    epoch: u32,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl OneCycleLr {
+    #[allow(clippy::too_many_arguments)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        max_lr: f32,
+        total_steps: u32,
+        pct_start: Option<f32>,
+        anneal: Option<AnnealStrategy>,
+        div_factor: Option<f32>,
+        final_div_factor: Option<f32>,
+        three_phase: Option<bool>,
+    ) -> OneCycleLr {
+        let pct_start = pct_start.unwrap_or(0.3);
+        assert!(total_steps > 0, "total_steps must be positive");
+        assert!(
+            (0. ..=1.).contains(&pct_start),
+            "pct_start must be in [0, 1], got {}",
+            pct_start
+        );
+        let initial = max_lr / div_factor.unwrap_or(25.);
+        let min = initial / final_div_factor.unwrap_or(1e4);
+        let warm = pct_start * total_steps as f32 - 1.;
+        let last = total_steps as f32 - 1.;
+        let phases = if three_phase.unwrap_or(false) {
+            vec![
+                (warm, initial, max_lr),
+                (2. * warm, max_lr, initial),
+                (last, initial, min),
+            ]
+        } else {
+            vec![(warm, initial, max_lr), (last, max_lr, min)]
+        };
+        OneCycleLr {
+            phases,
+            total_steps,
+            anneal: anneal.unwrap_or(AnnealStrategy::Cos),
+            epoch: 0,
+        }
+    }
+}
+
+impl OneCycleLr {
+    fn lr_at(&self, epoch: u32) -> f32 {
+        let step = epoch.min(self.total_steps - 1) as f32;
+        let mut begin = 0.;
+        for (i, &(end, from, to)) in self.phases.iter().enumerate() {
+            if step <= end || i == self.phases.len() - 1 {
+                let pct = if end > begin {
+                    (step - begin) / (end - begin)
+                } else {
+                    1.
+                };
+                return match self.anneal {
+                    AnnealStrategy::Cos => to + (from - to) / 2. * ((PI * pct).cos() + 1.),
+                    AnnealStrategy::Linear => from + (to - from) * pct,
+                };
+            }
+            begin = end;
+        }
+        unreachable!()
+    }
+}
+
+scheduler_methods!(OneCycleLr);
+
+/// Scales the rate linearly from `start_factor * base_lr` (default 1/3)
+/// up to `base_lr` over `warmup_steps`, then holds it.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct LinearWarmup {
+    base_lr: f32,
+    warmup_steps: u32,
+    start_factor: f32,
+    epoch: u32,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl LinearWarmup {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(base_lr: f32, warmup_steps: u32, start_factor: Option<f32>) -> LinearWarmup {
+        LinearWarmup {
+            base_lr,
+            warmup_steps,
+            start_factor: start_factor.unwrap_or(1. / 3.),
+            epoch: 0,
+        }
+    }
+}
+
+impl LinearWarmup {
+    fn lr_at(&self, epoch: u32) -> f32 {
+        if epoch >= self.warmup_steps {
+            return self.base_lr;
+        }
+        let pct = epoch as f32 / self.warmup_steps as f32;
+        self.base_lr * (self.start_factor + (1. - self.start_factor) * pct)
+    }
+}
+
+scheduler_methods!(LinearWarmup);
+
+/// Whether `ReduceLrOnPlateau` watches for a metric to decrease or
+/// increase.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlateauMode {
+    Min,
+    Max,
+}
+
+/// Whether an improvement must beat the best metric by a relative or
+/// absolute `threshold`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThresholdMode {
+    Rel,
+    Abs,
+}
+
+/// Multiplies the rate by `factor` once a metric has stopped improving for
+/// more than `patience` epochs, then waits `cooldown` epochs before
+/// watching again. The rate never drops below `min_lr`.
+///
+/// Defaults follow `torch.optim.lr_scheduler.ReduceLROnPlateau`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct ReduceLrOnPlateau {
+    lr: f32,
+    mode: PlateauMode,
+    factor: f32,
+    patience: u32,
+    threshold: f32,
+    threshold_mode: ThresholdMode,
+    cooldown: u32,
+    min_lr: f32,
+    best: f32,
+    bad_epochs: u32,
+    cooldown_left: u32,
+    epoch: u32,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl ReduceLrOnPlateau {
+    /// Defaults: `Min` mode, `factor = 0.1`, `patience = 10`, relative
+    /// `threshold = 1e-4`, no cooldown, `min_lr = 0`.
+    #[allow(clippy::too_many_arguments)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        lr: f32,
+        mode: Option<PlateauMode>,
+        factor: Option<f32>,
+        patience: Option<u32>,
+        threshold: Option<f32>,
+        threshold_mode: Option<ThresholdMode>,
+        cooldown: Option<u32>,
+        min_lr: Option<f32>,
+    ) -> ReduceLrOnPlateau {
+        let mode = mode.unwrap_or(PlateauMode::Min);
+        let factor = factor.unwrap_or(0.1);
+        assert!(factor < 1., "factor must be below 1, got {}", factor);
+        ReduceLrOnPlateau {
+            lr,
+            mode,
+            factor,
+            patience: patience.unwrap_or(10),
+            threshold: threshold.unwrap_or(1e-4),
+            threshold_mode: threshold_mode.unwrap_or(ThresholdMode::Rel),
+            cooldown: cooldown.unwrap_or(0),
+            min_lr: min_lr.unwrap_or(0.),
+            best: match mode {
+                PlateauMode::Min => f32::INFINITY,
+                PlateauMode::Max => f32::NEG_INFINITY,
+            },
+            bad_epochs: 0,
+            cooldown_left: 0,
+            epoch: 0,
+        }
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn lr(&self) -> f32 {
+        self.lr
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// Best metric seen so far.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn best(&self) -> f32 {
+        self.best
+    }
+
+    /// Records the epoch's `metric` and returns the learning rate to use
+    /// next.
+    pub fn step(&mut self, metric: f32) -> f32 {
+        self.epoch += 1;
+        if self.improves(metric) {
+            self.best = metric;
+            self.bad_epochs = 0;
+        } else {
+            self.bad_epochs += 1;
+        }
+        if self.cooldown_left > 0 {
+            self.cooldown_left -= 1;
+            self.bad_epochs = 0;
+        }
+        if self.bad_epochs > self.patience {
+            self.lr = (self.lr * self.factor).max(self.min_lr);
+            self.cooldown_left = self.cooldown;
+            self.bad_epochs = 0;
+        }
+        self.lr
+    }
+}
+
+impl ReduceLrOnPlateau {
+    fn improves(&self, metric: f32) -> bool {
+        match (self.mode, self.threshold_mode) {
+            (PlateauMode::Min, ThresholdMode::Rel) => metric < self.best * (1. - self.threshold),
+            (PlateauMode::Min, ThresholdMode::Abs) => metric < self.best - self.threshold,
+            (PlateauMode::Max, ThresholdMode::Rel) => metric > self.best * (1. + self.threshold),
+            (PlateauMode::Max, ThresholdMode::Abs) => metric > self.best + self.threshold,
+        }
+    }
+}
+
+#[cfg(test)]
+fn assert_schedule(mut next: impl FnMut() -> f32, first: f32, expected: &[f32]) {
+    let mut got = vec![first];
+    got.extend((1..expected.len()).map(|_| next()));
+    for (a, b) in got.iter().zip(expected) {
+        assert!((a - b).abs() < 1e-6, "{:?} vs {:?}", got, expected);
+    }
+}
+
+#[test]
+fn test_step_schedules() {
+    let mut s = StepLr::new(1., 2, Some(0.5));
+    assert_schedule(|| s.step(), 1., &[1., 1., 0.5, 0.5, 0.25]);
+    let mut s = MultiStepLr::new(1., &[1, 3], None);
+    assert_schedule(|| s.step(), 1., &[1., 0.1, 0.1, 0.01, 0.01]);
+    let mut s = ExponentialLr::new(2., 0.5);
+    assert_schedule(|| s.step(), 2., &[2., 1., 0.5]);
+    s.set_epoch(4);
+    assert_eq!(s.lr(), 0.125);
+    let mut s = LinearWarmup::new(0.3, 3, Some(0.));
+    assert_schedule(|| s.step(), 0., &[0., 0.1, 0.2, 0.3, 0.3]);
+}
+
+#[test]
+fn test_cosine_warm_restarts() {
+    let mut s = CosineAnnealingWarmRestarts::new(1., 2, Some(2), None);
+    // periods of 2, 4, ... epochs
+    assert_schedule(
+        || s.step(),
+        1.,
+        &[1., 0.5, 1., 0.853_553_4, 0.5, 0.146_446_6, 1.],
+    );
+
+    // the closed form agrees with walking the periods one by one
+    for (t_0, t_mult) in [(1, 2), (3, 2), (2, 3), (5, 7)] {
+        let s = CosineAnnealingWarmRestarts::new(1., t_0, Some(t_mult), None);
+        let (mut start, mut len) = (0, t_0);
+        for epoch in 0..500 {
+            if epoch == start + len {
+                start += len;
+                len *= t_mult;
+            }
+            let cos = (PI * (epoch - start) as f32 / len as f32).cos();
+            assert_eq!(s.lr_at(epoch), (1. + cos) / 2., "epoch {}", epoch);
+        }
+    }
+    // far into the schedule: period 20 starts at 2 * (2^20 - 1)
+    s.set_epoch(2 * ((1 << 20) - 1) + (1 << 20));
+    assert!((s.lr() - 0.5).abs() < 1e-6);
+    s.set_epoch(2 * ((1 << 20) - 1));
+    assert_eq!(s.lr(), 1.);
+    let mut s = CosineAnnealingWarmRestarts::new(1., 4, None, None);
+    s.set_epoch(u32::MAX - 3);
+    assert_eq!(s.lr(), 1.);
+    assert!((s.step() - 0.853_553_4).abs() < 1e-6);
+    let s = CosineAnnealingWarmRestarts::new(1., 3, Some(u32::MAX), None);
+    assert_eq!(s.lr_at(3), 1.);
+    assert!((0. ..=1.).contains(&s.lr_at(u32::MAX)));
+}
+
+#[test]
+fn test_one_cycle() {
+    let mut s = OneCycleLr::new(
+        1.,
+        10,
+        Some(0.3),
+        Some(AnnealStrategy::Linear),
+        Some(4.),
+        Some(10.),
+        None,
+    );
+    // warm up to step 2, then anneal to 0.025 at step 9
+    let down = (1. - 0.025) / 7.;
+    let expected: Vec<f32> = [0.25, 0.625, 1.]
+        .iter()
+        .copied()
+        .chain((1..8).map(|i| 1. - down * i as f32))
+        .collect();
+    assert_schedule(|| s.step(), 0.25, &expected);
+
+    let s = OneCycleLr::new(1., 100, None, None, None, None, Some(true));
+    assert!((s.lr_at(29) - 1.).abs() < 1e-6);
+    assert!((s.lr_at(58) - 0.04).abs() < 1e-6);
+    assert!((s.lr_at(99) - 4e-6).abs() < 1e-9);
+    // stepping past the end holds the final rate
+    let mut s = OneCycleLr::new(1., 3, None, None, None, None, None);
+    s.set_epoch(3);
+    assert_eq!(s.lr(), s.lr_at(2));
+    assert_eq!(s.step(), s.lr_at(2));
+}
+
+#[test]
+fn test_reduce_on_plateau() {
+    let mut s =
+        ReduceLrOnPlateau::new(1., None, Some(0.5), Some(1), None, None, Some(1), Some(0.2));
+    let lrs: Vec<f32> = [5., 4., 4., 4., 4., 4., 4., 4., 4.]
+        .iter()
+        .map(|m| s.step(*m))
+        .collect();
+    assert_eq!(lrs, vec![1., 1., 1., 0.5, 0.5, 0.5, 0.25, 0.25, 0.25]);
+    assert_eq!(s.best(), 4.);
+    let mut s = ReduceLrOnPlateau::new(1., None, Some(0.5), Some(0), None, None, None, Some(0.2));
+    s.step(1.);
+    s.step(1.);
+    s.step(1.);
+    assert_eq!(s.step(1.), 0.2);
+}
@@ -0,0 +1,537 @@
+//! Evaluation metrics for classification, segmentation and regression.
+//!
+//! Class targets are arrays of class indices stored as floats. Predictions
+//! are either indices of the same shape, or scores with an extra trailing
+//! class axis, `[..., C]`, which are reduced with an argmax. Binary curve
+//! metrics take one score per sample and targets of `0` or `1`.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::ndarray::NdArray;
+
+fn as_index(x: f32) -> usize {
+    if x < 0. || x.fract() != 0. {
+        panic!("{} is not a class index", x);
+    }
+    x as usize
+}
+
+/// Predicted class of every sample, taking the argmax of trailing class
+/// scores when `pred` has one more axis than `target`.
+fn predicted_labels(pred: &NdArray, target: &NdArray) -> Vec<usize> {
+    if pred.shape == target.shape {
+        return pred.buffer.iter().map(|x| as_index(*x)).collect();
+    }
+    if pred.shape.len() != target.shape.len() + 1
+        || pred.shape[..target.shape.len()] != target.shape[..]
+    {
+        panic!(
+            "predictions of shape {:?} don't match targets of shape {:?}",
+            pred.shape, target.shape
+        );
+    }
+    let classes = pred.shape[pred.shape.len() - 1];
+    pred.buffer
+        .chunks(classes.max(1))
+        .map(|row| {
+            row.iter()
+                .enumerate()
+                .fold((0, f32::NEG_INFINITY), |best, (i, x)| {
+                    if *x > best.1 {
+                        (i, *x)
+                    } else {
+                        best
+                    }
+                })
+                .0
+        })
+        .collect()
+}
+
+fn target_labels(target: &NdArray) -> Vec<usize> {
+    target.buffer.iter().map(|x| as_index(*x)).collect()
+}
+
+/// Fraction of samples whose predicted class is the target.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn accuracy(pred: &NdArray, target: &NdArray) -> f32 {
+    let pred = predicted_labels(pred, target);
+    let correct = pred
+        .iter()
+        .zip(target_labels(target))
+        .filter(|(p, t)| **p == *t)
+        .count();
+    correct as f32 / pred.len() as f32
+}
+
+/// Fraction of samples whose target is among the `k` highest of their
+/// `[N, C]` scores.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = topKAccuracy))]
+pub fn top_k_accuracy(scores: &NdArray, target: &NdArray, k: usize) -> f32 {
+    let classes = *scores.shape.last().unwrap_or(&0);
+    if scores.shape.len() != 2 || target.buffer.len() != scores.shape[0] {
+        panic!(
+            "expected [N, C] scores and N targets, got shapes {:?} and {:?}",
+            scores.shape, target.shape
+        );
+    }
+    let targets = target_labels(target);
+    if let Some(t) = targets.iter().find(|t| **t >= classes) {
+        panic!("target {} is out of bounds for {} classes", t, classes);
+    }
+    let hits = scores
+        .buffer
+        .chunks(classes.max(1))
+        .zip(targets)
+        .filter(|(row, t)| {
+            // the target is in the top k if fewer than k classes beat it
+            let score = row[*t];
+            row.iter().filter(|x| **x > score).count() < k
+        })
+        .count();
+    hits as f32 / target.buffer.len() as f32
+}
+
+fn confusion_counts(pred: &[usize], target: &[usize], classes: usize) -> Vec<usize> {
+    let mut counts = vec![0; classes * classes];
+    for (p, t) in pred.iter().zip(target) {
+        if *p >= classes || *t >= classes {
+            panic!(
+                "class {} is out of bounds for {} classes",
+                p.max(t),
+                classes
+            );
+        }
+        counts[t * classes + p] += 1;
+    }
+    counts
+}
+
+fn num_classes(pred: &[usize], target: &[usize], num_classes: Option<usize>) -> usize {
+    num_classes.unwrap_or_else(|| pred.iter().chain(target).max().map_or(0, |m| m + 1))
+}
+
+/// `[C, C]` matrix counting samples of true class `i` predicted as class
+/// `j` at `[i, j]`. The number of classes defaults to the largest label
+/// plus one.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = confusionMatrix))]
+pub fn confusion_matrix(pred: &NdArray, target: &NdArray, num_classes: Option<usize>) -> NdArray {
+    let (pred, target) = (predicted_labels(pred, target), target_labels(target));
+    let classes = self::num_classes(&pred, &target, num_classes);
+    let counts: Vec<f32> = confusion_counts(&pred, &target, classes)
+        .into_iter()
+        .map(|c| c as f32)
+        .collect();
+    NdArray::from(&counts, Some(vec![classes, classes]), None)
+}
+
+/// How per-class scores are combined.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Average {
+    /// Computed from the total true positives, false positives and false
+    /// negatives.
+    Micro,
+    /// Unweighted mean over classes.
+    Macro,
+    /// Mean over classes weighted by their support.
+    Weighted,
+}
+
+/// Precision, recall and F1 scores, per class or averaged, and the number
+/// of samples of every class.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct ClassificationScores {
+    precision: NdArray,
+    recall: NdArray,
+    f1: NdArray,
+    support: NdArray,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl ClassificationScores {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn precision(&self) -> NdArray {
+        self.precision.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn recall(&self) -> NdArray {
+        self.recall.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn f1(&self) -> NdArray {
+        self.f1.clone()
+    }
+
+    /// Number of samples of every class, `[C]`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn support(&self) -> NdArray {
+        self.support.clone()
+    }
+}
+
+/// `a / b`, or 0 when `b` is 0.
+fn ratio(a: f32, b: f32) -> f32 {
+    if b == 0. {
+        0.
+    } else {
+        a / b
+    }
+}
+
+fn f1_of(precision: f32, recall: f32) -> f32 {
+    ratio(2. * precision * recall, precision + recall)
+}
+
+/// Precision, recall and F1 score. Without `average` the scores are
+/// per class, `[C]`; otherwise they are combined into `[1]` arrays.
+/// Undefined ratios, e.g. the precision of a never predicted class, are 0.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = precisionRecallF1))]
+pub fn precision_recall_f1(
+    pred: &NdArray,
+    target: &NdArray,
+    num_classes: Option<usize>,
+    average: Option<Average>,
+) -> ClassificationScores {
+    let (pred, target) = (predicted_labels(pred, target), target_labels(target));
+    let classes = self::num_classes(&pred, &target, num_classes);
+    let counts = confusion_counts(&pred, &target, classes);
+    let mut tp = vec![0.; classes];
+    let (mut predicted, mut support) = (vec![0.; classes], vec![0.; classes]);
+    for t in 0..classes {
+        for p in 0..classes {
+            let n = counts[t * classes + p] as f32;
+            support[t] += n;
+            predicted[p] += n;
+        }
+        tp[t] = counts[t * classes + t] as f32;
+    }
+    let precision: Vec<f32> = (0..classes).map(|c| ratio(tp[c], predicted[c])).collect();
+    let recall: Vec<f32> = (0..classes).map(|c| ratio(tp[c], support[c])).collect();
+    let f1: Vec<f32> = (0..classes)
+        .map(|c| f1_of(precision[c], recall[c]))
+        .collect();
+    let scalar = |x: f32| NdArray::from(&[x], None, None);
+    let per_class = |v: &[f32]| NdArray::from(v, None, None);
+    let weighted_mean =
+        |v: &[f32], w: &[f32]| ratio(v.iter().zip(w).map(|(v, w)| v * w).sum(), w.iter().sum());
+    let (precision, recall, f1) = match average {
+        None => (per_class(&precision), per_class(&recall), per_class(&f1)),
+        Some(Average::Micro) => {
+            let tp: f32 = tp.iter().sum();
+            let p = ratio(tp, predicted.iter().sum());
+            let r = ratio(tp, support.iter().sum());
+            (scalar(p), scalar(r), scalar(f1_of(p, r)))
+        }
+        Some(Average::Macro) => {
+            let ones = vec![1.; classes];
+            (
+                scalar(weighted_mean(&precision, &ones)),
+                scalar(weighted_mean(&recall, &ones)),
+                scalar(weighted_mean(&f1, &ones)),
+            )
+        }
+        Some(Average::Weighted) => (
+            scalar(weighted_mean(&precision, &support)),
+            scalar(weighted_mean(&recall, &support)),
+            scalar(weighted_mean(&f1, &support)),
+        ),
+    };
+    ClassificationScores {
+        precision,
+        recall,
+        f1,
+        support: per_class(&support),
+    }
+}
+
+/// Cumulative true and false positive counts at every distinct score,
+/// taking scores in decreasing order, with those scores.
+fn binary_counts(scores: &NdArray, target: &NdArray) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+    if scores.buffer.len() != target.buffer.len() {
+        panic!(
+            "scores of shape {:?} don't match targets of shape {:?}",
+            scores.shape, target.shape
+        );
+    }
+    let mut order: Vec<usize> = (0..scores.buffer.len()).collect();
+    order.sort_by(|a, b| scores.buffer[*b].total_cmp(&scores.buffer[*a]));
+    let (mut tps, mut fps, mut thresholds) = (vec![], vec![], vec![]);
+    let (mut tp, mut fp) = (0., 0.);
+    for (k, i) in order.iter().enumerate() {
+        let y = target.buffer[*i];
+        if y == 1. {
+            tp += 1.;
+        } else if y == 0. {
+            fp += 1.;
+        } else {
+            panic!("binary target {} is neither 0 nor 1", y);
+        }
+        let last = order
+            .get(k + 1)
+            .is_none_or(|j| scores.buffer[*j] != scores.buffer[*i]);
+        if last {
+            tps.push(tp);
+            fps.push(fp);
+            thresholds.push(scores.buffer[*i]);
+        }
+    }
+    (tps, fps, thresholds)
+}
+
+/// Points of a curve over decreasing thresholds.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct Curve {
+    x: NdArray,
+    y: NdArray,
+    thresholds: NdArray,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Curve {
+    /// False positive rate for ROC curves, recall for PR curves.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn x(&self) -> NdArray {
+        self.x.clone()
+    }
+
+    /// True positive rate for ROC curves, precision for PR curves.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn y(&self) -> NdArray {
+        self.y.clone()
+    }
+
+    /// Score threshold of every point; a sample is positive when its score
+    /// is at least the threshold.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn thresholds(&self) -> NdArray {
+        self.thresholds.clone()
+    }
+}
+
+fn curve(x: Vec<f32>, y: Vec<f32>, thresholds: Vec<f32>) -> Curve {
+    Curve {
+        x: NdArray::from(&x, None, None),
+        y: NdArray::from(&y, None, None),
+        thresholds: NdArray::from(&thresholds, None, None),
+    }
+}
+
+/// Receiver operating characteristic, starting at `(0, 0)` with an
+/// infinite threshold.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = rocCurve))]
+pub fn roc_curve(scores: &NdArray, target: &NdArray) -> Curve {
+    let (tps, fps, thresholds) = binary_counts(scores, target);
+    let (pos, neg) = (*tps.last().unwrap_or(&0.), *fps.last().unwrap_or(&0.));
+    let fpr = std::iter::once(0.)
+        .chain(fps.iter().map(|f| f / neg))
+        .collect();
+    let tpr = std::iter::once(0.)
+        .chain(tps.iter().map(|t| t / pos))
+        .collect();
+    let thresholds = std::iter::once(f32::INFINITY).chain(thresholds).collect();
+    curve(fpr, tpr, thresholds)
+}
+
+/// Area under the ROC curve, by the trapezoidal rule.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = rocAuc))]
+pub fn roc_auc(scores: &NdArray, target: &NdArray) -> f32 {
+    let roc = roc_curve(scores, target);
+    let (x, y) = (&roc.x.buffer, &roc.y.buffer);
+    (1..x.len())
+        .map(|i| (x[i] - x[i - 1]) * (y[i] + y[i - 1]) / 2.)
+        .sum()
+}
+
+/// Precision-recall curve, starting at recall 0 and precision 1 with an
+/// infinite threshold.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = precisionRecallCurve))]
+pub fn precision_recall_curve(scores: &NdArray, target: &NdArray) -> Curve {
+    let (tps, fps, thresholds) = binary_counts(scores, target);
+    let pos = *tps.last().unwrap_or(&0.);
+    let recall = std::iter::once(0.)
+        .chain(tps.iter().map(|t| t / pos))
+        .collect();
+    let precision = std::iter::once(1.)
+        .chain(tps.iter().zip(&fps).map(|(t, f)| t / (t + f)))
+        .collect();
+    let thresholds = std::iter::once(f32::INFINITY).chain(thresholds).collect();
+    curve(recall, precision, thresholds)
+}
+
+/// Average precision: the mean of the precisions at every threshold,
+/// weighted by the increase in recall.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = averagePrecision))]
+pub fn average_precision(scores: &NdArray, target: &NdArray) -> f32 {
+    let pr = precision_recall_curve(scores, target);
+    let (recall, precision) = (&pr.x.buffer, &pr.y.buffer);
+    (1..recall.len())
+        .map(|i| (recall[i] - recall[i - 1]) * precision[i])
+        .sum()
+}
+
+/// Intersection over union averaged over the classes that appear in the
+/// predictions or the targets. Pixels whose target is `ignore_index` are
+/// skipped.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = meanIou))]
+pub fn mean_iou(
+    pred: &NdArray,
+    target: &NdArray,
+    num_classes: usize,
+    ignore_index: Option<u32>,
+) -> f32 {
+    let (pred, target) = (predicted_labels(pred, target), target_labels(target));
+    let (pred, target): (Vec<usize>, Vec<usize>) = pred
+        .into_iter()
+        .zip(target)
+        .filter(|(_, t)| Some(*t as u32) != ignore_index)
+        .unzip();
+    let counts = confusion_counts(&pred, &target, num_classes);
+    let ious: Vec<f32> = (0..num_classes)
+        .filter_map(|c| {
+            let tp = counts[c * num_classes + c];
+            let row: usize = counts[c * num_classes..(c + 1) * num_classes].iter().sum();
+            let col: usize = (0..num_classes).map(|t| counts[t * num_classes + c]).sum();
+            let union = row + col - tp;
+            if union == 0 {
+                None
+            } else {
+                Some(tp as f32 / union as f32)
+            }
+        })
+        .collect();
+    ious.iter().sum::<f32>() / ious.len() as f32
+}
+
+fn check_regression(pred: &NdArray, target: &NdArray) {
+    if pred.shape != target.shape {
+        panic!(
+            "predictions of shape {:?} don't match targets of shape {:?}",
+            pred.shape, target.shape
+        );
+    }
+}
+
+/// Coefficient of determination, `1 - SS_res / SS_tot`.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = r2Score))]
+pub fn r2_score(pred: &NdArray, target: &NdArray) -> f32 {
+    check_regression(pred, target);
+    let n = target.buffer.len() as f32;
+    let mean = target.buffer.iter().sum::<f32>() / n;
+    let ss_res: f32 = pred
+        .buffer
+        .iter()
+        .zip(&target.buffer)
+        .map(|(p, t)| (t - p).powi(2))
+        .sum();
+    let ss_tot: f32 = target.buffer.iter().map(|t| (t - mean).powi(2)).sum();
+    1. - ss_res / ss_tot
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = meanAbsoluteError))]
+pub fn mean_absolute_error(pred: &NdArray, target: &NdArray) -> f32 {
+    check_regression(pred, target);
+    let total: f32 = pred
+        .buffer
+        .iter()
+        .zip(&target.buffer)
+        .map(|(p, t)| (p - t).abs())
+        .sum();
+    total / pred.buffer.len() as f32
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = rootMeanSquaredError))]
+pub fn root_mean_squared_error(pred: &NdArray, target: &NdArray) -> f32 {
+    check_regression(pred, target);
+    let total: f32 = pred
+        .buffer
+        .iter()
+        .zip(&target.buffer)
+        .map(|(p, t)| (p - t).powi(2))
+        .sum();
+    (total / pred.buffer.len() as f32).sqrt()
+}
+
+#[cfg(test)]
+fn labels(v: &[f32]) -> NdArray {
+    NdArray::from(v, None, None)
+}
+
+#[test]
+fn test_classification_metrics() {
+    let scores = NdArray::from(
+        &[0.1, 0.7, 0.2, 0.5, 0.3, 0.2, 0.2, 0.3, 0.5, 0.6, 0.3, 0.1],
+        Some(vec![4, 3]),
+        None,
+    );
+    let target = labels(&[1., 0., 1., 2.]);
+    assert_eq!(accuracy(&scores, &target), 0.5);
+    assert_eq!(accuracy(&labels(&[1., 0., 2., 0.]), &target), 0.5);
+    assert_eq!(top_k_accuracy(&scores, &target, 2), 0.75);
+    assert_eq!(top_k_accuracy(&scores, &target, 3), 1.);
+
+    let cm = confusion_matrix(&scores, &target, None);
+    assert_eq!(cm.shape, vec![3, 3]);
+    assert_eq!(cm.buffer, vec![1., 0., 0., 0., 1., 1., 1., 0., 0.]);
+
+    // class 0: tp 1, predicted 2, support 1; class 1: tp 1, 1, 2; class 2: 0, 1, 1
+    let s = precision_recall_f1(&scores, &target, None, None);
+    assert_eq!(s.precision().buffer, vec![0.5, 1., 0.]);
+    assert_eq!(s.recall().buffer, vec![1., 0.5, 0.]);
+    assert_eq!(s.support().buffer, vec![1., 2., 1.]);
+    let f1 = 2. / 3.;
+    let s = precision_recall_f1(&scores, &target, None, Some(Average::Macro));
+    assert!((s.f1().buffer[0] - 2. * f1 / 3.).abs() < 1e-6);
+    let s = precision_recall_f1(&scores, &target, None, Some(Average::Weighted));
+    assert!((s.f1().buffer[0] - 3. * f1 / 4.).abs() < 1e-6);
+    let s = precision_recall_f1(&scores, &target, Some(4), Some(Average::Micro));
+    assert_eq!(s.precision().buffer, vec![0.5]);
+    assert_eq!(s.recall().buffer, vec![0.5]);
+    assert_eq!(s.support().shape, vec![4]);
+}
+
+#[test]
+#[should_panic(expected = "target 3 is out of bounds for 3 classes")]
+fn test_top_k_accuracy_target_bounds() {
+    top_k_accuracy(&NdArray::zeros(&[2, 3]), &labels(&[0., 3.]), 1);
+}
+
+#[test]
+fn test_binary_curves() {
+    let scores = labels(&[0.1, 0.4, 0.35, 0.8]);
+    let target = labels(&[0., 0., 1., 1.]);
+    let roc = roc_curve(&scores, &target);
+    assert_eq!(roc.x().buffer, vec![0., 0., 0.5, 0.5, 1.]);
+    assert_eq!(roc.y().buffer, vec![0., 0.5, 0.5, 1., 1.]);
+    assert_eq!(roc.thresholds().buffer[1..], [0.8, 0.4, 0.35, 0.1]);
+    assert_eq!(roc_auc(&scores, &target), 0.75);
+
+    let pr = precision_recall_curve(&scores, &target);
+    assert_eq!(pr.x().buffer, vec![0., 0.5, 0.5, 1., 1.]);
+    assert_eq!(pr.y().buffer, vec![1., 1., 0.5, 2. / 3., 0.5]);
+    let ap = average_precision(&scores, &target);
+    assert!((ap - (0.5 + 0.5 * 2. / 3.)).abs() < 1e-6);
+
+    // tied scores form one threshold
+    let roc = roc_curve(&labels(&[0.5, 0.5]), &labels(&[0., 1.]));
+    assert_eq!(roc.x().buffer, vec![0., 1.]);
+    assert_eq!(roc_auc(&labels(&[0.5, 0.5]), &labels(&[0., 1.])), 0.5);
+}
+
+#[test]
+fn test_segmentation_and_regression_metrics() {
+    let pred = NdArray::from(&[0., 1., 1., 2.], Some(vec![2, 2]), None);
+    let target = NdArray::from(&[0., 1., 2., 3.], Some(vec![2, 2]), None);
+    // class 0: 1/1, class 1: 1/2, class 2: 0/2; class 3 is ignored
+    let miou = mean_iou(&pred, &target, 4, Some(3));
+    assert!((miou - 0.5).abs() < 1e-6);
+
+    let pred = labels(&[2.5, 0., 2., 8.]);
+    let target = labels(&[3., -0.5, 2., 7.]);
+    assert_eq!(mean_absolute_error(&pred, &target), 0.5);
+    assert!((root_mean_squared_error(&pred, &target) - 0.375f32.sqrt()).abs() < 1e-6);
+    assert!((r2_score(&pred, &target) - 0.948_608_2).abs() < 1e-5);
+}
@@ -1,27 +1,29 @@
 use std::{borrow::Borrow, vec};
+use crate::dtype::DType;
 use crate::utils::{
     self, get_indexes, get_strides, nd_idx_to_offset, reorder, NormalRandomGenerater
 };
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
 use rand::{thread_rng, Rng};
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub struct NdArray {
     pub(super) buffer: Vec<f32>,
     pub(super) strides: Vec<usize>,
     pub(super) shape: Vec<usize>,
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl NdArray {
     pub fn from(buffer: &[f32], shape: Option<Vec<usize>>, strides: Option<Vec<usize>>) -> Self {
         let shape = shape.unwrap_or(vec![buffer.len()]);
         let strides = strides.unwrap_or(utils::get_strides(shape.borrow()));
         Self {
             buffer: buffer.to_vec(),
-            strides: strides,
-            shape: shape,
+            strides,
+            shape,
         }
     }
 
@@ -37,31 +39,31 @@ impl NdArray {
         )
     }
 
-    #[wasm_bindgen(getter, js_name = "buffer")]
+    /// A copy of the data; see `bufferView` for a view without copying.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter, js_name = "buffer"))]
     pub fn get_buffer(&self) -> Vec<f32> {
         self.buffer.clone()
     }
 
-    #[wasm_bindgen(getter, js_name = "shape")]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter, js_name = "shape"))]
     pub fn get_shape(&self) -> Vec<usize> {
         self.shape.clone()
     }
 
-    pub fn map(&self, f: &js_sys::Function) -> Self {
-        Self::from(
-            &self
-                .buffer
-                .iter()
-                .map(|x| {
-                    f.call1(&JsValue::null(), &JsValue::from(*x))
-                        .unwrap()
-                        .as_f64()
-                        .unwrap() as f32
-                })
-                .collect::<Vec<f32>>(),
-            Some(self.shape.clone()),
-            Some(self.strides.clone()),
-        )
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn dtype(&self) -> DType {
+        DType::Float32
+    }
+
+    #[cfg(feature = "wasm")]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = map))]
+    pub fn map_js(&self, f: &js_sys::Function) -> Self {
+        self.map(|x| {
+            f.call1(&JsValue::null(), &JsValue::from(x))
+                .unwrap()
+                .as_f64()
+                .unwrap() as f32
+        })
     }
 
     pub fn rand(shape: &[usize]) -> Self {
@@ -71,7 +73,7 @@ impl NdArray {
         Self::from(&buffer, Some(shape.to_vec()), None)
     }
 
-    #[wasm_bindgen(js_name = randBetween)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = randBetween))]
     pub fn rand_between(shape: &[usize], min: f32, max: f32) -> Self {
         let mut rng = thread_rng();
         let mut buffer = vec![0.0; shape.iter().product()];
@@ -126,50 +128,12 @@ impl NdArray {
     }
 
     pub fn reshape(&self, shape: &[i32]) -> Self {
-        let new_axis_count = shape.iter().filter(|x| (**x) <= 0).count();
-        if new_axis_count == 0 {
-            if shape
-                .iter()
-                .map(|x| *x as usize)
-                .reduce(|a, b| a * b)
-                .unwrap()
-                != self.buffer.len()
-            {
-                panic!("shape is not compatible with buffer")
-            }
-            let shape: Vec<usize> = shape.iter().map(|x| *x as usize).collect();
-            return Self {
-                buffer: self.buffer.clone(),
-                strides: utils::get_strides(shape.borrow()),
-                shape,
-            };
-        } else if new_axis_count == 1 {
-            let known_size = shape
-                .iter()
-                .map(|x| *x)
-                .reduce(|acc, e| {
-                    if e < -1 || e == 0 {
-                        panic!("unknown dimension size must be positive or -1.");
-                    } else if e == -1 {
-                        return acc;
-                    }
-                    acc * e
-                })
-                .unwrap();
-            let new_size = self.buffer.len() / known_size as usize;
-            let shape: Vec<usize> = shape
-                .iter()
-                .map(|x| {
-                    let x = *x;
-                    if x == -1 {
-                        return new_size;
-                    }
-                    return x as usize;
-                })
-                .collect();
-            return Self::from(&self.buffer, Some(shape), None);
+        let shape = utils::reshaped_shape(shape, self.buffer.len());
+        Self {
+            buffer: self.buffer.clone(),
+            strides: utils::get_strides(shape.borrow()),
+            shape,
         }
-        panic!("too many new axis");
     }
 
     pub fn transpose(&self) -> Self {
@@ -196,7 +160,7 @@ impl NdArray {
 
         NdArray::from(
             &buf,
-            Some(remaining.iter().map(|x| *x).collect::<Vec<usize>>()),
+            Some(remaining.to_vec()),
             None,
         )
     }
@@ -213,7 +177,7 @@ impl NdArray {
             .sum();
         assert_eq!(
             remaining.iter().product::<usize>(),
-            value.shape.iter().product()
+            value.shape.iter().product::<usize>()
         );
         self.buffer[offsets..offsets + remaining.iter().product::<usize>()]
             .clone_from_slice(&value.buffer);
@@ -230,6 +194,16 @@ impl NdArray {
 
 }
 
+impl NdArray {
+    pub fn map<F: FnMut(f32) -> f32>(&self, mut f: F) -> Self {
+        Self::from(
+            &self.buffer.iter().map(|x| f(*x)).collect::<Vec<f32>>(),
+            Some(self.shape.clone()),
+            Some(self.strides.clone()),
+        )
+    }
+}
+
 impl Clone for NdArray {
     fn clone(&self) -> Self {
         Self {
@@ -240,36 +214,13 @@ impl Clone for NdArray {
     }
 }
 
-// impl fmt::Display for NdArray {
-//     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-//         fn display(f: &mut std::fmt::Formatter<'_>, v: &[f32], shape: &[usize], deep: usize) {
-//             if shape.len() == 1 {
-//                 for el in v {
-//                     write!(f, "{}, ", el).unwrap();
-//                 }
-//                 return;
-//             }
-
-//             for _ in 0..deep {
-//                 write!(f, "\t").unwrap();
-//             }
-
-//             write!(f, "[").unwrap();
-//             for chunk in v.chunks(shape[0]) {
-//                 display(f, &chunk, &shape[1..], deep + 1);
-//             }
-//             write!(f, "]").unwrap();
-
-//             if deep != 0 {
-//                 write!(f, ",\n").unwrap();
-//             }
-//         }
-//         write!(f, "[").unwrap();
-//         display(f, &self.buffer, &self.shape, 0);
-//         write!(f, "]").unwrap();
-//         Ok(())
-//     }
-// }
+#[test]
+fn test_map() {
+    let a = NdArray::arange(0, 4, None).reshape(&[2, 2]);
+    let b = a.map(|x| x * x);
+    assert_eq!(b.shape, vec![2, 2]);
+    assert_eq!(b.buffer, vec![0., 1., 4., 9.]);
+}
 
 #[test]
 fn test_random() {
@@ -338,12 +289,36 @@ fn test_permute() {
     )
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn concat(a: &NdArray, b: &NdArray) -> NdArray {
     let mut c = a.clone();
     c.buffer.extend_from_slice(&b.buffer);
     c.shape[0] += b.shape[0];
-    return c;
+    c
+}
+
+/// Concatenates along `axis`; all other dimensions must match.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = concatAxis))]
+pub fn concat_axis(a: &NdArray, b: &NdArray, axis: usize) -> NdArray {
+    assert!(
+        axis < a.shape.len()
+            && a.shape.len() == b.shape.len()
+            && (0..a.shape.len()).all(|d| d == axis || a.shape[d] == b.shape[d]),
+        "cannot concat {:?} and {:?} along axis {}",
+        a.shape,
+        b.shape,
+        axis
+    );
+    let outer: usize = a.shape[..axis].iter().product();
+    let (ca, cb) = (a.buffer.len() / outer.max(1), b.buffer.len() / outer.max(1));
+    let mut buffer = Vec::with_capacity(a.buffer.len() + b.buffer.len());
+    for i in 0..outer {
+        buffer.extend_from_slice(&a.buffer[i * ca..(i + 1) * ca]);
+        buffer.extend_from_slice(&b.buffer[i * cb..(i + 1) * cb]);
+    }
+    let mut shape = a.shape.clone();
+    shape[axis] += b.shape[axis];
+    NdArray::from(&buffer, Some(shape), None)
 }
 
 #[test]
@@ -356,4 +331,7 @@ fn test_concat() {
         c.buffer,
         vec![1., 1., 1., 1., 1., 1., 0., 0., 0., 0., 0., 0.]
     );
+    let d = concat_axis(&a.reshape(&[2, 3, 1]), &b.reshape(&[2, 3, 1]), 2);
+    assert_eq!(d.shape, vec![2, 3, 2]);
+    assert_eq!(&d.buffer[..4], &[1., 0., 1., 0.]);
 }
@@ -0,0 +1,717 @@
+//! Normalization kernels for channel-last `[N, ..., C]` inputs, the layout
+//! `im2col` produces.
+//!
+//! Every forward pass returns the output together with the `mean` and
+//! `invstd` (`1 / sqrt(var + eps)`) it normalized with; pass those back to
+//! the matching `*_backward` to get the gradients. Inputs, affine params
+//! or saved stats of the wrong shape are reported as `ShapeMismatch`.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::error::{Error, JsResult, Result};
+#[cfg(test)]
+use crate::gradcheck::assert_grad;
+use crate::ndarray::NdArray;
+
+const EPS: f32 = 1e-5;
+
+/// Result of a normalization forward pass.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct NormOutput {
+    output: NdArray,
+    mean: NdArray,
+    invstd: NdArray,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl NormOutput {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn output(&self) -> NdArray {
+        self.output.clone()
+    }
+
+    /// Mean of each normalized group; zeros for `rms_norm`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn mean(&self) -> NdArray {
+        self.mean.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn invstd(&self) -> NdArray {
+        self.invstd.clone()
+    }
+}
+
+/// Gradients of a normalization with respect to its input and its affine
+/// weight and bias; `rms_norm` has no bias.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct NormGrads {
+    input: NdArray,
+    weight: NdArray,
+    bias: Option<NdArray>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl NormGrads {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn input(&self) -> NdArray {
+        self.input.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn weight(&self) -> NdArray {
+        self.weight.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn bias(&self) -> Option<NdArray> {
+        self.bias.clone()
+    }
+}
+
+/// The input viewed as `[n, s, c]` with the `c` channels split into
+/// `groups`; statistics are kept per `(n, group)`.
+struct Layout {
+    n: usize,
+    s: usize,
+    c: usize,
+    groups: usize,
+}
+
+impl Layout {
+    fn channel_last(x: &NdArray, groups: usize) -> Result<Self> {
+        if x.shape.len() < 2 {
+            return Err(Error::ShapeMismatch(format!(
+                "expected a [N, ..., C] input, got shape {:?}",
+                x.shape
+            )));
+        }
+        let c = x.shape[x.shape.len() - 1];
+        if groups == 0 || !c.is_multiple_of(groups) {
+            return Err(Error::InvalidArgument(format!(
+                "{} channels can't be split into {} groups",
+                c, groups
+            )));
+        }
+        Ok(Layout {
+            n: x.shape[0],
+            s: x.shape[1..x.shape.len() - 1].iter().product(),
+            c,
+            groups,
+        })
+    }
+
+    /// One group per channel, across the whole batch.
+    fn per_channel(x: &NdArray) -> Result<Self> {
+        let l = Layout::channel_last(x, 1)?;
+        Ok(Layout {
+            n: 1,
+            s: l.n * l.s,
+            c: l.c,
+            groups: l.c,
+        })
+    }
+
+    /// Every element of each row of the trailing `ndim` dims in one group.
+    fn trailing(x: &NdArray, ndim: usize) -> Result<Self> {
+        if ndim == 0 || ndim > x.shape.len() {
+            return Err(Error::ShapeMismatch(format!(
+                "can't normalize {} trailing dims of shape {:?}",
+                ndim, x.shape
+            )));
+        }
+        let split = x.shape.len() - ndim;
+        Ok(Layout {
+            n: x.shape[..split].iter().product(),
+            s: 1,
+            c: x.shape[split..].iter().product(),
+            groups: 1,
+        })
+    }
+
+    fn group_len(&self) -> usize {
+        self.s * self.c / self.groups
+    }
+
+    /// Stat index of every element, in buffer order.
+    fn stat_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        let per_group = self.c / self.groups;
+        (0..self.n).flat_map(move |n| {
+            (0..self.s * self.c).map(move |i| n * self.groups + (i % self.c) / per_group)
+        })
+    }
+
+    fn channels(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.n * self.s * self.c).map(move |i| i % self.c)
+    }
+
+    fn check_affine(&self, p: &NdArray, name: &str) -> Result<()> {
+        if p.buffer.len() != self.c {
+            return Err(Error::ShapeMismatch(format!(
+                "{} has shape {:?} but {} channels are normalized",
+                name, p.shape, self.c
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks a saved `mean` or `invstd` against the number of groups.
+    fn check_stats(&self, stats: &NdArray, name: &str) -> Result<()> {
+        if stats.buffer.len() != self.n * self.groups {
+            return Err(Error::ShapeMismatch(format!(
+                "{} has shape {:?} but the input has {} groups",
+                name,
+                stats.shape,
+                self.n * self.groups
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Per-group mean (zero unless `centered`) and `1 / sqrt(var + eps)`.
+fn group_stats(x: &NdArray, l: &Layout, centered: bool, eps: f32) -> (Vec<f32>, Vec<f32>) {
+    let stats = l.n * l.groups;
+    let len = l.group_len() as f64;
+    let mut sum = vec![0f64; stats];
+    if centered {
+        for (k, x) in l.stat_indices().zip(&x.buffer) {
+            sum[k] += *x as f64;
+        }
+    }
+    let mean: Vec<f64> = sum.iter().map(|s| s / len).collect();
+    let mut sq = vec![0f64; stats];
+    for (k, x) in l.stat_indices().zip(&x.buffer) {
+        sq[k] += (*x as f64 - mean[k]).powi(2);
+    }
+    let invstd = sq
+        .iter()
+        .map(|s| (1. / (s / len + eps as f64).sqrt()) as f32)
+        .collect();
+    (mean.into_iter().map(|m| m as f32).collect(), invstd)
+}
+
+fn normalize(
+    x: &NdArray,
+    l: &Layout,
+    mean: &[f32],
+    invstd: &[f32],
+    weight: &NdArray,
+    bias: Option<&NdArray>,
+) -> Result<NdArray> {
+    l.check_affine(weight, "weight")?;
+    if let Some(bias) = bias {
+        l.check_affine(bias, "bias")?;
+    }
+    let mut res = x.clone();
+    for ((y, k), c) in res
+        .buffer
+        .iter_mut()
+        .zip(l.stat_indices())
+        .zip(l.channels())
+    {
+        let mut v = (*y - mean[k]) * invstd[k] * weight.buffer[c];
+        if let Some(b) = bias {
+            v += b.buffer[c];
+        }
+        *y = v;
+    }
+    Ok(res)
+}
+
+/// Shared backward pass. With `fixed` stats (batch norm in eval mode) the
+/// mean and invstd are constants; otherwise they depend on the input, and
+/// `centered` says whether the mean was subtracted.
+#[allow(clippy::too_many_arguments)]
+fn backward(
+    dy: &NdArray,
+    x: &NdArray,
+    l: &Layout,
+    mean: &NdArray,
+    invstd: &NdArray,
+    weight: &NdArray,
+    centered: bool,
+    fixed: bool,
+) -> Result<NormGrads> {
+    if dy.shape != x.shape {
+        return Err(Error::ShapeMismatch(format!(
+            "grad of shape {:?} doesn't match input of shape {:?}",
+            dy.shape, x.shape
+        )));
+    }
+    l.check_stats(mean, "mean")?;
+    l.check_stats(invstd, "invstd")?;
+    l.check_affine(weight, "weight")?;
+    let (mean, invstd) = (&mean.buffer, &invstd.buffer);
+    let xhat: Vec<f32> = x
+        .buffer
+        .iter()
+        .zip(l.stat_indices())
+        .map(|(x, k)| (x - mean[k]) * invstd[k])
+        .collect();
+
+    let mut dweight = vec![0f64; l.c];
+    let mut dbias = vec![0f64; l.c];
+    for ((dy, xh), c) in dy.buffer.iter().zip(&xhat).zip(l.channels()) {
+        dweight[c] += (dy * xh) as f64;
+        dbias[c] += *dy as f64;
+    }
+    // gradient w.r.t. the normalized input
+    let g: Vec<f32> = dy
+        .buffer
+        .iter()
+        .zip(l.channels())
+        .map(|(dy, c)| dy * weight.buffer[c])
+        .collect();
+
+    let mut input = x.clone();
+    if fixed {
+        for ((dx, g), k) in input.buffer.iter_mut().zip(&g).zip(l.stat_indices()) {
+            *dx = g * invstd[k];
+        }
+    } else {
+        let stats = l.n * l.groups;
+        let len = l.group_len() as f64;
+        let (mut sum_g, mut sum_gx) = (vec![0f64; stats], vec![0f64; stats]);
+        for ((g, xh), k) in g.iter().zip(&xhat).zip(l.stat_indices()) {
+            sum_g[k] += *g as f64;
+            sum_gx[k] += (g * xh) as f64;
+        }
+        for (i, (dx, k)) in input.buffer.iter_mut().zip(l.stat_indices()).enumerate() {
+            let mut v = g[i] as f64 - xhat[i] as f64 * sum_gx[k] / len;
+            if centered {
+                v -= sum_g[k] / len;
+            }
+            *dx = (v * invstd[k] as f64) as f32;
+        }
+    }
+
+    let to_array = |v: Vec<f64>| {
+        let v: Vec<f32> = v.into_iter().map(|x| x as f32).collect();
+        NdArray::from(&v, Some(weight.shape.clone()), None)
+    };
+    Ok(NormGrads {
+        input,
+        weight: to_array(dweight),
+        bias: Some(to_array(dbias)),
+    })
+}
+
+/// Batch normalization over every axis but the channel axis.
+///
+/// In training mode the batch statistics are used and the running stats
+/// are updated in place as
+/// `running = (1 - momentum) * running + momentum * batch`, with the
+/// unbiased batch variance; `momentum` defaults to 0.1.
+/// In eval mode the running stats are used as-is.
+#[allow(clippy::too_many_arguments)]
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = batchNorm))]
+pub fn batch_norm(
+    x: &NdArray,
+    running_mean: &mut NdArray,
+    running_var: &mut NdArray,
+    weight: &NdArray,
+    bias: &NdArray,
+    training: bool,
+    momentum: Option<f32>,
+    eps: Option<f32>,
+) -> JsResult<NormOutput> {
+    let eps = eps.unwrap_or(EPS);
+    let l = Layout::per_channel(x)?;
+    l.check_affine(running_mean, "running_mean")?;
+    l.check_affine(running_var, "running_var")?;
+    let (mean, invstd) = if training {
+        let (mean, invstd) = group_stats(x, &l, true, eps);
+        let momentum = momentum.unwrap_or(0.1);
+        let count = l.s as f32;
+        let unbias = if count > 1. { count / (count - 1.) } else { 1. };
+        for c in 0..l.c {
+            let var = 1. / (invstd[c] * invstd[c]) - eps;
+            let rm = &mut running_mean.buffer[c];
+            *rm = (1. - momentum) * *rm + momentum * mean[c];
+            let rv = &mut running_var.buffer[c];
+            *rv = (1. - momentum) * *rv + momentum * var * unbias;
+        }
+        (mean, invstd)
+    } else {
+        let invstd = running_var
+            .buffer
+            .iter()
+            .map(|v| 1. / (v + eps).sqrt())
+            .collect();
+        (running_mean.buffer.clone(), invstd)
+    };
+    Ok(NormOutput {
+        output: normalize(x, &l, &mean, &invstd, weight, Some(bias))?,
+        mean: NdArray::from(&mean, None, None),
+        invstd: NdArray::from(&invstd, None, None),
+    })
+}
+
+/// `training` must match the forward pass: in eval mode the statistics
+/// are constants and don't contribute to the input gradient.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = batchNormBackward))]
+pub fn batch_norm_backward(
+    dy: &NdArray,
+    x: &NdArray,
+    weight: &NdArray,
+    mean: &NdArray,
+    invstd: &NdArray,
+    training: bool,
+) -> JsResult<NormGrads> {
+    let l = Layout::per_channel(x)?;
+    Ok(backward(dy, x, &l, mean, invstd, weight, true, !training)?)
+}
+
+/// Normalizes over the trailing `normalized_ndim` dims (default 1);
+/// `weight` and `bias` cover those dims.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = layerNorm))]
+pub fn layer_norm(
+    x: &NdArray,
+    normalized_ndim: Option<usize>,
+    weight: &NdArray,
+    bias: &NdArray,
+    eps: Option<f32>,
+) -> JsResult<NormOutput> {
+    let l = Layout::trailing(x, normalized_ndim.unwrap_or(1))?;
+    Ok(norm_forward(
+        x,
+        &l,
+        weight,
+        Some(bias),
+        true,
+        eps.unwrap_or(EPS),
+    )?)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = layerNormBackward))]
+pub fn layer_norm_backward(
+    dy: &NdArray,
+    x: &NdArray,
+    normalized_ndim: Option<usize>,
+    weight: &NdArray,
+    mean: &NdArray,
+    invstd: &NdArray,
+) -> JsResult<NormGrads> {
+    let l = Layout::trailing(x, normalized_ndim.unwrap_or(1))?;
+    Ok(backward(dy, x, &l, mean, invstd, weight, true, false)?)
+}
+
+/// Splits the channels into `groups` and normalizes each group of every
+/// sample over its channels and spatial positions. Stats have shape
+/// `[N, groups]`.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = groupNorm))]
+pub fn group_norm(
+    x: &NdArray,
+    groups: usize,
+    weight: &NdArray,
+    bias: &NdArray,
+    eps: Option<f32>,
+) -> JsResult<NormOutput> {
+    let l = Layout::channel_last(x, groups)?;
+    Ok(norm_forward(
+        x,
+        &l,
+        weight,
+        Some(bias),
+        true,
+        eps.unwrap_or(EPS),
+    )?)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = groupNormBackward))]
+pub fn group_norm_backward(
+    dy: &NdArray,
+    x: &NdArray,
+    groups: usize,
+    weight: &NdArray,
+    mean: &NdArray,
+    invstd: &NdArray,
+) -> JsResult<NormGrads> {
+    let l = Layout::channel_last(x, groups)?;
+    Ok(backward(dy, x, &l, mean, invstd, weight, true, false)?)
+}
+
+/// Normalizes every channel of every sample over its spatial positions;
+/// the same as `group_norm` with one group per channel.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = instanceNorm))]
+pub fn instance_norm(
+    x: &NdArray,
+    weight: &NdArray,
+    bias: &NdArray,
+    eps: Option<f32>,
+) -> JsResult<NormOutput> {
+    let channels = x.shape.last().copied().unwrap_or(1);
+    group_norm(x, channels, weight, bias, eps)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = instanceNormBackward))]
+pub fn instance_norm_backward(
+    dy: &NdArray,
+    x: &NdArray,
+    weight: &NdArray,
+    mean: &NdArray,
+    invstd: &NdArray,
+) -> JsResult<NormGrads> {
+    let channels = x.shape.last().copied().unwrap_or(1);
+    group_norm_backward(dy, x, channels, weight, mean, invstd)
+}
+
+/// Scales the trailing `normalized_ndim` dims (default 1) by their root
+/// mean square, without centering. `eps` defaults to 1e-6.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = rmsNorm))]
+pub fn rms_norm(
+    x: &NdArray,
+    normalized_ndim: Option<usize>,
+    weight: &NdArray,
+    eps: Option<f32>,
+) -> JsResult<NormOutput> {
+    let l = Layout::trailing(x, normalized_ndim.unwrap_or(1))?;
+    Ok(norm_forward(
+        x,
+        &l,
+        weight,
+        None,
+        false,
+        eps.unwrap_or(1e-6),
+    )?)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = rmsNormBackward))]
+pub fn rms_norm_backward(
+    dy: &NdArray,
+    x: &NdArray,
+    normalized_ndim: Option<usize>,
+    weight: &NdArray,
+    invstd: &NdArray,
+) -> JsResult<NormGrads> {
+    let l = Layout::trailing(x, normalized_ndim.unwrap_or(1))?;
+    let mean = NdArray::zeros(&invstd.shape);
+    let mut grads = backward(dy, x, &l, &mean, invstd, weight, false, false)?;
+    grads.bias = None;
+    Ok(grads)
+}
+
+fn norm_forward(
+    x: &NdArray,
+    l: &Layout,
+    weight: &NdArray,
+    bias: Option<&NdArray>,
+    centered: bool,
+    eps: f32,
+) -> Result<NormOutput> {
+    let (mean, invstd) = group_stats(x, l, centered, eps);
+    let stats_shape = if l.groups == 1 {
+        vec![l.n]
+    } else {
+        vec![l.n, l.groups]
+    };
+    Ok(NormOutput {
+        output: normalize(x, l, &mean, &invstd, weight, bias)?,
+        mean: NdArray::from(&mean, Some(stats_shape.clone()), None),
+        invstd: NdArray::from(&invstd, Some(stats_shape), None),
+    })
+}
+
+/// Checks `grads` against central differences of `sum(f(x) * r)`.
+#[cfg(test)]
+fn check_grads<F: Fn(&NdArray, &NdArray) -> NdArray>(
+    x: &NdArray,
+    weight: &NdArray,
+    f: F,
+    grads: impl Fn(&NdArray) -> NormGrads,
+) {
+    let out = f(x, weight);
+    let r = NdArray::from(
+        &(0..out.buffer.len())
+            .map(|i| ((i * 7) % 5) as f32 - 2.)
+            .collect::<Vec<_>>(),
+        Some(out.shape.clone()),
+        None,
+    );
+    let g = grads(&r);
+    assert_grad(&g.input, x, &r, |x| f(x, weight));
+    assert_grad(&g.weight, weight, &r, |w| f(x, w));
+}
+
+#[cfg(test)]
+fn sample_input() -> NdArray {
+    let v: Vec<f32> = (0..24).map(|i| ((i * 5) % 11) as f32 * 0.3 - 1.).collect();
+    NdArray::from(&v, Some(vec![2, 3, 4]), None)
+}
+
+#[test]
+fn test_batch_norm() {
+    let x = NdArray::from(
+        &[1., 10., 3., 20., 5., 30., 7., 40.],
+        Some(vec![2, 2, 2]),
+        None,
+    );
+    let mut rm = NdArray::zeros(&[2]);
+    let mut rv = NdArray::ones(&[2]);
+    let out = batch_norm(
+        &x,
+        &mut rm,
+        &mut rv,
+        &NdArray::ones(&[2]),
+        &NdArray::zeros(&[2]),
+        true,
+        None,
+        Some(0.),
+    )
+    .unwrap();
+    assert_eq!(out.mean().buffer, vec![4., 25.]);
+    let y = out.output();
+    let m: f32 = (0..4).map(|i| y.buffer[i * 2]).sum();
+    let v: f32 = (0..4).map(|i| y.buffer[i * 2].powi(2)).sum::<f32>() / 4.;
+    assert!(m.abs() < 1e-5 && (v - 1.).abs() < 1e-5);
+    // unbiased var of [1, 3, 5, 7] is 20 / 3
+    assert!((rm.buffer[0] - 0.4).abs() < 1e-6);
+    assert!((rv.buffer[0] - (0.9 + 0.1 * 20. / 3.)).abs() < 1e-5);
+
+    let eval = batch_norm(
+        &x,
+        &mut rm,
+        &mut rv,
+        &NdArray::ones(&[2]),
+        &NdArray::zeros(&[2]),
+        false,
+        None,
+        Some(0.),
+    )
+    .unwrap();
+    assert!((eval.output().buffer[0] - (1. - rm.buffer[0]) / rv.buffer[0].sqrt()).abs() < 1e-5);
+
+    let x = sample_input();
+    let weight = NdArray::from(&[0.5, 2., -1., 1.5], None, None);
+    let bias = NdArray::from(&[0.1, 0.2, 0.3, 0.4], None, None);
+    let f = |x: &NdArray, w: &NdArray| {
+        let (mut rm, mut rv) = (NdArray::zeros(&[4]), NdArray::ones(&[4]));
+        batch_norm(x, &mut rm, &mut rv, w, &bias, true, None, None)
+            .unwrap()
+            .output()
+    };
+    let out = f(&x, &weight);
+    let (mut rm, mut rv) = (NdArray::zeros(&[4]), NdArray::ones(&[4]));
+    let saved = batch_norm(&x, &mut rm, &mut rv, &weight, &bias, true, None, None).unwrap();
+    assert_eq!(out.shape, x.shape);
+    check_grads(&x, &weight, f, |dy| {
+        batch_norm_backward(dy, &x, &weight, &saved.mean(), &saved.invstd(), true).unwrap()
+    });
+}
+
+#[test]
+fn test_layer_and_rms_norm() {
+    let x = sample_input();
+    let weight = NdArray::from(&[0.5, 2., -1., 1.5], None, None);
+    let (ones, zeros) = (NdArray::ones(&[4]), NdArray::zeros(&[4]));
+    let out = layer_norm(&x, None, &ones, &zeros, None).unwrap();
+    assert_eq!(out.mean().shape, vec![6]);
+    let row = &out.output().buffer[..4];
+    assert!(row.iter().sum::<f32>().abs() < 1e-5);
+    check_grads(
+        &x,
+        &weight,
+        |x, w| layer_norm(x, None, w, &zeros, None).unwrap().output(),
+        |dy| {
+            let s = layer_norm(&x, None, &weight, &zeros, None).unwrap();
+            layer_norm_backward(dy, &x, None, &weight, &s.mean(), &s.invstd()).unwrap()
+        },
+    );
+
+    let w12 = NdArray::from(
+        &(0..12).map(|i| i as f32 * 0.1 + 0.5).collect::<Vec<_>>(),
+        Some(vec![3, 4]),
+        None,
+    );
+    let out = layer_norm(&x, Some(2), &w12, &w12, None).unwrap();
+    assert_eq!(out.mean().shape, vec![2]);
+    let g = layer_norm_backward(&x, &x, Some(2), &w12, &out.mean(), &out.invstd()).unwrap();
+    assert_eq!(g.weight().shape, vec![3, 4]);
+
+    let out = rms_norm(&x, None, &ones, None).unwrap();
+    let row = &out.output().buffer[..4];
+    let ms = row.iter().map(|v| v * v).sum::<f32>() / 4.;
+    assert!((ms - 1.).abs() < 1e-4);
+    check_grads(
+        &x,
+        &weight,
+        |x, w| rms_norm(x, None, w, None).unwrap().output(),
+        |dy| {
+            let s = rms_norm(&x, None, &weight, None).unwrap();
+            rms_norm_backward(dy, &x, None, &weight, &s.invstd()).unwrap()
+        },
+    );
+}
+
+#[test]
+fn test_group_and_instance_norm() {
+    let x = sample_input();
+    let weight = NdArray::from(&[0.5, 2., -1., 1.5], None, None);
+    let (ones, zeros) = (NdArray::ones(&[4]), NdArray::zeros(&[4]));
+    let out = group_norm(&x, 2, &ones, &zeros, None).unwrap();
+    assert_eq!(out.mean().shape, vec![2, 2]);
+    // sample 0, group 0 is channels 0..2 over 3 positions
+    let y = out.output();
+    let s: f32 = (0..3)
+        .flat_map(|p| [y.buffer[p * 4], y.buffer[p * 4 + 1]])
+        .sum();
+    assert!(s.abs() < 1e-5);
+    check_grads(
+        &x,
+        &weight,
+        |x, w| group_norm(x, 2, w, &zeros, None).unwrap().output(),
+        |dy| {
+            let s = group_norm(&x, 2, &weight, &zeros, None).unwrap();
+            group_norm_backward(dy, &x, 2, &weight, &s.mean(), &s.invstd()).unwrap()
+        },
+    );
+
+    let inst = instance_norm(&x, &ones, &zeros, None).unwrap();
+    assert_eq!(inst.mean().shape, vec![2, 4]);
+    assert_eq!(
+        inst.output().buffer,
+        group_norm(&x, 4, &ones, &zeros, None)
+            .unwrap()
+            .output()
+            .buffer
+    );
+    check_grads(
+        &x,
+        &weight,
+        |x, w| instance_norm(x, w, &zeros, None).unwrap().output(),
+        |dy| {
+            let s = instance_norm(&x, &weight, &zeros, None).unwrap();
+            instance_norm_backward(dy, &x, &weight, &s.mean(), &s.invstd()).unwrap()
+        },
+    );
+}
+
+#[test]
+fn test_norm_invalid_shapes() {
+    let x = sample_input();
+    let ones = NdArray::ones(&[4]);
+    let mismatch = |r: Result<()>| matches!(r, Err(Error::ShapeMismatch(_)));
+    assert!(mismatch(Layout::channel_last(&ones, 1).map(|_| ())));
+    assert!(mismatch(Layout::trailing(&x, 4).map(|_| ())));
+    assert!(mismatch(Layout::trailing(&x, 0).map(|_| ())));
+    assert!(matches!(
+        Layout::channel_last(&x, 3).map(|_| ()),
+        Err(Error::InvalidArgument(_))
+    ));
+
+    let l = Layout::trailing(&x, 1).unwrap();
+    let weight = NdArray::ones(&[3]);
+    assert!(mismatch(
+        norm_forward(&x, &l, &weight, None, true, EPS).map(|_| ())
+    ));
+    let s = norm_forward(&x, &l, &ones, None, true, EPS).unwrap();
+    let short = NdArray::zeros(&[5]);
+    assert!(mismatch(
+        backward(&x, &x, &l, &short, &s.invstd, &ones, true, false).map(|_| ())
+    ));
+    assert!(mismatch(
+        backward(&ones, &x, &l, &s.mean, &s.invstd, &ones, true, false).map(|_| ())
+    ));
+}
@@ -0,0 +1,704 @@
+//! NumPy `.npy` and `.npz` files.
+//!
+//! Everything works on in-memory byte slices so it can be used from wasm
+//! with the contents of a `fetch` response or a file input.
+
+use half::f16;
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Write};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    complex::ComplexNdArray,
+    dtype::DType,
+    error::{Error, JsResult, Result},
+    float16::{check_half, narrow, HalfNdArray},
+    ndarray::NdArray,
+};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ByteOrder {
+    Little,
+    Big,
+}
+
+/// Parsed `descr` field of an `.npy` header, e.g. `<f4`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Descr {
+    order: ByteOrder,
+    kind: char,
+    size: usize,
+}
+
+impl Descr {
+    fn parse(descr: &str) -> Result<Descr> {
+        let mut chars = descr.chars();
+        let order = match chars.next() {
+            Some('<') | Some('|') | Some('=') => ByteOrder::Little,
+            Some('>') => ByteOrder::Big,
+            _ => return Err(Error::UnsupportedDType(descr.to_string())),
+        };
+        let kind = chars
+            .next()
+            .ok_or_else(|| Error::UnsupportedDType(descr.to_string()))?;
+        let size = chars
+            .as_str()
+            .parse::<usize>()
+            .map_err(|_| Error::UnsupportedDType(descr.to_string()))?;
+        let supported = match kind {
+            'f' => matches!(size, 2 | 4 | 8),
+            'i' | 'u' => matches!(size, 1 | 2 | 4 | 8),
+            'b' => size == 1,
+            'c' => matches!(size, 8 | 16),
+            _ => false,
+        };
+        if !supported {
+            return Err(Error::UnsupportedDType(descr.to_string()));
+        }
+        Ok(Descr { order, kind, size })
+    }
+
+    fn is_complex(&self) -> bool {
+        self.kind == 'c'
+    }
+
+    fn is_f16(&self) -> bool {
+        self.kind == 'f' && self.size == 2
+    }
+
+    /// Decodes one (real) scalar of this type.
+    fn read(&self, bytes: &[u8]) -> f32 {
+        let mut buf = [0u8; 8];
+        let n = bytes.len();
+        buf[..n].copy_from_slice(bytes);
+        if self.order == ByteOrder::Big {
+            buf[..n].reverse();
+        }
+        match (self.kind, n) {
+            ('f', 2) => f16::from_le_bytes([buf[0], buf[1]]).to_f32(),
+            ('f', 4) | ('c', 4) => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]),
+            ('f', 8) | ('c', 8) => f64::from_le_bytes(buf) as f32,
+            ('i', 1) => buf[0] as i8 as f32,
+            ('i', 2) => i16::from_le_bytes([buf[0], buf[1]]) as f32,
+            ('i', 4) => i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f32,
+            ('i', 8) => i64::from_le_bytes(buf) as f32,
+            ('u', 1) | ('b', 1) => buf[0] as f32,
+            ('u', 2) => u16::from_le_bytes([buf[0], buf[1]]) as f32,
+            ('u', 4) => u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f32,
+            ('u', 8) => u64::from_le_bytes(buf) as f32,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Header value of the small Python-literal subset `.npy` headers use.
+#[derive(Clone, Debug, PartialEq)]
+enum Literal {
+    Str(String),
+    Bool(bool),
+    Tuple(Vec<usize>),
+}
+
+struct HeaderParser<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> HeaderParser<'a> {
+    fn skip_ws(&mut self) {
+        while self.pos < self.src.len() && self.src[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_ws();
+        self.src.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<()> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(Error::format(format!(
+                "expected '{}' in npy header at {}",
+                c as char, self.pos
+            )))
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let quote = self.peek().filter(|c| *c == b'\'' || *c == b'"');
+        let quote = quote.ok_or_else(|| Error::format("expected a string in npy header"))?;
+        self.pos += 1;
+        let start = self.pos;
+        while self.pos < self.src.len() && self.src[self.pos] != quote {
+            self.pos += 1;
+        }
+        let s = std::str::from_utf8(&self.src[start..self.pos])
+            .map_err(|_| Error::format("npy header is not valid utf-8"))?;
+        self.expect(quote)?;
+        Ok(s.to_string())
+    }
+
+    fn literal(&mut self) -> Result<Literal> {
+        match self.peek() {
+            Some(b'\'') | Some(b'"') => Ok(Literal::Str(self.string()?)),
+            Some(b'(') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                while self.peek() != Some(b')') {
+                    let start = self.pos;
+                    while self.pos < self.src.len() && self.src[self.pos].is_ascii_digit() {
+                        self.pos += 1;
+                    }
+                    let digits = std::str::from_utf8(&self.src[start..self.pos]).unwrap();
+                    // numpy writes `3L` for long ints in some python 2 files
+                    if self.src.get(self.pos) == Some(&b'L') {
+                        self.pos += 1;
+                    }
+                    items.push(
+                        digits
+                            .parse()
+                            .map_err(|_| Error::format("invalid shape in npy header"))?,
+                    );
+                    if self.peek() == Some(b',') {
+                        self.pos += 1;
+                    } else if self.peek() != Some(b')') {
+                        return Err(Error::format("invalid shape in npy header"));
+                    }
+                }
+                self.pos += 1;
+                Ok(Literal::Tuple(items))
+            }
+            _ => {
+                let rest = &self.src[self.pos..];
+                if rest.starts_with(b"True") {
+                    self.pos += 4;
+                    Ok(Literal::Bool(true))
+                } else if rest.starts_with(b"False") {
+                    self.pos += 5;
+                    Ok(Literal::Bool(false))
+                } else {
+                    Err(Error::format("unexpected value in npy header"))
+                }
+            }
+        }
+    }
+
+    fn dict(&mut self) -> Result<HashMap<String, Literal>> {
+        let mut res = HashMap::new();
+        self.expect(b'{')?;
+        while self.peek() != Some(b'}') {
+            let key = self.string()?;
+            self.expect(b':')?;
+            res.insert(key, self.literal()?);
+            if self.peek() == Some(b',') {
+                self.pos += 1;
+            } else if self.peek() != Some(b'}') {
+                return Err(Error::format("expected ',' or '}' in npy header"));
+            }
+        }
+        self.expect(b'}')?;
+        Ok(res)
+    }
+}
+
+/// Raw contents of an `.npy` file before conversion to an array type.
+struct NpyData<'a> {
+    descr: Descr,
+    fortran_order: bool,
+    shape: Vec<usize>,
+    data: &'a [u8],
+}
+
+fn parse_npy(bytes: &[u8]) -> Result<NpyData<'_>> {
+    if bytes.len() < 10 || &bytes[..6] != MAGIC {
+        return Err(Error::format("missing npy magic string"));
+    }
+    let major = bytes[6];
+    let (header_len, offset) = match major {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        2 | 3 => {
+            if bytes.len() < 12 {
+                return Err(Error::format("truncated npy header"));
+            }
+            (
+                u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize,
+                12,
+            )
+        }
+        _ => return Err(Error::format(format!("unknown npy version {}", major))),
+    };
+    if bytes.len() < offset + header_len {
+        return Err(Error::format("truncated npy header"));
+    }
+    let mut parser = HeaderParser {
+        src: &bytes[offset..offset + header_len],
+        pos: 0,
+    };
+    let header = parser.dict()?;
+    let descr = match header.get("descr") {
+        Some(Literal::Str(s)) => Descr::parse(s)?,
+        // structured dtypes are written as lists, which we don't support
+        _ => return Err(Error::UnsupportedDType("structured".to_string())),
+    };
+    let fortran_order = match header.get("fortran_order") {
+        Some(Literal::Bool(b)) => *b,
+        _ => return Err(Error::format("missing fortran_order in npy header")),
+    };
+    let shape = match header.get("shape") {
+        Some(Literal::Tuple(shape)) => shape.clone(),
+        _ => return Err(Error::format("missing shape in npy header")),
+    };
+    let data = &bytes[offset + header_len..];
+    let expected = shape
+        .iter()
+        .try_fold(descr.size, |acc, &d| acc.checked_mul(d))
+        .ok_or_else(|| Error::format("npy shape is too large"))?;
+    if data.len() < expected {
+        return Err(Error::format(format!(
+            "npy data has {} bytes, expected {}",
+            data.len(),
+            expected
+        )));
+    }
+    Ok(NpyData {
+        descr,
+        fortran_order,
+        shape,
+        data: &data[..expected],
+    })
+}
+
+/// Puts Fortran-ordered values back into C order. `width` is the number of
+/// floats per element (2 for complex).
+fn to_c_order(buffer: Vec<f32>, shape: &[usize], width: usize) -> Vec<f32> {
+    if shape.len() < 2 {
+        return buffer;
+    }
+    let mut reversed: Vec<usize> = shape.iter().rev().copied().collect();
+    reversed.push(width);
+    let mut axes: Vec<usize> = (0..shape.len()).rev().collect();
+    axes.push(shape.len());
+    NdArray::from(&buffer, Some(reversed), None)
+        .permute(&axes)
+        .buffer
+}
+
+fn write_header(descr: &str, fortran_order: bool, shape: &[usize]) -> Vec<u8> {
+    let shape = match shape.len() {
+        0 => "()".to_string(),
+        1 => format!("({},)", shape[0]),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': {}, 'shape': {}, }}",
+        descr,
+        if fortran_order { "True" } else { "False" },
+        shape
+    );
+    // the header (including the trailing newline) is padded so the data
+    // starts on a 64-byte boundary
+    let (version, prefix) = if header.len() + 11 < 65536 {
+        (1u8, 10)
+    } else {
+        (2u8, 12)
+    };
+    let pad = (64 - (prefix + header.len() + 1) % 64) % 64;
+    header.push_str(&" ".repeat(pad));
+    header.push('\n');
+
+    let mut res = Vec::with_capacity(prefix + header.len());
+    res.extend_from_slice(MAGIC);
+    res.push(version);
+    res.push(0);
+    if version == 1 {
+        res.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    } else {
+        res.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    }
+    res.extend_from_slice(header.as_bytes());
+    res
+}
+
+fn write_f32s(mut res: Vec<u8>, buffer: &[f32]) -> Vec<u8> {
+    res.reserve(buffer.len() * 4);
+    for x in buffer {
+        res.extend_from_slice(&x.to_le_bytes());
+    }
+    res
+}
+
+fn check_real(descr: &Descr) -> Result<()> {
+    if descr.is_complex() {
+        return Err(Error::UnsupportedDType(format!(
+            "{}{} (load it as a ComplexNdArray)",
+            descr.kind, descr.size
+        )));
+    }
+    Ok(())
+}
+
+/// Decodes an `.npy` file into an `NdArray`. Integer, boolean, `float16`
+/// and `float64` data are converted to `f32`; complex data needs
+/// [`read_npy_complex`].
+pub fn read_npy(bytes: &[u8]) -> Result<NdArray> {
+    let npy = parse_npy(bytes)?;
+    check_real(&npy.descr)?;
+    let buffer: Vec<f32> = npy
+        .data
+        .chunks(npy.descr.size)
+        .map(|x| npy.descr.read(x))
+        .collect();
+    let buffer = if npy.fortran_order {
+        to_c_order(buffer, &npy.shape, 1)
+    } else {
+        buffer
+    };
+    Ok(NdArray::from(&buffer, Some(npy.shape), None))
+}
+
+/// Decodes an `.npy` file into a `HalfNdArray` of `dtype` (`float16` by
+/// default). `float16` data is kept bit for bit; anything else is rounded
+/// to the nearest half.
+pub fn read_npy_half(bytes: &[u8], dtype: Option<DType>) -> Result<HalfNdArray> {
+    let npy = parse_npy(bytes)?;
+    check_real(&npy.descr)?;
+    let dtype = dtype.unwrap_or(DType::Float16);
+    check_half(dtype)?;
+    let bits: Vec<u16> = if npy.descr.is_f16() && dtype == DType::Float16 {
+        npy.data
+            .chunks(2)
+            .map(|b| match npy.descr.order {
+                ByteOrder::Little => u16::from_le_bytes([b[0], b[1]]),
+                ByteOrder::Big => u16::from_be_bytes([b[0], b[1]]),
+            })
+            .collect()
+    } else {
+        npy.data
+            .chunks(npy.descr.size)
+            .map(|x| narrow(npy.descr.read(x), dtype))
+            .collect()
+    };
+    let bits = if npy.fortran_order {
+        // every u16 is exact in f32, so the patterns can be reordered as floats
+        let floats = bits.iter().map(|b| *b as f32).collect();
+        to_c_order(floats, &npy.shape, 1)
+            .into_iter()
+            .map(|b| b as u16)
+            .collect()
+    } else {
+        bits
+    };
+    HalfNdArray::from_bits(bits, Some(npy.shape), dtype)
+}
+
+/// Decodes an `.npy` file into a `ComplexNdArray`. Real data is given a zero
+/// imaginary part.
+pub fn read_npy_complex(bytes: &[u8]) -> Result<ComplexNdArray> {
+    let npy = parse_npy(bytes)?;
+    let buffer: Vec<f32> = if npy.descr.is_complex() {
+        let half = Descr {
+            size: npy.descr.size / 2,
+            ..npy.descr.clone()
+        };
+        npy.data.chunks(half.size).map(|x| half.read(x)).collect()
+    } else {
+        npy.data
+            .chunks(npy.descr.size)
+            .flat_map(|x| [npy.descr.read(x), 0.0])
+            .collect()
+    };
+    let buffer = if npy.fortran_order {
+        to_c_order(buffer, &npy.shape, 2)
+    } else {
+        buffer
+    };
+    Ok(ComplexNdArray::from(&buffer, Some(npy.shape)))
+}
+
+/// Encodes an array as a little-endian `float32` `.npy` file. With
+/// `fortran_order` the data is written column-major.
+pub fn write_npy(a: &NdArray, fortran_order: bool) -> Vec<u8> {
+    let res = write_header("<f4", fortran_order, &a.shape);
+    if fortran_order && a.shape.len() > 1 {
+        write_f32s(res, &a.transpose().buffer)
+    } else {
+        write_f32s(res, &a.buffer)
+    }
+}
+
+/// Encodes a `float16` array as a little-endian `float16` `.npy` file.
+/// NumPy has no `bfloat16` type, so those arrays are rejected.
+pub fn write_npy_half(a: &HalfNdArray) -> Result<Vec<u8>> {
+    if a.dtype != DType::Float16 {
+        return Err(Error::UnsupportedDType(format!(
+            "{} (npy only stores float16)",
+            a.dtype.name()
+        )));
+    }
+    let mut res = write_header("<f2", false, &a.shape);
+    res.reserve(a.bits.len() * 2);
+    for x in &a.bits {
+        res.extend_from_slice(&x.to_le_bytes());
+    }
+    Ok(res)
+}
+
+/// Encodes a complex array as a little-endian `complex64` `.npy` file.
+pub fn write_npy_complex(a: &ComplexNdArray) -> Vec<u8> {
+    write_f32s(write_header("<c8", false, &a.shape), &a.buffer)
+}
+
+/// Reads every array of an `.npz` archive, in archive order. The `.npy`
+/// suffix is stripped from the names.
+pub fn read_npz(bytes: &[u8]) -> Result<Vec<(String, NdArray)>> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| Error::format(e.to_string()))?;
+    let mut res = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let mut file = archive
+            .by_index(i)
+            .map_err(|e| Error::format(e.to_string()))?;
+        let name = file.name().trim_end_matches(".npy").to_string();
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)
+            .map_err(|e| Error::format(e.to_string()))?;
+        res.push((name, read_npy(&buf)?));
+    }
+    Ok(res)
+}
+
+/// Packs named arrays into an `.npz` archive, deflated when `compress` is set
+/// (like `np.savez_compressed`).
+pub fn write_npz(arrays: &[(&str, &NdArray)], compress: bool) -> Result<Vec<u8>> {
+    let method = if compress {
+        zip::CompressionMethod::Deflated
+    } else {
+        zip::CompressionMethod::Stored
+    };
+    let options = zip::write::FileOptions::default().compression_method(method);
+    let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, array) in arrays {
+        writer
+            .start_file(format!("{}.npy", name), options)
+            .map_err(|e| Error::format(e.to_string()))?;
+        writer
+            .write_all(&write_npy(array, false))
+            .map_err(|e| Error::format(e.to_string()))?;
+    }
+    let buffer = writer.finish().map_err(|e| Error::format(e.to_string()))?;
+    Ok(buffer.into_inner())
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl NdArray {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = fromNpy))]
+    pub fn from_npy(bytes: &[u8]) -> JsResult<NdArray> {
+        Ok(read_npy(bytes)?)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = toNpy))]
+    pub fn to_npy(&self, fortran_order: Option<bool>) -> Vec<u8> {
+        write_npy(self, fortran_order.unwrap_or(false))
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl ComplexNdArray {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = fromNpy))]
+    pub fn from_npy(bytes: &[u8]) -> JsResult<ComplexNdArray> {
+        Ok(read_npy_complex(bytes)?)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = toNpy))]
+    pub fn to_npy(&self) -> Vec<u8> {
+        write_npy_complex(self)
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl HalfNdArray {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = fromNpy))]
+    pub fn from_npy(bytes: &[u8], dtype: Option<DType>) -> JsResult<HalfNdArray> {
+        Ok(read_npy_half(bytes, dtype)?)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = toNpy))]
+    pub fn to_npy(&self) -> JsResult<Vec<u8>> {
+        Ok(write_npy_half(self)?)
+    }
+}
+
+/// Arrays of a loaded `.npz` archive, looked up by name.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct NpzArchive {
+    arrays: Vec<(String, NdArray)>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl NpzArchive {
+    pub fn from(bytes: &[u8]) -> JsResult<NpzArchive> {
+        Ok(NpzArchive {
+            arrays: read_npz(bytes)?,
+        })
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.arrays.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<NdArray> {
+        self.arrays
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, a)| a.clone())
+    }
+}
+
+impl NpzArchive {
+    pub fn into_arrays(self) -> Vec<(String, NdArray)> {
+        self.arrays
+    }
+}
+
+/// Collects named arrays and encodes them as an `.npz` archive.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Default)]
+pub struct NpzWriter {
+    arrays: Vec<(String, NdArray)>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl NpzWriter {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> NpzWriter {
+        NpzWriter::default()
+    }
+
+    pub fn add(&mut self, name: &str, array: &NdArray) {
+        self.arrays.push((name.to_string(), array.clone()));
+    }
+
+    pub fn finish(&self, compress: Option<bool>) -> JsResult<Vec<u8>> {
+        let arrays: Vec<(&str, &NdArray)> = self
+            .arrays
+            .iter()
+            .map(|(name, a)| (name.as_str(), a))
+            .collect();
+        Ok(write_npz(&arrays, compress.unwrap_or(false))?)
+    }
+}
+
+/// A `.npy` file with the given header fields and raw data.
+#[cfg(test)]
+fn npy_file(descr: &str, fortran: bool, shape: &[usize], data: &[u8]) -> Vec<u8> {
+    let mut res = write_header(descr, fortran, shape);
+    res.extend_from_slice(data);
+    res
+}
+
+#[test]
+fn test_npy_roundtrip() {
+    let a = NdArray::arange(0, 6, None).reshape(&[2, 3]);
+    let bytes = write_npy(&a, false);
+    assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+    assert_eq!((bytes.len() - 24) % 64, 0);
+    let b = read_npy(&bytes).unwrap();
+    assert_eq!(b.shape, a.shape);
+    assert_eq!(b.buffer, a.buffer);
+
+    let c = read_npy(&write_npy(&a, true)).unwrap();
+    assert_eq!(c.shape, a.shape);
+    assert_eq!(c.buffer, a.buffer);
+}
+
+#[test]
+fn test_npy_dtypes() {
+    // big-endian int16
+    let a = read_npy(&npy_file(">i2", false, &[3], &[0, 1, 255, 254, 1, 0])).unwrap();
+    assert_eq!(a.buffer, vec![1., -2., 256.]);
+    // float64, Fortran order [[1, 2], [3, 4]]
+    let data: Vec<u8> = [1f64, 3., 2., 4.]
+        .iter()
+        .flat_map(|x| x.to_le_bytes().to_vec())
+        .collect();
+    let b = read_npy(&npy_file("<f8", true, &[2, 2], &data)).unwrap();
+    assert_eq!(b.buffer, vec![1., 2., 3., 4.]);
+    // bool and uint8
+    let c = read_npy(&npy_file("|b1", false, &[2], &[1, 0])).unwrap();
+    assert_eq!(c.buffer, vec![1., 0.]);
+
+    assert_eq!(
+        read_npy(&npy_file("<U8", false, &[1], &[0; 32])).err(),
+        Some(Error::UnsupportedDType("<U8".to_string()))
+    );
+    assert!(read_npy(&npy_file("<f4", false, &[4], &[0; 8])).is_err());
+    // a shape whose byte size overflows usize
+    assert!(matches!(
+        read_npy(&npy_file("<f4", false, &[usize::MAX / 2, 3], &[])),
+        Err(Error::Format(_))
+    ));
+    assert!(read_npy(b"not an npy file").is_err());
+}
+
+#[test]
+fn test_npy_complex() {
+    let z = ComplexNdArray::from(&[1., 2., 3., 4.], Some(vec![2]));
+    let bytes = write_npy_complex(&z);
+    assert!(read_npy(&bytes).is_err());
+    let w = read_npy_complex(&bytes).unwrap();
+    assert_eq!(w.shape, vec![2]);
+    assert_eq!(w.buffer, z.buffer);
+}
+
+#[test]
+fn test_npz_roundtrip() {
+    let a = NdArray::arange(0, 6, None).reshape(&[3, 2]);
+    let b = NdArray::ones(&[4]);
+    for compress in [false, true] {
+        let bytes = write_npz(&[("weight", &a), ("bias", &b)], compress).unwrap();
+        let archive = NpzArchive::from(&bytes).unwrap();
+        assert_eq!(archive.names(), vec!["weight", "bias"]);
+        let weight = archive.get("weight").unwrap();
+        assert_eq!(weight.shape, vec![3, 2]);
+        assert_eq!(weight.buffer, a.buffer);
+        assert_eq!(archive.get("bias").unwrap().buffer, b.buffer);
+        assert!(archive.get("missing").is_none());
+    }
+}
+
+#[test]
+fn test_npy_half() {
+    let a = NdArray::from(&[1.5, -2., 0.1, 65504.], Some(vec![2, 2]), None);
+    let h = HalfNdArray::new(&a, DType::Float16).unwrap();
+    let bytes = write_npy_half(&h).unwrap();
+    assert_eq!(read_npy_half(&bytes, None).unwrap(), h);
+    assert_eq!(read_npy(&bytes).unwrap().buffer, h.to_ndarray().buffer);
+    assert!(write_npy_half(&h.cast(DType::BFloat16).unwrap()).is_err());
+
+    // big-endian, Fortran-ordered [[1, 2], [3, 4]]
+    let data: Vec<u8> = [1f32, 3., 2., 4.]
+        .iter()
+        .flat_map(|x| f16::from_f32(*x).to_be_bytes())
+        .collect();
+    let b = read_npy_half(&npy_file(">f2", true, &[2, 2], &data), None).unwrap();
+    assert_eq!(b.to_ndarray().buffer, vec![1., 2., 3., 4.]);
+    // other dtypes are rounded to the requested half type
+    let c = read_npy_half(&write_npy(&a, false), Some(DType::BFloat16)).unwrap();
+    assert_eq!(c.dtype(), DType::BFloat16);
+    assert_eq!(c.to_ndarray().buffer, vec![1.5, -2., 0.100097656, 65536.]);
+    assert!(read_npy_half(&bytes, Some(DType::Float32)).is_err());
+}
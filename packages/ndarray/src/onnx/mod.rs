@@ -0,0 +1,919 @@
+//! ONNX model import and a small inference runtime.
+//!
+//! Graphs are executed node by node on top of the kernels in `ops.rs`.
+//! Every value is held as an `f32` `NdArray`, including integer tensors such
+//! as the target shape of a `Reshape`. Models using an operator outside of
+//! [`SUPPORTED_OPS`] are rejected when they are loaded.
+
+mod proto;
+
+use std::collections::HashMap;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    error::{Error, JsResult, Result},
+    ndarray::{concat_axis, NdArray},
+    ops::{add, broadcast_with, im2col, matmul, relu, sigmoid, softmax, tanh},
+    serialization::StateDict,
+};
+use proto::{ModelProto, NodeProto};
+
+pub const SUPPORTED_OPS: &[&str] = &[
+    "Add",
+    "Concat",
+    "Constant",
+    "Conv",
+    "Dropout",
+    "Flatten",
+    "Gemm",
+    "Identity",
+    "MatMul",
+    "MaxPool",
+    "Relu",
+    "Reshape",
+    "Sigmoid",
+    "Softmax",
+    "Tanh",
+    "Transpose",
+];
+
+fn invalid<S: Into<String>>(node: &NodeProto, msg: S) -> Error {
+    Error::InvalidGraph(format!(
+        "{} node '{}': {}",
+        node.op_type,
+        node.name,
+        msg.into()
+    ))
+}
+
+fn unsupported<S: Into<String>>(node: &NodeProto, msg: S) -> Error {
+    Error::UnsupportedOp(format!(
+        "{} node '{}': {}",
+        node.op_type,
+        node.name,
+        msg.into()
+    ))
+}
+
+fn attr_i(node: &NodeProto, name: &str, default: i64) -> i64 {
+    node.attribute(name).map(|a| a.i).unwrap_or(default)
+}
+
+fn attr_f(node: &NodeProto, name: &str, default: f32) -> f32 {
+    node.attribute(name).map(|a| a.f).unwrap_or(default)
+}
+
+fn attr_ints(node: &NodeProto, name: &str) -> Option<Vec<i64>> {
+    node.attribute(name).map(|a| a.ints.clone())
+}
+
+/// Reads an ints attribute that must hold `len` non-negative values.
+fn attr_usizes(node: &NodeProto, name: &str, len: usize) -> Result<Option<Vec<usize>>> {
+    let ints = match attr_ints(node, name) {
+        Some(ints) => ints,
+        None => return Ok(None),
+    };
+    if ints.len() != len || ints.iter().any(|x| *x < 0) {
+        return Err(invalid(
+            node,
+            format!("expected {} non-negative {}, got {:?}", len, name, ints),
+        ));
+    }
+    Ok(Some(ints.iter().map(|x| *x as usize).collect()))
+}
+
+/// Resolves a possibly negative axis against `ndim`.
+fn axis(node: &NodeProto, axis: i64, ndim: usize) -> Result<usize> {
+    let resolved = if axis < 0 { axis + ndim as i64 } else { axis };
+    if resolved < 0 || resolved as usize >= ndim {
+        return Err(invalid(node, format!("axis {} is out of range", axis)));
+    }
+    Ok(resolved as usize)
+}
+
+fn add_broadcast(node: &NodeProto, a: &NdArray, b: &NdArray) -> Result<NdArray> {
+    // `ops::add` repeats `b`, which is exact when b's shape is a suffix of a's
+    if a.shape.ends_with(&b.shape) && !b.buffer.is_empty() {
+        return Ok(add(a, b));
+    }
+    broadcast_with(a, b, |x, y| x + y).ok_or_else(|| {
+        invalid(
+            node,
+            format!("cannot broadcast {:?} and {:?}", a.shape, b.shape),
+        )
+    })
+}
+
+fn reshape_to(a: &NdArray, shape: &[usize]) -> NdArray {
+    NdArray::from(&a.buffer, Some(shape.to_vec()), None)
+}
+
+fn matmul_nd(node: &NodeProto, a: &NdArray, b: &NdArray) -> Result<NdArray> {
+    if a.shape.len() < 2 || b.shape.len() != 2 {
+        return Err(unsupported(
+            node,
+            format!("MatMul of {:?} and {:?}", a.shape, b.shape),
+        ));
+    }
+    let k = a.shape[a.shape.len() - 1];
+    if k != b.shape[0] {
+        return Err(invalid(
+            node,
+            format!("cannot multiply {:?} and {:?}", a.shape, b.shape),
+        ));
+    }
+    let rows = a.buffer.len() / k.max(1);
+    let res = matmul(&reshape_to(a, &[rows, k]), b);
+    let mut shape = a.shape.clone();
+    *shape.last_mut().unwrap() = b.shape[1];
+    Ok(reshape_to(&res, &shape))
+}
+
+fn softmax_axis(a: &NdArray, axis: usize) -> NdArray {
+    let ndim = a.shape.len();
+    if axis + 1 == ndim {
+        return softmax(a, None);
+    }
+    let mut perm: Vec<usize> = (0..ndim).filter(|d| *d != axis).collect();
+    perm.push(axis);
+    let mut inverse = vec![0; ndim];
+    for (i, p) in perm.iter().enumerate() {
+        inverse[*p] = i;
+    }
+    softmax(&a.permute(&perm), None).permute(&inverse)
+}
+
+/// Pads the two leading (spatial) axes of an `[H, W, C]` image.
+fn pad_hw(x: &NdArray, pads: [usize; 4], value: f32) -> NdArray {
+    let [top, left, bottom, right] = pads;
+    if pads == [0; 4] {
+        return x.clone();
+    }
+    let (h, w, c) = (x.shape[0], x.shape[1], x.shape[2]);
+    let (ph, pw) = (h + top + bottom, w + left + right);
+    let mut buffer = vec![value; ph * pw * c];
+    for r in 0..h {
+        let src = r * w * c;
+        let dst = ((r + top) * pw + left) * c;
+        buffer[dst..dst + w * c].copy_from_slice(&x.buffer[src..src + w * c]);
+    }
+    NdArray::from(&buffer, Some(vec![ph, pw, c]), None)
+}
+
+/// Spatial parameters shared by `Conv` and `MaxPool`, for 2-D windows.
+struct Window2d {
+    kernel: [usize; 2],
+    stride: [usize; 2],
+    pads: [usize; 4],
+}
+
+impl Window2d {
+    fn from_node(node: &NodeProto, kernel: [usize; 2], spatial: usize) -> Result<Window2d> {
+        let auto_pad = node
+            .attribute("auto_pad")
+            .map(|a| String::from_utf8_lossy(&a.s).to_string())
+            .unwrap_or_default();
+        if !auto_pad.is_empty() && auto_pad != "NOTSET" && auto_pad != "VALID" {
+            return Err(unsupported(node, format!("auto_pad={}", auto_pad)));
+        }
+        if attr_ints(node, "dilations").is_some_and(|d| d.iter().any(|x| *x != 1)) {
+            return Err(unsupported(node, "dilations other than 1"));
+        }
+        let stride = attr_usizes(node, "strides", spatial)?.unwrap_or_else(|| vec![1; spatial]);
+        if stride.contains(&0) {
+            return Err(invalid(
+                node,
+                format!("strides {:?} must be positive", stride),
+            ));
+        }
+        let pads = attr_usizes(node, "pads", spatial * 2)?.unwrap_or_else(|| vec![0; spatial * 2]);
+        // 1-D windows are run as 2-D ones over a height of 1
+        let (stride, pads) = if spatial == 1 {
+            ([1, stride[0]], [0, pads[0], 0, pads[1]])
+        } else {
+            ([stride[0], stride[1]], [pads[0], pads[1], pads[2], pads[3]])
+        };
+        Ok(Window2d {
+            kernel,
+            stride,
+            pads,
+        })
+    }
+
+    fn output_size(&self, h: usize, w: usize) -> Option<(usize, usize)> {
+        let ph = h + self.pads[0] + self.pads[2];
+        let pw = w + self.pads[1] + self.pads[3];
+        if ph < self.kernel[0] || pw < self.kernel[1] {
+            return None;
+        }
+        Some((
+            (ph - self.kernel[0]) / self.stride[0] + 1,
+            (pw - self.kernel[1]) / self.stride[1] + 1,
+        ))
+    }
+}
+
+/// Views `[N, C, L]` as `[N, C, 1, L]`; returns the spatial rank.
+fn as_4d(node: &NodeProto, x: &NdArray) -> Result<(NdArray, usize)> {
+    match x.shape.len() {
+        3 => Ok((reshape_to(x, &[x.shape[0], x.shape[1], 1, x.shape[2]]), 1)),
+        4 => Ok((x.clone(), 2)),
+        _ => Err(unsupported(node, format!("input of shape {:?}", x.shape))),
+    }
+}
+
+fn conv(node: &NodeProto, x: &NdArray, w: &NdArray, b: Option<&NdArray>) -> Result<NdArray> {
+    if attr_i(node, "group", 1) != 1 {
+        return Err(unsupported(node, "group other than 1"));
+    }
+    let (x, spatial) = as_4d(node, x)?;
+    let (w, _) = as_4d(node, w)?;
+    let (n, c, h, width) = (x.shape[0], x.shape[1], x.shape[2], x.shape[3]);
+    let (m, kc, kh, kw) = (w.shape[0], w.shape[1], w.shape[2], w.shape[3]);
+    if kc != c {
+        return Err(invalid(
+            node,
+            format!("weight {:?} does not match input {:?}", w.shape, x.shape),
+        ));
+    }
+    if let Some(b) = b {
+        if b.buffer.len() != m {
+            return Err(invalid(
+                node,
+                format!("bias of shape {:?} for {} output channels", b.shape, m),
+            ));
+        }
+    }
+    let window = Window2d::from_node(node, [kh, kw], spatial)?;
+    // the stride along H only matters when the window moves down
+    if window.stride[0] != window.stride[1] && h + window.pads[0] + window.pads[2] > kh {
+        return Err(unsupported(node, "different strides per axis"));
+    }
+    let (oh, ow) = window
+        .output_size(h, width)
+        .ok_or_else(|| invalid(node, "kernel is larger than the input"))?;
+
+    // [M, C, kh, kw] -> [kh * kw * C, M] to match the im2col column layout
+    let weight = w
+        .permute(&[0, 2, 3, 1])
+        .reshape(&[m as i32, (kh * kw * c) as i32])
+        .transpose();
+    let mut buffer = Vec::with_capacity(n * m * oh * ow);
+    for i in 0..n {
+        let img = pad_hw(&x.slice(&[i]).permute(&[1, 2, 0]), window.pads, 0.0);
+        let cols = im2col(&img, &[kh, kw], window.stride[1], &[], None);
+        // [oh * ow, M] -> [M, oh * ow]
+        let mut out = matmul(&cols, &weight).transpose();
+        if let Some(b) = b {
+            for (j, row) in out.buffer.chunks_mut(oh * ow).enumerate() {
+                row.iter_mut().for_each(|v| *v += b.buffer[j]);
+            }
+        }
+        buffer.extend_from_slice(&out.buffer);
+    }
+    let shape = if spatial == 1 {
+        vec![n, m, ow]
+    } else {
+        vec![n, m, oh, ow]
+    };
+    Ok(NdArray::from(&buffer, Some(shape), None))
+}
+
+fn max_pool(node: &NodeProto, x: &NdArray) -> Result<NdArray> {
+    if attr_i(node, "ceil_mode", 0) != 0 {
+        return Err(unsupported(node, "ceil_mode=1"));
+    }
+    let (x, spatial) = as_4d(node, x)?;
+    let kernel = attr_usizes(node, "kernel_shape", spatial)?
+        .ok_or_else(|| invalid(node, "missing kernel_shape"))?;
+    if kernel.contains(&0) {
+        return Err(invalid(
+            node,
+            format!("kernel_shape {:?} must be positive", kernel),
+        ));
+    }
+    let kernel = if spatial == 1 {
+        [1, kernel[0]]
+    } else {
+        [kernel[0], kernel[1]]
+    };
+    let window = Window2d::from_node(node, kernel, spatial)?;
+    let (n, c, h, w) = (x.shape[0], x.shape[1], x.shape[2], x.shape[3]);
+    let (oh, ow) = window
+        .output_size(h, w)
+        .ok_or_else(|| invalid(node, "kernel is larger than the input"))?;
+    let mut buffer = Vec::with_capacity(n * c * oh * ow);
+    for plane in x.buffer.chunks(h * w) {
+        for r in 0..oh {
+            for col in 0..ow {
+                let mut best = f32::NEG_INFINITY;
+                for i in 0..kernel[0] {
+                    for j in 0..kernel[1] {
+                        let y = (r * window.stride[0] + i) as isize - window.pads[0] as isize;
+                        let x = (col * window.stride[1] + j) as isize - window.pads[1] as isize;
+                        if y >= 0 && x >= 0 && (y as usize) < h && (x as usize) < w {
+                            best = best.max(plane[y as usize * w + x as usize]);
+                        }
+                    }
+                }
+                buffer.push(best);
+            }
+        }
+    }
+    let shape = if spatial == 1 {
+        vec![n, c, ow]
+    } else {
+        vec![n, c, oh, ow]
+    };
+    Ok(NdArray::from(&buffer, Some(shape), None))
+}
+
+fn reshape(node: &NodeProto, x: &NdArray, target: &NdArray) -> Result<NdArray> {
+    let allow_zero = attr_i(node, "allowzero", 0) != 0;
+    let mut shape = Vec::with_capacity(target.buffer.len());
+    let mut infer = None;
+    for (i, dim) in target.buffer.iter().enumerate() {
+        let dim = *dim as i64;
+        match dim {
+            -1 if infer.is_none() => {
+                infer = Some(i);
+                shape.push(1);
+            }
+            0 if !allow_zero => shape.push(
+                *x.shape
+                    .get(i)
+                    .ok_or_else(|| invalid(node, "0 refers to a missing dimension"))?,
+            ),
+            d if d >= 0 => shape.push(d as usize),
+            _ => {
+                return Err(invalid(
+                    node,
+                    format!("invalid target shape {:?}", target.buffer),
+                ))
+            }
+        }
+    }
+    let known: usize = shape.iter().product();
+    if let Some(i) = infer {
+        if known == 0 || !x.buffer.len().is_multiple_of(known) {
+            return Err(invalid(node, "cannot infer the -1 dimension"));
+        }
+        shape[i] = x.buffer.len() / known;
+    }
+    if shape.iter().product::<usize>() != x.buffer.len() {
+        return Err(invalid(
+            node,
+            format!("cannot reshape {:?} to {:?}", x.shape, shape),
+        ));
+    }
+    Ok(reshape_to(x, &shape))
+}
+
+/// A parsed ONNX model ready for inference.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct OnnxModel {
+    model: ModelProto,
+    initializers: HashMap<String, NdArray>,
+}
+
+impl OnnxModel {
+    pub fn parse(bytes: &[u8]) -> Result<OnnxModel> {
+        let model = ModelProto::decode(bytes)?;
+        for node in &model.graph.nodes {
+            let default_domain = node.domain.is_empty() || node.domain == "ai.onnx";
+            if !default_domain || !SUPPORTED_OPS.contains(&node.op_type.as_str()) {
+                return Err(Error::UnsupportedOp(format!(
+                    "{} (node '{}')",
+                    node.op_type, node.name
+                )));
+            }
+        }
+        let mut initializers = HashMap::new();
+        for tensor in &model.graph.initializers {
+            initializers.insert(tensor.name.clone(), tensor.to_ndarray()?);
+        }
+        Ok(OnnxModel {
+            model,
+            initializers,
+        })
+    }
+
+    /// Runs the graph and returns its outputs in declaration order.
+    pub fn run_with(&self, inputs: &[(&str, &NdArray)]) -> Result<Vec<(String, NdArray)>> {
+        let mut values: HashMap<&str, NdArray> = HashMap::new();
+        for (name, array) in &self.initializers {
+            values.insert(name, array.clone());
+        }
+        for (name, array) in inputs {
+            values.insert(name, (*array).clone());
+        }
+        for name in self.input_names() {
+            if !values.contains_key(name.as_str()) {
+                return Err(Error::InvalidGraph(format!("missing input '{}'", name)));
+            }
+        }
+
+        for node in &self.model.graph.nodes {
+            let mut args = Vec::with_capacity(node.inputs.len());
+            for name in &node.inputs {
+                if name.is_empty() {
+                    args.push(None);
+                    continue;
+                }
+                let value = values
+                    .get(name.as_str())
+                    .ok_or_else(|| invalid(node, format!("missing value '{}'", name)))?;
+                args.push(Some(value));
+            }
+            let output = self.eval(node, &args)?;
+            if let Some(name) = node.outputs.first() {
+                values.insert(name, output);
+            }
+        }
+
+        self.model
+            .graph
+            .outputs
+            .iter()
+            .map(|name| {
+                values
+                    .remove(name.as_str())
+                    .map(|v| (name.clone(), v))
+                    .ok_or_else(|| {
+                        Error::InvalidGraph(format!("output '{}' was not computed", name))
+                    })
+            })
+            .collect()
+    }
+
+    fn eval(&self, node: &NodeProto, args: &[Option<&NdArray>]) -> Result<NdArray> {
+        let arg = |i: usize| {
+            args.get(i)
+                .copied()
+                .flatten()
+                .ok_or_else(|| invalid(node, format!("missing input {}", i)))
+        };
+        let res = match node.op_type.as_str() {
+            "Identity" | "Dropout" => arg(0)?.clone(),
+            "Constant" => node
+                .attribute("value")
+                .and_then(|a| a.t.as_ref())
+                .ok_or_else(|| unsupported(node, "only tensor values are supported"))?
+                .to_ndarray()?,
+            "Add" => add_broadcast(node, arg(0)?, arg(1)?)?,
+            "Relu" => relu(arg(0)?),
+            "Sigmoid" => sigmoid(arg(0)?),
+            "Tanh" => tanh(arg(0)?),
+            "MatMul" => matmul_nd(node, arg(0)?, arg(1)?)?,
+            "Gemm" => {
+                let mut a = arg(0)?.clone();
+                let mut b = arg(1)?.clone();
+                if attr_i(node, "transA", 0) != 0 {
+                    a = a.transpose();
+                }
+                if attr_i(node, "transB", 0) != 0 {
+                    b = b.transpose();
+                }
+                let mut y = matmul_nd(node, &a, &b)?;
+                let alpha = attr_f(node, "alpha", 1.0);
+                if alpha != 1.0 {
+                    y = y.mul_scalar(alpha);
+                }
+                match args.get(2).copied().flatten() {
+                    Some(c) => {
+                        let beta = attr_f(node, "beta", 1.0);
+                        let c = if beta != 1.0 {
+                            c.mul_scalar(beta)
+                        } else {
+                            c.clone()
+                        };
+                        add_broadcast(node, &y, &c)?
+                    }
+                    None => y,
+                }
+            }
+            "Softmax" => {
+                let x = arg(0)?;
+                let ndim = x.shape.len();
+                if self.model.opset_version >= 13 {
+                    softmax_axis(x, axis(node, attr_i(node, "axis", -1), ndim)?)
+                } else {
+                    // older opsets flatten everything from `axis` onwards
+                    let a = axis(node, attr_i(node, "axis", 1), ndim)?;
+                    let outer: usize = x.shape[..a].iter().product();
+                    let flat = reshape_to(x, &[outer, x.buffer.len() / outer.max(1)]);
+                    reshape_to(&softmax(&flat, None), &x.shape)
+                }
+            }
+            "Conv" => conv(node, arg(0)?, arg(1)?, args.get(2).copied().flatten())?,
+            "MaxPool" => max_pool(node, arg(0)?)?,
+            "Reshape" => reshape(node, arg(0)?, arg(1)?)?,
+            "Flatten" => {
+                let x = arg(0)?;
+                let ndim = x.shape.len();
+                // unlike other ops, `axis` may be equal to the rank here
+                let a = match attr_i(node, "axis", 1) {
+                    a if a == ndim as i64 => ndim,
+                    a => axis(node, a, ndim)?,
+                };
+                let outer: usize = x.shape[..a].iter().product();
+                reshape_to(x, &[outer, x.buffer.len() / outer.max(1)])
+            }
+            "Transpose" => {
+                let x = arg(0)?;
+                match attr_ints(node, "perm") {
+                    Some(perm) => {
+                        let perm: Vec<usize> = perm.iter().map(|p| *p as usize).collect();
+                        let mut sorted = perm.clone();
+                        sorted.sort_unstable();
+                        if sorted != (0..x.shape.len()).collect::<Vec<usize>>() {
+                            return Err(invalid(node, format!("invalid perm {:?}", perm)));
+                        }
+                        x.permute(&perm)
+                    }
+                    None => x.transpose(),
+                }
+            }
+            "Concat" => {
+                let first = arg(0)?;
+                let a = axis(node, attr_i(node, "axis", 0), first.shape.len())?;
+                let mut res = first.clone();
+                for i in 1..args.len() {
+                    let next = arg(i)?;
+                    if next.shape.len() != res.shape.len()
+                        || (0..res.shape.len()).any(|d| d != a && next.shape[d] != res.shape[d])
+                    {
+                        return Err(invalid(
+                            node,
+                            format!("cannot concat {:?} and {:?}", res.shape, next.shape),
+                        ));
+                    }
+                    res = concat_axis(&res, next, a);
+                }
+                res
+            }
+            op => return Err(Error::UnsupportedOp(op.to_string())),
+        };
+        Ok(res)
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl OnnxModel {
+    pub fn from(bytes: &[u8]) -> JsResult<OnnxModel> {
+        Ok(OnnxModel::parse(bytes)?)
+    }
+
+    /// Graph inputs that are not provided by initializers.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = inputNames))]
+    pub fn input_names(&self) -> Vec<String> {
+        self.model
+            .graph
+            .inputs
+            .iter()
+            .filter(|name| !self.initializers.contains_key(name.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = outputNames))]
+    pub fn output_names(&self) -> Vec<String> {
+        self.model.graph.outputs.clone()
+    }
+
+    /// Runs a single-input model and returns its first output.
+    pub fn run(&self, input: &NdArray) -> JsResult<NdArray> {
+        let names = self.input_names();
+        let name = names
+            .first()
+            .ok_or_else(|| Error::InvalidGraph("model has no inputs".to_string()))?;
+        let mut outputs = self.run_with(&[(name, input)])?;
+        Ok(outputs.remove(0).1)
+    }
+
+    /// Runs the model with named inputs and returns every named output.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = runNamed))]
+    pub fn run_named(&self, inputs: &StateDict) -> JsResult<StateDict> {
+        let mut res = StateDict::new();
+        for (name, array) in self.run_with(&inputs.entries())? {
+            res.insert(&name, &array);
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+fn pb_key(out: &mut Vec<u8>, field: u64, wire: u64) {
+    pb_varint(out, (field << 3) | wire);
+}
+
+#[cfg(test)]
+fn pb_varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+#[cfg(test)]
+fn pb_bytes(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    pb_key(out, field, 2);
+    pb_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+#[cfg(test)]
+fn pb_tensor(name: &str, dims: &[usize], data: &[f32]) -> Vec<u8> {
+    let mut res = Vec::new();
+    for d in dims {
+        pb_key(&mut res, 1, 0);
+        pb_varint(&mut res, *d as u64);
+    }
+    pb_key(&mut res, 2, 0);
+    pb_varint(&mut res, 1);
+    pb_bytes(&mut res, 8, name.as_bytes());
+    let raw: Vec<u8> = data.iter().flat_map(|x| x.to_le_bytes()).collect();
+    pb_bytes(&mut res, 9, &raw);
+    res
+}
+
+#[cfg(test)]
+fn pb_attr_ints(name: &str, ints: &[i64]) -> Vec<u8> {
+    let mut res = Vec::new();
+    pb_bytes(&mut res, 1, name.as_bytes());
+    for i in ints {
+        pb_key(&mut res, 8, 0);
+        pb_varint(&mut res, *i as u64);
+    }
+    res
+}
+
+#[cfg(test)]
+fn pb_attr_int(name: &str, i: i64) -> Vec<u8> {
+    let mut res = Vec::new();
+    pb_bytes(&mut res, 1, name.as_bytes());
+    pb_key(&mut res, 3, 0);
+    pb_varint(&mut res, i as u64);
+    res
+}
+
+#[cfg(test)]
+fn pb_node(op: &str, inputs: &[&str], outputs: &[&str], attrs: &[Vec<u8>]) -> Vec<u8> {
+    let mut res = Vec::new();
+    for i in inputs {
+        pb_bytes(&mut res, 1, i.as_bytes());
+    }
+    for o in outputs {
+        pb_bytes(&mut res, 2, o.as_bytes());
+    }
+    pb_bytes(&mut res, 4, op.as_bytes());
+    for a in attrs {
+        pb_bytes(&mut res, 5, a);
+    }
+    res
+}
+
+/// Encodes a model with the given nodes, initializers and graph inputs and
+/// outputs.
+#[cfg(test)]
+fn pb_model(nodes: &[Vec<u8>], inits: &[Vec<u8>], inputs: &[&str], outputs: &[&str]) -> Vec<u8> {
+    let value_info = |name: &str| {
+        let mut res = Vec::new();
+        pb_bytes(&mut res, 1, name.as_bytes());
+        res
+    };
+    let mut graph = Vec::new();
+    for n in nodes {
+        pb_bytes(&mut graph, 1, n);
+    }
+    pb_bytes(&mut graph, 2, b"test");
+    for t in inits {
+        pb_bytes(&mut graph, 5, t);
+    }
+    for i in inputs {
+        pb_bytes(&mut graph, 11, &value_info(i));
+    }
+    for o in outputs {
+        pb_bytes(&mut graph, 12, &value_info(o));
+    }
+    let mut opset = Vec::new();
+    pb_key(&mut opset, 2, 0);
+    pb_varint(&mut opset, 13);
+    let mut res = Vec::new();
+    pb_key(&mut res, 1, 0);
+    pb_varint(&mut res, 8);
+    pb_bytes(&mut res, 7, &graph);
+    pb_bytes(&mut res, 8, &opset);
+    res
+}
+
+#[test]
+fn test_onnx_mlp() {
+    let w1 = [1., -1., 0.5, 2., 0., 1.];
+    let b1 = [0.5, -0.5];
+    let w2 = [1., 0., 0., 1.];
+    let bytes = pb_model(
+        &[
+            pb_node(
+                "Gemm",
+                &["x", "w1", "b1"],
+                &["h"],
+                &[pb_attr_int("transB", 1)],
+            ),
+            pb_node("Relu", &["h"], &["r"], &[]),
+            pb_node("MatMul", &["r", "w2"], &["z"], &[]),
+            pb_node("Softmax", &["z"], &["y"], &[]),
+        ],
+        &[
+            pb_tensor("w1", &[2, 3], &w1),
+            pb_tensor("b1", &[2], &b1),
+            pb_tensor("w2", &[2, 2], &w2),
+        ],
+        &["x", "w1", "b1", "w2"],
+        &["y"],
+    );
+    let model = OnnxModel::parse(&bytes).unwrap();
+    assert_eq!(model.input_names(), vec!["x"]);
+    assert_eq!(model.output_names(), vec!["y"]);
+
+    let x = NdArray::from(&[1., 2., 3.], Some(vec![1, 3]), None);
+    let y = model.run_with(&[("x", &x)]).unwrap().remove(0).1;
+    // h = [1 - 2 + 1.5 + 0.5, 2 + 0 + 3 - 0.5] = [1, 4.5]
+    let (a, b) = (1f32.exp(), 4.5f32.exp());
+    assert_eq!(y.shape, vec![1, 2]);
+    assert!((y.buffer[0] - a / (a + b)).abs() < 1e-6);
+    assert!((y.buffer[1] - b / (a + b)).abs() < 1e-6);
+}
+
+#[test]
+fn test_onnx_conv() {
+    let x = NdArray::arange(0, 2 * 2 * 4 * 5, None).reshape(&[2, 2, 4, 5]);
+    let w = NdArray::rand_between(&[3, 2, 3, 2], -1., 1.);
+    let bias = [0.5, -1., 2.];
+    let (stride, pad) = (2, 1);
+    let bytes = pb_model(
+        &[pb_node(
+            "Conv",
+            &["x", "w", "b"],
+            &["y"],
+            &[
+                pb_attr_ints("strides", &[stride, stride]),
+                pb_attr_ints("pads", &[pad, pad, pad, pad]),
+            ],
+        )],
+        &[
+            pb_tensor("w", &w.shape, &w.buffer),
+            pb_tensor("b", &[3], &bias),
+        ],
+        &["x"],
+        &["y"],
+    );
+    let y = OnnxModel::parse(&bytes).unwrap().run(&x).unwrap();
+    // (4 + 2 - 3) / 2 + 1 = 2, (5 + 2 - 2) / 2 + 1 = 3
+    assert_eq!(y.shape, vec![2, 3, 2, 3]);
+
+    let (stride, pad) = (stride as usize, pad as isize);
+    let mut expected = Vec::new();
+    for n in 0..2 {
+        for (m, bias) in bias.iter().enumerate() {
+            for r in 0..2 {
+                for c in 0..3 {
+                    let mut acc = *bias;
+                    for ch in 0..2 {
+                        for i in 0..3 {
+                            for j in 0..2 {
+                                let y = (r * stride + i) as isize - pad;
+                                let x_ = (c * stride + j) as isize - pad;
+                                if y < 0 || x_ < 0 || y >= 4 || x_ >= 5 {
+                                    continue;
+                                }
+                                let xv =
+                                    x.buffer[((n * 2 + ch) * 4 + y as usize) * 5 + x_ as usize];
+                                acc += xv * w.buffer[((m * 2 + ch) * 3 + i) * 2 + j];
+                            }
+                        }
+                    }
+                    expected.push(acc);
+                }
+            }
+        }
+    }
+    for (a, b) in y.buffer.iter().zip(&expected) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+}
+
+#[test]
+fn test_onnx_shape_ops() {
+    let bytes = pb_model(
+        &[
+            pb_node("Reshape", &["x", "shape"], &["r"], &[]),
+            pb_node(
+                "Transpose",
+                &["r"],
+                &["t"],
+                &[pb_attr_ints("perm", &[0, 2, 1])],
+            ),
+            pb_node("Concat", &["t", "t"], &["c"], &[pb_attr_int("axis", -1)]),
+            pb_node("Flatten", &["c"], &["y"], &[]),
+        ],
+        &[pb_tensor("shape", &[3], &[0., 3., -1.])],
+        &["x"],
+        &["y"],
+    );
+    let x = NdArray::arange(0, 12, None).reshape(&[2, 6]);
+    let y = OnnxModel::parse(&bytes).unwrap().run(&x).unwrap();
+    assert_eq!(y.shape, vec![2, 12]);
+    assert_eq!(
+        &y.buffer[..12],
+        &[0., 2., 4., 0., 2., 4., 1., 3., 5., 1., 3., 5.]
+    );
+}
+
+#[test]
+fn test_onnx_invalid_attributes() {
+    let x = NdArray::ones(&[1, 1, 4, 4]);
+    let w = NdArray::ones(&[2, 1, 2, 2]);
+    let run = |node: Vec<u8>, inits: &[Vec<u8>]| {
+        let bytes = pb_model(&[node], inits, &["x"], &["y"]);
+        OnnxModel::parse(&bytes).unwrap().run_with(&[("x", &x)])
+    };
+    let conv = |attrs: &[Vec<u8>], bias: &[f32]| {
+        run(
+            pb_node("Conv", &["x", "w", "b"], &["y"], attrs),
+            &[
+                pb_tensor("w", &w.shape, &w.buffer),
+                pb_tensor("b", &[bias.len()], bias),
+            ],
+        )
+    };
+    assert!(conv(&[], &[0., 0.]).is_ok());
+    for attrs in [
+        pb_attr_ints("strides", &[2]),
+        pb_attr_ints("strides", &[0, 0]),
+        pb_attr_ints("pads", &[-1, 0, 0, 0]),
+    ] {
+        assert!(matches!(
+            conv(&[attrs], &[0., 0.]),
+            Err(Error::InvalidGraph(_))
+        ));
+    }
+    assert!(matches!(conv(&[], &[0.]), Err(Error::InvalidGraph(_))));
+
+    let pool = |kernel: &[i64]| {
+        run(
+            pb_node(
+                "MaxPool",
+                &["x"],
+                &["y"],
+                &[pb_attr_ints("kernel_shape", kernel)],
+            ),
+            &[],
+        )
+    };
+    assert_eq!(pool(&[2, 2]).unwrap()[0].1.shape, vec![1, 1, 3, 3]);
+    for kernel in [&[2][..], &[-2, 2], &[0, 2]] {
+        assert!(matches!(pool(kernel), Err(Error::InvalidGraph(_))));
+    }
+}
+
+#[test]
+fn test_onnx_invalid_dims() {
+    // a dim of -1, and dims whose product overflows
+    for dims in [&[usize::MAX, 1][..], &[1 << 40, 1 << 40]] {
+        let bytes = pb_model(&[], &[pb_tensor("w", dims, &[])], &["x"], &["x"]);
+        assert!(matches!(OnnxModel::parse(&bytes), Err(Error::Format(_))));
+    }
+}
+
+#[test]
+fn test_onnx_unsupported() {
+    let bytes = pb_model(&[pb_node("LSTM", &["x"], &["y"], &[])], &[], &["x"], &["y"]);
+    match OnnxModel::parse(&bytes) {
+        Err(Error::UnsupportedOp(msg)) => assert!(msg.starts_with("LSTM")),
+        _ => panic!("expected an unsupported operator error"),
+    }
+    assert!(OnnxModel::parse(&[0xff]).is_err());
+
+    // mixed strides run only while the window can't move along H
+    let x = NdArray::ones(&[1, 1, 1, 4]);
+    let conv = |pads: &[i64]| {
+        let node = pb_node(
+            "Conv",
+            &["x", "w"],
+            &["y"],
+            &[pb_attr_ints("strides", &[1, 2]), pb_attr_ints("pads", pads)],
+        );
+        let bytes = pb_model(
+            &[node],
+            &[pb_tensor("w", &[1, 1, 1, 1], &[1.])],
+            &["x"],
+            &["y"],
+        );
+        OnnxModel::parse(&bytes).unwrap().run_with(&[("x", &x)])
+    };
+    assert_eq!(conv(&[0, 0, 0, 0]).unwrap()[0].1.shape, vec![1, 1, 1, 2]);
+    assert!(matches!(conv(&[1, 0, 1, 0]), Err(Error::UnsupportedOp(_))));
+}
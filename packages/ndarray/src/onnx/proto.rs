@@ -0,0 +1,421 @@
+//! Decoder for the subset of the ONNX protobuf schema the runtime needs.
+//!
+//! Field numbers follow `onnx.proto3`; unknown fields are skipped.
+
+use std::convert::TryFrom;
+
+use half::{bf16, f16};
+
+use crate::{
+    error::{Error, Result},
+    ndarray::NdArray,
+};
+
+enum Wire<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Reader { buf, pos: 0 }
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut res = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self
+                .buf
+                .get(self.pos)
+                .ok_or_else(|| Error::format("truncated protobuf varint"))?;
+            self.pos += 1;
+            res |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(res);
+            }
+        }
+        Err(Error::format("protobuf varint is too long"))
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.buf.len() - self.pos < n {
+            return Err(Error::format("truncated protobuf message"));
+        }
+        let res = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(res)
+    }
+
+    fn field(&mut self) -> Result<Option<(u64, Wire<'a>)>> {
+        if self.pos >= self.buf.len() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let wire = match key & 7 {
+            0 => Wire::Varint(self.varint()?),
+            1 => {
+                let mut b = [0u8; 8];
+                b.copy_from_slice(self.take(8)?);
+                Wire::Fixed64(u64::from_le_bytes(b))
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                Wire::Bytes(self.take(len)?)
+            }
+            5 => {
+                let b = self.take(4)?;
+                Wire::Fixed32(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            }
+            t => {
+                return Err(Error::format(format!(
+                    "unsupported protobuf wire type {}",
+                    t
+                )))
+            }
+        };
+        Ok(Some((key >> 3, wire)))
+    }
+}
+
+fn string(wire: Wire) -> Result<String> {
+    match wire {
+        Wire::Bytes(b) => String::from_utf8(b.to_vec())
+            .map_err(|_| Error::format("protobuf string is not valid utf-8")),
+        _ => Err(Error::format("expected a protobuf string")),
+    }
+}
+
+fn varint(wire: Wire) -> Result<u64> {
+    match wire {
+        Wire::Varint(v) => Ok(v),
+        _ => Err(Error::format("expected a protobuf varint")),
+    }
+}
+
+/// Appends a repeated varint field, accepting both packed and unpacked
+/// encodings.
+fn push_varints(wire: Wire, out: &mut Vec<i64>) -> Result<()> {
+    match wire {
+        Wire::Varint(v) => out.push(v as i64),
+        Wire::Bytes(b) => {
+            let mut reader = Reader::new(b);
+            while reader.pos < b.len() {
+                out.push(reader.varint()? as i64);
+            }
+        }
+        _ => return Err(Error::format("expected repeated varints")),
+    }
+    Ok(())
+}
+
+fn push_f32s(wire: Wire, out: &mut Vec<f32>) -> Result<()> {
+    match wire {
+        Wire::Fixed32(v) => out.push(f32::from_bits(v)),
+        Wire::Bytes(b) => out.extend(
+            b.chunks_exact(4)
+                .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])),
+        ),
+        _ => return Err(Error::format("expected repeated floats")),
+    }
+    Ok(())
+}
+
+fn push_f64s(wire: Wire, out: &mut Vec<f64>) -> Result<()> {
+    match wire {
+        Wire::Fixed64(v) => out.push(f64::from_bits(v)),
+        Wire::Bytes(b) => out.extend(b.chunks_exact(8).map(|c| {
+            let mut b = [0u8; 8];
+            b.copy_from_slice(c);
+            f64::from_le_bytes(b)
+        })),
+        _ => return Err(Error::format("expected repeated doubles")),
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TensorProto {
+    pub name: String,
+    pub dims: Vec<i64>,
+    pub data_type: i64,
+    float_data: Vec<f32>,
+    int32_data: Vec<i64>,
+    int64_data: Vec<i64>,
+    double_data: Vec<f64>,
+    raw_data: Vec<u8>,
+}
+
+impl TensorProto {
+    fn decode(buf: &[u8]) -> Result<Self> {
+        let mut res = TensorProto::default();
+        let mut reader = Reader::new(buf);
+        while let Some((field, wire)) = reader.field()? {
+            match field {
+                1 => push_varints(wire, &mut res.dims)?,
+                2 => res.data_type = varint(wire)? as i64,
+                4 => push_f32s(wire, &mut res.float_data)?,
+                5 => push_varints(wire, &mut res.int32_data)?,
+                7 => push_varints(wire, &mut res.int64_data)?,
+                8 => res.name = string(wire)?,
+                9 => {
+                    if let Wire::Bytes(b) = wire {
+                        res.raw_data = b.to_vec();
+                    }
+                }
+                10 => push_f64s(wire, &mut res.double_data)?,
+                _ => {}
+            }
+        }
+        Ok(res)
+    }
+
+    /// Converts the tensor to an `f32` `NdArray`, whatever its stored type.
+    pub fn to_ndarray(&self) -> Result<NdArray> {
+        let bad_dims = || {
+            Error::format(format!(
+                "invalid dims {:?} in tensor '{}'",
+                self.dims, self.name
+            ))
+        };
+        let shape = self
+            .dims
+            .iter()
+            .map(|d| usize::try_from(*d).map_err(|_| bad_dims()))
+            .collect::<Result<Vec<usize>>>()?;
+        let numel = shape
+            .iter()
+            .try_fold(1usize, |n, d| n.checked_mul(*d))
+            .ok_or_else(bad_dims)?;
+        let raw = &self.raw_data;
+        let buffer: Vec<f32> = match self.data_type {
+            // FLOAT
+            1 if !raw.is_empty() => raw
+                .chunks_exact(4)
+                .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                .collect(),
+            1 => self.float_data.clone(),
+            // UINT8, BOOL
+            2 | 9 if !raw.is_empty() => raw.iter().map(|x| *x as f32).collect(),
+            // INT8
+            3 if !raw.is_empty() => raw.iter().map(|x| *x as i8 as f32).collect(),
+            // UINT16, INT16
+            4 | 5 if !raw.is_empty() => raw
+                .chunks_exact(2)
+                .map(|c| {
+                    if self.data_type == 4 {
+                        u16::from_le_bytes([c[0], c[1]]) as f32
+                    } else {
+                        i16::from_le_bytes([c[0], c[1]]) as f32
+                    }
+                })
+                .collect(),
+            // INT32
+            6 if !raw.is_empty() => raw
+                .chunks_exact(4)
+                .map(|c| i32::from_le_bytes([c[0], c[1], c[2], c[3]]) as f32)
+                .collect(),
+            2..=6 | 9 => self.int32_data.iter().map(|x| *x as i32 as f32).collect(),
+            // INT64
+            7 if !raw.is_empty() => raw
+                .chunks_exact(8)
+                .map(|c| {
+                    let mut b = [0u8; 8];
+                    b.copy_from_slice(c);
+                    i64::from_le_bytes(b) as f32
+                })
+                .collect(),
+            7 => self.int64_data.iter().map(|x| *x as f32).collect(),
+            // FLOAT16, BFLOAT16
+            10 | 16 => {
+                let bits: Vec<u16> = if raw.is_empty() {
+                    self.int32_data.iter().map(|x| *x as u16).collect()
+                } else {
+                    raw.chunks_exact(2)
+                        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                        .collect()
+                };
+                bits.iter()
+                    .map(|b| {
+                        if self.data_type == 10 {
+                            f16::from_bits(*b).to_f32()
+                        } else {
+                            bf16::from_bits(*b).to_f32()
+                        }
+                    })
+                    .collect()
+            }
+            // DOUBLE
+            11 if !raw.is_empty() => raw
+                .chunks_exact(8)
+                .map(|c| {
+                    let mut b = [0u8; 8];
+                    b.copy_from_slice(c);
+                    f64::from_le_bytes(b) as f32
+                })
+                .collect(),
+            11 => self.double_data.iter().map(|x| *x as f32).collect(),
+            t => {
+                return Err(Error::UnsupportedDType(format!(
+                    "onnx tensor data type {} ({})",
+                    t, self.name
+                )))
+            }
+        };
+        if buffer.len() != numel {
+            return Err(Error::format(format!(
+                "tensor {} has {} values, expected {}",
+                self.name,
+                buffer.len(),
+                numel
+            )));
+        }
+        Ok(NdArray::from(&buffer, Some(shape), None))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AttributeProto {
+    pub name: String,
+    pub f: f32,
+    pub i: i64,
+    pub s: Vec<u8>,
+    pub t: Option<TensorProto>,
+    pub floats: Vec<f32>,
+    pub ints: Vec<i64>,
+}
+
+impl AttributeProto {
+    fn decode(buf: &[u8]) -> Result<Self> {
+        let mut res = AttributeProto::default();
+        let mut reader = Reader::new(buf);
+        while let Some((field, wire)) = reader.field()? {
+            match (field, wire) {
+                (1, wire) => res.name = string(wire)?,
+                (2, Wire::Fixed32(v)) => res.f = f32::from_bits(v),
+                (3, wire) => res.i = varint(wire)? as i64,
+                (4, Wire::Bytes(b)) => res.s = b.to_vec(),
+                (5, Wire::Bytes(b)) => res.t = Some(TensorProto::decode(b)?),
+                (7, wire) => push_f32s(wire, &mut res.floats)?,
+                (8, wire) => push_varints(wire, &mut res.ints)?,
+                _ => {}
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct NodeProto {
+    pub name: String,
+    pub op_type: String,
+    pub domain: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub attributes: Vec<AttributeProto>,
+}
+
+impl NodeProto {
+    fn decode(buf: &[u8]) -> Result<Self> {
+        let mut res = NodeProto::default();
+        let mut reader = Reader::new(buf);
+        while let Some((field, wire)) = reader.field()? {
+            match (field, wire) {
+                (1, wire) => res.inputs.push(string(wire)?),
+                (2, wire) => res.outputs.push(string(wire)?),
+                (3, wire) => res.name = string(wire)?,
+                (4, wire) => res.op_type = string(wire)?,
+                (5, Wire::Bytes(b)) => res.attributes.push(AttributeProto::decode(b)?),
+                (7, wire) => res.domain = string(wire)?,
+                _ => {}
+            }
+        }
+        Ok(res)
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&AttributeProto> {
+        self.attributes.iter().find(|a| a.name == name)
+    }
+}
+
+/// Name of a `ValueInfoProto`; types and shapes are not needed.
+fn value_info_name(buf: &[u8]) -> Result<String> {
+    let mut reader = Reader::new(buf);
+    while let Some((field, wire)) = reader.field()? {
+        if field == 1 {
+            return string(wire);
+        }
+    }
+    Ok(String::new())
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GraphProto {
+    pub name: String,
+    pub nodes: Vec<NodeProto>,
+    pub initializers: Vec<TensorProto>,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+}
+
+impl GraphProto {
+    fn decode(buf: &[u8]) -> Result<Self> {
+        let mut res = GraphProto::default();
+        let mut reader = Reader::new(buf);
+        while let Some((field, wire)) = reader.field()? {
+            match (field, wire) {
+                (1, Wire::Bytes(b)) => res.nodes.push(NodeProto::decode(b)?),
+                (2, wire) => res.name = string(wire)?,
+                (5, Wire::Bytes(b)) => res.initializers.push(TensorProto::decode(b)?),
+                (11, Wire::Bytes(b)) => res.inputs.push(value_info_name(b)?),
+                (12, Wire::Bytes(b)) => res.outputs.push(value_info_name(b)?),
+                _ => {}
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ModelProto {
+    pub graph: GraphProto,
+    /// Version of the default (`ai.onnx`) operator set.
+    pub opset_version: i64,
+}
+
+impl ModelProto {
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        let mut res = ModelProto::default();
+        let mut graph = None;
+        let mut reader = Reader::new(buf);
+        while let Some((field, wire)) = reader.field()? {
+            match (field, wire) {
+                (7, Wire::Bytes(b)) => graph = Some(GraphProto::decode(b)?),
+                (8, Wire::Bytes(b)) => {
+                    let mut domain = String::new();
+                    let mut version = 0;
+                    let mut opset = Reader::new(b);
+                    while let Some((field, wire)) = opset.field()? {
+                        match field {
+                            1 => domain = string(wire)?,
+                            2 => version = varint(wire)? as i64,
+                            _ => {}
+                        }
+                    }
+                    if domain.is_empty() || domain == "ai.onnx" {
+                        res.opset_version = version;
+                    }
+                }
+                _ => {}
+            }
+        }
+        res.graph = graph.ok_or_else(|| Error::format("onnx model has no graph"))?;
+        Ok(res)
+    }
+}
@@ -1,17 +1,11 @@
 use std::{borrow::Borrow, vec};
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
-use crate::{ndarray::NdArray, utils::nd_idx_to_offset};
+use crate::{ndarray::NdArray, simd, utils::nd_idx_to_offset};
 
 
-#[wasm_bindgen]
-extern "C" {
-    #[wasm_bindgen(js_namespace = console)]
-    fn error(s: &str);
-}
-
 
-
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl NdArray {
     pub fn matmul(&self, b: &NdArray) -> NdArray {
         matmul(self, b)
@@ -21,7 +15,7 @@ impl NdArray {
         dot(self, b)
     }
 
-    #[wasm_bindgen(js_name = mulScalar)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = mulScalar))]
     pub fn mul_scalar(&self, b: f32) -> NdArray {
         mul_scalar(self, b)
     }
@@ -30,7 +24,7 @@ impl NdArray {
         add(self, b)
     }
 
-    #[wasm_bindgen(js_name = addScalar)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = addScalar))]
     pub fn add_scalar(&self, b: f32) -> NdArray {
         add_scalar(self, b)
     }
@@ -39,7 +33,7 @@ impl NdArray {
         sub(self, b)
     }
 
-    #[wasm_bindgen(js_name = subScalar)]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = subScalar))]
     pub fn sub_scalar(&self, b: f32) -> NdArray {
         sub_scalar(self, b)
     }
@@ -88,87 +82,188 @@ impl NdArray {
     }
 
     pub fn sum(&self) -> f32 {
-        self.buffer.iter().sum()
+        simd::sum(&self.buffer)
     }
 }
 
-#[wasm_bindgen]
+/// Element-wise op with NumPy-style broadcasting, or `None` when the
+/// shapes are incompatible.
+pub(crate) fn broadcast_with<F: Fn(f32, f32) -> f32>(
+    a: &NdArray,
+    b: &NdArray,
+    f: F,
+) -> Option<NdArray> {
+    let ndim = a.shape.len().max(b.shape.len());
+    let pad = |shape: &[usize]| {
+        let mut res = vec![1; ndim - shape.len()];
+        res.extend_from_slice(shape);
+        res
+    };
+    let (sa, sb) = (pad(&a.shape), pad(&b.shape));
+    let mut shape = Vec::with_capacity(ndim);
+    for (x, y) in sa.iter().zip(&sb) {
+        if x != y && *x != 1 && *y != 1 {
+            return None;
+        }
+        shape.push(*x.max(y));
+    }
+    let strides = |s: &[usize]| {
+        let mut res = vec![0; ndim];
+        let mut acc = 1;
+        for d in (0..ndim).rev() {
+            res[d] = if s[d] == 1 { 0 } else { acc };
+            acc *= s[d];
+        }
+        res
+    };
+    let (ta, tb) = (strides(&sa), strides(&sb));
+    let numel: usize = shape.iter().product();
+    let mut buffer = Vec::with_capacity(numel);
+    let mut index = vec![0; ndim];
+    let (mut oa, mut ob) = (0, 0);
+    for _ in 0..numel {
+        buffer.push(f(a.buffer[oa], b.buffer[ob]));
+        for d in (0..ndim).rev() {
+            index[d] += 1;
+            oa += ta[d];
+            ob += tb[d];
+            if index[d] < shape[d] {
+                break;
+            }
+            oa -= ta[d] * shape[d];
+            ob -= tb[d] * shape[d];
+            index[d] = 0;
+        }
+    }
+    Some(NdArray::from(&buffer, Some(shape), None))
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn matmul(a: &NdArray, b: &NdArray) -> NdArray {
     if a.shape.len() != b.shape.len() || a.shape.len() > 2 {
         todo!("Not implemented for matrices with more than 2 dimensions");
     }
-    let size = a.shape[0] * b.shape[1];
-    let mut buffer = Vec::with_capacity(size);
-    for i in 0..a.shape[0] {
-        for j in 0..b.shape[1] {
-            let mut sum = 0.0;
-            for k in 0..a.shape[1] {
-                sum += a.buffer[i * a.shape[1] + k] * b.buffer[k * b.shape[1] + j];
+    let m = a.shape.first().copied().unwrap_or(0);
+    let mut out = NdArray::zeros(&[m, b.shape.last().copied().unwrap_or(0)]);
+    crate::inplace::matmul_out(a, b, &mut out);
+    out
+}
+
+/// `batch` independent products of `[m, k]` by `[k, n]` matrices stored
+/// back to back. With `ta`/`tb` the operands are stored transposed, i.e. as
+/// `[k, m]` and `[n, k]`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn bmm(
+    a: &[f32],
+    b: &[f32],
+    batch: usize,
+    m: usize,
+    k: usize,
+    n: usize,
+    ta: bool,
+    tb: bool,
+) -> Vec<f32> {
+    let mut res = vec![0.; batch * m * n];
+    for (i, out) in res.chunks_mut((m * n).max(1)).enumerate().take(batch) {
+        let a = &a[i * m * k..(i + 1) * m * k];
+        let b = &b[i * k * n..(i + 1) * k * n];
+        for r in 0..m {
+            for p in 0..k {
+                let x = if ta { a[p * m + r] } else { a[r * k + p] };
+                let out = &mut out[r * n..(r + 1) * n];
+                if tb {
+                    for (c, o) in out.iter_mut().enumerate() {
+                        *o += x * b[c * k + p];
+                    }
+                } else {
+                    simd::axpy(out, x, &b[p * n..(p + 1) * n]);
+                }
             }
-            buffer.push(sum);
         }
     }
-    NdArray::from(&buffer, Some(vec![a.shape[0], b.shape[1]]), None)
+    res
+}
+
+/// Matrix product over the last two axes of `[..., M, K]` and `[..., K, N]`
+/// arrays whose leading axes are equal.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = batchMatmul))]
+pub fn batch_matmul(a: &NdArray, b: &NdArray) -> NdArray {
+    let (ra, rb) = (a.shape.len(), b.shape.len());
+    if ra < 2
+        || ra != rb
+        || a.shape[..ra - 2] != b.shape[..rb - 2]
+        || a.shape[ra - 1] != b.shape[rb - 2]
+    {
+        panic!("can't multiply shapes {:?} and {:?}", a.shape, b.shape);
+    }
+    let (m, k, n) = (a.shape[ra - 2], a.shape[ra - 1], b.shape[rb - 1]);
+    let batch = a.shape[..ra - 2].iter().product();
+    let mut shape = a.shape.clone();
+    shape[ra - 1] = n;
+    let buffer = bmm(&a.buffer, &b.buffer, batch, m, k, n, false, false);
+    NdArray::from(&buffer, Some(shape), None)
 }
 
-#[wasm_bindgen(js_name = mulScalar)]
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = mulScalar))]
 pub fn mul_scalar(a: &NdArray, b: f32) -> NdArray {
     let mut c = a.clone();
-    for i in 0..c.buffer.len() {
-        c.buffer[i] = a.buffer[i] * b;
-    }
+    simd::mul_scalar(&mut c.buffer, b);
     c
 }
 
-#[wasm_bindgen]
+/// Panics unless the operands of an element-wise `op` have the same shape.
+fn check_same_shape(op: &str, a: &NdArray, b: &NdArray) {
+    if a.shape != b.shape {
+        panic!(
+            "can't {} arrays of shapes {:?} and {:?} element-wise",
+            op, a.shape, b.shape
+        );
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn dot(a: &NdArray, b: &NdArray) -> NdArray {
+    check_same_shape("multiply", a, b);
     let mut c = a.clone();
-    for i in 0..c.buffer.len() {
-        c.buffer[i] = a.buffer[i] * b.buffer[i];
-    }
+    simd::mul(&mut c.buffer, &b.buffer);
     c
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn add(a: &NdArray, b: &NdArray) -> NdArray {
     let mut c = a.clone();
-    if a.buffer.len() % b.buffer.len() != 0 {
+    if !a.buffer.len().is_multiple_of(b.buffer.len()) {
         todo!("Not implemented for matrices with unequal shapes");
     }
-    for i in 0..c.buffer.len() {
-        c.buffer[i] = a.buffer[i] + b.buffer[i % b.buffer.len()];
+    for chunk in c.buffer.chunks_mut(b.buffer.len().max(1)) {
+        simd::add(chunk, &b.buffer);
     }
     c
 }
 
-#[wasm_bindgen(js_name = addScalar)]
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = addScalar))]
 pub fn add_scalar(a: &NdArray, b: f32) -> NdArray {
     let mut c = a.clone();
-    for i in 0..c.buffer.len() {
-        c.buffer[i] = a.buffer[i] + b;
-    }
+    simd::add_scalar(&mut c.buffer, b);
     c
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn sub(a: &NdArray, b: &NdArray) -> NdArray {
+    check_same_shape("subtract", a, b);
     let mut c = a.clone();
-    for i in 0..c.buffer.len() {
-        c.buffer[i] = a.buffer[i] - b.buffer[i];
-    }
+    simd::sub(&mut c.buffer, &b.buffer);
     c
 }
 
-#[wasm_bindgen(js_name = subScalar)]
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = subScalar))]
 pub fn sub_scalar(a: &NdArray, b: f32) -> NdArray {
     let mut c = a.clone();
-    for i in 0..c.buffer.len() {
-        c.buffer[i] = a.buffer[i] - b;
-    }
+    simd::sub_scalar(&mut c.buffer, b);
     c
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn log(a: &NdArray, base: f32) -> NdArray {
     let mut res = a.clone();
     for i in 0..res.buffer.len() {
@@ -177,7 +272,7 @@ pub fn log(a: &NdArray, base: f32) -> NdArray {
     res
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn ln(a: &NdArray) -> NdArray {
     let mut res = a.clone();
     for i in 0..res.buffer.len() {
@@ -186,25 +281,21 @@ pub fn ln(a: &NdArray) -> NdArray {
     res
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn exp(a: &NdArray) -> NdArray {
     let mut res = a.clone();
-    for i in 0..res.buffer.len() {
-        res.buffer[i] = f32::exp(res.buffer[i]);
-    }
+    simd::exp(&mut res.buffer);
     res
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn relu(a: &NdArray) -> NdArray {
     let mut res = a.clone();
-    for i in 0..res.buffer.len() {
-        res.buffer[i] = f32::max(0.0, res.buffer[i]);
-    }
+    simd::relu(&mut res.buffer);
     res
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn sigmoid(a: &NdArray) -> NdArray {
     let mut res = a.clone();
     for i in 0..res.buffer.len() {
@@ -213,7 +304,7 @@ pub fn sigmoid(a: &NdArray) -> NdArray {
     res
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn tanh(a: &NdArray) -> NdArray {
     let mut res = a.clone();
     for i in 0..res.buffer.len() {
@@ -223,11 +314,11 @@ pub fn tanh(a: &NdArray) -> NdArray {
 }
 
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn softmax(a: &NdArray, dim: Option<usize>) -> NdArray {
     fn softmax_internal(buffer: &mut [f32]) {
-        let sum  = buffer.iter().sum::<f32>();
-        buffer.iter_mut().for_each(|x| *x /= sum);
+        let sum = simd::sum(buffer);
+        simd::div_scalar(buffer, sum);
     }
 
     fn softmax_last_dim(buffer: &mut [f32],  chunk_size: usize) {
@@ -251,24 +342,24 @@ pub fn softmax(a: &NdArray, dim: Option<usize>) -> NdArray {
                     ofst += stride;
                 }
             }
-            return  res;
+            res
         } else {
            softmax_last_dim(&mut res.buffer, *res.shape.last().unwrap());
-           return  res;
+           res
         }
     } else {
         if a.shape.len() == 1 {
             softmax_internal(&mut res.buffer);
-            return  res;
+            res
         }else {
             // default: dim is -1
             softmax_last_dim(&mut res.buffer, *res.shape.last().unwrap());
-            return res;
+            res
         }
     }
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn pow(a: &NdArray, b: f32) -> NdArray {
     let mut res = a.clone();
     for i in 0..res.buffer.len() {
@@ -277,7 +368,7 @@ pub fn pow(a: &NdArray, b: f32) -> NdArray {
     res
 }
 
-#[wasm_bindgen(js_name = padY2D)]
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = padY2D))]
 pub fn pad_y_2d(a: &NdArray, size: usize, value: Option<f32>) -> NdArray {
     if size == 0 {
         return a.clone();
@@ -302,7 +393,7 @@ pub fn pad_y_2d(a: &NdArray, size: usize, value: Option<f32>) -> NdArray {
 /**
  * a: [H, W]
  */
-#[wasm_bindgen(js_name = padX1D)]
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = padX1D))]
 pub fn pad_x_1d(a: &NdArray, size: usize, value: Option<f32>) -> NdArray {
     if size == 0 {
         return a.clone();
@@ -320,7 +411,7 @@ pub fn pad_x_1d(a: &NdArray, size: usize, value: Option<f32>) -> NdArray {
     NdArray::from(&buffer, Some(shape), None)
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn im2col(
     x: &NdArray,
     kernel_size: &[usize],
@@ -332,13 +423,12 @@ pub fn im2col(
         panic!("padding.length must be 0 or 2")
     }
     let mut x = x.to_owned();
-    if let Some(&px) = padding.get(0) {
+    if let Some(&px) = padding.first() {
         x = pad_x_1d(&x, px, pad_value);
     }
     // if let Some(&py) = padding.get(1) {
     //     x = pad_y_1d(&x, py, pad_value);
     // }
-    dbg!(&x.shape);
     let mut res = Vec::new();
     let chunk_size = kernel_size.iter().product::<usize>() * x.shape.last().unwrap();
     match x.shape.len() {
@@ -347,7 +437,7 @@ pub fn im2col(
             let mut cnt = 0;
             let step = stride * x.shape[1];
             // dbg!(&step);
-            for i in 0..x.buffer.len() / step {
+            for i in 0..x.buffer.len().div_ceil(step) {
                 let offset = i * step;
                 if offset + chunk_size > x.buffer.len() {
                     continue;
@@ -365,17 +455,16 @@ pub fn im2col(
             //      x: [H, W, C]
             //      k: [k, k]
             let mut cnt = 0;
-            for i in 0..x.shape[0] / stride {
+            for i in 0..x.shape[0].div_ceil(stride) {
                 let r = i * stride;
                 if r + kernel_size[0] > x.shape[0] {
                     continue;
                 }
-                for j in 0..x.shape[1] / stride {
+                for j in 0..x.shape[1].div_ceil(stride) {
                     let c = j * stride;
                     if c + kernel_size[1] > x.shape[1] {
                         continue;
                     }
-                    println!("r:{}, c:{}", r, c);
                     let mut chunk = Vec::new();
                     for k in 0..kernel_size[0] {
                         let start_ofst = nd_idx_to_offset(&[r + k, c, 0], &x.strides);
@@ -408,6 +497,12 @@ fn test_add() {
     assert_eq!(c.buffer, vec![0., 1., 2., 3., 0., 1., 2., 3.]);
 }
 
+#[test]
+#[should_panic(expected = "can't subtract arrays of shapes [2] and [3] element-wise")]
+fn test_sub_shape_mismatch() {
+    sub(&NdArray::zeros(&[2]), &NdArray::zeros(&[3]));
+}
+
 #[test]
 fn test_matmul() {
     let a = NdArray::from(&[1., 2., 3., 4., 5., 6.], Some(vec![2, 3]), None);
@@ -417,19 +512,35 @@ fn test_matmul() {
     assert_eq!(c.buffer, vec![22., 28., 49., 64.]);
 }
 
+#[test]
+fn test_batch_matmul() {
+    let a = NdArray::arange(0, 12, None).reshape(&[2, 2, 3]);
+    let b = NdArray::arange(0, 12, None).reshape(&[2, 3, 2]);
+    let c = batch_matmul(&a, &b);
+    assert_eq!(c.shape, vec![2, 2, 2]);
+    assert_eq!(c.buffer, vec![10., 13., 28., 40., 172., 193., 244., 274.]);
+}
+
 #[test]
 fn test_softmax() {
+    #[cfg(not(feature = "simd"))]
+    let close = |a: &[f32], b: &[f32]| assert_eq!(a, b);
+    // the simd kernels round differently in the last place
+    #[cfg(feature = "simd")]
+    let close = |a: &[f32], b: &[f32]| {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b) {
+            assert!((x - y).abs() <= 4. * f32::EPSILON * y, "{:?} vs {:?}", a, b);
+        }
+    };
     let a = NdArray::from(&[1., 2., 3., 4.], Some(vec![4]), None);
     let b = softmax(&a, None);
-    assert_eq!(
-        b.buffer,
-        vec![0.032058604, 0.08714432, 0.23688282, 0.6439142]
-    );
+    close(&b.buffer, &[0.032058604, 0.08714432, 0.23688282, 0.6439142]);
     let c = a.reshape(&[2, 2]);
     let d = softmax(&c, Some(0));
-    assert_eq!(d.buffer, vec![0.11920293, 0.11920293, 0.88079715, 0.8807971]);
+    close(&d.buffer, &[0.11920293, 0.11920293, 0.88079715, 0.8807971]);
     let e = softmax(&c, Some(1));
-    assert_eq!(e.buffer, vec![0.2689414, 0.7310586, 0.26894143, 0.7310586]);
+    close(&e.buffer, &[0.2689414, 0.7310586, 0.26894143, 0.7310586]);
 }
 
 // #[test]
@@ -483,6 +594,13 @@ fn test_im2col() {
             13.0, 14.0
         ]
     );
+    // -------- stride not dividing the input keeps the last window
+    let col = im2col(&seq, &[1], 2, &[0], None);
+    assert_eq!(col.shape, vec![3, 3]);
+    assert_eq!(
+        col.buffer,
+        vec![0.0, 1.0, 2.0, 6.0, 7.0, 8.0, 12.0, 13.0, 14.0]
+    );
     // -------- padding
     // let col = im2col(&seq, &[3], 2, &[1], None);
     // assert_eq!(col.shape, vec![3, 9]);
@@ -501,4 +619,12 @@ fn test_im2col() {
     
     let col = im2col(&img, &[3, 3], 2, &[0, 0], None);
     assert_eq!(col.shape, vec![13 * 13, 3 * 3 * 3]);
+
+    let img = NdArray::arange(0, 5 * 5, None).reshape(&[5, 5, 1]);
+    let col = im2col(&img, &[1, 1], 2, &[0, 0], None);
+    assert_eq!(col.shape, vec![3 * 3, 1]);
+    assert_eq!(
+        col.buffer,
+        vec![0.0, 2.0, 4.0, 10.0, 12.0, 14.0, 20.0, 22.0, 24.0]
+    );
 }
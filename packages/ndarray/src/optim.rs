@@ -0,0 +1,896 @@
+//! Optimizers updating parameters in place.
+//!
+//! Parameters are identified by an index chosen by the caller, usually
+//! their position in the model's parameter list; the optimizer keeps its
+//! per-parameter state (step count, momentum buffers, moment estimates)
+//! under that index:
+//!
+//! ```ignore
+//! let mut opt = Adam::new(Some(1e-3), None, None, None, None, None);
+//! for (i, (param, grad)) in params.iter_mut().zip(&grads).enumerate() {
+//!     opt.step(i as u32, param, grad);
+//! }
+//! ```
+//!
+//! `state_dict` exports that state as a `StateDict` with entries named
+//! `"<index>.step"` and `"<index>.<buffer>"`, using PyTorch's buffer names.
+//! A step count is stored as its high and low 16 bits, `[step >> 16,
+//! step & 0xffff]`, since `f32` only holds integers exactly up to 2^24.
+//! Hyperparameters aren't included; they come from the constructor and the
+//! `lr` setter. `load_state_dict` also takes the parameters, in index order,
+//! and rejects state whose buffers don't match them.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use std::collections::BTreeMap;
+
+use crate::error::{Error, JsResult};
+use crate::ndarray::NdArray;
+use crate::serialization::StateDict;
+
+/// State of one parameter.
+#[derive(Clone, Debug, Default)]
+struct Slot {
+    step: u32,
+    buffers: BTreeMap<String, NdArray>,
+}
+
+impl Slot {
+    /// Removes buffer `name`, or creates it filled with `init`. Put it back
+    /// with `put` once updated.
+    fn take(&mut self, name: &str, param: &NdArray, init: f32) -> NdArray {
+        match self.buffers.remove(name) {
+            Some(buffer) => {
+                if buffer.shape != param.shape {
+                    panic!(
+                        "optimizer state {} of shape {:?} doesn't match parameter of shape {:?}",
+                        name, buffer.shape, param.shape
+                    );
+                }
+                buffer
+            }
+            None => NdArray::from(
+                &vec![init; param.buffer.len()],
+                Some(param.shape.clone()),
+                None,
+            ),
+        }
+    }
+
+    fn put(&mut self, name: &str, buffer: NdArray) {
+        self.buffers.insert(name.to_string(), buffer);
+    }
+}
+
+/// Per-parameter states keyed by parameter index.
+#[derive(Clone, Debug, Default)]
+struct States {
+    slots: BTreeMap<u32, Slot>,
+}
+
+impl States {
+    fn to_state_dict(&self) -> StateDict {
+        let mut dict = StateDict::new();
+        for (index, slot) in &self.slots {
+            dict.insert(
+                &format!("{}.step", index),
+                &NdArray::from(
+                    &[(slot.step >> 16) as f32, (slot.step & 0xffff) as f32],
+                    None,
+                    None,
+                ),
+            );
+            for (name, buffer) in &slot.buffers {
+                dict.insert(&format!("{}.{}", index, name), buffer);
+            }
+        }
+        dict
+    }
+
+    /// Parses a `to_state_dict` export, checking it against the optimizer's
+    /// `buffers` and against `params`, the parameters in index order: every
+    /// slot needs a step count, exactly those buffers, each shaped like its
+    /// parameter.
+    fn from_state_dict(
+        dict: &StateDict,
+        buffers: &[&str],
+        params: &StateDict,
+    ) -> crate::error::Result<Self> {
+        let mut slots = BTreeMap::<u32, (Option<u32>, Slot)>::new();
+        for (name, array) in dict.entries() {
+            let (index, buffer) = name
+                .split_once('.')
+                .and_then(|(i, b)| Some((i.parse::<u32>().ok()?, b)))
+                .ok_or_else(|| {
+                    Error::format(format!("unexpected optimizer state entry {:?}", name))
+                })?;
+            let (step, slot) = slots.entry(index).or_default();
+            if buffer == "step" {
+                let half = |x: f32| (0. ..=65535.).contains(&x) && x.fract() == 0.;
+                *step = match array.buffer.as_slice() {
+                    [hi, lo] if half(*hi) && half(*lo) => Some((*hi as u32) << 16 | *lo as u32),
+                    _ => return Err(Error::format(format!("invalid step count in {:?}", name))),
+                };
+            } else {
+                slot.put(buffer, array.clone());
+            }
+        }
+
+        let params = params.entries();
+        let invalid = |msg: String| Error::InvalidArgument(msg);
+        let mut res = States::default();
+        for (index, (step, mut slot)) in slots {
+            let (_, param) = params.get(index as usize).ok_or_else(|| {
+                invalid(format!(
+                    "optimizer state for parameter {}, but only {} parameters given",
+                    index,
+                    params.len()
+                ))
+            })?;
+            slot.step = step
+                .ok_or_else(|| invalid(format!("optimizer state is missing \"{}.step\"", index)))?;
+            if let Some(name) = buffers.iter().find(|b| !slot.buffers.contains_key(**b)) {
+                return Err(invalid(format!(
+                    "optimizer state is missing \"{}.{}\"",
+                    index, name
+                )));
+            }
+            for (name, buffer) in &slot.buffers {
+                if !buffers.contains(&name.as_str()) {
+                    return Err(invalid(format!(
+                        "unexpected optimizer state entry \"{}.{}\"",
+                        index, name
+                    )));
+                }
+                if buffer.shape != param.shape {
+                    return Err(invalid(format!(
+                        "optimizer state \"{}.{}\" of shape {:?} doesn't match parameter of shape {:?}",
+                        index, name, buffer.shape, param.shape
+                    )));
+                }
+            }
+            res.slots.insert(index, slot);
+        }
+        Ok(res)
+    }
+
+    /// Runs `update` on the state of parameter `index` with its step count
+    /// already incremented.
+    fn step<F: FnOnce(&mut Slot, &mut NdArray, &NdArray)>(
+        &mut self,
+        index: u32,
+        param: &mut NdArray,
+        grad: &NdArray,
+        update: F,
+    ) {
+        if param.shape != grad.shape {
+            panic!(
+                "grad of shape {:?} doesn't match parameter of shape {:?}",
+                grad.shape, param.shape
+            );
+        }
+        let slot = self.slots.entry(index).or_default();
+        slot.step += 1;
+        update(slot, param, grad);
+    }
+}
+
+/// `grad + weight_decay * param`, the L2-penalized gradient.
+fn decayed(grad: &NdArray, param: &NdArray, weight_decay: f32) -> Vec<f32> {
+    if weight_decay == 0. {
+        return grad.buffer.clone();
+    }
+    grad.buffer
+        .iter()
+        .zip(&param.buffer)
+        .map(|(g, p)| g + weight_decay * p)
+        .collect()
+}
+
+/// Adds the methods every optimizer shares: the `lr` property, `step`
+/// (dispatching to the optimizer's `update`), and state (de)serialization.
+macro_rules! optimizer_methods {
+    ($name:ident) => {
+        #[cfg_attr(feature = "wasm", wasm_bindgen)]
+        impl $name {
+            /// Learning rate, adjustable between steps, e.g. by a scheduler.
+            #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+            pub fn lr(&self) -> f32 {
+                self.lr
+            }
+
+            #[cfg_attr(feature = "wasm", wasm_bindgen(setter))]
+            pub fn set_lr(&mut self, lr: f32) {
+                self.lr = lr;
+            }
+
+            /// Updates `param` in place from its gradient. `index`
+            /// identifies the parameter's state across steps.
+            pub fn step(&mut self, index: u32, param: &mut NdArray, grad: &NdArray) {
+                let mut states = std::mem::take(&mut self.states);
+                states.step(index, param, grad, |slot, param, grad| {
+                    self.update(slot, param, grad)
+                });
+                self.states = states;
+            }
+
+            /// Number of steps taken for parameter `index`.
+            #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = stepCount))]
+            pub fn step_count(&self, index: u32) -> u32 {
+                self.states.slots.get(&index).map_or(0, |s| s.step)
+            }
+
+            /// Drops the state of every parameter.
+            pub fn reset(&mut self) {
+                self.states.slots.clear();
+            }
+
+            #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = stateDict))]
+            pub fn state_dict(&self) -> StateDict {
+                self.states.to_state_dict()
+            }
+
+            /// Replaces the per-parameter state with one saved by
+            /// `state_dict`. `params` holds the parameters in index order
+            /// (only their shapes are read); the state must have exactly the
+            /// buffers this optimizer keeps, each shaped like its parameter.
+            #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = loadStateDict))]
+            pub fn load_state_dict(
+                &mut self,
+                state: &StateDict,
+                params: &StateDict,
+            ) -> JsResult<()> {
+                self.states = States::from_state_dict(state, &self.buffer_names(), params)?;
+                Ok(())
+            }
+        }
+    };
+}
+
+/// Stochastic gradient descent with optional momentum, Nesterov momentum
+/// and L2 weight decay, following `torch.optim.SGD`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct Sgd {
+    lr: f32,
+    momentum: f32,
+    dampening: f32,
+    weight_decay: f32,
+    nesterov: bool,
+    states: States,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Sgd {
+    /// Defaults: no momentum, dampening or weight decay. Nesterov momentum
+    /// needs a positive `momentum` and zero `dampening`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        lr: f32,
+        momentum: Option<f32>,
+        dampening: Option<f32>,
+        weight_decay: Option<f32>,
+        nesterov: Option<bool>,
+    ) -> Sgd {
+        let (momentum, dampening) = (momentum.unwrap_or(0.), dampening.unwrap_or(0.));
+        let nesterov = nesterov.unwrap_or(false);
+        if nesterov && (momentum <= 0. || dampening != 0.) {
+            panic!("nesterov momentum requires a momentum and zero dampening");
+        }
+        Sgd {
+            lr,
+            momentum,
+            dampening,
+            weight_decay: weight_decay.unwrap_or(0.),
+            nesterov,
+            states: States::default(),
+        }
+    }
+}
+
+impl Sgd {
+    fn buffer_names(&self) -> Vec<&'static str> {
+        if self.momentum != 0. {
+            vec!["momentum_buffer"]
+        } else {
+            vec![]
+        }
+    }
+
+    fn update(&self, slot: &mut Slot, param: &mut NdArray, grad: &NdArray) {
+        let mut g = decayed(grad, param, self.weight_decay);
+        if self.momentum != 0. {
+            let mut buf = slot.take("momentum_buffer", param, 0.);
+            let first = slot.step == 1;
+            for (b, g) in buf.buffer.iter_mut().zip(g.iter_mut()) {
+                *b = if first {
+                    *g
+                } else {
+                    self.momentum * *b + (1. - self.dampening) * *g
+                };
+                *g = if self.nesterov {
+                    *g + self.momentum * *b
+                } else {
+                    *b
+                };
+            }
+            slot.put("momentum_buffer", buf);
+        }
+        for (p, g) in param.buffer.iter_mut().zip(&g) {
+            *p -= self.lr * g;
+        }
+    }
+}
+
+optimizer_methods!(Sgd);
+
+/// Moment estimation shared by `Adam` and `AdamW`.
+#[derive(Clone, Debug)]
+struct Moments {
+    beta1: f32,
+    beta2: f32,
+    eps: f32,
+    amsgrad: bool,
+}
+
+impl Moments {
+    fn new(
+        beta1: Option<f32>,
+        beta2: Option<f32>,
+        eps: Option<f32>,
+        amsgrad: Option<bool>,
+    ) -> Self {
+        Moments {
+            beta1: beta1.unwrap_or(0.9),
+            beta2: beta2.unwrap_or(0.999),
+            eps: eps.unwrap_or(1e-8),
+            amsgrad: amsgrad.unwrap_or(false),
+        }
+    }
+
+    fn buffer_names(&self) -> Vec<&'static str> {
+        if self.amsgrad {
+            vec!["exp_avg", "exp_avg_sq", "max_exp_avg_sq"]
+        } else {
+            vec!["exp_avg", "exp_avg_sq"]
+        }
+    }
+
+    fn update(&self, lr: f32, slot: &mut Slot, param: &mut NdArray, g: &[f32]) {
+        let mut m = slot.take("exp_avg", param, 0.);
+        let mut v = slot.take("exp_avg_sq", param, 0.);
+        let mut v_max = if self.amsgrad {
+            Some(slot.take("max_exp_avg_sq", param, 0.))
+        } else {
+            None
+        };
+        let t = slot.step as i32;
+        let step_size = lr / (1. - self.beta1.powi(t));
+        let correction2_sqrt = (1. - self.beta2.powi(t)).sqrt();
+        for (i, (p, g)) in param.buffer.iter_mut().zip(g).enumerate() {
+            m.buffer[i] = self.beta1 * m.buffer[i] + (1. - self.beta1) * g;
+            v.buffer[i] = self.beta2 * v.buffer[i] + (1. - self.beta2) * g * g;
+            let second = match v_max.as_mut() {
+                Some(v_max) => {
+                    v_max.buffer[i] = v_max.buffer[i].max(v.buffer[i]);
+                    v_max.buffer[i]
+                }
+                None => v.buffer[i],
+            };
+            *p -= step_size * m.buffer[i] / (second.sqrt() / correction2_sqrt + self.eps);
+        }
+        slot.put("exp_avg", m);
+        slot.put("exp_avg_sq", v);
+        if let Some(v_max) = v_max {
+            slot.put("max_exp_avg_sq", v_max);
+        }
+    }
+}
+
+/// Adam with bias-corrected moment estimates and optional AMSGrad. Weight
+/// decay is added to the gradient (L2); see `AdamW` for the decoupled form.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct Adam {
+    lr: f32,
+    moments: Moments,
+    weight_decay: f32,
+    states: States,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Adam {
+    /// Defaults: `lr = 1e-3`, `betas = (0.9, 0.999)`, `eps = 1e-8`, no
+    /// weight decay or AMSGrad.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        lr: Option<f32>,
+        beta1: Option<f32>,
+        beta2: Option<f32>,
+        eps: Option<f32>,
+        weight_decay: Option<f32>,
+        amsgrad: Option<bool>,
+    ) -> Adam {
+        Adam {
+            lr: lr.unwrap_or(1e-3),
+            moments: Moments::new(beta1, beta2, eps, amsgrad),
+            weight_decay: weight_decay.unwrap_or(0.),
+            states: States::default(),
+        }
+    }
+}
+
+impl Adam {
+    fn buffer_names(&self) -> Vec<&'static str> {
+        self.moments.buffer_names()
+    }
+
+    fn update(&self, slot: &mut Slot, param: &mut NdArray, grad: &NdArray) {
+        let g = decayed(grad, param, self.weight_decay);
+        self.moments.update(self.lr, slot, param, &g);
+    }
+}
+
+optimizer_methods!(Adam);
+
+/// Adam with decoupled weight decay: parameters shrink by
+/// `lr * weight_decay` each step independently of the gradient.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct AdamW {
+    lr: f32,
+    moments: Moments,
+    weight_decay: f32,
+    states: States,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl AdamW {
+    /// Same defaults as `Adam`, except `weight_decay = 1e-2`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        lr: Option<f32>,
+        beta1: Option<f32>,
+        beta2: Option<f32>,
+        eps: Option<f32>,
+        weight_decay: Option<f32>,
+        amsgrad: Option<bool>,
+    ) -> AdamW {
+        AdamW {
+            lr: lr.unwrap_or(1e-3),
+            moments: Moments::new(beta1, beta2, eps, amsgrad),
+            weight_decay: weight_decay.unwrap_or(1e-2),
+            states: States::default(),
+        }
+    }
+}
+
+impl AdamW {
+    fn buffer_names(&self) -> Vec<&'static str> {
+        self.moments.buffer_names()
+    }
+
+    fn update(&self, slot: &mut Slot, param: &mut NdArray, grad: &NdArray) {
+        let shrink = 1. - self.lr * self.weight_decay;
+        param.buffer.iter_mut().for_each(|p| *p *= shrink);
+        self.moments.update(self.lr, slot, param, &grad.buffer);
+    }
+}
+
+optimizer_methods!(AdamW);
+
+/// RMSprop with optional momentum and centering, following
+/// `torch.optim.RMSprop`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct RmsProp {
+    lr: f32,
+    alpha: f32,
+    eps: f32,
+    weight_decay: f32,
+    momentum: f32,
+    centered: bool,
+    states: States,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl RmsProp {
+    /// Defaults: `lr = 1e-2`, `alpha = 0.99`, `eps = 1e-8`, no weight
+    /// decay or momentum, not centered.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        lr: Option<f32>,
+        alpha: Option<f32>,
+        eps: Option<f32>,
+        weight_decay: Option<f32>,
+        momentum: Option<f32>,
+        centered: Option<bool>,
+    ) -> RmsProp {
+        RmsProp {
+            lr: lr.unwrap_or(1e-2),
+            alpha: alpha.unwrap_or(0.99),
+            eps: eps.unwrap_or(1e-8),
+            weight_decay: weight_decay.unwrap_or(0.),
+            momentum: momentum.unwrap_or(0.),
+            centered: centered.unwrap_or(false),
+            states: States::default(),
+        }
+    }
+}
+
+impl RmsProp {
+    fn buffer_names(&self) -> Vec<&'static str> {
+        let mut names = vec!["square_avg"];
+        if self.centered {
+            names.push("grad_avg");
+        }
+        if self.momentum > 0. {
+            names.push("momentum_buffer");
+        }
+        names
+    }
+
+    fn update(&self, slot: &mut Slot, param: &mut NdArray, grad: &NdArray) {
+        let g = decayed(grad, param, self.weight_decay);
+        let mut sq = slot.take("square_avg", param, 0.);
+        let mut avg = if self.centered {
+            Some(slot.take("grad_avg", param, 0.))
+        } else {
+            None
+        };
+        let mut buf = if self.momentum > 0. {
+            Some(slot.take("momentum_buffer", param, 0.))
+        } else {
+            None
+        };
+        let a = self.alpha;
+        for (i, (p, g)) in param.buffer.iter_mut().zip(&g).enumerate() {
+            sq.buffer[i] = a * sq.buffer[i] + (1. - a) * g * g;
+            let mut var = sq.buffer[i];
+            if let Some(avg) = avg.as_mut() {
+                avg.buffer[i] = a * avg.buffer[i] + (1. - a) * g;
+                var -= avg.buffer[i] * avg.buffer[i];
+            }
+            let scaled = g / (var.max(0.).sqrt() + self.eps);
+            match buf.as_mut() {
+                Some(buf) => {
+                    buf.buffer[i] = self.momentum * buf.buffer[i] + scaled;
+                    *p -= self.lr * buf.buffer[i];
+                }
+                None => *p -= self.lr * scaled,
+            }
+        }
+        slot.put("square_avg", sq);
+        if let Some(avg) = avg {
+            slot.put("grad_avg", avg);
+        }
+        if let Some(buf) = buf {
+            slot.put("momentum_buffer", buf);
+        }
+    }
+}
+
+optimizer_methods!(RmsProp);
+
+/// Adagrad: per-element learning rates scaled by the root of the sum of
+/// squared gradients.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct Adagrad {
+    lr: f32,
+    lr_decay: f32,
+    weight_decay: f32,
+    initial_accumulator_value: f32,
+    eps: f32,
+    states: States,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Adagrad {
+    /// Defaults: `lr = 1e-2`, `eps = 1e-10`, no lr decay or weight decay,
+    /// accumulators starting at 0.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        lr: Option<f32>,
+        lr_decay: Option<f32>,
+        weight_decay: Option<f32>,
+        initial_accumulator_value: Option<f32>,
+        eps: Option<f32>,
+    ) -> Adagrad {
+        Adagrad {
+            lr: lr.unwrap_or(1e-2),
+            lr_decay: lr_decay.unwrap_or(0.),
+            weight_decay: weight_decay.unwrap_or(0.),
+            initial_accumulator_value: initial_accumulator_value.unwrap_or(0.),
+            eps: eps.unwrap_or(1e-10),
+            states: States::default(),
+        }
+    }
+}
+
+impl Adagrad {
+    fn buffer_names(&self) -> Vec<&'static str> {
+        vec!["sum"]
+    }
+
+    fn update(&self, slot: &mut Slot, param: &mut NdArray, grad: &NdArray) {
+        let g = decayed(grad, param, self.weight_decay);
+        let mut sum = slot.take("sum", param, self.initial_accumulator_value);
+        let lr = self.lr / (1. + (slot.step - 1) as f32 * self.lr_decay);
+        for (i, (p, g)) in param.buffer.iter_mut().zip(&g).enumerate() {
+            sum.buffer[i] += g * g;
+            *p -= lr * g / (sum.buffer[i].sqrt() + self.eps);
+        }
+        slot.put("sum", sum);
+    }
+}
+
+optimizer_methods!(Adagrad);
+
+/// `norm_type`-norm of all gradients taken together, as if concatenated
+/// into one vector.
+fn total_norm(grads: &[NdArray], norm_type: f32) -> f32 {
+    let values = grads.iter().flat_map(|g| g.buffer.iter());
+    if norm_type == f32::INFINITY {
+        values.fold(0f32, |m, x| m.max(x.abs()))
+    } else {
+        values
+            .map(|x| x.abs().powf(norm_type))
+            .sum::<f32>()
+            .powf(1. / norm_type)
+    }
+}
+
+/// Rescales `grads` in place so their total `norm_type`-norm (default 2,
+/// `f32::INFINITY` for the max norm) is at most `max_norm`. Returns the
+/// total norm before clipping.
+pub fn clip_grad_norm_(grads: &mut [NdArray], max_norm: f32, norm_type: Option<f32>) -> f32 {
+    let total = total_norm(grads, norm_type.unwrap_or(2.));
+    let coef = max_norm / (total + 1e-6);
+    if coef < 1. {
+        grads
+            .iter_mut()
+            .flat_map(|g| g.buffer.iter_mut())
+            .for_each(|x| *x *= coef);
+    }
+    total
+}
+
+/// Clamps every gradient element to `[-clip_value, clip_value]` in place.
+/// Returns the total 2-norm before clipping.
+pub fn clip_grad_value_(grads: &mut [NdArray], clip_value: f32) -> f32 {
+    let total = total_norm(grads, 2.);
+    grads
+        .iter_mut()
+        .flat_map(|g| g.buffer.iter_mut())
+        .for_each(|x| *x = x.clamp(-clip_value, clip_value));
+    total
+}
+
+/// Gradients after clipping, with their total norm before clipping.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct ClippedGrads {
+    grads: Vec<NdArray>,
+    total_norm: f32,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl ClippedGrads {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn grads(&self) -> Vec<NdArray> {
+        self.grads.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = totalNorm))]
+    pub fn total_norm(&self) -> f32 {
+        self.total_norm
+    }
+}
+
+/// `clip_grad_norm_` for JS, which can't lend a list of arrays mutably:
+/// takes the gradients and hands back the clipped ones.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = clipGradNorm))]
+pub fn clip_grad_norm(
+    mut grads: Vec<NdArray>,
+    max_norm: f32,
+    norm_type: Option<f32>,
+) -> ClippedGrads {
+    let total_norm = clip_grad_norm_(&mut grads, max_norm, norm_type);
+    ClippedGrads { grads, total_norm }
+}
+
+/// `clip_grad_value_` for JS, returning the clipped gradients.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = clipGradValue))]
+pub fn clip_grad_value(mut grads: Vec<NdArray>, clip_value: f32) -> ClippedGrads {
+    let total_norm = clip_grad_value_(&mut grads, clip_value);
+    ClippedGrads { grads, total_norm }
+}
+
+#[cfg(test)]
+fn close(a: &[f32], b: &[f32]) {
+    assert_eq!(a.len(), b.len());
+    for (x, y) in a.iter().zip(b) {
+        assert!((x - y).abs() < 1e-5, "{:?} vs {:?}", a, b);
+    }
+}
+
+#[test]
+fn test_sgd() {
+    let mut p = NdArray::from(&[1., 2.], None, None);
+    let g = NdArray::from(&[0.5, -1.], None, None);
+    let mut opt = Sgd::new(0.1, Some(0.9), None, Some(0.1), None);
+    // g' = g + 0.1 p = [0.6, -0.8]; first step seeds the buffer with g'
+    opt.step(0, &mut p, &g);
+    close(&p.buffer, &[0.94, 2.08]);
+    // g' = [0.594, -0.792]; buf = 0.9 * [0.6, -0.8] + g'
+    opt.step(0, &mut p, &g);
+    close(&p.buffer, &[0.94 - 0.1134, 2.08 + 0.1512]);
+    assert_eq!(opt.step_count(0), 2);
+    assert_eq!(opt.step_count(1), 0);
+
+    let mut p = NdArray::from(&[1.], None, None);
+    let g = NdArray::from(&[1.], None, None);
+    let mut opt = Sgd::new(0.1, Some(0.5), None, None, Some(true));
+    opt.step(0, &mut p, &g);
+    // buf = 1, update = g + 0.5 buf
+    close(&p.buffer, &[0.85]);
+}
+
+#[test]
+fn test_adaptive_optimizers() {
+    let g = NdArray::from(&[0.5, -2., 0.], None, None);
+
+    // the first bias-corrected Adam step moves by about lr * sign(g)
+    let mut p = NdArray::zeros(&[3]);
+    let mut adam = Adam::new(Some(0.1), None, None, None, None, Some(true));
+    adam.step(0, &mut p, &g);
+    close(&p.buffer, &[-0.1, 0.1, 0.]);
+
+    let mut p = NdArray::ones(&[3]);
+    let mut adamw = AdamW::new(Some(0.1), None, None, None, Some(0.5), None);
+    adamw.step(0, &mut p, &g);
+    close(&p.buffer, &[0.95 - 0.1, 0.95 + 0.1, 0.95]);
+
+    let mut p = NdArray::zeros(&[3]);
+    let mut rms = RmsProp::new(Some(0.01), None, None, None, None, None);
+    rms.step(0, &mut p, &g);
+    // square_avg = 0.01 g^2, so the step is lr * g / (0.1 |g|)
+    close(&p.buffer, &[-0.1, 0.1, 0.]);
+
+    let mut p = NdArray::zeros(&[3]);
+    let mut adagrad = Adagrad::new(Some(0.1), None, None, None, None);
+    adagrad.step(0, &mut p, &g);
+    adagrad.step(0, &mut p, &g);
+    let second = 0.1 / 2f32.sqrt();
+    close(&p.buffer, &[-0.1 - second, 0.1 + second, 0.]);
+}
+
+#[test]
+fn test_optimizers_minimize_quadratic() {
+    // minimize |p - target|^2
+    let target = NdArray::from(&[3., -1., 0.5], None, None);
+    let run = |step: &mut dyn FnMut(&mut NdArray, &NdArray)| {
+        let mut p = NdArray::zeros(&[3]);
+        for _ in 0..500 {
+            let g = p
+                .buffer
+                .iter()
+                .zip(&target.buffer)
+                .map(|(p, t)| 2. * (p - t))
+                .collect::<Vec<_>>();
+            step(&mut p, &NdArray::from(&g, None, None));
+        }
+        close_enough(&p, &target);
+    };
+    fn close_enough(p: &NdArray, t: &NdArray) {
+        for (x, y) in p.buffer.iter().zip(&t.buffer) {
+            assert!((x - y).abs() < 1e-2, "{:?}", p.buffer);
+        }
+    }
+    let mut sgd = Sgd::new(0.05, Some(0.9), None, None, Some(true));
+    run(&mut |p, g| sgd.step(0, p, g));
+    let mut adam = Adam::new(Some(0.05), None, None, None, None, None);
+    run(&mut |p, g| adam.step(0, p, g));
+    let mut rms = RmsProp::new(Some(0.01), None, None, None, Some(0.5), Some(true));
+    run(&mut |p, g| rms.step(0, p, g));
+    let mut adagrad = Adagrad::new(Some(0.5), None, None, None, None);
+    run(&mut |p, g| adagrad.step(0, p, g));
+}
+
+#[test]
+fn test_optimizer_state_dict() {
+    let g = NdArray::from(&[0.3, -0.7], None, None);
+    let mut opt = Adam::new(Some(0.1), None, None, None, Some(0.01), Some(true));
+    let (mut a, mut b) = (NdArray::ones(&[2]), NdArray::ones(&[2]));
+    opt.step(0, &mut a, &g);
+    opt.step(0, &mut a, &g);
+
+    let state = opt.state_dict();
+    assert_eq!(
+        state.names(),
+        vec!["0.step", "0.exp_avg", "0.exp_avg_sq", "0.max_exp_avg_sq"]
+    );
+    let bytes = state.to_bytes();
+    let mut params = StateDict::new();
+    params.insert("weight", &a);
+    let mut restored = Adam::new(Some(0.1), None, None, None, Some(0.01), Some(true));
+    restored
+        .load_state_dict(&StateDict::from_bytes(&bytes).unwrap(), &params)
+        .unwrap();
+    assert_eq!(restored.step_count(0), 2);
+    b.buffer.clone_from(&a.buffer);
+    opt.step(0, &mut a, &g);
+    restored.step(0, &mut b, &g);
+    assert_eq!(a.buffer, b.buffer);
+
+    let names = restored.buffer_names();
+    let load = |dict: &StateDict, params: &StateDict| {
+        States::from_state_dict(dict, &names, params).map(|_| ())
+    };
+    let invalid = |dict: &StateDict, params: &StateDict| {
+        matches!(load(dict, params), Err(Error::InvalidArgument(_)))
+    };
+    assert!(load(&state, &params).is_ok());
+    let mut bad = StateDict::new();
+    bad.insert("momentum", &g);
+    assert!(load(&bad, &params).is_err());
+    let mut bad = StateDict::new();
+    bad.insert("0.step", &NdArray::from(&[2.5], None, None));
+    assert!(load(&bad, &params).is_err());
+    // no parameter 0
+    assert!(invalid(&state, &StateDict::new()));
+    // wrong parameter shape
+    let mut wide = StateDict::new();
+    wide.insert("weight", &NdArray::ones(&[3]));
+    assert!(invalid(&state, &wide));
+    // missing step, missing buffer, unknown buffer
+    let mut bad = state.clone();
+    bad.remove("0.step");
+    assert!(invalid(&bad, &params));
+    let mut bad = state.clone();
+    bad.remove("0.max_exp_avg_sq");
+    assert!(invalid(&bad, &params));
+    let mut bad = state.clone();
+    bad.insert("0.momentum_buffer", &g);
+    assert!(invalid(&bad, &params));
+
+    // counts past 2^24 survive the round trip
+    let mut states = States::default();
+    states.slots.entry(3).or_default().step = (1 << 24) + 1;
+    let dict = StateDict::from_bytes(&states.to_state_dict().to_bytes()).unwrap();
+    let mut params = StateDict::new();
+    for i in 0..4 {
+        params.insert(&i.to_string(), &g);
+    }
+    assert_eq!(
+        States::from_state_dict(&dict, &[], &params).unwrap().slots[&3].step,
+        (1 << 24) + 1
+    );
+}
+
+#[test]
+fn test_clip_grad() {
+    let grads = || {
+        vec![
+            NdArray::from(&[3., 0.], None, None),
+            NdArray::from(&[0., -4.], None, None),
+        ]
+    };
+    let mut g = grads();
+    assert_eq!(clip_grad_norm_(&mut g, 10., None), 5.);
+    assert_eq!(g[0].buffer, vec![3., 0.]);
+    let clipped = clip_grad_norm(grads(), 1., None);
+    assert_eq!(clipped.total_norm(), 5.);
+    close(&clipped.grads()[0].buffer, &[0.6, 0.]);
+    close(&clipped.grads()[1].buffer, &[0., -0.8]);
+
+    let mut g = grads();
+    assert_eq!(clip_grad_norm_(&mut g, 2., Some(f32::INFINITY)), 4.);
+    close(&g[1].buffer, &[0., -2.]);
+
+    let clipped = clip_grad_value(grads(), 1.);
+    assert_eq!(clipped.total_norm(), 5.);
+    assert_eq!(clipped.grads()[0].buffer, vec![1., 0.]);
+    assert_eq!(clipped.grads()[1].buffer, vec![0., -1.]);
+}
@@ -0,0 +1,467 @@
+//! Text rendering of arrays, modelled on NumPy's print options.
+//!
+//! `Display` renders like `str(ndarray)` and `Debug` like `repr(ndarray)`.
+//! Both use the global options set by [`set_print_options`]; a precision
+//! given in the format string (`{:.3}`) takes priority.
+
+use std::{cell::Cell, fmt};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::ndarray::NdArray;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PrintOptions {
+    pub(crate) precision: usize,
+    pub(crate) threshold: usize,
+    pub(crate) edge_items: usize,
+    pub(crate) line_width: usize,
+    pub(crate) sci_mode: Option<bool>,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        PrintOptions {
+            precision: 8,
+            threshold: 1000,
+            edge_items: 3,
+            line_width: 75,
+            sci_mode: None,
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl PrintOptions {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> PrintOptions {
+        PrintOptions::default()
+    }
+
+    /// Maximum number of digits after the decimal point.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn precision(&self) -> usize {
+        self.precision
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(setter))]
+    pub fn set_precision(&mut self, value: usize) {
+        self.precision = value;
+    }
+
+    /// Arrays with more elements than this are summarized.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(setter))]
+    pub fn set_threshold(&mut self, value: usize) {
+        self.threshold = value;
+    }
+
+    /// Number of items kept at each end of a summarized axis.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = edgeItems))]
+    pub fn edge_items(&self) -> usize {
+        self.edge_items
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(setter = edgeItems))]
+    pub fn set_edge_items(&mut self, value: usize) {
+        self.edge_items = value;
+    }
+
+    /// Number of characters per line before wrapping.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = lineWidth))]
+    pub fn line_width(&self) -> usize {
+        self.line_width
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(setter = lineWidth))]
+    pub fn set_line_width(&mut self, value: usize) {
+        self.line_width = value;
+    }
+
+    /// Forces (`true`) or disables (`false`) scientific notation. When
+    /// unset it is used for very large or very small magnitudes.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = sciMode))]
+    pub fn sci_mode(&self) -> Option<bool> {
+        self.sci_mode
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(setter = sciMode))]
+    pub fn set_sci_mode(&mut self, value: Option<bool>) {
+        self.sci_mode = value;
+    }
+}
+
+thread_local! {
+    static PRINT_OPTIONS: Cell<PrintOptions> = Cell::new(PrintOptions::default());
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = setPrintOptions))]
+pub fn set_print_options(options: PrintOptions) {
+    PRINT_OPTIONS.with(|o| o.set(options));
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = getPrintOptions))]
+pub fn get_print_options() -> PrintOptions {
+    PRINT_OPTIONS.with(|o| o.get())
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = resetPrintOptions))]
+pub fn reset_print_options() {
+    set_print_options(PrintOptions::default());
+}
+
+/// Number of fractional digits needed to show `x` at `precision`.
+fn frac_digits(x: f32, sci: bool, precision: usize) -> usize {
+    let frac_len = |s: &str| {
+        let s = s.split('e').next().unwrap();
+        s.split('.')
+            .nth(1)
+            .unwrap_or("")
+            .trim_end_matches('0')
+            .len()
+    };
+    // the shortest representation that reads back as `x`
+    let shortest = if sci {
+        frac_len(&format!("{:e}", x))
+    } else {
+        frac_len(&format!("{}", x))
+    };
+    if shortest <= precision {
+        shortest
+    } else if sci {
+        frac_len(&format!("{:.*e}", precision, x))
+    } else {
+        frac_len(&format!("{:.*}", precision, x))
+    }
+}
+
+/// Shared number format of every printed element. Values are aligned on
+/// their decimal point.
+struct FloatFormat {
+    sci: bool,
+    precision: usize,
+    /// Scientific notation uses the same number of digits for all values.
+    digits: usize,
+    left: usize,
+    right: usize,
+}
+
+impl FloatFormat {
+    fn new(values: &[f32], options: &PrintOptions) -> Self {
+        let finite: Vec<f32> = values.iter().copied().filter(|x| x.is_finite()).collect();
+        let max = finite.iter().fold(0f32, |acc, x| acc.max(x.abs()));
+        let min = finite
+            .iter()
+            .filter(|x| **x != 0.)
+            .fold(f32::INFINITY, |acc, x| acc.min(x.abs()));
+        let sci = options
+            .sci_mode
+            .unwrap_or_else(|| max >= 1e8 || (min.is_finite() && (min < 1e-4 || max / min > 1e3)));
+        let precision = options.precision;
+        let digits = finite
+            .iter()
+            .map(|x| frac_digits(*x, sci, precision))
+            .max()
+            .unwrap_or(0);
+        let mut res = FloatFormat {
+            sci,
+            precision,
+            digits,
+            left: 0,
+            right: 0,
+        };
+        let mut width = 0;
+        for x in values {
+            let raw = res.raw(*x);
+            match raw.find('.') {
+                Some(dot) => {
+                    res.left = res.left.max(dot);
+                    res.right = res.right.max(raw.len() - dot);
+                }
+                None => width = width.max(raw.len()),
+            }
+        }
+        // make room for `nan` and `inf` when they are the widest values
+        res.left += width.saturating_sub(res.left + res.right);
+        res
+    }
+
+    fn raw(&self, x: f32) -> String {
+        if x.is_nan() {
+            return "nan".to_string();
+        }
+        if x.is_infinite() {
+            return if x > 0. { "inf" } else { "-inf" }.to_string();
+        }
+        if self.sci {
+            let s = format!("{:.*e}", self.digits, x);
+            let (mantissa, exp) = s.split_at(s.find('e').unwrap());
+            let exp: i32 = exp[1..].parse().unwrap();
+            let dot = if self.digits == 0 { "." } else { "" };
+            let sign = if exp < 0 { '-' } else { '+' };
+            format!("{}{}e{}{:02}", mantissa, dot, sign, exp.abs())
+        } else {
+            let digits = frac_digits(x, false, self.precision);
+            let dot = if digits == 0 { "." } else { "" };
+            format!("{:.*}{}", digits, x, dot)
+        }
+    }
+
+    fn format(&self, x: f32) -> String {
+        let raw = self.raw(x);
+        match raw.find('.') {
+            Some(dot) => format!(
+                "{}{}{}",
+                " ".repeat(self.left - dot),
+                raw,
+                " ".repeat(self.right - (raw.len() - dot))
+            ),
+            None => format!("{:>1$}", raw, self.left + self.right),
+        }
+    }
+}
+
+struct Printer<'a> {
+    array: &'a NdArray,
+    format: FloatFormat,
+    /// Items kept per axis end, or `None` to print everything.
+    edge_items: Option<usize>,
+    line_width: usize,
+    separator: &'a str,
+    /// Column of the outermost `[`.
+    indent: usize,
+}
+
+/// Indices printed along an axis of length `len`; `None` marks the `...`.
+fn axis_items(len: usize, edge_items: Option<usize>) -> Vec<Option<usize>> {
+    match edge_items {
+        Some(edge) if len > 2 * edge => (0..edge)
+            .map(Some)
+            .chain(std::iter::once(None))
+            .chain((len - edge..len).map(Some))
+            .collect(),
+        _ => (0..len).map(Some).collect(),
+    }
+}
+
+/// Values that end up being printed, used to size the number format.
+fn visible_values(
+    a: &NdArray,
+    edge_items: Option<usize>,
+    depth: usize,
+    offset: usize,
+    out: &mut Vec<f32>,
+) {
+    if depth == a.shape.len() {
+        out.push(a.buffer[offset]);
+        return;
+    }
+    for i in axis_items(a.shape[depth], edge_items).into_iter().flatten() {
+        visible_values(a, edge_items, depth + 1, offset + i * a.strides[depth], out);
+    }
+}
+
+impl<'a> Printer<'a> {
+    fn new(a: &'a NdArray, options: &PrintOptions, separator: &'a str, indent: usize) -> Self {
+        let edge_items = if a.buffer.len() > options.threshold {
+            Some(options.edge_items)
+        } else {
+            None
+        };
+        let mut values = Vec::new();
+        if !a.buffer.is_empty() {
+            visible_values(a, edge_items, 0, 0, &mut values);
+        }
+        Printer {
+            array: a,
+            format: FloatFormat::new(&values, options),
+            edge_items,
+            line_width: options.line_width,
+            separator,
+            indent,
+        }
+    }
+
+    fn write(&self, out: &mut String) {
+        let shape = &self.array.shape;
+        // checked first: a 0-d array can have an empty buffer too
+        if self.array.buffer.is_empty() {
+            out.push_str("[]");
+        } else if shape.is_empty() {
+            out.push_str(&self.format.raw(self.array.buffer[0]));
+        } else {
+            self.write_axis(out, 0, 0);
+        }
+    }
+
+    fn write_axis(&self, out: &mut String, depth: usize, offset: usize) {
+        let a = self.array;
+        let ndim = a.shape.len();
+        let items = axis_items(a.shape[depth], self.edge_items);
+        let column = self.indent + depth + 1;
+        let sep = self.separator.trim_end();
+        out.push('[');
+        if depth + 1 == ndim {
+            let mut col = column;
+            for (i, item) in items.iter().enumerate() {
+                let word = match item {
+                    Some(j) => self.format.format(a.buffer[offset + j * a.strides[depth]]),
+                    None => "...".to_string(),
+                };
+                if i > 0 {
+                    out.push_str(sep);
+                    col += sep.len();
+                    // leave room for the closing brackets
+                    if col + 1 + word.len() + ndim > self.line_width {
+                        out.push('\n');
+                        out.push_str(&" ".repeat(column));
+                        col = column;
+                    } else {
+                        out.push(' ');
+                        col += 1;
+                    }
+                }
+                out.push_str(&word);
+                col += word.len();
+            }
+        } else {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(sep);
+                    out.push_str(&"\n".repeat(ndim - depth - 1));
+                    out.push_str(&" ".repeat(column));
+                }
+                match item {
+                    Some(j) => self.write_axis(out, depth + 1, offset + j * a.strides[depth]),
+                    None => out.push_str("..."),
+                }
+            }
+        }
+        out.push(']');
+    }
+}
+
+/// Renders `a` with the given options, NumPy `str` style.
+pub fn format_array(a: &NdArray, options: &PrintOptions) -> String {
+    let mut res = String::new();
+    Printer::new(a, options, " ", 0).write(&mut res);
+    res
+}
+
+fn options_for(f: &fmt::Formatter<'_>) -> PrintOptions {
+    let mut options = get_print_options();
+    if let Some(precision) = f.precision() {
+        options.precision = precision;
+    }
+    options
+}
+
+impl fmt::Display for NdArray {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_array(self, &options_for(f)))
+    }
+}
+
+impl fmt::Debug for NdArray {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const PREFIX: &str = "NdArray(";
+        let options = options_for(f);
+        let mut res = PREFIX.to_string();
+        Printer::new(self, &options, ", ", PREFIX.len()).write(&mut res);
+        // like NumPy, spell out the shape when the brackets don't show it
+        if self.shape.is_empty() || self.buffer.is_empty() || self.buffer.len() > options.threshold
+        {
+            res.push_str(&format!(", shape={:?}", self.shape));
+        }
+        res.push(')');
+        f.write_str(&res)
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl NdArray {
+    /// Nested-bracket rendering; uses the global print options unless
+    /// `options` is given.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = toString))]
+    pub fn to_string_with(&self, options: Option<PrintOptions>) -> String {
+        format_array(self, &options.unwrap_or_else(get_print_options))
+    }
+}
+
+#[test]
+fn test_display() {
+    let a = NdArray::arange(0, 6, None).reshape(&[2, 3]);
+    assert_eq!(format!("{}", a), "[[0. 1. 2.]\n [3. 4. 5.]]");
+    assert_eq!(
+        format!("{:?}", a),
+        "NdArray([[0., 1., 2.],\n         [3., 4., 5.]])"
+    );
+
+    let b = NdArray::from(&[0.5, -1.25, 10.], None, None);
+    assert_eq!(format!("{}", b), "[ 0.5  -1.25 10.  ]");
+    assert_eq!(format!("{:.1}", b), "[ 0.5 -1.2 10. ]");
+
+    let c = NdArray::arange(0, 8, None).reshape(&[2, 2, 2]);
+    assert_eq!(
+        format!("{}", c),
+        "[[[0. 1.]\n  [2. 3.]]\n\n [[4. 5.]\n  [6. 7.]]]"
+    );
+
+    let d = NdArray::from(&[f32::NAN, f32::NEG_INFINITY, 1.], None, None);
+    assert_eq!(format!("{}", d), "[ nan -inf   1.]");
+    assert_eq!(format!("{}", NdArray::zeros(&[0])), "[]");
+    let empty = NdArray {
+        buffer: vec![],
+        strides: vec![],
+        shape: vec![],
+    };
+    assert_eq!(format!("{}", empty), "[]");
+    assert_eq!(format!("{:?}", empty), "NdArray([], shape=[])");
+}
+
+#[test]
+fn test_print_options() {
+    let a = NdArray::arange(100, 2100, None);
+    let opts = PrintOptions::default();
+    assert_eq!(
+        format_array(&a, &opts),
+        "[ 100.  101.  102. ... 2097. 2098. 2099.]"
+    );
+
+    let b = NdArray::arange(0, 1200, None).reshape(&[100, 12]);
+    let s = format_array(
+        &b,
+        &PrintOptions {
+            edge_items: 1,
+            ..opts
+        },
+    );
+    assert_eq!(s, "[[   0. ...   11.]\n ...\n [1188. ... 1199.]]");
+
+    let c = NdArray::from(&[1e-5, 1., 12345.], None, None);
+    assert_eq!(
+        format_array(&c, &opts),
+        "[1.0000e-05 1.0000e+00 1.2345e+04]"
+    );
+    let fixed = PrintOptions {
+        sci_mode: Some(false),
+        precision: 3,
+        ..opts
+    };
+    assert_eq!(format_array(&c, &fixed), "[    0.     1. 12345.]");
+
+    let d = NdArray::arange(0, 10, None);
+    let narrow = PrintOptions {
+        line_width: 20,
+        ..opts
+    };
+    assert_eq!(
+        format_array(&d, &narrow),
+        "[0. 1. 2. 3. 4. 5.\n 6. 7. 8. 9.]"
+    );
+}
@@ -0,0 +1,769 @@
+//! Affine int8 quantization: `real = scale * (q - zero_point)`.
+//!
+//! A `QTensor` is quantized either per tensor, with one scale and zero
+//! point, or per channel along one axis, with one pair per slice along
+//! that axis. Quantized kernels accumulate products in `i32` and
+//! requantize the result to int8 with the output's scale and zero point.
+//! Rounding is to nearest, ties to even, like PyTorch.
+//!
+//! The quantizers and `QTensor::from_parts` return an error for parameters
+//! that don't match the shape or don't fit int8.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::error::{Error, JsResult, Result};
+use crate::ndarray::NdArray;
+
+/// int8 data with its quantization parameters.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct QTensor {
+    data: Vec<i8>,
+    shape: Vec<usize>,
+    scales: Vec<f32>,
+    zero_points: Vec<i32>,
+    axis: Option<usize>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl QTensor {
+    /// Wraps int8 `data` already quantized with `scales` and `zero_points`,
+    /// per tensor or per channel along `axis`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = fromParts))]
+    pub fn from_parts_js(
+        data: Vec<i8>,
+        shape: Vec<usize>,
+        scales: Vec<f32>,
+        zero_points: Vec<i32>,
+        axis: Option<usize>,
+    ) -> JsResult<QTensor> {
+        Ok(QTensor::from_parts(data, shape, scales, zero_points, axis)?)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn data(&self) -> Vec<i8> {
+        self.data.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn shape(&self) -> Vec<usize> {
+        self.shape.clone()
+    }
+
+    /// One scale per tensor, or per channel along `axis`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn scales(&self) -> Vec<f32> {
+        self.scales.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = zeroPoints))]
+    pub fn zero_points(&self) -> Vec<i32> {
+        self.zero_points.clone()
+    }
+
+    /// Channel axis of per-channel quantization.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn axis(&self) -> Option<usize> {
+        self.axis
+    }
+
+    pub fn dequantize(&self) -> NdArray {
+        dequantize(self)
+    }
+}
+
+impl QTensor {
+    /// Checks that `data` fills `shape` and that there is one scale and
+    /// zero point per tensor, or per slice along `axis`.
+    pub fn from_parts(
+        data: Vec<i8>,
+        shape: Vec<usize>,
+        scales: Vec<f32>,
+        zero_points: Vec<i32>,
+        axis: Option<usize>,
+    ) -> Result<QTensor> {
+        let len = shape.iter().try_fold(1usize, |n, d| n.checked_mul(*d));
+        if len != Some(data.len()) {
+            return Err(Error::ShapeMismatch(format!(
+                "{} values can't have shape {:?}",
+                data.len(),
+                shape
+            )));
+        }
+        let channels = match axis {
+            None => 1,
+            Some(axis) if axis < shape.len() => shape[axis],
+            Some(axis) => {
+                return Err(Error::InvalidArgument(format!(
+                    "axis {} is out of bounds for shape {:?}",
+                    axis, shape
+                )))
+            }
+        };
+        if scales.len() != channels || zero_points.len() != channels {
+            return Err(Error::ShapeMismatch(format!(
+                "expected {} scales and zero points, got {} and {}",
+                channels,
+                scales.len(),
+                zero_points.len()
+            )));
+        }
+        validate_params(&scales, &zero_points)?;
+        Ok(QTensor {
+            data,
+            shape,
+            scales,
+            zero_points,
+            axis,
+        })
+    }
+
+    /// Channel of element `i`, or 0 when quantized per tensor.
+    fn channel_of(&self, i: usize) -> usize {
+        match self.axis {
+            None => 0,
+            Some(axis) => {
+                let inner: usize = self.shape[axis + 1..].iter().product();
+                i / inner % self.shape[axis]
+            }
+        }
+    }
+
+    /// Scale and zero point of `channel` along `axis`, checking the tensor
+    /// is quantized per tensor or per channel along `axis`.
+    fn params(&self, axis: usize, channel: usize) -> (f32, i32) {
+        match self.axis {
+            None => (self.scales[0], self.zero_points[0]),
+            Some(a) if a == axis => (self.scales[channel], self.zero_points[channel]),
+            Some(a) => panic!(
+                "expected per-tensor or axis {} quantization, got axis {}",
+                axis, a
+            ),
+        }
+    }
+
+    fn per_tensor(&self) -> (f32, i32) {
+        if self.axis.is_some() {
+            panic!("expected per-tensor quantization");
+        }
+        (self.scales[0], self.zero_points[0])
+    }
+}
+
+fn quantize_value(x: f32, scale: f32, zero_point: i32) -> i8 {
+    ((x / scale).round_ties_even() + zero_point as f32).clamp(-128., 127.) as i8
+}
+
+fn validate_params(scales: &[f32], zero_points: &[i32]) -> Result<()> {
+    if let Some(s) = scales.iter().find(|s| !(**s > 0. && s.is_finite())) {
+        return Err(Error::InvalidArgument(format!(
+            "scales must be positive and finite, got {}",
+            s
+        )));
+    }
+    if let Some(z) = zero_points.iter().find(|z| !(-128..=127).contains(*z)) {
+        return Err(Error::InvalidArgument(format!(
+            "zero points must fit in i8, got {}",
+            z
+        )));
+    }
+    Ok(())
+}
+
+fn check_params(scales: &[f32], zero_points: &[i32]) {
+    if let Err(e) = validate_params(scales, zero_points) {
+        panic!("{}", e);
+    }
+}
+
+fn quantize_with(
+    x: &NdArray,
+    scales: Vec<f32>,
+    zero_points: Vec<i32>,
+    axis: Option<usize>,
+) -> Result<QTensor> {
+    // checks the parameters against the shape and axis
+    let mut q = QTensor::from_parts(
+        vec![0; x.buffer.len()],
+        x.shape.clone(),
+        scales,
+        zero_points,
+        axis,
+    )?;
+    q.data = x
+        .buffer
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            let c = q.channel_of(i);
+            quantize_value(*v, q.scales[c], q.zero_points[c])
+        })
+        .collect();
+    Ok(q)
+}
+
+/// Quantizes `x` with one `scale` and `zero_point`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn quantize(x: &NdArray, scale: f32, zero_point: i32) -> JsResult<QTensor> {
+    Ok(quantize_with(x, vec![scale], vec![zero_point], None)?)
+}
+
+/// Quantizes `x` with one scale and zero point per slice along `axis`.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = quantizePerChannel))]
+pub fn quantize_per_channel(
+    x: &NdArray,
+    scales: Vec<f32>,
+    zero_points: Vec<i32>,
+    axis: usize,
+) -> JsResult<QTensor> {
+    Ok(quantize_with(x, scales, zero_points, Some(axis))?)
+}
+
+/// Scale and zero point covering `[min, max]`, widened to include 0 so
+/// that zero is represented exactly.
+fn choose_params(min: f32, max: f32, symmetric: bool) -> (f32, i32) {
+    let (min, max) = (min.min(0.), max.max(0.));
+    if symmetric {
+        let bound = min.abs().max(max);
+        let scale = if bound > 0. { bound / 127. } else { 1. };
+        return (scale, 0);
+    }
+    if max == min {
+        return (1., 0);
+    }
+    let scale = (max - min) / 255.;
+    let zero_point = (-128. - min / scale).round_ties_even().clamp(-128., 127.) as i32;
+    (scale, zero_point)
+}
+
+/// Quantizes `x` with parameters fitted to its range, per tensor or per
+/// slice along `axis`. `symmetric` (default false) fixes the zero points
+/// at 0, as usually done for weights.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = quantizeDynamic))]
+pub fn quantize_dynamic(
+    x: &NdArray,
+    axis: Option<usize>,
+    symmetric: Option<bool>,
+) -> JsResult<QTensor> {
+    Ok(fit_and_quantize(x, axis, symmetric.unwrap_or(false))?)
+}
+
+fn fit_and_quantize(x: &NdArray, axis: Option<usize>, symmetric: bool) -> Result<QTensor> {
+    let channels = match axis {
+        None => 1,
+        Some(axis) if axis < x.shape.len() => x.shape[axis],
+        Some(axis) => {
+            return Err(Error::InvalidArgument(format!(
+                "axis {} is out of bounds for shape {:?}",
+                axis, x.shape
+            )))
+        }
+    };
+    let mut ranges = vec![(f32::INFINITY, f32::NEG_INFINITY); channels];
+    let probe = QTensor {
+        data: vec![],
+        shape: x.shape.clone(),
+        scales: vec![],
+        zero_points: vec![],
+        axis,
+    };
+    for (i, v) in x.buffer.iter().enumerate() {
+        let r = &mut ranges[probe.channel_of(i)];
+        *r = (r.0.min(*v), r.1.max(*v));
+    }
+    let (scales, zero_points) = ranges
+        .into_iter()
+        .map(|(min, max)| choose_params(min, max, symmetric))
+        .unzip();
+    quantize_with(x, scales, zero_points, axis)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn dequantize(q: &QTensor) -> NdArray {
+    let buffer: Vec<f32> = q
+        .data
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            let c = q.channel_of(i);
+            q.scales[c] * (*v as i32 - q.zero_points[c]) as f32
+        })
+        .collect();
+    NdArray::from(&buffer, Some(q.shape.clone()), None)
+}
+
+/// Longest reduction `accumulate` can sum without overflowing `i32`: with
+/// int8 data and zero points both operands lie in [-255, 255].
+const MAX_DEPTH: usize = i32::MAX as usize / (255 * 255);
+
+/// `(a - a_zero) @ (b - b_zero)` for row-major `[m, k]` and `[k, n]` int8
+/// matrices, accumulated in `i32`. `b_zero` holds one zero point per
+/// tensor or per column, and `k` is at most `MAX_DEPTH`.
+fn accumulate(
+    a: &[i8],
+    a_zero: i32,
+    b: &[i8],
+    b_zero: &[i32],
+    m: usize,
+    k: usize,
+    n: usize,
+) -> Vec<i32> {
+    assert!(
+        k <= MAX_DEPTH,
+        "can't accumulate {} products in i32, at most {} fit",
+        k,
+        MAX_DEPTH
+    );
+    let mut acc = vec![0i32; m * n];
+    for i in 0..m {
+        let out = &mut acc[i * n..(i + 1) * n];
+        for p in 0..k {
+            let x = a[i * k + p] as i32 - a_zero;
+            if x == 0 {
+                continue;
+            }
+            for (j, o) in out.iter_mut().enumerate() {
+                *o += x * (b[p * n + j] as i32 - b_zero[j % b_zero.len()]);
+            }
+        }
+    }
+    acc
+}
+
+/// int8 `[m, k] @ [k, n]` with `i32` accumulation, for raw data with the
+/// given zero points, which must fit in i8.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = qmatmulI32))]
+#[allow(clippy::too_many_arguments)]
+pub fn qmatmul_i32(
+    a: &[i8],
+    a_zero_point: i32,
+    b: &[i8],
+    b_zero_point: i32,
+    m: usize,
+    k: usize,
+    n: usize,
+) -> Vec<i32> {
+    if a.len() != m * k || b.len() != k * n {
+        panic!(
+            "can't multiply {} by {} elements as [{}, {}] @ [{}, {}]",
+            a.len(),
+            b.len(),
+            m,
+            k,
+            k,
+            n
+        );
+    }
+    check_params(&[], &[a_zero_point, b_zero_point]);
+    accumulate(a, a_zero_point, b, &[b_zero_point], m, k, n)
+}
+
+/// Converts `i32` accumulators of output channel `c` with real scale
+/// `scale(c)` (plus an optional real bias) to int8 with the output
+/// parameters.
+fn requantize(
+    acc: &[i32],
+    channels: usize,
+    scale: impl Fn(usize) -> f32,
+    bias: &Option<Vec<f32>>,
+    out_scale: f32,
+    out_zero_point: i32,
+) -> Vec<i8> {
+    check_params(&[out_scale], &[out_zero_point]);
+    acc.iter()
+        .enumerate()
+        .map(|(i, a)| {
+            let c = i % channels;
+            let real = scale(c) * *a as f32 + bias.as_ref().map_or(0., |b| b[c]);
+            quantize_value(real, out_scale, out_zero_point)
+        })
+        .collect()
+}
+
+fn check_bias(bias: &Option<Vec<f32>>, channels: usize) {
+    if let Some(b) = bias {
+        if b.len() != channels {
+            panic!("expected {} bias values, got {}", channels, b.len());
+        }
+    }
+}
+
+fn output(data: Vec<i8>, shape: Vec<usize>, scale: f32, zero_point: i32) -> QTensor {
+    QTensor {
+        data,
+        shape,
+        scales: vec![scale],
+        zero_points: vec![zero_point],
+        axis: None,
+    }
+}
+
+/// Quantized `[M, K] @ [K, N]`. `a` is quantized per tensor and `b` per
+/// tensor or per column (axis 1); the result is requantized to
+/// `out_scale` and `out_zero_point`.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = quantizedMatmul))]
+pub fn quantized_matmul(a: &QTensor, b: &QTensor, out_scale: f32, out_zero_point: i32) -> QTensor {
+    if a.shape.len() != 2 || b.shape.len() != 2 || a.shape[1] != b.shape[0] {
+        panic!("can't multiply shapes {:?} and {:?}", a.shape, b.shape);
+    }
+    let (m, k, n) = (a.shape[0], a.shape[1], b.shape[1]);
+    let (sa, za) = a.per_tensor();
+    let params: Vec<(f32, i32)> = (0..n).map(|j| b.params(1, j)).collect();
+    let zeros: Vec<i32> = params.iter().map(|p| p.1).collect();
+    let acc = accumulate(&a.data, za, &b.data, &zeros, m, k, n);
+    let data = requantize(
+        &acc,
+        n,
+        |j| sa * params[j].0,
+        &None,
+        out_scale,
+        out_zero_point,
+    );
+    output(data, vec![m, n], out_scale, out_zero_point)
+}
+
+/// Per-output-channel parameters of a `[out, ...]` weight quantized per
+/// tensor or along axis 0.
+fn weight_params(weight: &QTensor) -> (Vec<f32>, Vec<i32>) {
+    (0..weight.shape[0]).map(|c| weight.params(0, c)).unzip()
+}
+
+/// `weight` `[out, in]` transposed to `[in, out]`.
+fn transposed(weight: &QTensor) -> Vec<i8> {
+    let (rows, cols) = (weight.shape[0], weight.shape[1]);
+    let mut res = vec![0; rows * cols];
+    for r in 0..rows {
+        for c in 0..cols {
+            res[c * rows + r] = weight.data[r * cols + c];
+        }
+    }
+    res
+}
+
+/// Quantized `x @ weight^T + bias` for `x` `[B, in]` quantized per tensor
+/// and `weight` `[out, in]` quantized per tensor or per output channel
+/// (axis 0). The f32 `bias` is added before requantization.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = quantizedLinear))]
+pub fn quantized_linear(
+    x: &QTensor,
+    weight: &QTensor,
+    bias: Option<Vec<f32>>,
+    out_scale: f32,
+    out_zero_point: i32,
+) -> QTensor {
+    let (acc, scales, shape) = linear_acc(x, weight, &bias);
+    let data = requantize(
+        &acc,
+        scales.len(),
+        |c| scales[c],
+        &bias,
+        out_scale,
+        out_zero_point,
+    );
+    output(data, shape, out_scale, out_zero_point)
+}
+
+/// `i32` accumulators of a quantized linear layer with the real scale of
+/// every output channel and the output shape.
+fn linear_acc(
+    x: &QTensor,
+    weight: &QTensor,
+    bias: &Option<Vec<f32>>,
+) -> (Vec<i32>, Vec<f32>, Vec<usize>) {
+    if x.shape.len() != 2 || weight.shape.len() != 2 || x.shape[1] != weight.shape[1] {
+        panic!(
+            "input of shape {:?} doesn't match weight of shape {:?}",
+            x.shape, weight.shape
+        );
+    }
+    let (batch, inputs, outputs) = (x.shape[0], x.shape[1], weight.shape[0]);
+    check_bias(bias, outputs);
+    let (sx, zx) = x.per_tensor();
+    let (scales, zeros) = weight_params(weight);
+    let acc = accumulate(
+        &x.data,
+        zx,
+        &transposed(weight),
+        &zeros,
+        batch,
+        inputs,
+        outputs,
+    );
+    let scales = scales.iter().map(|s| sx * s).collect();
+    (acc, scales, vec![batch, outputs])
+}
+
+/// Linear layer with an int8 weight and float activations: `x` is
+/// quantized on the fly with its own range, the product accumulated in
+/// `i32`, and the result returned in f32 without requantization.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = dynamicQuantizedLinear))]
+pub fn dynamic_quantized_linear(x: &NdArray, weight: &QTensor, bias: Option<Vec<f32>>) -> NdArray {
+    let x = fit_and_quantize(x, None, false).unwrap_or_else(|e| panic!("{}", e));
+    let (acc, scales, shape) = linear_acc(&x, weight, &bias);
+    let buffer: Vec<f32> = acc
+        .iter()
+        .enumerate()
+        .map(|(i, a)| {
+            let c = i % scales.len();
+            scales[c] * *a as f32 + bias.as_ref().map_or(0., |b| b[c])
+        })
+        .collect();
+    NdArray::from(&buffer, Some(shape), None)
+}
+
+/// Quantized 2-D convolution of an `[N, C, H, W]` input quantized per
+/// tensor with an `[M, C, kh, kw]` weight quantized per tensor or per
+/// output channel (axis 0). Padding uses the input's zero point, i.e. a
+/// real zero. The result is `[N, M, oh, ow]`.
+#[allow(clippy::too_many_arguments)]
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = quantizedConv2d))]
+pub fn quantized_conv2d(
+    x: &QTensor,
+    weight: &QTensor,
+    bias: Option<Vec<f32>>,
+    stride: Option<usize>,
+    padding: Option<usize>,
+    out_scale: f32,
+    out_zero_point: i32,
+) -> QTensor {
+    let (stride, pad) = (stride.unwrap_or(1), padding.unwrap_or(0));
+    assert!(stride > 0, "stride must be positive");
+    if x.shape.len() != 4 || weight.shape.len() != 4 || x.shape[1] != weight.shape[1] {
+        panic!(
+            "input of shape {:?} doesn't match weight of shape {:?}",
+            x.shape, weight.shape
+        );
+    }
+    let (n, c, h, w) = (x.shape[0], x.shape[1], x.shape[2], x.shape[3]);
+    let (m, kh, kw) = (weight.shape[0], weight.shape[2], weight.shape[3]);
+    if h + 2 * pad < kh || w + 2 * pad < kw {
+        panic!("kernel {}x{} is larger than the padded input", kh, kw);
+    }
+    let (oh, ow) = (
+        (h + 2 * pad - kh) / stride + 1,
+        (w + 2 * pad - kw) / stride + 1,
+    );
+    check_bias(&bias, m);
+    let (sx, zx) = x.per_tensor();
+    let (scales, zeros) = weight_params(weight);
+    let mut data = Vec::with_capacity(n * m * oh * ow);
+    let window = c * kh * kw;
+    let wt = transposed(&QTensor {
+        data: weight.data.clone(),
+        shape: vec![m, window],
+        ..weight.clone()
+    });
+    for b in 0..n {
+        let img = &x.data[b * c * h * w..(b + 1) * c * h * w];
+        // im2col: one row of C * kh * kw values per output pixel, with the
+        // input's zero point in the padding
+        let mut cols = vec![zx as i8; oh * ow * window];
+        for r in 0..oh {
+            for s in 0..ow {
+                let row = &mut cols[(r * ow + s) * window..(r * ow + s + 1) * window];
+                for ch in 0..c {
+                    for i in 0..kh {
+                        let y = (r * stride + i) as isize - pad as isize;
+                        if y < 0 || y as usize >= h {
+                            continue;
+                        }
+                        for j in 0..kw {
+                            let xx = (s * stride + j) as isize - pad as isize;
+                            if xx >= 0 && (xx as usize) < w {
+                                row[(ch * kh + i) * kw + j] =
+                                    img[(ch * h + y as usize) * w + xx as usize];
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        // [oh * ow, M] accumulators, stored channel-major in the output
+        let acc = accumulate(&cols, zx, &wt, &zeros, oh * ow, window, m);
+        let q = requantize(
+            &acc,
+            m,
+            |c| sx * scales[c],
+            &bias,
+            out_scale,
+            out_zero_point,
+        );
+        for ch in 0..m {
+            data.extend((0..oh * ow).map(|p| q[p * m + ch]));
+        }
+    }
+    output(data, vec![n, m, oh, ow], out_scale, out_zero_point)
+}
+
+#[cfg(test)]
+fn assert_close(a: &[f32], b: &[f32], tol: f32) {
+    assert_eq!(a.len(), b.len());
+    for (x, y) in a.iter().zip(b) {
+        assert!((x - y).abs() <= tol, "{:?} vs {:?}", a, b);
+    }
+}
+
+#[test]
+fn test_quantize_roundtrip() {
+    let x = NdArray::from(&[-1., -0.25, 0., 0.3, 0.5, 2.], Some(vec![2, 3]), None);
+    let q = quantize(&x, 0.25, 3).unwrap();
+    assert_eq!(q.data(), vec![-1, 2, 3, 4, 5, 11]);
+    assert_close(&q.dequantize().buffer, &[-1., -0.25, 0., 0.25, 0.5, 2.], 0.);
+    // saturates instead of wrapping
+    assert_eq!(quantize(&x, 0.01, 0).unwrap().data()[5], 127);
+
+    let q = quantize_dynamic(&x, None, None).unwrap();
+    assert_close(&q.dequantize().buffer, &x.buffer, q.scales()[0] / 2.);
+    assert_eq!(q.dequantize().buffer[2], 0.);
+
+    let q = quantize_dynamic(&x, Some(0), Some(true)).unwrap();
+    assert_eq!(q.zero_points(), vec![0, 0]);
+    assert_close(&q.scales(), &[1. / 127., 2. / 127.], 1e-7);
+    assert_eq!(q.data()[0], -127);
+    assert_eq!(q.data()[5], 127);
+
+    let q = quantize_per_channel(&x, vec![0.5, 0.25, 0.1], vec![0, 1, -2], 1).unwrap();
+    assert_eq!(q.data(), vec![-2, 0, -2, 1, 3, 18]);
+    assert_close(
+        &dequantize(&q).buffer,
+        &[-1., -0.25, 0., 0.5, 0.5, 2.],
+        1e-6,
+    );
+
+    let parts = QTensor::from_parts(q.data(), q.shape(), q.scales(), q.zero_points(), q.axis());
+    assert_eq!(parts.unwrap().dequantize().buffer, q.dequantize().buffer);
+    let data = vec![1i8; 6];
+    for (shape, scales, zero_points, axis) in [
+        (vec![3, 3], vec![1.], vec![0], None),
+        (vec![usize::MAX, 2], vec![1.], vec![0], None),
+        (vec![2, 3], vec![1.], vec![0], Some(2)),
+        (vec![2, 3], vec![1., 1.], vec![0, 0], Some(1)),
+        (vec![2, 3], vec![0.], vec![0], None),
+        (vec![2, 3], vec![1.], vec![128], None),
+    ] {
+        assert!(QTensor::from_parts(data.clone(), shape, scales, zero_points, axis).is_err());
+    }
+    // the quantizers check their parameters the same way
+    assert!(quantize_with(&x, vec![0.], vec![0], None).is_err());
+    assert!(quantize_with(&x, vec![1.], vec![128], None).is_err());
+    assert!(quantize_with(&x, vec![1.; 3], vec![0; 3], Some(2)).is_err());
+    assert!(quantize_with(&x, vec![1.; 2], vec![0; 2], Some(1)).is_err());
+    assert!(matches!(
+        fit_and_quantize(&x, Some(2), false),
+        Err(Error::InvalidArgument(_))
+    ));
+    let inf = NdArray::from(&[f32::INFINITY], None, None);
+    assert!(fit_and_quantize(&inf, None, true).is_err());
+}
+
+#[test]
+fn test_quantized_matmul() {
+    // values that overflow an i8 accumulator many times over
+    let a = vec![127i8; 64];
+    let b = vec![-128i8; 64];
+    assert_eq!(qmatmul_i32(&a, 0, &b, 0, 1, 64, 1), vec![127 * -128 * 64]);
+    assert_eq!(
+        qmatmul_i32(&[3, 4], 1, &[5, 6], 2, 1, 2, 1),
+        vec![2 * 3 + 3 * 4]
+    );
+
+    let x = NdArray::from(&[0.5, -1.2, 0.3, 2.0, 0.0, -0.7], Some(vec![2, 3]), None);
+    let w = NdArray::from(&[1.5, -0.5, 0.25, 0.75, -1.0, 0.6], Some(vec![3, 2]), None);
+    let qx = quantize_dynamic(&x, None, None).unwrap();
+    let qw = quantize_dynamic(&w, Some(1), Some(true)).unwrap();
+    let expected = qx.dequantize().matmul(&qw.dequantize());
+    let out = quantized_matmul(&qx, &qw, 0.03, -5);
+    assert_eq!(out.shape(), vec![2, 2]);
+    assert_close(&out.dequantize().buffer, &expected.buffer, 0.015);
+}
+
+#[test]
+#[should_panic(expected = "at most 33025 fit")]
+fn test_accumulate_depth() {
+    let k = MAX_DEPTH + 1;
+    qmatmul_i32(&vec![127; k], -128, &vec![127; k], -128, 1, k, 1);
+}
+
+#[test]
+fn test_quantized_layers() {
+    let x = NdArray::from(&[0.5, -1.2, 0.3, 2.0, 0.0, -0.7], Some(vec![2, 3]), None);
+    let w = NdArray::from(
+        &[
+            1.5, -0.5, 0.25, 0.75, -1.0, 0.6, 0.1, 0.2, -0.3, 0.4, 0.0, -0.9,
+        ],
+        Some(vec![4, 3]),
+        None,
+    );
+    let bias = vec![0.1, -0.2, 0.3, 0.];
+    let qw = quantize_dynamic(&w, Some(0), Some(true)).unwrap();
+    let reference = |x: &NdArray| {
+        let mut y = x.matmul(&qw.dequantize().transpose());
+        for (i, v) in y.buffer.iter_mut().enumerate() {
+            *v += bias[i % 4];
+        }
+        y
+    };
+    let qx = quantize_dynamic(&x, None, None).unwrap();
+    let out = quantized_linear(&qx, &qw, Some(bias.clone()), 0.03, 0);
+    assert_close(
+        &out.dequantize().buffer,
+        &reference(&qx.dequantize()).buffer,
+        0.015,
+    );
+    let out = dynamic_quantized_linear(&x, &qw, Some(bias.clone()));
+    assert_close(&out.buffer, &reference(&qx.dequantize()).buffer, 1e-5);
+
+    // conv against a direct float convolution of the dequantized values
+    let x = NdArray::from(
+        &(0..32).map(|i| (i as f32 * 0.37).sin()).collect::<Vec<_>>(),
+        Some(vec![1, 2, 4, 4]),
+        None,
+    );
+    let w = NdArray::from(
+        &(0..54)
+            .map(|i| (i as f32 * 0.91).cos() * 0.5)
+            .collect::<Vec<_>>(),
+        Some(vec![3, 2, 3, 3]),
+        None,
+    );
+    let (qx, qw) = (
+        quantize_dynamic(&x, None, None).unwrap(),
+        quantize_dynamic(&w, Some(0), Some(true)).unwrap(),
+    );
+    let (xd, wd) = (qx.dequantize(), qw.dequantize());
+    let bias = vec![0.5, 0., -0.5];
+    let out = quantized_conv2d(&qx, &qw, Some(bias.clone()), Some(2), Some(1), 0.02, 10);
+    assert_eq!(out.shape(), vec![1, 3, 2, 2]);
+    let mut expected = vec![];
+    for (m, b) in bias.iter().enumerate() {
+        for r in 0..2 {
+            for s in 0..2 {
+                let mut sum = *b;
+                for c in 0..2 {
+                    for i in 0..3 {
+                        for j in 0..3 {
+                            let (y, xx) = ((r * 2 + i) as isize - 1, (s * 2 + j) as isize - 1);
+                            if (0..4).contains(&y) && (0..4).contains(&xx) {
+                                sum += xd.buffer[(c * 4 + y as usize) * 4 + xx as usize]
+                                    * wd.buffer[((m * 2 + c) * 3 + i) * 3 + j];
+                            }
+                        }
+                    }
+                }
+                expected.push(sum);
+            }
+        }
+    }
+    assert_close(&out.dequantize().buffer, &expected, 0.01 + 1e-6);
+
+    // without input channels only the bias is left
+    let qx = QTensor::from_parts(vec![], vec![2, 0, 3, 3], vec![1.], vec![0], None).unwrap();
+    let qw = QTensor::from_parts(vec![], vec![2, 0, 2, 2], vec![1.], vec![0], None).unwrap();
+    let out = quantized_conv2d(&qx, &qw, Some(vec![0.5, -0.5]), None, None, 0.02, 0);
+    assert_eq!(out.shape(), vec![2, 2, 2, 2]);
+    let expected: Vec<f32> = (0..16)
+        .map(|i| if i / 4 % 2 == 0 { 0.5 } else { -0.5 })
+        .collect();
+    assert_close(&out.dequantize().buffer, &expected, 1e-6);
+}
@@ -0,0 +1,89 @@
+//! Seedable random number generation.
+//!
+//! `NdArray::rand` and friends draw from the thread-local generator; kernels
+//! that need reproducible randomness take a [`Generator`] instead.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::ndarray::NdArray;
+
+/// A random number generator that yields the same sequence for the same
+/// seed.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct Generator {
+    rng: StdRng,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Generator {
+    /// Seeds from `seed`, or from the OS when it is omitted.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(seed: Option<u64>) -> Generator {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Generator { rng }
+    }
+
+    /// Restarts the sequence from `seed`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = manualSeed))]
+    pub fn manual_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Uniform samples in `[0, 1)`.
+    pub fn rand(&mut self, shape: &[usize]) -> NdArray {
+        let buffer: Vec<f32> = (0..shape.iter().product())
+            .map(|_| self.uniform())
+            .collect();
+        NdArray::from(&buffer, Some(shape.to_vec()), None)
+    }
+
+    /// Standard normal samples.
+    pub fn randn(&mut self, shape: &[usize]) -> NdArray {
+        let buffer: Vec<f32> = (0..shape.iter().product())
+            .map(|_| {
+                let u = 1.0 - self.uniform();
+                let v = self.uniform();
+                f32::sqrt(-2.0 * f32::ln(u)) * f32::cos(2.0 * std::f32::consts::PI * v)
+            })
+            .collect();
+        NdArray::from(&buffer, Some(shape.to_vec()), None)
+    }
+}
+
+impl Generator {
+    pub fn uniform(&mut self) -> f32 {
+        self.rng.gen()
+    }
+
+    /// `true` with probability `p`.
+    pub fn bernoulli(&mut self, p: f32) -> bool {
+        self.uniform() < p
+    }
+}
+
+impl Default for Generator {
+    fn default() -> Self {
+        Generator::new(None)
+    }
+}
+
+#[test]
+fn test_generator_is_reproducible() {
+    let mut a = Generator::new(Some(7));
+    let mut b = Generator::new(Some(7));
+    let x = a.rand(&[2, 3]);
+    assert_eq!(x.buffer, b.rand(&[2, 3]).buffer);
+    assert!(x.buffer.iter().all(|v| (0. ..1.).contains(v)));
+    assert_ne!(x.buffer, a.rand(&[2, 3]).buffer);
+    a.manual_seed(7);
+    assert_eq!(a.rand(&[2, 3]).buffer, x.buffer);
+    assert_eq!(a.randn(&[4]).buffer, b.randn(&[4]).buffer);
+}
@@ -0,0 +1,1009 @@
+//! Recurrent cells and full-sequence LSTM/GRU with backprop through time.
+//!
+//! Weights are laid out like `torch.nn.LSTM`/`GRU`: `weight_ih` is
+//! `[G * H, F]` and `weight_hh` is `[G * H, H]`, with the `G` gates stacked
+//! as `i, f, g, o` (LSTM) or `r, z, n` (GRU). Sequences are `[T, B, F]` and
+//! initial/final states are `[L * D, B, H]` for `L` layers and `D`
+//! directions, ordered layer-major.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+#[cfg(test)]
+use crate::gradcheck::assert_grad;
+use crate::ndarray::NdArray;
+use crate::random::Generator;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Cell {
+    Rnn,
+    Lstm,
+    Gru,
+}
+
+impl Cell {
+    fn gates(self) -> usize {
+        match self {
+            Cell::Rnn => 1,
+            Cell::Lstm => 4,
+            Cell::Gru => 3,
+        }
+    }
+}
+
+/// Parameters of one recurrent cell.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct CellWeights {
+    weight_ih: NdArray,
+    weight_hh: NdArray,
+    bias_ih: NdArray,
+    bias_hh: NdArray,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CellWeights {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        weight_ih: &NdArray,
+        weight_hh: &NdArray,
+        bias_ih: &NdArray,
+        bias_hh: &NdArray,
+    ) -> CellWeights {
+        let rows = weight_hh.shape.first().copied().unwrap_or(0);
+        if weight_ih.shape.len() != 2
+            || weight_hh.shape.len() != 2
+            || weight_ih.shape[0] != rows
+            || bias_ih.shape != [rows]
+            || bias_hh.shape != [rows]
+        {
+            panic!(
+                "inconsistent cell weights {:?}, {:?}, {:?} and {:?}",
+                weight_ih.shape, weight_hh.shape, bias_ih.shape, bias_hh.shape
+            );
+        }
+        CellWeights {
+            weight_ih: weight_ih.clone(),
+            weight_hh: weight_hh.clone(),
+            bias_ih: bias_ih.clone(),
+            bias_hh: bias_hh.clone(),
+        }
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = weightIh))]
+    pub fn weight_ih(&self) -> NdArray {
+        self.weight_ih.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = weightHh))]
+    pub fn weight_hh(&self) -> NdArray {
+        self.weight_hh.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = biasIh))]
+    pub fn bias_ih(&self) -> NdArray {
+        self.bias_ih.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = biasHh))]
+    pub fn bias_hh(&self) -> NdArray {
+        self.bias_hh.clone()
+    }
+}
+
+impl CellWeights {
+    /// Uniform in `[-1 / sqrt(H), 1 / sqrt(H)]`, as PyTorch initializes.
+    fn init(gates: usize, input: usize, hidden: usize, rng: &mut Generator) -> Self {
+        let k = 1. / (hidden as f32).sqrt();
+        let mut uniform = |shape: &[usize]| rng.rand(shape).mul_scalar(2. * k).add_scalar(-k);
+        CellWeights {
+            weight_ih: uniform(&[gates * hidden, input]),
+            weight_hh: uniform(&[gates * hidden, hidden]),
+            bias_ih: uniform(&[gates * hidden]),
+            bias_hh: uniform(&[gates * hidden]),
+        }
+    }
+
+    fn zeros_like(&self) -> Self {
+        CellWeights {
+            weight_ih: NdArray::zeros(&self.weight_ih.shape),
+            weight_hh: NdArray::zeros(&self.weight_hh.shape),
+            bias_ih: NdArray::zeros(&self.bias_ih.shape),
+            bias_hh: NdArray::zeros(&self.bias_hh.shape),
+        }
+    }
+
+    fn accumulate(&mut self, other: &CellWeights) {
+        self.weight_ih.add_(&other.weight_ih);
+        self.weight_hh.add_(&other.weight_hh);
+        self.bias_ih.add_(&other.bias_ih);
+        self.bias_hh.add_(&other.bias_hh);
+    }
+
+    fn hidden_size(&self) -> usize {
+        self.weight_hh.shape[1]
+    }
+
+    fn check(&self, cell: Cell, x: &NdArray, h: &NdArray) {
+        let hidden = self.hidden_size();
+        if self.weight_hh.shape[0] != cell.gates() * hidden {
+            panic!(
+                "weight_hh of shape {:?} doesn't hold {} gates",
+                self.weight_hh.shape,
+                cell.gates()
+            );
+        }
+        if x.shape.len() != 2
+            || x.shape[1] != self.weight_ih.shape[1]
+            || h.shape != [x.shape[0], hidden]
+        {
+            panic!(
+                "input {:?} and state {:?} don't match weight_ih {:?}",
+                x.shape, h.shape, self.weight_ih.shape
+            );
+        }
+    }
+}
+
+/// Hidden and cell state of an LSTM.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct LstmState {
+    h: NdArray,
+    c: NdArray,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl LstmState {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn h(&self) -> NdArray {
+        self.h.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn c(&self) -> NdArray {
+        self.c.clone()
+    }
+}
+
+/// Gradients of one cell step with respect to its input, previous states
+/// and weights.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct CellGrads {
+    input: NdArray,
+    hidden: NdArray,
+    cell: Option<NdArray>,
+    weights: CellWeights,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CellGrads {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn input(&self) -> NdArray {
+        self.input.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn hidden(&self) -> NdArray {
+        self.hidden.clone()
+    }
+
+    /// Gradient of the previous cell state; LSTM only.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn cell(&self) -> Option<NdArray> {
+        self.cell.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn weights(&self) -> CellWeights {
+        self.weights.clone()
+    }
+}
+
+/// Everything a cell step computes. `acts` holds the activated gates as
+/// `[B, G * H]`; `extra` is `tanh(c')` for an LSTM and the hidden part of
+/// the `n` gate, `h @ w_hn^T + b_hn`, for a GRU.
+struct Step {
+    h: NdArray,
+    c: Option<NdArray>,
+    acts: Vec<f32>,
+    extra: Vec<f32>,
+}
+
+fn affine(x: &NdArray, w: &NdArray, b: &NdArray) -> NdArray {
+    x.matmul(&w.transpose()).add(b)
+}
+
+fn step(cell: Cell, x: &NdArray, h: &NdArray, c: Option<&NdArray>, w: &CellWeights) -> Step {
+    w.check(cell, x, h);
+    let (batch, hidden) = (x.shape[0], w.hidden_size());
+    let gates = cell.gates();
+    let at = |b: usize, j: usize, k: usize| (b * gates + j) * hidden + k;
+    let gi = affine(x, &w.weight_ih, &w.bias_ih);
+    let gh = affine(h, &w.weight_hh, &w.bias_hh);
+    let mut next = NdArray::zeros(&[batch, hidden]);
+    match cell {
+        Cell::Rnn => {
+            let h = gi.add(&gh).tanh();
+            Step {
+                acts: h.buffer.clone(),
+                h,
+                c: None,
+                extra: vec![],
+            }
+        }
+        Cell::Lstm => {
+            let c = c.expect("an LSTM step needs a cell state");
+            let a = gi.add(&gh);
+            let (mut acts, th) = (a.sigmoid().buffer, a.tanh());
+            let mut c_next = NdArray::zeros(&[batch, hidden]);
+            for b in 0..batch {
+                for k in 0..hidden {
+                    acts[at(b, 2, k)] = th.buffer[at(b, 2, k)];
+                    let (i, f, g) = (acts[at(b, 0, k)], acts[at(b, 1, k)], acts[at(b, 2, k)]);
+                    c_next.buffer[b * hidden + k] = f * c.buffer[b * hidden + k] + i * g;
+                }
+            }
+            let tc = c_next.tanh();
+            for b in 0..batch {
+                for k in 0..hidden {
+                    next.buffer[b * hidden + k] = acts[at(b, 3, k)] * tc.buffer[b * hidden + k];
+                }
+            }
+            Step {
+                h: next,
+                c: Some(c_next),
+                acts,
+                extra: tc.buffer,
+            }
+        }
+        Cell::Gru => {
+            let mut acts = gi.add(&gh).sigmoid().buffer;
+            let mut n = NdArray::zeros(&[batch, hidden]);
+            let mut extra = vec![0.; batch * hidden];
+            for b in 0..batch {
+                for k in 0..hidden {
+                    let ghn = gh.buffer[at(b, 2, k)];
+                    extra[b * hidden + k] = ghn;
+                    n.buffer[b * hidden + k] = gi.buffer[at(b, 2, k)] + acts[at(b, 0, k)] * ghn;
+                }
+            }
+            let n = n.tanh();
+            for b in 0..batch {
+                for k in 0..hidden {
+                    let e = b * hidden + k;
+                    let z = acts[at(b, 1, k)];
+                    acts[at(b, 2, k)] = n.buffer[e];
+                    next.buffer[e] = (1. - z) * n.buffer[e] + z * h.buffer[e];
+                }
+            }
+            Step {
+                h: next,
+                c: None,
+                acts,
+                extra,
+            }
+        }
+    }
+}
+
+fn column_sums(a: &NdArray) -> NdArray {
+    let cols = a.shape[1];
+    let mut res = NdArray::zeros(&[cols]);
+    for row in a.buffer.chunks(cols.max(1)) {
+        res.buffer.iter_mut().zip(row).for_each(|(s, x)| *s += x);
+    }
+    res
+}
+
+fn step_backward(
+    cell: Cell,
+    dh: &NdArray,
+    dc: Option<&NdArray>,
+    x: &NdArray,
+    h: &NdArray,
+    c: Option<&NdArray>,
+    w: &CellWeights,
+) -> CellGrads {
+    let s = step(cell, x, h, c, w);
+    assert_eq!(dh.shape, s.h.shape, "grad doesn't match the hidden state");
+    let (batch, hidden) = (x.shape[0], w.hidden_size());
+    let gates = cell.gates();
+    let at = |b: usize, j: usize, k: usize| (b * gates + j) * hidden + k;
+    let mut dgi = NdArray::zeros(&[batch, gates * hidden]);
+    let mut direct = None;
+    let mut dc_prev = None;
+    match cell {
+        Cell::Rnn => {
+            for (d, (g, h)) in dgi.buffer.iter_mut().zip(dh.buffer.iter().zip(&s.acts)) {
+                *d = g * (1. - h * h);
+            }
+        }
+        Cell::Lstm => {
+            let c = c.unwrap();
+            let mut prev = NdArray::zeros(&[batch, hidden]);
+            for b in 0..batch {
+                for k in 0..hidden {
+                    let e = b * hidden + k;
+                    let (i, f, g, o) = (
+                        s.acts[at(b, 0, k)],
+                        s.acts[at(b, 1, k)],
+                        s.acts[at(b, 2, k)],
+                        s.acts[at(b, 3, k)],
+                    );
+                    let tc = s.extra[e];
+                    let dct = dc.map_or(0., |dc| dc.buffer[e]) + dh.buffer[e] * o * (1. - tc * tc);
+                    prev.buffer[e] = dct * f;
+                    dgi.buffer[at(b, 0, k)] = dct * g * i * (1. - i);
+                    dgi.buffer[at(b, 1, k)] = dct * c.buffer[e] * f * (1. - f);
+                    dgi.buffer[at(b, 2, k)] = dct * i * (1. - g * g);
+                    dgi.buffer[at(b, 3, k)] = dh.buffer[e] * tc * o * (1. - o);
+                }
+            }
+            dc_prev = Some(prev);
+        }
+        Cell::Gru => {
+            let mut d = NdArray::zeros(&[batch, hidden]);
+            for b in 0..batch {
+                for k in 0..hidden {
+                    let e = b * hidden + k;
+                    let (r, z, n) = (
+                        s.acts[at(b, 0, k)],
+                        s.acts[at(b, 1, k)],
+                        s.acts[at(b, 2, k)],
+                    );
+                    let g = dh.buffer[e];
+                    d.buffer[e] = g * z;
+                    let dan = g * (1. - z) * (1. - n * n);
+                    dgi.buffer[at(b, 0, k)] = dan * s.extra[e] * r * (1. - r);
+                    dgi.buffer[at(b, 1, k)] = g * (h.buffer[e] - n) * z * (1. - z);
+                    dgi.buffer[at(b, 2, k)] = dan;
+                }
+            }
+            direct = Some(d);
+        }
+    }
+    // the hidden-side pre-activations get the same gradient, except for the
+    // GRU's n gate where they are scaled by r
+    let mut dgh = dgi.clone();
+    if cell == Cell::Gru {
+        for b in 0..batch {
+            for k in 0..hidden {
+                dgh.buffer[at(b, 2, k)] *= s.acts[at(b, 0, k)];
+            }
+        }
+    }
+    let mut hidden_grad = dgh.matmul(&w.weight_hh);
+    if let Some(d) = direct {
+        hidden_grad.add_(&d);
+    }
+    CellGrads {
+        input: dgi.matmul(&w.weight_ih),
+        hidden: hidden_grad,
+        cell: dc_prev,
+        weights: CellWeights {
+            weight_ih: dgi.transpose().matmul(x),
+            weight_hh: dgh.transpose().matmul(h),
+            bias_ih: column_sums(&dgi),
+            bias_hh: column_sums(&dgh),
+        },
+    }
+}
+
+/// `h' = tanh(x @ w_ih^T + b_ih + h @ w_hh^T + b_hh)` for `[B, F]` input
+/// and `[B, H]` state.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = rnnCell))]
+pub fn rnn_cell(x: &NdArray, h: &NdArray, weights: &CellWeights) -> NdArray {
+    step(Cell::Rnn, x, h, None, weights).h
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = lstmCell))]
+pub fn lstm_cell(x: &NdArray, h: &NdArray, c: &NdArray, weights: &CellWeights) -> LstmState {
+    let s = step(Cell::Lstm, x, h, Some(c), weights);
+    LstmState {
+        h: s.h,
+        c: s.c.unwrap(),
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = gruCell))]
+pub fn gru_cell(x: &NdArray, h: &NdArray, weights: &CellWeights) -> NdArray {
+    step(Cell::Gru, x, h, None, weights).h
+}
+
+/// Gradients of `rnn_cell` given the gradient of the new hidden state.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = rnnCellBackward))]
+pub fn rnn_cell_backward(
+    grad_h: &NdArray,
+    x: &NdArray,
+    h: &NdArray,
+    weights: &CellWeights,
+) -> CellGrads {
+    step_backward(Cell::Rnn, grad_h, None, x, h, None, weights)
+}
+
+/// Gradients of `lstm_cell` given the gradients of the new hidden and
+/// cell states.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = lstmCellBackward))]
+pub fn lstm_cell_backward(
+    grad_h: &NdArray,
+    grad_c: &NdArray,
+    x: &NdArray,
+    h: &NdArray,
+    c: &NdArray,
+    weights: &CellWeights,
+) -> CellGrads {
+    step_backward(Cell::Lstm, grad_h, Some(grad_c), x, h, Some(c), weights)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = gruCellBackward))]
+pub fn gru_cell_backward(
+    grad_h: &NdArray,
+    x: &NdArray,
+    h: &NdArray,
+    weights: &CellWeights,
+) -> CellGrads {
+    step_backward(Cell::Gru, grad_h, None, x, h, None, weights)
+}
+
+/// Cell weights of a stacked, optionally bidirectional, recurrent network.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct RnnWeights {
+    cells: Vec<CellWeights>,
+    num_layers: usize,
+    bidirectional: bool,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl RnnWeights {
+    /// Randomly initialized LSTM weights.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = initLstm))]
+    pub fn init_lstm(
+        input_size: usize,
+        hidden_size: usize,
+        num_layers: usize,
+        bidirectional: bool,
+        rng: &mut Generator,
+    ) -> RnnWeights {
+        RnnWeights::init(
+            Cell::Lstm,
+            input_size,
+            hidden_size,
+            num_layers,
+            bidirectional,
+            rng,
+        )
+    }
+
+    /// Randomly initialized GRU weights.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = initGru))]
+    pub fn init_gru(
+        input_size: usize,
+        hidden_size: usize,
+        num_layers: usize,
+        bidirectional: bool,
+        rng: &mut Generator,
+    ) -> RnnWeights {
+        RnnWeights::init(
+            Cell::Gru,
+            input_size,
+            hidden_size,
+            num_layers,
+            bidirectional,
+            rng,
+        )
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = numLayers))]
+    pub fn num_layers(&self) -> usize {
+        self.num_layers
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn bidirectional(&self) -> bool {
+        self.bidirectional
+    }
+
+    /// Weights of `layer` in `direction` (0 forward, 1 backward).
+    pub fn get(&self, layer: usize, direction: usize) -> CellWeights {
+        self.cells[self.index(layer, direction)].clone()
+    }
+
+    pub fn set(&mut self, layer: usize, direction: usize, weights: &CellWeights) {
+        let i = self.index(layer, direction);
+        if weights.weight_ih.shape != self.cells[i].weight_ih.shape
+            || weights.weight_hh.shape != self.cells[i].weight_hh.shape
+        {
+            panic!(
+                "expected weights of shapes {:?} and {:?}",
+                self.cells[i].weight_ih.shape, self.cells[i].weight_hh.shape
+            );
+        }
+        self.cells[i] = weights.clone();
+    }
+}
+
+impl RnnWeights {
+    fn init(
+        cell: Cell,
+        input_size: usize,
+        hidden_size: usize,
+        num_layers: usize,
+        bidirectional: bool,
+        rng: &mut Generator,
+    ) -> Self {
+        assert!(num_layers > 0, "an RNN needs at least one layer");
+        let directions = if bidirectional { 2 } else { 1 };
+        let mut cells = Vec::with_capacity(num_layers * directions);
+        for layer in 0..num_layers {
+            let input = if layer == 0 {
+                input_size
+            } else {
+                hidden_size * directions
+            };
+            for _ in 0..directions {
+                cells.push(CellWeights::init(cell.gates(), input, hidden_size, rng));
+            }
+        }
+        RnnWeights {
+            cells,
+            num_layers,
+            bidirectional,
+        }
+    }
+
+    fn directions(&self) -> usize {
+        if self.bidirectional {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn index(&self, layer: usize, direction: usize) -> usize {
+        assert!(
+            layer < self.num_layers && direction < self.directions(),
+            "no weights for layer {} direction {}",
+            layer,
+            direction
+        );
+        layer * self.directions() + direction
+    }
+
+    fn hidden_size(&self) -> usize {
+        self.cells[0].hidden_size()
+    }
+}
+
+/// Result of `lstm` or `gru`, holding what the backward pass needs.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct SequenceOutput {
+    output: NdArray,
+    h_n: NdArray,
+    c_n: Option<NdArray>,
+    cell: Cell,
+    /// Input of every layer, `[T, B, F]`.
+    inputs: Vec<NdArray>,
+    /// For every layer and direction, the states fed into the step at each
+    /// time index.
+    prev: Vec<Vec<(NdArray, Option<NdArray>)>>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl SequenceOutput {
+    /// Last layer's hidden state at every step, `[T, B, D * H]`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn output(&self) -> NdArray {
+        self.output.clone()
+    }
+
+    /// Final hidden state of every layer and direction, `[L * D, B, H]`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = hN))]
+    pub fn h_n(&self) -> NdArray {
+        self.h_n.clone()
+    }
+
+    /// Final cell state; LSTM only.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = cN))]
+    pub fn c_n(&self) -> Option<NdArray> {
+        self.c_n.clone()
+    }
+}
+
+/// Gradients of `lstm` or `gru`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct SequenceGrads {
+    input: NdArray,
+    h0: NdArray,
+    c0: Option<NdArray>,
+    weights: RnnWeights,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl SequenceGrads {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn input(&self) -> NdArray {
+        self.input.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn h0(&self) -> NdArray {
+        self.h0.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn c0(&self) -> Option<NdArray> {
+        self.c0.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn weights(&self) -> RnnWeights {
+        self.weights.clone()
+    }
+}
+
+/// Stacks arrays of shape `item` along a new first axis. `item` is given
+/// explicitly so that an empty sequence stacks to `[0, ...item]`.
+fn stack(parts: &[NdArray], item: &[usize]) -> NdArray {
+    assert!(
+        parts.iter().all(|p| p.shape == item),
+        "can only stack arrays of shape {:?}",
+        item
+    );
+    let mut shape = vec![parts.len()];
+    shape.extend_from_slice(item);
+    let buffer: Vec<f32> = parts
+        .iter()
+        .flat_map(|p| p.buffer.iter().copied())
+        .collect();
+    NdArray::from(&buffer, Some(shape), None)
+}
+
+/// Joins one or more `[B, H]` arrays along their last axis.
+fn concat_last(parts: &[NdArray]) -> NdArray {
+    let (first, rest) = parts.split_first().expect("concat_last needs an array");
+    rest.iter().fold(first.clone(), |acc, p| {
+        crate::ndarray::concat_axis(&acc, p, 1)
+    })
+}
+
+/// Columns `from..from + len` of a `[B, N]` array.
+fn columns(a: &NdArray, from: usize, len: usize) -> NdArray {
+    let n = a.shape[1];
+    let buffer: Vec<f32> = a
+        .buffer
+        .chunks(n.max(1))
+        .flat_map(|row| row[from..from + len].iter().copied())
+        .collect();
+    NdArray::from(&buffer, Some(vec![a.shape[0], len]), None)
+}
+
+fn time_order(steps: usize, direction: usize) -> Box<dyn Iterator<Item = usize>> {
+    if direction == 0 {
+        Box::new(0..steps)
+    } else {
+        Box::new((0..steps).rev())
+    }
+}
+
+fn check_state(state: &NdArray, weights: &RnnWeights, batch: usize, name: &str) {
+    let expected = [
+        weights.num_layers * weights.directions(),
+        batch,
+        weights.hidden_size(),
+    ];
+    if state.shape != expected {
+        panic!(
+            "{} has shape {:?}, expected {:?}",
+            name, state.shape, expected
+        );
+    }
+}
+
+fn run(
+    cell: Cell,
+    input: &NdArray,
+    h0: &NdArray,
+    c0: Option<&NdArray>,
+    weights: &RnnWeights,
+) -> SequenceOutput {
+    if input.shape.len() != 3 {
+        panic!("expected a [T, B, F] input, got shape {:?}", input.shape);
+    }
+    let (steps, batch) = (input.shape[0], input.shape[1]);
+    check_state(h0, weights, batch, "h0");
+    if let Some(c0) = c0 {
+        check_state(c0, weights, batch, "c0");
+    }
+    let (dirs, hidden) = (weights.directions(), weights.hidden_size());
+    let mut layer_input = input.clone();
+    let (mut inputs, mut prev) = (vec![], vec![]);
+    let (mut h_n, mut c_n) = (vec![], vec![]);
+    for layer in 0..weights.num_layers {
+        let mut outputs = vec![vec![]; steps];
+        for dir in 0..dirs {
+            let i = weights.index(layer, dir);
+            let w = &weights.cells[i];
+            let mut h = h0.slice(&[i]);
+            let mut c = c0.map(|c0| c0.slice(&[i]));
+            let mut fed = vec![(h.clone(), c.clone()); steps];
+            for t in time_order(steps, dir) {
+                fed[t] = (h.clone(), c.clone());
+                let s = step(cell, &layer_input.slice(&[t]), &h, c.as_ref(), w);
+                h = s.h;
+                c = s.c;
+                outputs[t].push(h.clone());
+            }
+            prev.push(fed);
+            h_n.push(h);
+            c_n.extend(c);
+        }
+        let out: Vec<NdArray> = outputs.iter().map(|parts| concat_last(parts)).collect();
+        inputs.push(layer_input);
+        layer_input = stack(&out, &[batch, dirs * hidden]);
+    }
+    SequenceOutput {
+        output: layer_input,
+        h_n: stack(&h_n, &[batch, hidden]),
+        c_n: c0.map(|_| stack(&c_n, &[batch, hidden])),
+        cell,
+        inputs,
+        prev,
+    }
+}
+
+fn run_backward(
+    grad_output: &NdArray,
+    grad_h_n: &NdArray,
+    grad_c_n: Option<&NdArray>,
+    weights: &RnnWeights,
+    saved: &SequenceOutput,
+) -> SequenceGrads {
+    assert_eq!(
+        grad_output.shape, saved.output.shape,
+        "grad doesn't match the output"
+    );
+    assert_eq!(
+        grad_h_n.shape, saved.h_n.shape,
+        "grad_h_n doesn't match h_n"
+    );
+    let (steps, batch) = (grad_output.shape[0], grad_output.shape[1]);
+    let (dirs, hidden) = (weights.directions(), weights.hidden_size());
+    let mut grad_weights = RnnWeights {
+        cells: weights.cells.iter().map(CellWeights::zeros_like).collect(),
+        ..weights.clone()
+    };
+    let mut dh0 = vec![NdArray::zeros(&[batch, hidden]); weights.cells.len()];
+    let mut dc0 = dh0.clone();
+    let mut grad_above = grad_output.clone();
+    for layer in (0..weights.num_layers).rev() {
+        let x = &saved.inputs[layer];
+        let mut grad_in = vec![NdArray::zeros(&[batch, x.shape[2]]); steps];
+        for dir in 0..dirs {
+            let i = weights.index(layer, dir);
+            let w = &weights.cells[i];
+            let mut dh = grad_h_n.slice(&[i]);
+            let mut dc = grad_c_n.map(|g| g.slice(&[i]));
+            for t in time_order(steps, dir).collect::<Vec<_>>().into_iter().rev() {
+                dh.add_(&columns(&grad_above.slice(&[t]), dir * hidden, hidden));
+                let (h, c) = &saved.prev[i][t];
+                let g = step_backward(
+                    saved.cell,
+                    &dh,
+                    dc.as_ref(),
+                    &x.slice(&[t]),
+                    h,
+                    c.as_ref(),
+                    w,
+                );
+                grad_in[t].add_(&g.input);
+                grad_weights.cells[i].accumulate(&g.weights);
+                dh = g.hidden;
+                dc = g.cell;
+            }
+            dh0[i] = dh;
+            if let Some(dc) = dc {
+                dc0[i] = dc;
+            }
+        }
+        grad_above = stack(&grad_in, &[batch, x.shape[2]]);
+    }
+    SequenceGrads {
+        input: grad_above,
+        h0: stack(&dh0, &[batch, hidden]),
+        c0: saved.c_n.as_ref().map(|_| stack(&dc0, &[batch, hidden])),
+        weights: grad_weights,
+    }
+}
+
+/// Runs a multi-layer LSTM over a `[T, B, F]` sequence from the initial
+/// states `h0` and `c0`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn lstm(input: &NdArray, h0: &NdArray, c0: &NdArray, weights: &RnnWeights) -> SequenceOutput {
+    run(Cell::Lstm, input, h0, Some(c0), weights)
+}
+
+/// Runs a multi-layer GRU over a `[T, B, F]` sequence from the initial
+/// state `h0`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn gru(input: &NdArray, h0: &NdArray, weights: &RnnWeights) -> SequenceOutput {
+    run(Cell::Gru, input, h0, None, weights)
+}
+
+/// Backprop through time for `lstm`. Pass zeros for the gradients of the
+/// final states when they aren't used.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = lstmBackward))]
+pub fn lstm_backward(
+    grad_output: &NdArray,
+    grad_h_n: &NdArray,
+    grad_c_n: &NdArray,
+    weights: &RnnWeights,
+    saved: &SequenceOutput,
+) -> SequenceGrads {
+    assert_eq!(saved.cell, Cell::Lstm, "saved output isn't from an LSTM");
+    run_backward(grad_output, grad_h_n, Some(grad_c_n), weights, saved)
+}
+
+/// Backprop through time for `gru`.
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = gruBackward))]
+pub fn gru_backward(
+    grad_output: &NdArray,
+    grad_h_n: &NdArray,
+    weights: &RnnWeights,
+    saved: &SequenceOutput,
+) -> SequenceGrads {
+    assert_eq!(saved.cell, Cell::Gru, "saved output isn't from a GRU");
+    run_backward(grad_output, grad_h_n, None, weights, saved)
+}
+
+#[test]
+fn test_cells() {
+    let mut rng = Generator::new(Some(0));
+    let (x, h, c) = (rng.randn(&[2, 3]), rng.randn(&[2, 4]), rng.randn(&[2, 4]));
+    let r = rng.randn(&[2, 4]);
+
+    let w = CellWeights::init(1, 3, 4, &mut rng);
+    let out = rnn_cell(&x, &h, &w);
+    assert_eq!(out.shape, vec![2, 4]);
+    let g = rnn_cell_backward(&r, &x, &h, &w);
+    assert_grad(&g.input, &x, &r, |x| rnn_cell(x, &h, &w));
+    assert_grad(&g.hidden, &h, &r, |h| rnn_cell(&x, h, &w));
+
+    let w = CellWeights::init(4, 3, 4, &mut rng);
+    let rc = rng.randn(&[2, 4]);
+    let g = lstm_cell_backward(&r, &rc, &x, &h, &c, &w);
+    // loss = sum(h' * r) + sum(c' * rc)
+    let both = |x: &NdArray, h: &NdArray, c: &NdArray| {
+        let s = lstm_cell(x, h, c, &w);
+        concat_last(&[s.h, s.c])
+    };
+    let rr = concat_last(&[r.clone(), rc]);
+    assert_grad(&g.input, &x, &rr, |x| both(x, &h, &c));
+    assert_grad(&g.hidden, &h, &rr, |h| both(&x, h, &c));
+    assert_grad(g.cell.as_ref().unwrap(), &c, &rr, |c| both(&x, &h, c));
+    assert_grad(&g.weights.weight_hh, &w.weight_hh, &rr, |wh| {
+        let w = CellWeights {
+            weight_hh: wh.clone(),
+            ..w.clone()
+        };
+        let s = lstm_cell(&x, &h, &c, &w);
+        concat_last(&[s.h, s.c])
+    });
+
+    let w = CellWeights::init(3, 3, 4, &mut rng);
+    let g = gru_cell_backward(&r, &x, &h, &w);
+    assert_grad(&g.input, &x, &r, |x| gru_cell(x, &h, &w));
+    assert_grad(&g.hidden, &h, &r, |h| gru_cell(&x, h, &w));
+    assert_grad(&g.weights.weight_ih, &w.weight_ih, &r, |wi| {
+        gru_cell(
+            &x,
+            &h,
+            &CellWeights {
+                weight_ih: wi.clone(),
+                ..w.clone()
+            },
+        )
+    });
+    assert_grad(&g.weights.bias_hh, &w.bias_hh, &r, |bh| {
+        gru_cell(
+            &x,
+            &h,
+            &CellWeights {
+                bias_hh: bh.clone(),
+                ..w.clone()
+            },
+        )
+    });
+}
+
+#[test]
+fn test_sequence_models() {
+    let mut rng = Generator::new(Some(1));
+    let (steps, batch, features, hidden) = (3, 2, 3, 2);
+    let x = rng.randn(&[steps, batch, features]);
+
+    let weights = RnnWeights::init_lstm(features, hidden, 2, true, &mut rng);
+    let h0 = rng.randn(&[4, batch, hidden]);
+    let c0 = rng.randn(&[4, batch, hidden]);
+    let out = lstm(&x, &h0, &c0, &weights);
+    assert_eq!(out.output().shape, vec![steps, batch, 2 * hidden]);
+    assert_eq!(out.h_n().shape, vec![4, batch, hidden]);
+    // the last layer's forward direction ends at the last step, the
+    // backward direction at the first
+    let last = out.output().slice(&[steps - 1]);
+    assert_eq!(
+        columns(&last, 0, hidden).buffer,
+        out.h_n().slice(&[2]).buffer
+    );
+    let first = out.output().slice(&[0]);
+    assert_eq!(
+        columns(&first, hidden, hidden).buffer,
+        out.h_n().slice(&[3]).buffer
+    );
+
+    let r = rng.randn(&out.output().shape);
+    let zeros = NdArray::zeros(&[4, batch, hidden]);
+    let g = lstm_backward(&r, &zeros, &zeros, &weights, &out);
+    assert_grad(&g.input(), &x, &r, |x| lstm(x, &h0, &c0, &weights).output());
+    assert_grad(&g.h0(), &h0, &r, |h0| lstm(&x, h0, &c0, &weights).output());
+    assert_grad(&g.c0().unwrap(), &c0, &r, |c0| {
+        lstm(&x, &h0, c0, &weights).output()
+    });
+    let w = weights.get(0, 1);
+    assert_grad(&g.weights().get(0, 1).weight_ih, &w.weight_ih, &r, |wi| {
+        let mut weights = weights.clone();
+        weights.set(
+            0,
+            1,
+            &CellWeights {
+                weight_ih: wi.clone(),
+                ..w.clone()
+            },
+        );
+        lstm(&x, &h0, &c0, &weights).output()
+    });
+
+    let weights = RnnWeights::init_gru(features, hidden, 2, false, &mut rng);
+    let h0 = rng.randn(&[2, batch, hidden]);
+    let out = gru(&x, &h0, &weights);
+    assert_eq!(out.output().shape, vec![steps, batch, hidden]);
+    assert!(out.c_n().is_none());
+    let r = rng.randn(&out.output().shape);
+    let rh = rng.randn(&[2, batch, hidden]);
+    let g = gru_backward(&r, &rh, &weights, &out);
+    // loss = sum(output * r) + sum(h_n * rh)
+    let both = |x: &NdArray, h0: &NdArray| {
+        let out = gru(x, h0, &weights);
+        crate::ndarray::concat_axis(&out.output().flatten(), &out.h_n().flatten(), 0)
+    };
+    let rr = crate::ndarray::concat_axis(&r.flatten(), &rh.flatten(), 0);
+    assert_grad(&g.input(), &x, &rr, |x| both(x, &h0));
+    assert_grad(&g.h0(), &h0, &rr, |h0| both(&x, h0));
+    assert_grad(
+        &g.weights().get(1, 0).bias_hh,
+        &weights.get(1, 0).bias_hh,
+        &rr,
+        |b| {
+            let mut weights = weights.clone();
+            weights.set(
+                1,
+                0,
+                &CellWeights {
+                    bias_hh: b.clone(),
+                    ..weights.get(1, 0)
+                },
+            );
+            let out = gru(&x, &h0, &weights);
+            crate::ndarray::concat_axis(&out.output().flatten(), &out.h_n().flatten(), 0)
+        },
+    );
+
+    // an empty sequence passes the states and their grads straight through
+    let empty = NdArray::zeros(&[0, batch, features]);
+    let out = gru(&empty, &h0, &weights);
+    assert_eq!(out.output().shape, vec![0, batch, hidden]);
+    assert_eq!(out.h_n().buffer, h0.buffer);
+    let g = gru_backward(&out.output(), &rh, &weights, &out);
+    assert_eq!(g.input().shape, vec![0, batch, features]);
+    assert_eq!(g.h0().buffer, rh.buffer);
+}
@@ -0,0 +1,430 @@
+//! The [safetensors](https://github.com/huggingface/safetensors) format.
+//!
+//! A file is a little-endian `u64` header length, a JSON header mapping each
+//! tensor name to its dtype, shape and byte range, and the raw tensor data.
+//! Tensors are loaded as `f32`, converting half-precision and integer data
+//! on the way in, or kept in 16 bits as `HalfNdArray`s.
+
+use half::{bf16, f16};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    dtype::DType,
+    error::{Error, JsResult, Result},
+    float16::{check_half, narrow, HalfNdArray},
+    ndarray::NdArray,
+};
+
+#[derive(Serialize, Deserialize)]
+struct TensorInfo {
+    dtype: String,
+    shape: Vec<usize>,
+    data_offsets: [usize; 2],
+}
+
+fn dtype_size(dtype: &str) -> Result<usize> {
+    match dtype {
+        "BOOL" | "U8" | "I8" => Ok(1),
+        "F16" | "BF16" | "I16" | "U16" => Ok(2),
+        "F32" | "I32" | "U32" => Ok(4),
+        "F64" | "I64" | "U64" => Ok(8),
+        _ => Err(Error::UnsupportedDType(dtype.to_string())),
+    }
+}
+
+fn le8(b: &[u8]) -> [u8; 8] {
+    let mut res = [0u8; 8];
+    res.copy_from_slice(b);
+    res
+}
+
+fn decode(dtype: &str, bytes: &[u8]) -> Vec<f32> {
+    let size = dtype_size(dtype).unwrap();
+    bytes
+        .chunks(size)
+        .map(|b| match dtype {
+            "BOOL" | "U8" => b[0] as f32,
+            "I8" => b[0] as i8 as f32,
+            "F16" => f16::from_le_bytes([b[0], b[1]]).to_f32(),
+            "BF16" => bf16::from_le_bytes([b[0], b[1]]).to_f32(),
+            "I16" => i16::from_le_bytes([b[0], b[1]]) as f32,
+            "U16" => u16::from_le_bytes([b[0], b[1]]) as f32,
+            "F32" => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            "I32" => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32,
+            "U32" => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32,
+            "F64" => f64::from_le_bytes(le8(b)) as f32,
+            "I64" => i64::from_le_bytes(le8(b)) as f32,
+            "U64" => u64::from_le_bytes(le8(b)) as f32,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Decoded contents of a safetensors file.
+#[derive(Default)]
+pub struct SafeTensorsData {
+    /// Tensors in the order they are laid out in the file.
+    pub tensors: Vec<(String, NdArray)>,
+    /// Free-form `__metadata__` string map.
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// A tensor of a safetensors file before decoding.
+struct RawTensor<'a> {
+    name: String,
+    dtype: String,
+    shape: Vec<usize>,
+    data: &'a [u8],
+}
+
+/// Splits a safetensors file into its tensors, in the order they are laid
+/// out in the file, and its metadata.
+fn parse_safetensors(bytes: &[u8]) -> Result<(Vec<RawTensor<'_>>, BTreeMap<String, String>)> {
+    if bytes.len() < 8 {
+        return Err(Error::format("safetensors file is too short"));
+    }
+    let header_len = u64::from_le_bytes(le8(&bytes[..8])) as usize;
+    if bytes.len() - 8 < header_len {
+        return Err(Error::format("truncated safetensors header"));
+    }
+    let header: Map<String, Value> = serde_json::from_slice(&bytes[8..8 + header_len])
+        .map_err(|e| Error::format(format!("safetensors header: {}", e)))?;
+    let data = &bytes[8 + header_len..];
+
+    let mut metadata = BTreeMap::new();
+    let mut tensors = Vec::new();
+    for (name, value) in header {
+        if name == "__metadata__" {
+            metadata = serde_json::from_value(value)
+                .map_err(|e| Error::format(format!("safetensors metadata: {}", e)))?;
+            continue;
+        }
+        let info: TensorInfo = serde_json::from_value(value)
+            .map_err(|e| Error::format(format!("safetensors tensor {}: {}", name, e)))?;
+        let [begin, end] = info.data_offsets;
+        let expected = info
+            .shape
+            .iter()
+            .try_fold(dtype_size(&info.dtype)?, |acc, &d| acc.checked_mul(d))
+            .ok_or_else(|| Error::format(format!("tensor {} is too large", name)))?;
+        if begin > end || end > data.len() || end - begin != expected {
+            return Err(Error::format(format!(
+                "invalid data offsets for tensor {}",
+                name
+            )));
+        }
+        tensors.push((
+            begin,
+            RawTensor {
+                name,
+                dtype: info.dtype,
+                shape: info.shape,
+                data: &data[begin..end],
+            },
+        ));
+    }
+    tensors.sort_by_key(|(begin, _)| *begin);
+    Ok((tensors.into_iter().map(|(_, t)| t).collect(), metadata))
+}
+
+pub fn read_safetensors(bytes: &[u8]) -> Result<SafeTensorsData> {
+    let (tensors, metadata) = parse_safetensors(bytes)?;
+    let tensors = tensors
+        .into_iter()
+        .map(|t| {
+            let buffer = decode(&t.dtype, t.data);
+            (t.name, NdArray::from(&buffer, Some(t.shape), None))
+        })
+        .collect();
+    Ok(SafeTensorsData { tensors, metadata })
+}
+
+fn decode_half(t: &RawTensor, dtype: Option<DType>) -> Result<HalfNdArray> {
+    let stored = match t.dtype.as_str() {
+        "F16" => Some(DType::Float16),
+        "BF16" => Some(DType::BFloat16),
+        _ => None,
+    };
+    let dtype = dtype.or(stored).unwrap_or(DType::Float16);
+    check_half(dtype)?;
+    let bits = if stored == Some(dtype) {
+        t.data
+            .chunks(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect()
+    } else {
+        decode(&t.dtype, t.data)
+            .into_iter()
+            .map(|x| narrow(x, dtype))
+            .collect()
+    };
+    HalfNdArray::from_bits(bits, Some(t.shape.clone()), dtype)
+}
+
+/// Loads every tensor of a safetensors file as a `HalfNdArray`. `F16` and
+/// `BF16` tensors keep their bits unless `dtype` asks for the other type;
+/// everything else is rounded to `dtype`, `Float16` by default.
+pub fn read_safetensors_half(
+    bytes: &[u8],
+    dtype: Option<DType>,
+) -> Result<Vec<(String, HalfNdArray)>> {
+    let (tensors, _) = parse_safetensors(bytes)?;
+    tensors
+        .iter()
+        .map(|t| Ok((t.name.clone(), decode_half(t, dtype)?)))
+        .collect()
+}
+
+/// Encodes named arrays as `F32` tensors. Names must be unique and can't be
+/// the reserved `__metadata__` key.
+pub fn write_safetensors(
+    tensors: &[(&str, &NdArray)],
+    metadata: &BTreeMap<String, String>,
+) -> Result<Vec<u8>> {
+    let mut header = Map::new();
+    if !metadata.is_empty() {
+        header.insert(
+            "__metadata__".to_string(),
+            serde_json::to_value(metadata).unwrap(),
+        );
+    }
+    let mut offset = 0;
+    for (name, array) in tensors {
+        if *name == "__metadata__" {
+            return Err(Error::format("__metadata__ is reserved as a tensor name"));
+        }
+        if header.contains_key(*name) {
+            return Err(Error::format(format!("duplicate tensor name {}", name)));
+        }
+        let size = array.buffer.len() * 4;
+        let info = TensorInfo {
+            dtype: "F32".to_string(),
+            shape: array.shape.clone(),
+            data_offsets: [offset, offset + size],
+        };
+        header.insert(name.to_string(), serde_json::to_value(info).unwrap());
+        offset += size;
+    }
+    let mut header = serde_json::to_vec(&header).unwrap();
+    // pad with spaces so the data starts 8-byte aligned
+    while !header.len().is_multiple_of(8) {
+        header.push(b' ');
+    }
+
+    let mut res = Vec::with_capacity(8 + header.len() + offset);
+    res.extend_from_slice(&(header.len() as u64).to_le_bytes());
+    res.extend_from_slice(&header);
+    for (_, array) in tensors {
+        for x in &array.buffer {
+            res.extend_from_slice(&x.to_le_bytes());
+        }
+    }
+    Ok(res)
+}
+
+/// Tensors of a loaded safetensors file, looked up by name.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct SafeTensors {
+    data: SafeTensorsData,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl SafeTensors {
+    pub fn from(bytes: &[u8]) -> JsResult<SafeTensors> {
+        Ok(SafeTensors {
+            data: read_safetensors(bytes)?,
+        })
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.data
+            .tensors
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<NdArray> {
+        self.data
+            .tensors
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, a)| a.clone())
+    }
+
+    pub fn metadata(&self, key: &str) -> Option<String> {
+        self.data.metadata.get(key).cloned()
+    }
+}
+
+impl SafeTensors {
+    pub fn into_data(self) -> SafeTensorsData {
+        self.data
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl HalfNdArray {
+    /// Loads the tensor `name` of a safetensors file without widening
+    /// `F16`/`BF16` data to `f32`; see [`read_safetensors_half`].
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = fromSafetensors))]
+    pub fn from_safetensors(
+        bytes: &[u8],
+        name: &str,
+        dtype: Option<DType>,
+    ) -> JsResult<HalfNdArray> {
+        let (tensors, _) = parse_safetensors(bytes)?;
+        let tensor = tensors
+            .iter()
+            .find(|t| t.name == name)
+            .ok_or_else(|| Error::format(format!("no tensor named {}", name)))?;
+        Ok(decode_half(tensor, dtype)?)
+    }
+}
+
+/// Collects named arrays and encodes them as a safetensors file.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Default)]
+pub struct SafeTensorsWriter {
+    tensors: Vec<(String, NdArray)>,
+    metadata: BTreeMap<String, String>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl SafeTensorsWriter {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> SafeTensorsWriter {
+        SafeTensorsWriter::default()
+    }
+
+    pub fn add(&mut self, name: &str, array: &NdArray) {
+        self.tensors.push((name.to_string(), array.clone()));
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = setMetadata))]
+    pub fn set_metadata(&mut self, key: &str, value: &str) {
+        self.metadata.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn finish(&self) -> JsResult<Vec<u8>> {
+        let tensors: Vec<(&str, &NdArray)> = self
+            .tensors
+            .iter()
+            .map(|(name, a)| (name.as_str(), a))
+            .collect();
+        Ok(write_safetensors(&tensors, &self.metadata)?)
+    }
+}
+
+#[test]
+fn test_safetensors_roundtrip() {
+    let a = NdArray::arange(0, 6, None).reshape(&[2, 3]);
+    let b = NdArray::ones(&[2]);
+    let mut writer = SafeTensorsWriter::new();
+    writer.add("layer.weight", &a);
+    writer.add("layer.bias", &b);
+    writer.set_metadata("format", "pt");
+    let bytes = writer.finish().unwrap();
+    let header_len = u64::from_le_bytes(le8(&bytes[..8])) as usize;
+    assert_eq!(header_len % 8, 0);
+
+    let file = SafeTensors::from(&bytes).unwrap();
+    assert_eq!(file.names(), vec!["layer.weight", "layer.bias"]);
+    assert_eq!(file.metadata("format"), Some("pt".to_string()));
+    let weight = file.get("layer.weight").unwrap();
+    assert_eq!(weight.shape, vec![2, 3]);
+    assert_eq!(weight.buffer, a.buffer);
+    assert_eq!(file.get("layer.bias").unwrap().buffer, b.buffer);
+
+    let duplicate = write_safetensors(&[("b", &b), ("b", &b)], &BTreeMap::new());
+    assert!(matches!(duplicate, Err(Error::Format(_))));
+    let reserved = write_safetensors(&[("__metadata__", &b)], &BTreeMap::new());
+    assert!(matches!(reserved, Err(Error::Format(_))));
+}
+
+#[cfg(test)]
+fn single_tensor_file(dtype: &str, shape: &[usize], data: &[u8]) -> Vec<u8> {
+    let header = format!(
+        r#"{{"x":{{"dtype":"{}","shape":{:?},"data_offsets":[0,{}]}}}}"#,
+        dtype,
+        shape,
+        data.len()
+    );
+    let mut res = (header.len() as u64).to_le_bytes().to_vec();
+    res.extend_from_slice(header.as_bytes());
+    res.extend_from_slice(data);
+    res
+}
+
+/// Values for the half-precision tests with their little-endian F16
+/// bytes, and the BF16 bytes of the first three (the last isn't exact in
+/// BF16).
+#[cfg(test)]
+fn half_fixture() -> ([f32; 4], Vec<u8>, Vec<u8>) {
+    let values = [1.5f32, -2.0, 0.25, 65504.0];
+    let f16_bytes = values
+        .iter()
+        .flat_map(|x| f16::from_f32(*x).to_le_bytes())
+        .collect();
+    let bf16_bytes = values[..3]
+        .iter()
+        .flat_map(|x| bf16::from_f32(*x).to_le_bytes())
+        .collect();
+    (values, f16_bytes, bf16_bytes)
+}
+
+#[test]
+fn test_safetensors_half() {
+    let (values, f16_bytes, bf16_bytes) = half_fixture();
+    let t = read_safetensors(&single_tensor_file("F16", &[2, 2], &f16_bytes)).unwrap();
+    assert_eq!(t.tensors[0].1.shape, vec![2, 2]);
+    assert_eq!(t.tensors[0].1.buffer, values.to_vec());
+
+    let t = read_safetensors(&single_tensor_file("BF16", &[3], &bf16_bytes)).unwrap();
+    assert_eq!(t.tensors[0].1.buffer, values[..3].to_vec());
+
+    assert_eq!(
+        read_safetensors(&single_tensor_file("F8_E4M3", &[1], &[0])).err(),
+        Some(Error::UnsupportedDType("F8_E4M3".to_string()))
+    );
+    assert!(read_safetensors(&single_tensor_file("F32", &[2], &[0; 4])).is_err());
+    assert!(matches!(
+        read_safetensors(&single_tensor_file("F32", &[usize::MAX / 2, 3], &[])),
+        Err(Error::Format(_))
+    ));
+}
+
+#[test]
+fn test_read_safetensors_half() {
+    // kept in 16 bits, or converted when another type is asked for
+    let (values, f16_bytes, bf16_bytes) = half_fixture();
+    let t = read_safetensors_half(&single_tensor_file("BF16", &[3], &bf16_bytes), None).unwrap();
+    assert_eq!(t[0].1.dtype(), DType::BFloat16);
+    assert_eq!(t[0].1.to_ndarray().buffer, values[..3].to_vec());
+    let h = read_safetensors_half(&single_tensor_file("F16", &[2, 2], &f16_bytes), None).unwrap();
+    assert_eq!(
+        h[0].1.as_bits(),
+        &f16_bytes
+            .chunks(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect::<Vec<_>>()[..]
+    );
+    let b = read_safetensors_half(
+        &single_tensor_file("F16", &[2, 2], &f16_bytes),
+        Some(DType::BFloat16),
+    )
+    .unwrap();
+    assert_eq!(b[0].1.to_ndarray().buffer, vec![1.5, -2.0, 0.25, 65536.0]);
+    let writer = write_safetensors(
+        &[("w", &NdArray::from(&[0.1, 3.], None, None))],
+        &BTreeMap::new(),
+    )
+    .unwrap();
+    let w = HalfNdArray::from_safetensors(&writer, "w", None).unwrap();
+    assert_eq!(w.dtype(), DType::Float16);
+    assert_eq!(w.to_ndarray().buffer, vec![0.099975586, 3.]);
+}
@@ -0,0 +1,345 @@
+//! Serde support for `NdArray` and a binary format for state dicts.
+//!
+//! The binary layout (all integers little-endian) is:
+//!
+//! ```text
+//! magic  b"MUAS"
+//! u16    format version
+//! u32    number of arrays
+//! for each array:
+//!     u32 name length, name (utf-8)
+//!     u8  dtype
+//!     u32 ndim, u64 * ndim shape
+//!     f32 * numel data
+//! u32    CRC-32 of everything above
+//! ```
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    dtype::DType,
+    error::{Error, JsResult, Result},
+    ndarray::NdArray,
+    utils::get_strides,
+};
+
+const MAGIC: &[u8] = b"MUAS";
+const VERSION: u16 = 1;
+
+#[derive(Serialize)]
+struct NdArrayRef<'a> {
+    shape: &'a [usize],
+    strides: &'a [usize],
+    dtype: &'a str,
+    data: &'a [f32],
+}
+
+#[derive(Deserialize)]
+struct NdArrayOwned {
+    shape: Vec<usize>,
+    strides: Option<Vec<usize>>,
+    dtype: Option<String>,
+    data: Vec<f32>,
+}
+
+impl Serialize for NdArray {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        NdArrayRef {
+            shape: &self.shape,
+            strides: &self.strides,
+            dtype: DType::Float32.name(),
+            data: &self.buffer,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for NdArray {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let repr = NdArrayOwned::deserialize(deserializer)?;
+        if let Some(dtype) = &repr.dtype {
+            if DType::from_name(dtype) != Some(DType::Float32) {
+                return Err(de::Error::custom(format!("unsupported dtype: {}", dtype)));
+            }
+        }
+        let numel = repr
+            .shape
+            .iter()
+            .try_fold(1usize, |acc, dim| acc.checked_mul(*dim));
+        if numel != Some(repr.data.len()) {
+            return Err(de::Error::custom("shape is not compatible with data"));
+        }
+        let shape = repr.shape;
+        // every kernel assumes C order, so strides are only a redundant check
+        let strides = get_strides(&shape);
+        if repr.strides.is_some_and(|s| s != strides) {
+            return Err(de::Error::custom("only C-contiguous strides are supported"));
+        }
+        Ok(NdArray::from(&repr.data, Some(shape), Some(strides)))
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() - self.pos < n {
+            return Err(Error::format("unexpected end of state dict"));
+        }
+        let res = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(res)
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    /// Fails unless `count` items of at least `min_size` bytes each could
+    /// still follow, so a corrupt count can't trigger a huge allocation.
+    fn check_count(&self, count: usize, min_size: usize) -> Result<()> {
+        match count.checked_mul(min_size) {
+            Some(size) if size <= self.remaining() => Ok(()),
+            _ => Err(Error::format("unexpected end of state dict")),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+}
+
+/// Encodes named arrays in the versioned, checksummed binary format.
+pub fn encode_state_dict(arrays: &[(&str, &NdArray)]) -> Vec<u8> {
+    let mut res = Vec::new();
+    res.extend_from_slice(MAGIC);
+    res.extend_from_slice(&VERSION.to_le_bytes());
+    res.extend_from_slice(&(arrays.len() as u32).to_le_bytes());
+    for (name, array) in arrays {
+        res.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        res.extend_from_slice(name.as_bytes());
+        res.push(DType::Float32.into());
+        res.extend_from_slice(&(array.shape.len() as u32).to_le_bytes());
+        for dim in &array.shape {
+            res.extend_from_slice(&(*dim as u64).to_le_bytes());
+        }
+        res.reserve(array.buffer.len() * 4);
+        for x in &array.buffer {
+            res.extend_from_slice(&x.to_le_bytes());
+        }
+    }
+    let checksum = crc32fast::hash(&res);
+    res.extend_from_slice(&checksum.to_le_bytes());
+    res
+}
+
+/// Decodes the output of [`encode_state_dict`], verifying its checksum.
+pub fn decode_state_dict(bytes: &[u8]) -> Result<Vec<(String, NdArray)>> {
+    if bytes.len() < MAGIC.len() + 10 || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(Error::format("not a state dict"));
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - 4);
+    let checksum = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+    if crc32fast::hash(body) != checksum {
+        return Err(Error::format("state dict checksum mismatch"));
+    }
+    let mut reader = Reader {
+        bytes: body,
+        pos: MAGIC.len(),
+    };
+    let version = reader.take(2)?;
+    let version = u16::from_le_bytes([version[0], version[1]]);
+    if version > VERSION {
+        return Err(Error::format(format!(
+            "state dict version {} is newer than the supported version {}",
+            version, VERSION
+        )));
+    }
+    let count = reader.u32()? as usize;
+    // name length, dtype and ndim
+    reader.check_count(count, 9)?;
+    let mut res = Vec::with_capacity(count);
+    for _ in 0..count {
+        let len = reader.u32()? as usize;
+        let name = std::str::from_utf8(reader.take(len)?)
+            .map_err(|_| Error::format("array name is not valid utf-8"))?
+            .to_string();
+        let dtype = reader.take(1)?[0];
+        if dtype != u8::from(DType::Float32) {
+            return Err(Error::UnsupportedDType(format!("{}", dtype)));
+        }
+        let ndim = reader.u32()? as usize;
+        reader.check_count(ndim, 8)?;
+        let mut shape = Vec::with_capacity(ndim);
+        for _ in 0..ndim {
+            shape.push(reader.u64()? as usize);
+        }
+        let size = shape
+            .iter()
+            .try_fold(4usize, |acc, dim| acc.checked_mul(*dim))
+            .ok_or_else(|| Error::format("array is too large"))?;
+        let data = reader.take(size)?;
+        let buffer: Vec<f32> = data
+            .chunks(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        res.push((name, NdArray::from(&buffer, Some(shape), None)));
+    }
+    if reader.pos != body.len() {
+        return Err(Error::format("trailing bytes after state dict"));
+    }
+    Ok(res)
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+impl NdArray {
+    /// Plain `{ shape, strides, dtype, data }` object, also used by
+    /// `JSON.stringify`.
+    #[wasm_bindgen(js_name = toJSON)]
+    pub fn to_json(&self) -> JsResult<JsValue> {
+        Ok(serde_wasm_bindgen::to_value(self)?)
+    }
+
+    #[wasm_bindgen(js_name = fromJSON)]
+    pub fn from_json(value: JsValue) -> JsResult<NdArray> {
+        Ok(serde_wasm_bindgen::from_value(value)?)
+    }
+}
+
+/// Ordered collection of named arrays, e.g. the parameters of a model.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Default)]
+pub struct StateDict {
+    arrays: Vec<(String, NdArray)>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl StateDict {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> StateDict {
+        StateDict::default()
+    }
+
+    /// Adds an array, replacing any array with the same name.
+    pub fn insert(&mut self, name: &str, array: &NdArray) {
+        match self.arrays.iter_mut().find(|(n, _)| n == name) {
+            Some(entry) => entry.1 = array.clone(),
+            None => self.arrays.push((name.to_string(), array.clone())),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<NdArray> {
+        self.arrays
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, a)| a.clone())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<NdArray> {
+        let idx = self.arrays.iter().position(|(n, _)| n == name)?;
+        Some(self.arrays.remove(idx).1)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.arrays.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn length(&self) -> usize {
+        self.arrays.len()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = toBytes))]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode_state_dict(&self.entries())
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = fromBytes))]
+    pub fn from_bytes(bytes: &[u8]) -> JsResult<StateDict> {
+        Ok(StateDict {
+            arrays: decode_state_dict(bytes)?,
+        })
+    }
+}
+
+impl StateDict {
+    pub fn entries(&self) -> Vec<(&str, &NdArray)> {
+        self.arrays
+            .iter()
+            .map(|(name, a)| (name.as_str(), a))
+            .collect()
+    }
+}
+
+#[test]
+fn test_serde_json() {
+    let a = NdArray::arange(0, 6, None).reshape(&[2, 3]);
+    let json = serde_json::to_string(&a).unwrap();
+    assert_eq!(
+        json,
+        r#"{"shape":[2,3],"strides":[3,1],"dtype":"float32","data":[0.0,1.0,2.0,3.0,4.0,5.0]}"#
+    );
+    let b: NdArray = serde_json::from_str(&json).unwrap();
+    assert_eq!(b.shape, a.shape);
+    assert_eq!(b.strides, a.strides);
+    assert_eq!(b.buffer, a.buffer);
+
+    let c: NdArray = serde_json::from_str(r#"{"shape":[2],"data":[1,2]}"#).unwrap();
+    assert_eq!(c.strides, vec![1]);
+    assert!(serde_json::from_str::<NdArray>(r#"{"shape":[3],"data":[1,2]}"#).is_err());
+    assert!(serde_json::from_str::<NdArray>(r#"{"shape":[1],"dtype":"int8","data":[1]}"#).is_err());
+    for strides in ["[1,2]", "[4,1]", "[2]"] {
+        let json = format!(
+            r#"{{"shape":[2,2],"strides":{},"data":[1,2,3,4]}}"#,
+            strides
+        );
+        assert!(serde_json::from_str::<NdArray>(&json).is_err());
+    }
+}
+
+#[test]
+fn test_state_dict_bytes() {
+    let mut dict = StateDict::new();
+    dict.insert("fc.weight", &NdArray::arange(0, 6, None).reshape(&[3, 2]));
+    dict.insert("fc.bias", &NdArray::zeros(&[2]));
+    dict.insert("fc.bias", &NdArray::ones(&[2]));
+    assert_eq!(dict.length(), 2);
+
+    let bytes = dict.to_bytes();
+    let restored = decode_state_dict(&bytes).unwrap();
+    assert_eq!(restored.len(), 2);
+    assert_eq!(restored[0].0, "fc.weight");
+    assert_eq!(restored[0].1.shape, vec![3, 2]);
+    assert_eq!(restored[1].1.buffer, vec![1., 1.]);
+
+    let mut corrupted = bytes.clone();
+    corrupted[20] ^= 1;
+    assert_eq!(
+        decode_state_dict(&corrupted).err(),
+        Some(Error::format("state dict checksum mismatch"))
+    );
+    assert!(decode_state_dict(&bytes[..bytes.len() - 1]).is_err());
+
+    // a huge array count with a valid checksum fails instead of allocating
+    let mut forged = bytes[..bytes.len() - 4].to_vec();
+    forged[6..10].copy_from_slice(&u32::MAX.to_le_bytes());
+    let checksum = crc32fast::hash(&forged);
+    forged.extend_from_slice(&checksum.to_le_bytes());
+    assert_eq!(
+        decode_state_dict(&forged).err(),
+        Some(Error::format("unexpected end of state dict"))
+    );
+}
@@ -0,0 +1,661 @@
+//! Kernels for the hot loops of `ops`, vectorised with the `simd` feature.
+//!
+//! The vector kernels work on 4-lane `f32` vectors: wasm `simd128` when the
+//! target enables it (`-C target-feature=+simd128`), SSE2 on x86_64 and NEON
+//! on aarch64. The native versions let `cargo test --features simd` check
+//! the vector code against the scalar kernels, which are used on every
+//! other target and for the tails that don't fill a vector; `wasm-pack test
+//! --node -- --features simd` with `+simd128` runs the same checks on wasm.
+//!
+//! Element-wise kernels and `axpy` give the same bits as the scalar ones.
+//! `sum` adds four interleaved partial sums and `exp` is a polynomial
+//! approximation within a few ulp of `f32::exp` that flushes subnormal
+//! results to zero, so those two only agree within rounding.
+
+/// Reference kernels. All binary kernels require equal lengths.
+pub(crate) mod scalar {
+    pub fn add(a: &mut [f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len());
+        a.iter_mut().zip(b).for_each(|(x, y)| *x += *y);
+    }
+
+    pub fn sub(a: &mut [f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len());
+        a.iter_mut().zip(b).for_each(|(x, y)| *x -= *y);
+    }
+
+    pub fn mul(a: &mut [f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len());
+        a.iter_mut().zip(b).for_each(|(x, y)| *x *= *y);
+    }
+
+    pub fn add_scalar(a: &mut [f32], b: f32) {
+        a.iter_mut().for_each(|x| *x += b);
+    }
+
+    pub fn sub_scalar(a: &mut [f32], b: f32) {
+        a.iter_mut().for_each(|x| *x -= b);
+    }
+
+    pub fn mul_scalar(a: &mut [f32], b: f32) {
+        a.iter_mut().for_each(|x| *x *= b);
+    }
+
+    pub fn div_scalar(a: &mut [f32], b: f32) {
+        a.iter_mut().for_each(|x| *x /= b);
+    }
+
+    pub fn relu(a: &mut [f32]) {
+        a.iter_mut().for_each(|x| *x = f32::max(0.0, *x));
+    }
+
+    pub fn exp(a: &mut [f32]) {
+        a.iter_mut().for_each(|x| *x = x.exp());
+    }
+
+    pub fn sum(a: &[f32]) -> f32 {
+        a.iter().sum()
+    }
+
+    /// `y += alpha * x`, the inner loop of `matmul`.
+    pub fn axpy(y: &mut [f32], alpha: f32, x: &[f32]) {
+        assert_eq!(y.len(), x.len());
+        y.iter_mut().zip(x).for_each(|(y, x)| *y += alpha * *x);
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+mod arch {
+    use core::arch::wasm32::*;
+
+    #[derive(Clone, Copy)]
+    pub struct V(v128);
+
+    #[allow(unused_unsafe)]
+    impl V {
+        #[inline(always)]
+        pub fn splat(x: f32) -> V {
+            V(f32x4_splat(x))
+        }
+
+        #[inline(always)]
+        pub fn load(s: &[f32]) -> V {
+            let s = &s[..4];
+            unsafe { V(v128_load(s.as_ptr() as *const v128)) }
+        }
+
+        #[inline(always)]
+        pub fn store(self, s: &mut [f32]) {
+            let s = &mut s[..4];
+            unsafe { v128_store(s.as_mut_ptr() as *mut v128, self.0) }
+        }
+
+        #[inline(always)]
+        pub fn add(self, o: V) -> V {
+            V(f32x4_add(self.0, o.0))
+        }
+
+        #[inline(always)]
+        pub fn sub(self, o: V) -> V {
+            V(f32x4_sub(self.0, o.0))
+        }
+
+        #[inline(always)]
+        pub fn mul(self, o: V) -> V {
+            V(f32x4_mul(self.0, o.0))
+        }
+
+        #[inline(always)]
+        pub fn div(self, o: V) -> V {
+            V(f32x4_div(self.0, o.0))
+        }
+
+        #[inline(always)]
+        pub fn max(self, o: V) -> V {
+            V(f32x4_max(self.0, o.0))
+        }
+
+        #[inline(always)]
+        pub fn min(self, o: V) -> V {
+            V(f32x4_min(self.0, o.0))
+        }
+
+        /// `max(0, x)` with NaN lanes becoming 0, like `f32::max`.
+        #[inline(always)]
+        pub fn relu(self) -> V {
+            V(f32x4_pmax(f32x4_splat(0.), self.0))
+        }
+
+        /// Rounds to the nearest integer, ties to even.
+        #[inline(always)]
+        pub fn round(self) -> V {
+            V(f32x4_nearest(self.0))
+        }
+
+        /// `2^self` for integral lanes in `-126..=127`.
+        #[inline(always)]
+        pub fn pow2(self) -> V {
+            let n = i32x4_add(i32x4_trunc_sat_f32x4(self.0), i32x4_splat(127));
+            V(i32x4_shl(n, 23))
+        }
+
+        #[inline(always)]
+        pub fn lt(self, o: V) -> V {
+            V(f32x4_lt(self.0, o.0))
+        }
+
+        #[inline(always)]
+        pub fn ne(self, o: V) -> V {
+            V(f32x4_ne(self.0, o.0))
+        }
+
+        /// Lanes of `a` where `mask` is set, of `b` elsewhere.
+        #[inline(always)]
+        pub fn select(mask: V, a: V, b: V) -> V {
+            V(v128_bitselect(a.0, b.0, mask.0))
+        }
+
+        #[inline(always)]
+        pub fn lanes(self) -> [f32; 4] {
+            [
+                f32x4_extract_lane::<0>(self.0),
+                f32x4_extract_lane::<1>(self.0),
+                f32x4_extract_lane::<2>(self.0),
+                f32x4_extract_lane::<3>(self.0),
+            ]
+        }
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod arch {
+    use std::arch::x86_64::*;
+
+    #[derive(Clone, Copy)]
+    pub struct V(__m128);
+
+    // SSE2 is part of the x86_64 baseline, so no runtime detection is needed
+    #[allow(unused_unsafe)]
+    impl V {
+        #[inline(always)]
+        pub fn splat(x: f32) -> V {
+            unsafe { V(_mm_set1_ps(x)) }
+        }
+
+        #[inline(always)]
+        pub fn load(s: &[f32]) -> V {
+            let s = &s[..4];
+            unsafe { V(_mm_loadu_ps(s.as_ptr())) }
+        }
+
+        #[inline(always)]
+        pub fn store(self, s: &mut [f32]) {
+            let s = &mut s[..4];
+            unsafe { _mm_storeu_ps(s.as_mut_ptr(), self.0) }
+        }
+
+        #[inline(always)]
+        pub fn add(self, o: V) -> V {
+            unsafe { V(_mm_add_ps(self.0, o.0)) }
+        }
+
+        #[inline(always)]
+        pub fn sub(self, o: V) -> V {
+            unsafe { V(_mm_sub_ps(self.0, o.0)) }
+        }
+
+        #[inline(always)]
+        pub fn mul(self, o: V) -> V {
+            unsafe { V(_mm_mul_ps(self.0, o.0)) }
+        }
+
+        #[inline(always)]
+        pub fn div(self, o: V) -> V {
+            unsafe { V(_mm_div_ps(self.0, o.0)) }
+        }
+
+        #[inline(always)]
+        pub fn max(self, o: V) -> V {
+            unsafe { V(_mm_max_ps(self.0, o.0)) }
+        }
+
+        #[inline(always)]
+        pub fn min(self, o: V) -> V {
+            unsafe { V(_mm_min_ps(self.0, o.0)) }
+        }
+
+        /// `max(0, x)` with NaN lanes becoming 0, like `f32::max`.
+        #[inline(always)]
+        pub fn relu(self) -> V {
+            // maxps returns its second operand when either is NaN
+            unsafe { V(_mm_max_ps(self.0, _mm_setzero_ps())) }
+        }
+
+        /// Rounds to the nearest integer, ties to even (the default MXCSR
+        /// rounding mode).
+        #[inline(always)]
+        pub fn round(self) -> V {
+            unsafe { V(_mm_cvtepi32_ps(_mm_cvtps_epi32(self.0))) }
+        }
+
+        /// `2^self` for integral lanes in `-126..=127`.
+        #[inline(always)]
+        pub fn pow2(self) -> V {
+            unsafe {
+                let n = _mm_add_epi32(_mm_cvtps_epi32(self.0), _mm_set1_epi32(127));
+                V(_mm_castsi128_ps(_mm_slli_epi32::<23>(n)))
+            }
+        }
+
+        #[inline(always)]
+        pub fn lt(self, o: V) -> V {
+            unsafe { V(_mm_cmplt_ps(self.0, o.0)) }
+        }
+
+        #[inline(always)]
+        pub fn ne(self, o: V) -> V {
+            unsafe { V(_mm_cmpneq_ps(self.0, o.0)) }
+        }
+
+        /// Lanes of `a` where `mask` is set, of `b` elsewhere.
+        #[inline(always)]
+        pub fn select(mask: V, a: V, b: V) -> V {
+            unsafe {
+                V(_mm_or_ps(
+                    _mm_and_ps(mask.0, a.0),
+                    _mm_andnot_ps(mask.0, b.0),
+                ))
+            }
+        }
+
+        #[inline(always)]
+        pub fn lanes(self) -> [f32; 4] {
+            let mut res = [0.; 4];
+            self.store(&mut res);
+            res
+        }
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "aarch64"))]
+mod arch {
+    use std::arch::aarch64::*;
+
+    #[derive(Clone, Copy)]
+    pub struct V(float32x4_t);
+
+    // NEON is part of the aarch64 baseline, so no runtime detection is needed
+    #[allow(unused_unsafe)]
+    impl V {
+        #[inline(always)]
+        pub fn splat(x: f32) -> V {
+            unsafe { V(vdupq_n_f32(x)) }
+        }
+
+        #[inline(always)]
+        pub fn load(s: &[f32]) -> V {
+            let s = &s[..4];
+            unsafe { V(vld1q_f32(s.as_ptr())) }
+        }
+
+        #[inline(always)]
+        pub fn store(self, s: &mut [f32]) {
+            let s = &mut s[..4];
+            unsafe { vst1q_f32(s.as_mut_ptr(), self.0) }
+        }
+
+        #[inline(always)]
+        pub fn add(self, o: V) -> V {
+            unsafe { V(vaddq_f32(self.0, o.0)) }
+        }
+
+        #[inline(always)]
+        pub fn sub(self, o: V) -> V {
+            unsafe { V(vsubq_f32(self.0, o.0)) }
+        }
+
+        #[inline(always)]
+        pub fn mul(self, o: V) -> V {
+            unsafe { V(vmulq_f32(self.0, o.0)) }
+        }
+
+        #[inline(always)]
+        pub fn div(self, o: V) -> V {
+            unsafe { V(vdivq_f32(self.0, o.0)) }
+        }
+
+        #[inline(always)]
+        pub fn max(self, o: V) -> V {
+            unsafe { V(vmaxq_f32(self.0, o.0)) }
+        }
+
+        #[inline(always)]
+        pub fn min(self, o: V) -> V {
+            unsafe { V(vminq_f32(self.0, o.0)) }
+        }
+
+        /// `max(0, x)` with NaN lanes becoming 0, like `f32::max`.
+        #[inline(always)]
+        pub fn relu(self) -> V {
+            unsafe { V(vmaxnmq_f32(self.0, vdupq_n_f32(0.))) }
+        }
+
+        /// Rounds to the nearest integer, ties to even.
+        #[inline(always)]
+        pub fn round(self) -> V {
+            unsafe { V(vrndnq_f32(self.0)) }
+        }
+
+        /// `2^self` for integral lanes in `-126..=127`.
+        #[inline(always)]
+        pub fn pow2(self) -> V {
+            unsafe {
+                let n = vaddq_s32(vcvtq_s32_f32(self.0), vdupq_n_s32(127));
+                V(vreinterpretq_f32_s32(vshlq_n_s32::<23>(n)))
+            }
+        }
+
+        #[inline(always)]
+        pub fn lt(self, o: V) -> V {
+            unsafe { V(vreinterpretq_f32_u32(vcltq_f32(self.0, o.0))) }
+        }
+
+        #[inline(always)]
+        pub fn ne(self, o: V) -> V {
+            unsafe { V(vreinterpretq_f32_u32(vmvnq_u32(vceqq_f32(self.0, o.0)))) }
+        }
+
+        /// Lanes of `a` where `mask` is set, of `b` elsewhere.
+        #[inline(always)]
+        pub fn select(mask: V, a: V, b: V) -> V {
+            unsafe { V(vbslq_f32(vreinterpretq_u32_f32(mask.0), a.0, b.0)) }
+        }
+
+        #[inline(always)]
+        pub fn lanes(self) -> [f32; 4] {
+            let mut res = [0.; 4];
+            self.store(&mut res);
+            res
+        }
+    }
+}
+
+#[cfg(all(
+    feature = "simd",
+    any(
+        all(target_arch = "wasm32", target_feature = "simd128"),
+        target_arch = "x86_64",
+        target_arch = "aarch64"
+    )
+))]
+pub(crate) mod vector {
+    use super::{arch::V, scalar};
+
+    /// Length of the prefix of `len` elements that fills whole vectors.
+    fn body(len: usize) -> usize {
+        len - len % 4
+    }
+
+    fn zip(a: &mut [f32], b: &[f32], f: impl Fn(V, V) -> V, tail: impl FnOnce(&mut [f32], &[f32])) {
+        assert_eq!(a.len(), b.len());
+        let n = body(a.len());
+        let (head, rest) = a.split_at_mut(n);
+        for (x, y) in head.chunks_exact_mut(4).zip(b.chunks_exact(4)) {
+            f(V::load(x), V::load(y)).store(x);
+        }
+        tail(rest, &b[n..]);
+    }
+
+    fn map(a: &mut [f32], f: impl Fn(V) -> V, tail: impl FnOnce(&mut [f32])) {
+        let n = body(a.len());
+        let (head, rest) = a.split_at_mut(n);
+        for x in head.chunks_exact_mut(4) {
+            f(V::load(x)).store(x);
+        }
+        tail(rest);
+    }
+
+    pub fn add(a: &mut [f32], b: &[f32]) {
+        zip(a, b, V::add, scalar::add);
+    }
+
+    pub fn sub(a: &mut [f32], b: &[f32]) {
+        zip(a, b, V::sub, scalar::sub);
+    }
+
+    pub fn mul(a: &mut [f32], b: &[f32]) {
+        zip(a, b, V::mul, scalar::mul);
+    }
+
+    pub fn add_scalar(a: &mut [f32], b: f32) {
+        let v = V::splat(b);
+        map(a, |x| x.add(v), |t| scalar::add_scalar(t, b));
+    }
+
+    pub fn sub_scalar(a: &mut [f32], b: f32) {
+        let v = V::splat(b);
+        map(a, |x| x.sub(v), |t| scalar::sub_scalar(t, b));
+    }
+
+    pub fn mul_scalar(a: &mut [f32], b: f32) {
+        let v = V::splat(b);
+        map(a, |x| x.mul(v), |t| scalar::mul_scalar(t, b));
+    }
+
+    pub fn div_scalar(a: &mut [f32], b: f32) {
+        let v = V::splat(b);
+        map(a, |x| x.div(v), |t| scalar::div_scalar(t, b));
+    }
+
+    pub fn relu(a: &mut [f32]) {
+        map(a, V::relu, scalar::relu);
+    }
+
+    /// Cephes' `expf`: `exp(x) = 2^n * exp(r)` with `n = round(x / ln 2)`
+    /// and a degree-5 polynomial for `exp(r)` on `|r| <= ln 2 / 2`.
+    #[inline(always)]
+    fn exp_v(x: V) -> V {
+        const MAX: f32 = 88.72284;
+        const MIN: f32 = -87.33654;
+        const LOG2E: f32 = std::f32::consts::LOG2_E;
+        // ln 2 split so that `n * LN2_HI` is exact
+        const LN2_HI: f32 = 0.693_359_4;
+        const LN2_LO: f32 = -2.121_944_4e-4;
+        const P: [f32; 6] = [
+            1.987_569_1e-4,
+            1.398_2e-3,
+            8.333_452e-3,
+            4.166_579_6e-2,
+            0.166_666_66,
+            0.5,
+        ];
+
+        let c = x.max(V::splat(MIN)).min(V::splat(MAX));
+        // n <= 127 keeps 2^n finite; r then reaches ln 2 for the largest x
+        let n = c.mul(V::splat(LOG2E)).round().min(V::splat(127.));
+        let r = c.sub(n.mul(V::splat(LN2_HI))).sub(n.mul(V::splat(LN2_LO)));
+        let p = P[1..]
+            .iter()
+            .fold(V::splat(P[0]), |p, k| p.mul(r).add(V::splat(*k)));
+        let y = p.mul(r.mul(r)).add(r).add(V::splat(1.)).mul(n.pow2());
+
+        let y = V::select(x.lt(V::splat(MIN)), V::splat(0.), y);
+        let y = V::select(V::splat(MAX).lt(x), V::splat(f32::INFINITY), y);
+        // NaN lanes are the only ones unequal to themselves
+        V::select(x.ne(x), x, y)
+    }
+
+    pub fn exp(a: &mut [f32]) {
+        map(a, exp_v, scalar::exp);
+    }
+
+    pub fn sum(a: &[f32]) -> f32 {
+        let n = body(a.len());
+        let acc = a[..n]
+            .chunks_exact(4)
+            .fold(V::splat(0.), |acc, x| acc.add(V::load(x)));
+        let [x, y, z, w] = acc.lanes();
+        (x + y) + (z + w) + scalar::sum(&a[n..])
+    }
+
+    pub fn axpy(y: &mut [f32], alpha: f32, x: &[f32]) {
+        let v = V::splat(alpha);
+        zip(
+            y,
+            x,
+            |y, x| y.add(v.mul(x)),
+            |y, x| scalar::axpy(y, alpha, x),
+        );
+    }
+}
+
+#[cfg(all(
+    feature = "simd",
+    any(
+        all(target_arch = "wasm32", target_feature = "simd128"),
+        target_arch = "x86_64",
+        target_arch = "aarch64"
+    )
+))]
+pub(crate) use vector::*;
+
+#[cfg(not(all(
+    feature = "simd",
+    any(
+        all(target_arch = "wasm32", target_feature = "simd128"),
+        target_arch = "x86_64",
+        target_arch = "aarch64"
+    )
+)))]
+pub(crate) use scalar::*;
+
+#[cfg(all(
+    test,
+    feature = "simd",
+    any(
+        all(target_arch = "wasm32", target_feature = "simd128"),
+        target_arch = "x86_64",
+        target_arch = "aarch64"
+    )
+))]
+fn inputs(len: usize) -> Vec<f32> {
+    (0..len)
+        .map(|i| match i % 7 {
+            0 => i as f32 * 0.37 - 3.,
+            1 => -(i as f32).sqrt(),
+            2 => 1e-39 * i as f32,
+            3 => -0.,
+            4 => 1e30 / (i as f32 + 1.),
+            _ => (i as f32 * 1.3).sin() * 20.,
+        })
+        .collect()
+}
+
+#[cfg(all(
+    test,
+    feature = "simd",
+    any(
+        all(target_arch = "wasm32", target_feature = "simd128"),
+        target_arch = "x86_64",
+        target_arch = "aarch64"
+    )
+))]
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn test_vector_kernels() {
+    type Binary = fn(&mut [f32], &[f32]);
+    type WithScalar = fn(&mut [f32], f32);
+    let same = |a: &[f32], b: &[f32]| {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b) {
+            let equal = x.to_bits() == y.to_bits() || (x.is_nan() && y.is_nan());
+            assert!(equal, "{:?} vs {:?}", a, b);
+        }
+    };
+    // every length up to a few vectors, so the tails are covered
+    for len in 0..19 {
+        let a = inputs(len);
+        let b: Vec<f32> = inputs(len + 3)[3..].to_vec();
+
+        let binary: [(Binary, Binary); 3] = [
+            (vector::add, scalar::add),
+            (vector::sub, scalar::sub),
+            (vector::mul, scalar::mul),
+        ];
+        for (v, s) in binary {
+            let (mut x, mut y) = (a.clone(), a.clone());
+            v(&mut x, &b);
+            s(&mut y, &b);
+            same(&x, &y);
+        }
+        let with_scalar: [(WithScalar, WithScalar); 4] = [
+            (vector::add_scalar, scalar::add_scalar),
+            (vector::sub_scalar, scalar::sub_scalar),
+            (vector::mul_scalar, scalar::mul_scalar),
+            (vector::div_scalar, scalar::div_scalar),
+        ];
+        for (v, s) in with_scalar {
+            for k in [1.5, -0.3, 0., f32::NAN] {
+                let (mut x, mut y) = (a.clone(), a.clone());
+                v(&mut x, k);
+                s(&mut y, k);
+                same(&x, &y);
+            }
+        }
+        let mut x = a.clone();
+        x.push(f32::NAN);
+        let mut y = x.clone();
+        vector::relu(&mut x);
+        scalar::relu(&mut y);
+        same(&x, &y);
+        let (mut x, mut y) = (a.clone(), a.clone());
+        vector::axpy(&mut x, -0.7, &b);
+        scalar::axpy(&mut y, -0.7, &b);
+        same(&x, &y);
+
+        // reductions only agree within rounding
+        let bound = a.iter().map(|x| x.abs()).sum::<f32>() * len as f32 * f32::EPSILON;
+        assert!((vector::sum(&a) - scalar::sum(&a)).abs() <= bound);
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "simd",
+    any(
+        all(target_arch = "wasm32", target_feature = "simd128"),
+        target_arch = "x86_64",
+        target_arch = "aarch64"
+    )
+))]
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn test_vector_exp() {
+    let mut x: Vec<f32> = (-1100..=1100).map(|i| i as f32 * 0.0807).collect();
+    x.extend([
+        0.,
+        -0.,
+        1e-30,
+        88.72,
+        88.73,
+        -87.3,
+        -87.4,
+        -200.,
+        f32::INFINITY,
+        f32::NEG_INFINITY,
+        f32::NAN,
+    ]);
+    let mut v = x.clone();
+    let mut s = x.clone();
+    vector::exp(&mut v);
+    scalar::exp(&mut s);
+    for ((x, v), s) in x.iter().zip(&v).zip(&s) {
+        if !s.is_finite() {
+            assert!(v == s || v.is_nan() && s.is_nan(), "exp({}) = {}", x, v);
+            continue;
+        }
+        // a few ulp, with results below the normal range flushed to zero
+        let tol = (s * 4. * f32::EPSILON).max(f32::MIN_POSITIVE);
+        assert!((v - s).abs() <= tol, "exp({}) = {} vs {}", x, v, s);
+    }
+}
@@ -0,0 +1,652 @@
+//! Sparse matrices: COO for construction, CSR and CSC for compute.
+//!
+//! All formats are 2-D and store `f32` values with `u32` indices. COO
+//! entries may be unsorted and repeat a position, in which case they add
+//! up; converting to CSR or CSC sorts them and sums the duplicates.
+//! Transposing a CSR matrix gives a CSC matrix over the same arrays, and
+//! vice versa.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::ndarray::NdArray;
+
+/// CSR or CSC storage: `indptr[m]..indptr[m + 1]` are the entries of row
+/// (CSR) or column (CSC) `m`, with their other coordinate in `indices`.
+#[derive(Clone, Debug, PartialEq)]
+struct Compressed {
+    major: usize,
+    minor: usize,
+    indptr: Vec<u32>,
+    indices: Vec<u32>,
+    values: Vec<f32>,
+}
+
+impl Compressed {
+    fn new(major: usize, minor: usize, indptr: &[u32], indices: &[u32], values: &[f32]) -> Self {
+        if indptr.len() != major + 1 || indptr[0] != 0 {
+            panic!(
+                "indptr must start at 0 and have {} entries, got {:?}",
+                major + 1,
+                indptr
+            );
+        }
+        if indptr.windows(2).any(|w| w[0] > w[1]) {
+            panic!("indptr must be non-decreasing, got {:?}", indptr);
+        }
+        let nnz = indptr[major] as usize;
+        if indices.len() != nnz || values.len() != nnz {
+            panic!(
+                "expected {} indices and values, got {} and {}",
+                nnz,
+                indices.len(),
+                values.len()
+            );
+        }
+        if let Some(i) = indices.iter().find(|i| **i as usize >= minor) {
+            panic!("index {} is out of bounds for dimension {}", i, minor);
+        }
+        Compressed {
+            major,
+            minor,
+            indptr: indptr.to_vec(),
+            indices: indices.to_vec(),
+            values: values.to_vec(),
+        }
+    }
+
+    /// Sorts entries by `(major, minor)` and sums duplicates.
+    fn from_triplets(
+        major: usize,
+        minor: usize,
+        major_idx: &[u32],
+        minor_idx: &[u32],
+        values: &[f32],
+    ) -> Self {
+        let mut order: Vec<usize> = (0..values.len()).collect();
+        order.sort_by_key(|&k| (major_idx[k], minor_idx[k]));
+        let mut indptr = vec![0u32; major + 1];
+        let (mut indices, mut data) = (Vec::new(), Vec::<f32>::new());
+        let mut last = None;
+        for k in order {
+            let key = (major_idx[k], minor_idx[k]);
+            if last == Some(key) {
+                *data.last_mut().unwrap() += values[k];
+                continue;
+            }
+            last = Some(key);
+            indptr[key.0 as usize + 1] += 1;
+            indices.push(key.1);
+            data.push(values[k]);
+        }
+        for m in 0..major {
+            indptr[m + 1] += indptr[m];
+        }
+        Compressed {
+            major,
+            minor,
+            indptr,
+            indices,
+            values: data,
+        }
+    }
+
+    /// `(major, minor)` coordinates of every entry.
+    fn triplets(&self) -> (Vec<u32>, Vec<u32>) {
+        let mut major = Vec::with_capacity(self.values.len());
+        for m in 0..self.major {
+            let len = (self.indptr[m + 1] - self.indptr[m]) as usize;
+            major.extend(std::iter::repeat_n(m as u32, len));
+        }
+        (major, self.indices.clone())
+    }
+
+    /// The other compressed layout of the same matrix.
+    fn swap_layout(&self) -> Self {
+        let (major, minor) = self.triplets();
+        Compressed::from_triplets(self.minor, self.major, &minor, &major, &self.values)
+    }
+
+    fn entries(&self, m: usize) -> std::ops::Range<usize> {
+        self.indptr[m] as usize..self.indptr[m + 1] as usize
+    }
+
+    /// Dense array with entry `(major, minor)` at `index(major, minor)`.
+    fn to_dense(&self, shape: [usize; 2], index: impl Fn(usize, usize) -> usize) -> NdArray {
+        let mut res = NdArray::zeros(&shape);
+        for m in 0..self.major {
+            for k in self.entries(m) {
+                res.buffer[index(m, self.indices[k] as usize)] += self.values[k];
+            }
+        }
+        res
+    }
+}
+
+/// Non-zero coordinates and values of a dense 2-D array, row-major.
+fn nonzeros(a: &NdArray) -> (Vec<u32>, Vec<u32>, Vec<f32>) {
+    check_matrix(a);
+    let cols = a.shape[1];
+    let (mut rows, mut cs, mut values) = (vec![], vec![], vec![]);
+    for (i, x) in a.buffer.iter().enumerate() {
+        if *x != 0. {
+            rows.push((i / cols) as u32);
+            cs.push((i % cols) as u32);
+            values.push(*x);
+        }
+    }
+    (rows, cs, values)
+}
+
+fn check_matrix(a: &NdArray) {
+    if a.shape.len() != 2 {
+        panic!("expected a 2-D array, got shape {:?}", a.shape);
+    }
+}
+
+/// Checks `dense` can be multiplied from the left by a `[_, inner]`
+/// matrix and returns its number of columns.
+fn check_matmul(inner: usize, dense: &NdArray) -> usize {
+    check_matrix(dense);
+    if dense.shape[0] != inner {
+        panic!(
+            "can't multiply a sparse matrix with {} columns by shape {:?}",
+            inner, dense.shape
+        );
+    }
+    dense.shape[1]
+}
+
+/// Adds the scalar operations shared by every format; they only touch
+/// the stored values, so zeros stay zero.
+macro_rules! scalar_ops {
+    ($name:ident, $($values:ident).+) => {
+        #[cfg_attr(feature = "wasm", wasm_bindgen)]
+        impl $name {
+            #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = mulScalar))]
+            pub fn mul_scalar(&self, b: f32) -> $name {
+                self.map_values(|x| x * b)
+            }
+
+            #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = divScalar))]
+            pub fn div_scalar(&self, b: f32) -> $name {
+                self.map_values(|x| x / b)
+            }
+
+            /// Raises every stored value to `p`, which must be positive so
+            /// that implicit zeros stay zero.
+            #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = powScalar))]
+            pub fn pow_scalar(&self, p: f32) -> $name {
+                assert!(p > 0., "sparse power must be positive, got {}", p);
+                self.map_values(|x| x.powf(p))
+            }
+
+            pub fn neg(&self) -> $name {
+                self.map_values(|x| -x)
+            }
+
+            pub fn abs(&self) -> $name {
+                self.map_values(f32::abs)
+            }
+        }
+
+        impl $name {
+            fn map_values<F: Fn(f32) -> f32>(&self, f: F) -> $name {
+                let mut res = self.clone();
+                res.$($values).+.iter_mut().for_each(|x| *x = f(*x));
+                res
+            }
+        }
+    };
+}
+
+/// Coordinate-format sparse matrix.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct CooMatrix {
+    rows: usize,
+    cols: usize,
+    row_indices: Vec<u32>,
+    col_indices: Vec<u32>,
+    values: Vec<f32>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CooMatrix {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        rows: usize,
+        cols: usize,
+        row_indices: &[u32],
+        col_indices: &[u32],
+        values: &[f32],
+    ) -> CooMatrix {
+        if row_indices.len() != values.len() || col_indices.len() != values.len() {
+            panic!(
+                "expected as many indices as values, got {}, {} and {}",
+                row_indices.len(),
+                col_indices.len(),
+                values.len()
+            );
+        }
+        for (i, j) in row_indices.iter().zip(col_indices) {
+            if *i as usize >= rows || *j as usize >= cols {
+                panic!("({}, {}) is out of bounds for {}x{}", i, j, rows, cols);
+            }
+        }
+        CooMatrix {
+            rows,
+            cols,
+            row_indices: row_indices.to_vec(),
+            col_indices: col_indices.to_vec(),
+            values: values.to_vec(),
+        }
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = fromDense))]
+    pub fn from_dense(a: &NdArray) -> CooMatrix {
+        let (row_indices, col_indices, values) = nonzeros(a);
+        CooMatrix {
+            rows: a.shape[0],
+            cols: a.shape[1],
+            row_indices,
+            col_indices,
+            values,
+        }
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn shape(&self) -> Vec<usize> {
+        vec![self.rows, self.cols]
+    }
+
+    /// Number of stored entries, counting duplicates.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = rowIndices))]
+    pub fn row_indices(&self) -> Vec<u32> {
+        self.row_indices.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter = colIndices))]
+    pub fn col_indices(&self) -> Vec<u32> {
+        self.col_indices.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn values(&self) -> Vec<f32> {
+        self.values.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = toDense))]
+    pub fn to_dense(&self) -> NdArray {
+        let mut res = NdArray::zeros(&[self.rows, self.cols]);
+        for ((i, j), x) in self
+            .row_indices
+            .iter()
+            .zip(&self.col_indices)
+            .zip(&self.values)
+        {
+            res.buffer[*i as usize * self.cols + *j as usize] += x;
+        }
+        res
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = toCsr))]
+    pub fn to_csr(&self) -> CsrMatrix {
+        CsrMatrix {
+            inner: Compressed::from_triplets(
+                self.rows,
+                self.cols,
+                &self.row_indices,
+                &self.col_indices,
+                &self.values,
+            ),
+        }
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = toCsc))]
+    pub fn to_csc(&self) -> CscMatrix {
+        CscMatrix {
+            inner: Compressed::from_triplets(
+                self.cols,
+                self.rows,
+                &self.col_indices,
+                &self.row_indices,
+                &self.values,
+            ),
+        }
+    }
+
+    pub fn transpose(&self) -> CooMatrix {
+        CooMatrix {
+            rows: self.cols,
+            cols: self.rows,
+            row_indices: self.col_indices.clone(),
+            col_indices: self.row_indices.clone(),
+            values: self.values.clone(),
+        }
+    }
+}
+
+scalar_ops!(CooMatrix, values);
+
+/// Compressed sparse row matrix.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct CsrMatrix {
+    inner: Compressed,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CsrMatrix {
+    /// Row `i` holds entries `indptr[i]..indptr[i + 1]` of `indices` (their
+    /// columns) and `values`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        rows: usize,
+        cols: usize,
+        indptr: &[u32],
+        indices: &[u32],
+        values: &[f32],
+    ) -> CsrMatrix {
+        CsrMatrix {
+            inner: Compressed::new(rows, cols, indptr, indices, values),
+        }
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = fromDense))]
+    pub fn from_dense(a: &NdArray) -> CsrMatrix {
+        let (rows, cols, values) = nonzeros(a);
+        CsrMatrix {
+            inner: Compressed::from_triplets(a.shape[0], a.shape[1], &rows, &cols, &values),
+        }
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn shape(&self) -> Vec<usize> {
+        vec![self.inner.major, self.inner.minor]
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn nnz(&self) -> usize {
+        self.inner.values.len()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn indptr(&self) -> Vec<u32> {
+        self.inner.indptr.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn indices(&self) -> Vec<u32> {
+        self.inner.indices.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn values(&self) -> Vec<f32> {
+        self.inner.values.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = toDense))]
+    pub fn to_dense(&self) -> NdArray {
+        let cols = self.inner.minor;
+        self.inner
+            .to_dense([self.inner.major, cols], |i, j| i * cols + j)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = toCoo))]
+    pub fn to_coo(&self) -> CooMatrix {
+        let (row_indices, col_indices) = self.inner.triplets();
+        CooMatrix {
+            rows: self.inner.major,
+            cols: self.inner.minor,
+            row_indices,
+            col_indices,
+            values: self.inner.values.clone(),
+        }
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = toCsc))]
+    pub fn to_csc(&self) -> CscMatrix {
+        CscMatrix {
+            inner: self.inner.swap_layout(),
+        }
+    }
+
+    /// The transpose in CSC format, sharing this matrix's arrays.
+    pub fn transpose(&self) -> CscMatrix {
+        CscMatrix {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// `self @ dense` for a dense `[cols, n]` array.
+    pub fn matmul(&self, dense: &NdArray) -> NdArray {
+        let n = check_matmul(self.inner.minor, dense);
+        let mut res = NdArray::zeros(&[self.inner.major, n]);
+        for (i, out) in res.buffer.chunks_mut(n.max(1)).enumerate() {
+            for k in self.inner.entries(i) {
+                let (j, a) = (self.inner.indices[k] as usize, self.inner.values[k]);
+                for (o, b) in out.iter_mut().zip(&dense.buffer[j * n..(j + 1) * n]) {
+                    *o += a * b;
+                }
+            }
+        }
+        res
+    }
+
+    /// Rows `start..end`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = sliceRows))]
+    pub fn slice_rows(&self, start: usize, end: usize) -> CsrMatrix {
+        if start > end || end > self.inner.major {
+            panic!(
+                "rows {}..{} are out of bounds for {} rows",
+                start, end, self.inner.major
+            );
+        }
+        let (from, to) = (self.inner.indptr[start], self.inner.indptr[end]);
+        let indptr: Vec<u32> = self.inner.indptr[start..=end]
+            .iter()
+            .map(|p| p - from)
+            .collect();
+        let range = from as usize..to as usize;
+        CsrMatrix {
+            inner: Compressed {
+                major: end - start,
+                minor: self.inner.minor,
+                indptr,
+                indices: self.inner.indices[range.clone()].to_vec(),
+                values: self.inner.values[range].to_vec(),
+            },
+        }
+    }
+
+    /// Row `i` as a dense `[cols]` array.
+    pub fn row(&self, i: usize) -> NdArray {
+        let mut res = NdArray::zeros(&[self.inner.minor]);
+        for k in self.inner.entries(i) {
+            res.buffer[self.inner.indices[k] as usize] += self.inner.values[k];
+        }
+        res
+    }
+}
+
+scalar_ops!(CsrMatrix, inner.values);
+
+/// Compressed sparse column matrix.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct CscMatrix {
+    inner: Compressed,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CscMatrix {
+    /// Column `j` holds entries `indptr[j]..indptr[j + 1]` of `indices`
+    /// (their rows) and `values`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(
+        rows: usize,
+        cols: usize,
+        indptr: &[u32],
+        indices: &[u32],
+        values: &[f32],
+    ) -> CscMatrix {
+        CscMatrix {
+            inner: Compressed::new(cols, rows, indptr, indices, values),
+        }
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = fromDense))]
+    pub fn from_dense(a: &NdArray) -> CscMatrix {
+        let (rows, cols, values) = nonzeros(a);
+        CscMatrix {
+            inner: Compressed::from_triplets(a.shape[1], a.shape[0], &cols, &rows, &values),
+        }
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn shape(&self) -> Vec<usize> {
+        vec![self.inner.minor, self.inner.major]
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn nnz(&self) -> usize {
+        self.inner.values.len()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn indptr(&self) -> Vec<u32> {
+        self.inner.indptr.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn indices(&self) -> Vec<u32> {
+        self.inner.indices.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn values(&self) -> Vec<f32> {
+        self.inner.values.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = toDense))]
+    pub fn to_dense(&self) -> NdArray {
+        let cols = self.inner.major;
+        self.inner
+            .to_dense([self.inner.minor, cols], |j, i| i * cols + j)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = toCoo))]
+    pub fn to_coo(&self) -> CooMatrix {
+        let (col_indices, row_indices) = self.inner.triplets();
+        CooMatrix {
+            rows: self.inner.minor,
+            cols: self.inner.major,
+            row_indices,
+            col_indices,
+            values: self.inner.values.clone(),
+        }
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = toCsr))]
+    pub fn to_csr(&self) -> CsrMatrix {
+        CsrMatrix {
+            inner: self.inner.swap_layout(),
+        }
+    }
+
+    /// The transpose in CSR format, sharing this matrix's arrays.
+    pub fn transpose(&self) -> CsrMatrix {
+        CsrMatrix {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// `self @ dense` for a dense `[cols, n]` array.
+    pub fn matmul(&self, dense: &NdArray) -> NdArray {
+        let n = check_matmul(self.inner.major, dense);
+        let mut res = NdArray::zeros(&[self.inner.minor, n]);
+        for j in 0..self.inner.major {
+            let b = &dense.buffer[j * n..(j + 1) * n];
+            for k in self.inner.entries(j) {
+                let (i, a) = (self.inner.indices[k] as usize, self.inner.values[k]);
+                for (o, b) in res.buffer[i * n..(i + 1) * n].iter_mut().zip(b) {
+                    *o += a * b;
+                }
+            }
+        }
+        res
+    }
+}
+
+scalar_ops!(CscMatrix, inner.values);
+
+#[cfg(test)]
+fn sample() -> NdArray {
+    NdArray::from(
+        &[0., 2., 0., 0., 1., 0., 0., 3., 0., 0., 0., 0.],
+        Some(vec![3, 4]),
+        None,
+    )
+}
+
+#[test]
+fn test_sparse_conversions() {
+    let dense = sample();
+    let csr = CsrMatrix::from_dense(&dense);
+    assert_eq!(csr.nnz(), 3);
+    assert_eq!(csr.indptr(), vec![0, 1, 3, 3]);
+    assert_eq!(csr.indices(), vec![1, 0, 3]);
+    assert_eq!(csr.to_dense().buffer, dense.buffer);
+    let csc = csr.to_csc();
+    assert_eq!(csc.indptr(), vec![0, 1, 2, 2, 3]);
+    assert_eq!(csc.indices(), vec![1, 0, 1]);
+    assert_eq!(csc.to_dense().buffer, dense.buffer);
+    assert_eq!(CscMatrix::from_dense(&dense).indptr(), csc.indptr());
+    assert_eq!(csc.to_csr().indices(), csr.indices());
+    assert_eq!(csr.to_coo().to_dense().buffer, dense.buffer);
+    assert_eq!(csc.to_coo().to_csr().values(), csr.values());
+
+    // duplicates add up and unsorted entries are ordered
+    let coo = CooMatrix::new(2, 2, &[1, 0, 1], &[0, 1, 0], &[1., 2., 3.]);
+    assert_eq!(coo.to_dense().buffer, vec![0., 2., 4., 0.]);
+    let csr = coo.to_csr();
+    assert_eq!(csr.nnz(), 2);
+    assert_eq!(csr.values(), vec![2., 4.]);
+    assert_eq!(coo.to_csc().to_dense().buffer, coo.to_dense().buffer);
+}
+
+#[test]
+fn test_sparse_ops() {
+    let dense = sample();
+    let csr = CsrMatrix::from_dense(&dense);
+    let b = NdArray::arange(0, 8, None).reshape(&[4, 2]);
+    assert_eq!(csr.matmul(&b).buffer, dense.matmul(&b).buffer);
+    assert_eq!(csr.to_csc().matmul(&b).buffer, dense.matmul(&b).buffer);
+
+    let t = csr.transpose();
+    assert_eq!(t.shape(), vec![4, 3]);
+    assert_eq!(t.to_dense().buffer, dense.transpose().buffer);
+    let c = NdArray::arange(0, 6, None).reshape(&[3, 2]);
+    assert_eq!(t.matmul(&c).buffer, dense.transpose().matmul(&c).buffer);
+    assert_eq!(t.transpose().to_dense().buffer, dense.buffer);
+    assert_eq!(
+        CooMatrix::from_dense(&dense).transpose().to_dense().buffer,
+        dense.transpose().buffer
+    );
+
+    assert_eq!(csr.mul_scalar(2.).values(), vec![4., 2., 6.]);
+    assert_eq!(csr.neg().pow_scalar(2.).values(), vec![4., 1., 9.]);
+    assert_eq!(csr.to_coo().div_scalar(2.).values(), vec![1., 0.5, 1.5]);
+
+    let rows = csr.slice_rows(1, 3);
+    assert_eq!(rows.shape(), vec![2, 4]);
+    assert_eq!(rows.indptr(), vec![0, 2, 2]);
+    assert_eq!(rows.to_dense().buffer, dense.buffer[4..]);
+    assert_eq!(csr.row(1).buffer, vec![1., 0., 0., 3.]);
+}
@@ -0,0 +1,838 @@
+//! Descriptive statistics.
+//!
+//! Reductions take an optional `axis`: with `Some(axis)` that axis is
+//! removed from the result, with `None` every element is reduced and the
+//! result has shape `[1]`. Cumulative ops keep the shape, or flatten the
+//! input when `axis` is `None`, like NumPy. Sums are accumulated in `f64`.
+//! An axis out of bounds or an argument out of range is returned as an
+//! error.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    error::{Error, JsResult, Result},
+    ndarray::NdArray,
+};
+
+/// Largest number of bins [`bincount`] will allocate.
+pub const BINCOUNT_MAX_BINS: usize = 1 << 24;
+
+/// How a quantile that falls between two data points is computed.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    Linear,
+    Lower,
+    Higher,
+    Nearest,
+    Midpoint,
+}
+
+/// Splits `a` into lanes along `axis` (or a single lane of every element
+/// when `axis` is `None`), maps each lane to `n_out` values and puts the
+/// results back in place of the lane.
+fn map_lanes<F>(a: &NdArray, axis: Option<usize>, n_out: usize, mut f: F) -> NdArray
+where
+    F: FnMut(&[f32], &mut Vec<f32>),
+{
+    let mut out = Vec::with_capacity(n_out);
+    let axis = match axis {
+        Some(axis) => axis,
+        None => {
+            f(&a.buffer, &mut out);
+            assert_eq!(out.len(), n_out);
+            return NdArray::from(&out, Some(vec![n_out]), None);
+        }
+    };
+    assert!(axis < a.shape.len(), "axis {} is out of bounds", axis);
+    let len = a.shape[axis];
+    let inner: usize = a.shape[axis + 1..].iter().product();
+    let outer: usize = a.shape[..axis].iter().product();
+    let mut shape = a.shape.clone();
+    shape[axis] = n_out;
+    let mut buffer = vec![0.; outer * n_out * inner];
+    let mut lane = Vec::with_capacity(len);
+    for o in 0..outer {
+        for i in 0..inner {
+            lane.clear();
+            lane.extend((0..len).map(|k| a.buffer[(o * len + k) * inner + i]));
+            out.clear();
+            f(&lane, &mut out);
+            assert_eq!(out.len(), n_out);
+            for (k, v) in out.iter().enumerate() {
+                buffer[(o * n_out + k) * inner + i] = *v;
+            }
+        }
+    }
+    NdArray::from(&buffer, Some(shape), None)
+}
+
+/// Checks that `axis`, if given, exists in `a`.
+fn check_axis(a: &NdArray, axis: Option<usize>) -> Result<()> {
+    match axis {
+        Some(axis) if axis >= a.shape.len() => Err(Error::ShapeMismatch(format!(
+            "axis {} is out of bounds for shape {:?}",
+            axis, a.shape
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// `axis`, or the last axis when it is `None`; fails on 0-d arrays.
+fn axis_or_last(a: &NdArray, axis: Option<usize>) -> Result<usize> {
+    if a.shape.is_empty() {
+        return Err(Error::ShapeMismatch(
+            "expected at least 1 dimension".to_string(),
+        ));
+    }
+    let axis = axis.unwrap_or(a.shape.len() - 1);
+    check_axis(a, Some(axis))?;
+    Ok(axis)
+}
+
+/// Checks that `q` is in `[0, top]`, e.g. 1 for quantiles or 100 for
+/// percentiles.
+fn check_q(q: f32, top: f32) -> Result<()> {
+    if !(0. ..=top).contains(&q) {
+        return Err(Error::InvalidArgument(format!(
+            "q must be in [0, {}], got {}",
+            top, q
+        )));
+    }
+    Ok(())
+}
+
+fn reduce<F: FnMut(&[f32]) -> f32>(a: &NdArray, axis: Option<usize>, mut f: F) -> NdArray {
+    let mut res = map_lanes(a, axis, 1, |lane, out| out.push(f(lane)));
+    if let Some(axis) = axis {
+        if res.shape.len() > 1 {
+            res.shape.remove(axis);
+            res.strides = crate::utils::get_strides(&res.shape);
+        }
+    }
+    res
+}
+
+fn not_nan(lane: &[f32]) -> Vec<f32> {
+    lane.iter().copied().filter(|x| !x.is_nan()).collect()
+}
+
+fn sum_of(lane: &[f32]) -> f64 {
+    lane.iter().map(|x| *x as f64).sum()
+}
+
+fn mean_of(lane: &[f32]) -> f32 {
+    (sum_of(lane) / lane.len() as f64) as f32
+}
+
+fn var_of(lane: &[f32], ddof: usize) -> f32 {
+    if lane.len() <= ddof {
+        return f32::NAN;
+    }
+    let mean = sum_of(lane) / lane.len() as f64;
+    let ss: f64 = lane.iter().map(|x| (*x as f64 - mean).powi(2)).sum();
+    (ss / (lane.len() - ddof) as f64) as f32
+}
+
+fn quantile_of(lane: &[f32], q: f32, interpolation: Interpolation) -> f32 {
+    if lane.is_empty() || lane.iter().any(|x| x.is_nan()) {
+        return f32::NAN;
+    }
+    let mut sorted = lane.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let pos = q as f64 * (sorted.len() - 1) as f64;
+    let (lo, hi) = (pos.floor() as usize, pos.ceil() as usize);
+    let frac = pos - lo as f64;
+    let (a, b) = (sorted[lo] as f64, sorted[hi] as f64);
+    let res = match interpolation {
+        Interpolation::Linear => a + (b - a) * frac,
+        Interpolation::Lower => a,
+        Interpolation::Higher => b,
+        // ties go to the even index, as in NumPy
+        Interpolation::Nearest => {
+            if frac > 0.5 || (frac == 0.5 && lo % 2 == 1) {
+                b
+            } else {
+                a
+            }
+        }
+        Interpolation::Midpoint => (a + b) / 2.,
+    };
+    res as f32
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn mean(a: &NdArray, axis: Option<usize>) -> JsResult<NdArray> {
+    check_axis(a, axis)?;
+    Ok(reduce(a, axis, mean_of))
+}
+
+/// Variance with `ddof` delta degrees of freedom (default 0).
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn var(a: &NdArray, axis: Option<usize>, ddof: Option<usize>) -> JsResult<NdArray> {
+    check_axis(a, axis)?;
+    let ddof = ddof.unwrap_or(0);
+    Ok(reduce(a, axis, |lane| var_of(lane, ddof)))
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(js_name = std))]
+pub fn std_dev(a: &NdArray, axis: Option<usize>, ddof: Option<usize>) -> JsResult<NdArray> {
+    check_axis(a, axis)?;
+    let ddof = ddof.unwrap_or(0);
+    Ok(reduce(a, axis, |lane| var_of(lane, ddof).sqrt()))
+}
+
+/// The `q`-th quantile, `q` in `[0, 1]`, interpolating linearly by default.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn quantile(
+    a: &NdArray,
+    q: f32,
+    axis: Option<usize>,
+    interpolation: Option<Interpolation>,
+) -> JsResult<NdArray> {
+    check_axis(a, axis)?;
+    check_q(q, 1.)?;
+    let interpolation = interpolation.unwrap_or(Interpolation::Linear);
+    Ok(reduce(a, axis, |lane| quantile_of(lane, q, interpolation)))
+}
+
+/// Like [`quantile`], with `q` in `[0, 100]`.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn percentile(
+    a: &NdArray,
+    q: f32,
+    axis: Option<usize>,
+    interpolation: Option<Interpolation>,
+) -> JsResult<NdArray> {
+    check_q(q, 100.)?;
+    quantile(a, q / 100., axis, interpolation)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn median(a: &NdArray, axis: Option<usize>) -> JsResult<NdArray> {
+    quantile(a, 0.5, axis, None)
+}
+
+/// Sum ignoring NaNs; all-NaN lanes sum to 0.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn nansum(a: &NdArray, axis: Option<usize>) -> JsResult<NdArray> {
+    check_axis(a, axis)?;
+    Ok(reduce(a, axis, |lane| sum_of(&not_nan(lane)) as f32))
+}
+
+/// Mean ignoring NaNs; all-NaN lanes give NaN.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn nanmean(a: &NdArray, axis: Option<usize>) -> JsResult<NdArray> {
+    check_axis(a, axis)?;
+    Ok(reduce(a, axis, |lane| mean_of(&not_nan(lane))))
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn nanvar(a: &NdArray, axis: Option<usize>, ddof: Option<usize>) -> JsResult<NdArray> {
+    check_axis(a, axis)?;
+    let ddof = ddof.unwrap_or(0);
+    Ok(reduce(a, axis, |lane| var_of(&not_nan(lane), ddof)))
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn nanstd(a: &NdArray, axis: Option<usize>, ddof: Option<usize>) -> JsResult<NdArray> {
+    check_axis(a, axis)?;
+    let ddof = ddof.unwrap_or(0);
+    Ok(reduce(a, axis, |lane| var_of(&not_nan(lane), ddof).sqrt()))
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn nanmin(a: &NdArray, axis: Option<usize>) -> JsResult<NdArray> {
+    check_axis(a, axis)?;
+    Ok(reduce(a, axis, |lane| {
+        not_nan(lane)
+            .into_iter()
+            .reduce(f32::min)
+            .unwrap_or(f32::NAN)
+    }))
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn nanmax(a: &NdArray, axis: Option<usize>) -> JsResult<NdArray> {
+    check_axis(a, axis)?;
+    Ok(reduce(a, axis, |lane| {
+        not_nan(lane)
+            .into_iter()
+            .reduce(f32::max)
+            .unwrap_or(f32::NAN)
+    }))
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn nanquantile(
+    a: &NdArray,
+    q: f32,
+    axis: Option<usize>,
+    interpolation: Option<Interpolation>,
+) -> JsResult<NdArray> {
+    check_axis(a, axis)?;
+    check_q(q, 1.)?;
+    let interpolation = interpolation.unwrap_or(Interpolation::Linear);
+    Ok(reduce(a, axis, |lane| {
+        quantile_of(&not_nan(lane), q, interpolation)
+    }))
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn nanmedian(a: &NdArray, axis: Option<usize>) -> JsResult<NdArray> {
+    nanquantile(a, 0.5, axis, None)
+}
+
+fn scan<F: Fn(f64, f32) -> f64>(a: &NdArray, axis: Option<usize>, f: F) -> Result<NdArray> {
+    check_axis(a, axis)?;
+    let len = match axis {
+        Some(axis) => a.shape[axis],
+        None => a.buffer.len(),
+    };
+    Ok(map_lanes(a, axis, len, |lane, out| {
+        let mut acc = None;
+        for x in lane {
+            let next = match acc {
+                Some(acc) => f(acc, *x),
+                None => *x as f64,
+            };
+            acc = Some(next);
+            out.push(next as f32);
+        }
+    }))
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn cumsum(a: &NdArray, axis: Option<usize>) -> JsResult<NdArray> {
+    Ok(scan(a, axis, |acc, x| acc + x as f64)?)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn cumprod(a: &NdArray, axis: Option<usize>) -> JsResult<NdArray> {
+    Ok(scan(a, axis, |acc, x| acc * x as f64)?)
+}
+
+/// Running maximum; a NaN propagates to the rest of the lane.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn cummax(a: &NdArray, axis: Option<usize>) -> JsResult<NdArray> {
+    Ok(scan(a, axis, |acc, x| {
+        if acc.is_nan() || x.is_nan() {
+            f64::NAN
+        } else {
+            acc.max(x as f64)
+        }
+    })?)
+}
+
+/// `n`-th discrete difference (default 1) along `axis` (default: last).
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn diff(a: &NdArray, n: Option<usize>, axis: Option<usize>) -> JsResult<NdArray> {
+    let axis = axis_or_last(a, axis)?;
+    let n = n.unwrap_or(1);
+    let len = a.shape[axis];
+    Ok(map_lanes(
+        a,
+        Some(axis),
+        len.saturating_sub(n),
+        |lane, out| {
+            out.extend_from_slice(lane);
+            for _ in 0..n.min(len) {
+                for i in 0..out.len() - 1 {
+                    out[i] = out[i + 1] - out[i];
+                }
+                out.pop();
+            }
+        },
+    ))
+}
+
+/// Counts and bin edges computed by [`histogram`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct Histogram {
+    counts: NdArray,
+    edges: NdArray,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Histogram {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn counts(&self) -> NdArray {
+        self.counts.clone()
+    }
+
+    /// `bins + 1` edges; every bin is half-open except the last.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn edges(&self) -> NdArray {
+        self.edges.clone()
+    }
+}
+
+/// Checks the bin count and optional `[min, max]` range of [`histogram`].
+fn check_histogram(bins: usize, range: Option<&[f32]>) -> Result<()> {
+    if bins == 0 {
+        return Err(Error::InvalidArgument("bins must be positive".to_string()));
+    }
+    match range {
+        Some([min, max]) if min <= max => Ok(()),
+        Some(range) => Err(Error::InvalidArgument(format!(
+            "range must be [min, max], got {:?}",
+            range
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Histogram of all elements in `bins` equal-width bins over `range`
+/// (default: the data's min and max). NaNs and values outside the range
+/// are ignored.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn histogram(a: &NdArray, bins: usize, range: Option<Vec<f32>>) -> JsResult<Histogram> {
+    check_histogram(bins, range.as_deref())?;
+    let values = not_nan(&a.buffer);
+    let (mut lo, mut hi) = match range {
+        Some(range) => (range[0] as f64, range[1] as f64),
+        None => values
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), x| {
+                (lo.min(*x as f64), hi.max(*x as f64))
+            }),
+    };
+    if values.is_empty() && !lo.is_finite() {
+        lo = 0.;
+        hi = 1.;
+    }
+    if lo == hi {
+        lo -= 0.5;
+        hi += 0.5;
+    }
+    let width = (hi - lo) / bins as f64;
+    let mut counts = vec![0f32; bins];
+    for x in values {
+        let x = x as f64;
+        if x < lo || x > hi {
+            continue;
+        }
+        let bin = (((x - lo) / width) as usize).min(bins - 1);
+        counts[bin] += 1.;
+    }
+    let edges: Vec<f32> = (0..=bins).map(|i| (lo + width * i as f64) as f32).collect();
+    Ok(Histogram {
+        counts: NdArray::from(&counts, None, None),
+        edges: NdArray::from(&edges, None, None),
+    })
+}
+
+/// Validates the input of [`bincount`] and returns the number of bins.
+fn bincount_len(a: &NdArray, weights: Option<&[f32]>, minlength: usize) -> Result<usize> {
+    if let Some(weights) = weights {
+        if weights.len() != a.buffer.len() {
+            return Err(Error::ShapeMismatch(format!(
+                "{} weights for {} values",
+                weights.len(),
+                a.buffer.len()
+            )));
+        }
+    }
+    let mut bins = minlength;
+    for x in &a.buffer {
+        if !(*x >= 0. && x.fract() == 0.) {
+            return Err(Error::InvalidArgument(format!(
+                "bincount expects non-negative integers, got {}",
+                x
+            )));
+        }
+        bins = bins.max((*x as usize).saturating_add(1));
+    }
+    if bins > BINCOUNT_MAX_BINS {
+        return Err(Error::InvalidArgument(format!(
+            "bincount would need {} bins, more than {}",
+            bins, BINCOUNT_MAX_BINS
+        )));
+    }
+    Ok(bins)
+}
+
+/// Number of occurrences of each non-negative integer in `a`, or the sum of
+/// their `weights`. The result has at least `minlength` bins, and at most
+/// [`BINCOUNT_MAX_BINS`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn bincount(
+    a: &NdArray,
+    weights: Option<Vec<f32>>,
+    minlength: Option<usize>,
+) -> JsResult<NdArray> {
+    let bins = bincount_len(a, weights.as_deref(), minlength.unwrap_or(0))?;
+    let mut counts = vec![0f64; bins];
+    for (i, x) in a.buffer.iter().enumerate() {
+        counts[*x as usize] += weights.as_ref().map_or(1., |w| w[i] as f64);
+    }
+    let counts: Vec<f32> = counts.into_iter().map(|x| x as f32).collect();
+    Ok(NdArray::from(&counts, None, None))
+}
+
+/// Whether monotonic `bins` are increasing rather than decreasing.
+fn bins_increasing(bins: &[f32]) -> Result<bool> {
+    if bins.windows(2).all(|w| w[0] <= w[1]) {
+        Ok(true)
+    } else if bins.windows(2).all(|w| w[0] >= w[1]) {
+        Ok(false)
+    } else {
+        Err(Error::InvalidArgument(format!(
+            "bins must be monotonic, got {:?}",
+            bins
+        )))
+    }
+}
+
+/// Index of the bin each value falls in, for monotonic `bins`. With
+/// increasing bins, `i` satisfies `bins[i - 1] <= x < bins[i]`, or
+/// `bins[i - 1] < x <= bins[i]` when `right` is set.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn digitize(a: &NdArray, bins: &[f32], right: Option<bool>) -> JsResult<NdArray> {
+    let right = right.unwrap_or(false);
+    let increasing = bins_increasing(bins)?;
+    let mut res = a.clone();
+    for x in res.buffer.iter_mut() {
+        let v = *x;
+        let idx = if v.is_nan() {
+            bins.len()
+        } else if increasing {
+            bins.iter()
+                .filter(|b| if right { **b < v } else { **b <= v })
+                .count()
+        } else {
+            bins.iter()
+                .filter(|b| if right { **b >= v } else { **b > v })
+                .count()
+        };
+        *x = idx as f32;
+    }
+    Ok(res)
+}
+
+/// Variables and observations of `m` as rows, with each variable centered.
+fn centered_rows(m: &NdArray, rowvar: bool) -> Result<Vec<Vec<f64>>> {
+    let m = match m.shape.len() {
+        1 => NdArray::from(&m.buffer, Some(vec![1, m.buffer.len()]), None),
+        2 if rowvar => m.clone(),
+        2 => m.transpose(),
+        _ => {
+            return Err(Error::ShapeMismatch(format!(
+                "cov expects a 1-D or 2-D array, got shape {:?}",
+                m.shape
+            )))
+        }
+    };
+    Ok(m.buffer
+        .chunks(m.shape[1].max(1))
+        .take(m.shape[0])
+        .map(|row| {
+            let mean = sum_of(row) / row.len() as f64;
+            row.iter().map(|x| *x as f64 - mean).collect()
+        })
+        .collect())
+}
+
+/// Covariance matrix `[V, V]` of `m`. Each row of `m` is a variable and
+/// each column an observation, unless `rowvar` is `false`. `ddof` defaults
+/// to 1.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn cov(m: &NdArray, rowvar: Option<bool>, ddof: Option<usize>) -> JsResult<NdArray> {
+    let rows = centered_rows(m, rowvar.unwrap_or(true))?;
+    let n = rows.len();
+    let obs = rows.first().map_or(0, |r| r.len());
+    let denom = obs as f64 - ddof.unwrap_or(1) as f64;
+    let mut buffer = vec![0f32; n * n];
+    for i in 0..n {
+        for j in i..n {
+            let c: f64 = rows[i].iter().zip(&rows[j]).map(|(x, y)| x * y).sum();
+            let c = (c / denom) as f32;
+            buffer[i * n + j] = c;
+            buffer[j * n + i] = c;
+        }
+    }
+    Ok(NdArray::from(&buffer, Some(vec![n, n]), None))
+}
+
+/// Pearson correlation coefficients, laid out like [`cov`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn corrcoef(m: &NdArray, rowvar: Option<bool>) -> JsResult<NdArray> {
+    let mut c = cov(m, rowvar, None)?;
+    let n = c.shape[0];
+    let std: Vec<f32> = (0..n).map(|i| c.buffer[i * n + i].sqrt()).collect();
+    for i in 0..n {
+        for j in 0..n {
+            let r = c.buffer[i * n + j] / (std[i] * std[j]);
+            c.buffer[i * n + j] = r.clamp(-1., 1.);
+        }
+    }
+    Ok(c)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl NdArray {
+    pub fn mean(&self, axis: Option<usize>) -> JsResult<NdArray> {
+        mean(self, axis)
+    }
+
+    pub fn var(&self, axis: Option<usize>, ddof: Option<usize>) -> JsResult<NdArray> {
+        var(self, axis, ddof)
+    }
+
+    pub fn std(&self, axis: Option<usize>, ddof: Option<usize>) -> JsResult<NdArray> {
+        std_dev(self, axis, ddof)
+    }
+
+    pub fn quantile(
+        &self,
+        q: f32,
+        axis: Option<usize>,
+        interpolation: Option<Interpolation>,
+    ) -> JsResult<NdArray> {
+        quantile(self, q, axis, interpolation)
+    }
+
+    pub fn percentile(
+        &self,
+        q: f32,
+        axis: Option<usize>,
+        interpolation: Option<Interpolation>,
+    ) -> JsResult<NdArray> {
+        percentile(self, q, axis, interpolation)
+    }
+
+    pub fn median(&self, axis: Option<usize>) -> JsResult<NdArray> {
+        median(self, axis)
+    }
+
+    pub fn nansum(&self, axis: Option<usize>) -> JsResult<NdArray> {
+        nansum(self, axis)
+    }
+
+    pub fn nanmean(&self, axis: Option<usize>) -> JsResult<NdArray> {
+        nanmean(self, axis)
+    }
+
+    pub fn cumsum(&self, axis: Option<usize>) -> JsResult<NdArray> {
+        cumsum(self, axis)
+    }
+
+    pub fn cumprod(&self, axis: Option<usize>) -> JsResult<NdArray> {
+        cumprod(self, axis)
+    }
+
+    pub fn cummax(&self, axis: Option<usize>) -> JsResult<NdArray> {
+        cummax(self, axis)
+    }
+
+    pub fn diff(&self, n: Option<usize>, axis: Option<usize>) -> JsResult<NdArray> {
+        diff(self, n, axis)
+    }
+}
+
+#[cfg(test)]
+fn assert_close(a: &NdArray, expected: &[f32]) {
+    assert_eq!(a.buffer.len(), expected.len(), "{:?}", a.buffer);
+    for (x, y) in a.buffer.iter().zip(expected) {
+        assert!(
+            (x - y).abs() < 1e-5 || (x.is_nan() && y.is_nan()),
+            "{:?} != {:?}",
+            a.buffer,
+            expected
+        );
+    }
+}
+
+#[test]
+fn test_moments_and_quantiles() {
+    let a = NdArray::from(
+        &[1., 2., 3., 4., 10., 20., 30., 40.],
+        Some(vec![2, 4]),
+        None,
+    );
+    assert_close(&mean(&a, None).unwrap(), &[13.75]);
+    assert_close(&mean(&a, Some(1)).unwrap(), &[2.5, 25.]);
+    assert_eq!(mean(&a, Some(0)).unwrap().shape, vec![4]);
+    assert_close(&var(&a, Some(1), None).unwrap(), &[1.25, 125.]);
+    assert_close(
+        &a.std(Some(1), Some(1)).unwrap(),
+        &[1.6666666f32.sqrt(), 166.66667f32.sqrt()],
+    );
+    assert_close(&var(&a, Some(0), Some(2)).unwrap(), &[f32::NAN; 4]);
+
+    let b = NdArray::from(&[1., 2., 3., 4.], None, None);
+    assert_close(&quantile(&b, 0.4, None, None).unwrap(), &[2.2]);
+    assert_close(
+        &quantile(&b, 0.4, None, Some(Interpolation::Lower)).unwrap(),
+        &[2.],
+    );
+    assert_close(
+        &quantile(&b, 0.4, None, Some(Interpolation::Higher)).unwrap(),
+        &[3.],
+    );
+    assert_close(
+        &quantile(&b, 0.4, None, Some(Interpolation::Nearest)).unwrap(),
+        &[2.],
+    );
+    assert_close(
+        &percentile(&b, 50., None, Some(Interpolation::Nearest)).unwrap(),
+        &[3.],
+    );
+    assert_close(
+        &quantile(&b, 0.4, None, Some(Interpolation::Midpoint)).unwrap(),
+        &[2.5],
+    );
+    assert_close(&a.median(Some(1)).unwrap(), &[2.5, 25.]);
+}
+
+#[test]
+fn test_nan_reductions() {
+    let a = NdArray::from(
+        &[1., f32::NAN, 3., f32::NAN, f32::NAN, f32::NAN],
+        Some(vec![2, 3]),
+        None,
+    );
+    assert_close(&nansum(&a, Some(1)).unwrap(), &[4., 0.]);
+    assert_close(&nanmean(&a, Some(1)).unwrap(), &[2., f32::NAN]);
+    assert_close(&nanvar(&a, Some(1), None).unwrap(), &[1., f32::NAN]);
+    assert_close(&nanstd(&a, None, None).unwrap(), &[1.]);
+    assert_close(&nanmin(&a, Some(0)).unwrap(), &[1., f32::NAN, 3.]);
+    assert_close(&nanmax(&a, None).unwrap(), &[3.]);
+    assert_close(&nanmedian(&a, None).unwrap(), &[2.]);
+    assert_close(&mean(&a, None).unwrap(), &[f32::NAN]);
+}
+
+#[test]
+fn test_cumulative_and_diff() {
+    let a = NdArray::from(&[1., 3., 2., 4., 0., 5.], Some(vec![2, 3]), None);
+    assert_close(&cumsum(&a, Some(1)).unwrap(), &[1., 4., 6., 4., 4., 9.]);
+    assert_close(&cumsum(&a, Some(0)).unwrap(), &[1., 3., 2., 5., 3., 7.]);
+    assert_close(&cumprod(&a, None).unwrap(), &[1., 3., 6., 24., 0., 0.]);
+    assert_eq!(cumprod(&a, None).unwrap().shape, vec![6]);
+    assert_close(&cummax(&a, Some(1)).unwrap(), &[1., 3., 3., 4., 4., 5.]);
+
+    let d = diff(&a, None, None).unwrap();
+    assert_eq!(d.shape, vec![2, 2]);
+    assert_close(&d, &[2., -1., -4., 5.]);
+    assert_close(&diff(&a, Some(2), None).unwrap(), &[-3., 9.]);
+    assert_close(&diff(&a, None, Some(0)).unwrap(), &[3., -3., 3.]);
+    assert_eq!(diff(&a, Some(5), None).unwrap().shape, vec![2, 0]);
+}
+
+#[test]
+fn test_histogram_bincount_digitize() {
+    let a = NdArray::from(&[0., 0.5, 1., 1.5, 2., 4., f32::NAN], None, None);
+    let h = histogram(&a, 4, None).unwrap();
+    assert_close(&h.counts(), &[2., 2., 1., 1.]);
+    assert_close(&h.edges(), &[0., 1., 2., 3., 4.]);
+    let h = histogram(&a, 2, Some(vec![0., 2.])).unwrap();
+    assert_close(&h.counts(), &[2., 3.]);
+
+    let b = NdArray::from(&[0., 1., 1., 3.], None, None);
+    assert_close(&bincount(&b, None, None).unwrap(), &[1., 2., 0., 1.]);
+    assert_close(
+        &bincount(&b, Some(vec![0.5, 1., 1., 2.]), Some(6)).unwrap(),
+        &[0.5, 2., 0., 2., 0., 0.],
+    );
+
+    let x = NdArray::from(&[0.2, 6.4, 3.0, 1.6], None, None);
+    assert_close(
+        &digitize(&x, &[0., 1., 2.5, 4., 10.], None).unwrap(),
+        &[1., 4., 3., 2.],
+    );
+    let y = NdArray::from(&[1., 2.5], None, None);
+    assert_close(
+        &digitize(&y, &[0., 1., 2.5], Some(true)).unwrap(),
+        &[1., 2.],
+    );
+    assert_close(&digitize(&y, &[0., 1., 2.5], None).unwrap(), &[2., 3.]);
+    assert_close(&digitize(&y, &[2.5, 1., 0.], None).unwrap(), &[1., 0.]);
+}
+
+#[test]
+fn test_cov_corrcoef() {
+    let m = NdArray::from(&[0., 1., 2., 2., 1., 0.], Some(vec![2, 3]), None);
+    assert_close(&cov(&m, None, None).unwrap(), &[1., -1., -1., 1.]);
+    assert_close(
+        &cov(&m.transpose(), Some(false), Some(0)).unwrap(),
+        &[2. / 3., -2. / 3., -2. / 3., 2. / 3.],
+    );
+    let x = NdArray::from(&[1., 2., 4., 2., 4., 8.5], Some(vec![2, 3]), None);
+    let r = corrcoef(&x, None).unwrap();
+    assert_eq!(r.shape, vec![2, 2]);
+    assert_close(
+        &NdArray::from(&[r.buffer[0], r.buffer[3]], None, None),
+        &[1., 1.],
+    );
+    assert!(r.buffer[1] > 0.99 && r.buffer[1] < 1.);
+    assert_eq!(
+        cov(&NdArray::from(&[1., 2., 3.], None, None), None, None)
+            .unwrap()
+            .shape,
+        vec![1, 1]
+    );
+}
+
+// the public functions return JsValue errors with the wasm feature, which
+// can't be dropped natively, so errors are checked on the validators
+#[test]
+fn test_invalid_inputs() {
+    let scalar = NdArray::from(&[1.], Some(vec![]), None);
+    assert!(matches!(
+        axis_or_last(&scalar, None),
+        Err(Error::ShapeMismatch(_))
+    ));
+    assert!(matches!(
+        check_axis(&scalar, Some(0)),
+        Err(Error::ShapeMismatch(_))
+    ));
+    assert_eq!(std_dev(&scalar, None, None).unwrap().buffer, vec![0.]);
+
+    let a = NdArray::from(&[1., 2.], None, None);
+    assert!(matches!(
+        axis_or_last(&a, Some(1)),
+        Err(Error::ShapeMismatch(_))
+    ));
+    for (q, top) in [(1.5, 1.), (-0.1, 1.), (f32::NAN, 1.), (101., 100.)] {
+        assert!(matches!(check_q(q, top), Err(Error::InvalidArgument(_))));
+    }
+    assert!(check_q(100., 100.).is_ok());
+    assert!(matches!(
+        check_histogram(0, None),
+        Err(Error::InvalidArgument(_))
+    ));
+    for range in [&[1., 0.][..], &[0.], &[0., f32::NAN]] {
+        assert!(matches!(
+            check_histogram(2, Some(range)),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+    assert!(matches!(
+        bins_increasing(&[0., 2., 1.]),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        centered_rows(&NdArray::zeros(&[1, 1, 1]), true),
+        Err(Error::ShapeMismatch(_))
+    ));
+
+    assert!(matches!(
+        bincount_len(&a, Some(&[1.]), 0),
+        Err(Error::ShapeMismatch(_))
+    ));
+    let huge = NdArray::from(&[1e30], None, None);
+    assert!(matches!(
+        bincount_len(&huge, None, 0),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        bincount_len(&a, None, BINCOUNT_MAX_BINS + 1),
+        Err(Error::InvalidArgument(_))
+    ));
+    let negative = NdArray::from(&[-1.], None, None);
+    assert!(matches!(
+        bincount_len(&negative, None, 0),
+        Err(Error::InvalidArgument(_))
+    ));
+}
@@ -0,0 +1,296 @@
+//! Standard library trait impls, so Rust code can write `&a + &b * 2.0`,
+//! `a[[i, j]]` or `a.iter().sum()`.
+//!
+//! Arithmetic is element-wise with NumPy-style broadcasting and panics on
+//! incompatible shapes, like the kernels in `ops.rs`. `*` is the
+//! element-wise product; use `matmul` for matrix products.
+
+use std::iter::FromIterator;
+use std::ops::{
+    Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
+};
+
+use crate::{ndarray::NdArray, ops::broadcast_with, utils::get_strides};
+
+fn binary<F: Fn(f32, f32) -> f32>(a: &NdArray, b: &NdArray, f: F) -> NdArray {
+    broadcast_with(a, b, f).unwrap_or_else(|| {
+        panic!(
+            "operands could not be broadcast together with shapes {:?} {:?}",
+            a.shape, b.shape
+        )
+    })
+}
+
+/// Like [`binary`], but reuses `a`'s buffer when no broadcasting is needed.
+fn binary_owned<F: Fn(f32, f32) -> f32>(mut a: NdArray, b: &NdArray, f: F) -> NdArray {
+    if a.shape == b.shape {
+        a.buffer
+            .iter_mut()
+            .zip(&b.buffer)
+            .for_each(|(x, y)| *x = f(*x, *y));
+        a
+    } else {
+        binary(&a, b, f)
+    }
+}
+
+fn scalar<F: Fn(f32) -> f32>(mut a: NdArray, f: F) -> NdArray {
+    a.buffer.iter_mut().for_each(|x| *x = f(*x));
+    a
+}
+
+macro_rules! impl_binary_op {
+    ($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident, $op:tt) => {
+        impl $trait<&NdArray> for &NdArray {
+            type Output = NdArray;
+            fn $method(self, rhs: &NdArray) -> NdArray {
+                binary(self, rhs, |x, y| x $op y)
+            }
+        }
+
+        impl $trait<NdArray> for &NdArray {
+            type Output = NdArray;
+            fn $method(self, rhs: NdArray) -> NdArray {
+                if self.shape == rhs.shape {
+                    binary_owned(rhs, self, |y, x| x $op y)
+                } else {
+                    binary(self, &rhs, |x, y| x $op y)
+                }
+            }
+        }
+
+        impl $trait<&NdArray> for NdArray {
+            type Output = NdArray;
+            fn $method(self, rhs: &NdArray) -> NdArray {
+                binary_owned(self, rhs, |x, y| x $op y)
+            }
+        }
+
+        impl $trait<NdArray> for NdArray {
+            type Output = NdArray;
+            fn $method(self, rhs: NdArray) -> NdArray {
+                binary_owned(self, &rhs, |x, y| x $op y)
+            }
+        }
+
+        impl $trait<f32> for &NdArray {
+            type Output = NdArray;
+            fn $method(self, rhs: f32) -> NdArray {
+                scalar(self.clone(), |x| x $op rhs)
+            }
+        }
+
+        impl $trait<f32> for NdArray {
+            type Output = NdArray;
+            fn $method(self, rhs: f32) -> NdArray {
+                scalar(self, |x| x $op rhs)
+            }
+        }
+
+        impl $trait<&NdArray> for f32 {
+            type Output = NdArray;
+            fn $method(self, rhs: &NdArray) -> NdArray {
+                scalar(rhs.clone(), |x| self $op x)
+            }
+        }
+
+        impl $trait<NdArray> for f32 {
+            type Output = NdArray;
+            fn $method(self, rhs: NdArray) -> NdArray {
+                scalar(rhs, |x| self $op x)
+            }
+        }
+
+        impl $assign_trait<&NdArray> for NdArray {
+            fn $assign_method(&mut self, rhs: &NdArray) {
+                let res = std::mem::replace(self, NdArray::zeros(&[0]));
+                *self = binary_owned(res, rhs, |x, y| x $op y);
+            }
+        }
+
+        impl $assign_trait<NdArray> for NdArray {
+            fn $assign_method(&mut self, rhs: NdArray) {
+                self.$assign_method(&rhs);
+            }
+        }
+
+        impl $assign_trait<f32> for NdArray {
+            fn $assign_method(&mut self, rhs: f32) {
+                self.buffer.iter_mut().for_each(|x| *x = *x $op rhs);
+            }
+        }
+    };
+}
+
+impl_binary_op!(Add, add, AddAssign, add_assign, +);
+impl_binary_op!(Sub, sub, SubAssign, sub_assign, -);
+impl_binary_op!(Mul, mul, MulAssign, mul_assign, *);
+impl_binary_op!(Div, div, DivAssign, div_assign, /);
+
+impl Neg for &NdArray {
+    type Output = NdArray;
+    fn neg(self) -> NdArray {
+        scalar(self.clone(), |x| -x)
+    }
+}
+
+impl Neg for NdArray {
+    type Output = NdArray;
+    fn neg(self) -> NdArray {
+        scalar(self, |x| -x)
+    }
+}
+
+impl NdArray {
+    fn offset_of(&self, index: &[usize]) -> usize {
+        if index.len() != self.shape.len() {
+            panic!(
+                "index {:?} has {} dimensions but the array has {}",
+                index,
+                index.len(),
+                self.shape.len()
+            );
+        }
+        index
+            .iter()
+            .zip(&self.shape)
+            .zip(&self.strides)
+            .map(|((i, dim), stride)| {
+                if i >= dim {
+                    panic!(
+                        "index {:?} is out of bounds for shape {:?}",
+                        index, self.shape
+                    );
+                }
+                i * stride
+            })
+            .sum()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, f32> {
+        self.buffer.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, f32> {
+        self.buffer.iter_mut()
+    }
+}
+
+impl Index<&[usize]> for NdArray {
+    type Output = f32;
+    fn index(&self, index: &[usize]) -> &f32 {
+        &self.buffer[self.offset_of(index)]
+    }
+}
+
+impl IndexMut<&[usize]> for NdArray {
+    fn index_mut(&mut self, index: &[usize]) -> &mut f32 {
+        let offset = self.offset_of(index);
+        &mut self.buffer[offset]
+    }
+}
+
+impl<const N: usize> Index<[usize; N]> for NdArray {
+    type Output = f32;
+    fn index(&self, index: [usize; N]) -> &f32 {
+        &self[&index[..]]
+    }
+}
+
+impl<const N: usize> IndexMut<[usize; N]> for NdArray {
+    fn index_mut(&mut self, index: [usize; N]) -> &mut f32 {
+        &mut self[&index[..]]
+    }
+}
+
+/// Iterates over the elements in row-major order.
+impl IntoIterator for NdArray {
+    type Item = f32;
+    type IntoIter = std::vec::IntoIter<f32>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.buffer.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a NdArray {
+    type Item = &'a f32;
+    type IntoIter = std::slice::Iter<'a, f32>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.buffer.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut NdArray {
+    type Item = &'a mut f32;
+    type IntoIter = std::slice::IterMut<'a, f32>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.buffer.iter_mut()
+    }
+}
+
+/// Collects into a 1-D array.
+impl FromIterator<f32> for NdArray {
+    fn from_iter<I: IntoIterator<Item = f32>>(iter: I) -> Self {
+        let buffer: Vec<f32> = iter.into_iter().collect();
+        let shape = vec![buffer.len()];
+        NdArray {
+            strides: get_strides(&shape),
+            buffer,
+            shape,
+        }
+    }
+}
+
+/// Arrays are equal when they have the same shape and elements. As with
+/// `f32`, an array containing NaN is not equal to itself.
+impl PartialEq for NdArray {
+    fn eq(&self, other: &NdArray) -> bool {
+        self.shape == other.shape && self.buffer == other.buffer
+    }
+}
+
+#[test]
+fn test_arithmetic() {
+    let a = NdArray::arange(0, 6, None).reshape(&[2, 3]);
+    let b = NdArray::from(&[1., 2., 3.], None, None);
+    let c = &a + &b * 2.0;
+    assert_eq!(c.buffer, vec![2., 5., 8., 5., 8., 11.]);
+    assert_eq!(c.shape, vec![2, 3]);
+
+    let col = NdArray::from(&[10., 20.], Some(vec![2, 1]), None);
+    assert_eq!((&a - col).buffer, vec![-10., -9., -8., -17., -16., -15.]);
+    assert_eq!((1.0 - &b).buffer, vec![0., -1., -2.]);
+    assert_eq!((6.0 / b.clone()).buffer, vec![6., 3., 2.]);
+    assert_eq!(-&b, b.clone() * -1.0);
+    assert_eq!(a.clone() * a.clone(), a.dot(&a));
+
+    let mut d = a.clone();
+    d += &b;
+    d -= 1.0;
+    d /= NdArray::from(&[2.], None, None);
+    assert_eq!(d.buffer, vec![0., 1., 2., 1.5, 2.5, 3.5]);
+}
+
+#[test]
+#[should_panic(expected = "could not be broadcast")]
+fn test_arithmetic_shape_mismatch() {
+    let _ = NdArray::zeros(&[2, 3]) + NdArray::zeros(&[2]);
+}
+
+#[test]
+fn test_index_and_iter() {
+    let mut a = NdArray::arange(0, 6, None).reshape(&[2, 3]);
+    assert_eq!(a[[1, 2]], 5.);
+    a[[0, 1]] = 10.;
+    assert_eq!(a[&[0, 1][..]], 10.);
+
+    for x in &mut a {
+        *x += 1.;
+    }
+    assert_eq!(a.iter().sum::<f32>(), 30.);
+    let b: NdArray = a.clone().into_iter().map(|x| x * 2.).collect();
+    assert_eq!(b.shape, vec![6]);
+    assert_eq!(b.buffer[1], 22.);
+    assert_ne!(a, b);
+    assert_eq!(b.reshape(&[2, 3]), &a * 2.0);
+}
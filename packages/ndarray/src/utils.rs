@@ -1,12 +1,7 @@
 use image::{load_from_memory, DynamicImage};
 use rand::rngs::ThreadRng;
-use wasm_bindgen::prelude::*;
 
-#[wasm_bindgen]
-extern "C" {
-    #[wasm_bindgen(js_namespace = console)]
-    fn error(s: &str);
-}
+use crate::error::{Error, Result};
 
 
 use rand::Rng;
@@ -20,13 +15,14 @@ pub fn get_strides(shape: &[usize]) -> Vec<usize> {
 }
 
 #[test]
+#[allow(clippy::useless_vec)]
 fn test_get_strides() {
     assert_eq!(get_strides(&vec![1, 2, 3]), vec![6, 3, 1]);
     assert_eq!(get_strides(&vec![9, 8, 7]), vec![56, 7, 1]);
 }
 
-pub fn get_indexes(shape: &Vec<usize>) -> Vec<Vec<usize>> {
-    assert!(shape.len() > 0);
+pub fn get_indexes(shape: &[usize]) -> Vec<Vec<usize>> {
+    assert!(!shape.is_empty());
     let mut res: Vec<Vec<usize>> = (0..shape[0]).map(|x| vec![x]).collect();
     for size in shape[1..].iter() {
         let mut new: Vec<Vec<usize>> = Vec::new();
@@ -72,6 +68,7 @@ impl Iterator for NormalRandomGenerater {
 }
 
 #[test]
+#[allow(clippy::useless_vec)]
 fn test_get_indexes() {
     assert_eq!(
         get_indexes(&vec![3, 2, 3]),
@@ -98,9 +95,9 @@ fn test_get_indexes() {
     );
 }
 
-pub fn reorder<T: Copy>(origin: &Vec<T>, order: &Vec<usize>) -> Vec<T> {
+pub fn reorder<T: Copy>(origin: &[T], order: &[usize]) -> Vec<T> {
     assert_eq!(origin.len(), order.len());
-    let mut target = origin.clone();
+    let mut target = origin.to_vec();
     for (src, tgt) in order.iter().enumerate() {
         target[src] = origin[*tgt];
     }
@@ -108,13 +105,14 @@ pub fn reorder<T: Copy>(origin: &Vec<T>, order: &Vec<usize>) -> Vec<T> {
 }
 
 #[test]
+#[allow(clippy::useless_vec)]
 fn test_reorder_inplace() {
     let a = vec![2, 4, 6];
     let b = reorder(&a, &vec![2, 0, 1]);
     assert_eq!(b, [6, 2, 4]);
 }
 
-pub fn nd_idx_to_offset(index: &[usize], strides: &Vec<usize>) -> usize {
+pub fn nd_idx_to_offset(index: &[usize], strides: &[usize]) -> usize {
     assert_eq!(index.len(), strides.len());
     let mut res = 0;
     for (axis, i) in index.iter().enumerate() {
@@ -124,17 +122,58 @@ pub fn nd_idx_to_offset(index: &[usize], strides: &Vec<usize>) -> usize {
 }
 
 #[test]
+#[allow(clippy::useless_vec)]
 fn test_nd_idx_to_offset() {
     assert_eq!(nd_idx_to_offset(&vec![1, 5, 1], &vec![3, 2, 3]), 16)
 }
 
-pub fn load_image_from_array_buffer(_array: &[u8]) -> Option<DynamicImage> {
-    match load_from_memory(_array) {
-        Ok(img) => Some(img),
-        Err(err) => {
-            error(err.to_string().as_str());
-            None
-        }
+/// Shape of `len` elements reshaped to `shape`, where one dimension may be
+/// -1 to infer it from the others.
+pub fn reshaped_shape(shape: &[i32], len: usize) -> Vec<usize> {
+    let new_axis_count = shape.iter().filter(|x| (**x) <= 0).count();
+    if new_axis_count > 1 {
+        panic!("too many new axis");
+    }
+    if shape.iter().any(|e| *e < -1 || *e == 0) {
+        panic!("unknown dimension size must be positive or -1.");
     }
+    let known_size = shape
+        .iter()
+        .filter(|e| **e > 0)
+        .try_fold(1usize, |acc, e| acc.checked_mul(*e as usize));
+    let new_size = match known_size {
+        Some(known) if new_axis_count == 0 && known == len => 1,
+        Some(known) if new_axis_count == 1 && len.is_multiple_of(known) => len / known,
+        _ => panic!("shape {:?} is not compatible with {} elements", shape, len),
+    };
+    shape
+        .iter()
+        .map(|x| if *x == -1 { new_size } else { *x as usize })
+        .collect()
 }
 
+#[test]
+fn test_reshaped_shape() {
+    assert_eq!(reshaped_shape(&[3, 2], 6), vec![3, 2]);
+    assert_eq!(reshaped_shape(&[2, -1], 6), vec![2, 3]);
+    assert_eq!(reshaped_shape(&[-1, 2], 6), vec![3, 2]);
+    assert_eq!(reshaped_shape(&[], 1), Vec::<usize>::new());
+}
+
+#[test]
+#[should_panic(expected = "not compatible with 7 elements")]
+fn test_reshaped_shape_indivisible() {
+    reshaped_shape(&[2, -1], 7);
+}
+
+pub fn load_image_from_array_buffer(_array: &[u8]) -> Result<DynamicImage> {
+    load_from_memory(_array).map_err(|err| Error::format(err.to_string()))
+}
+
+#[test]
+fn test_load_image_invalid() {
+    assert!(matches!(
+        load_image_from_array_buffer(b"not an image"),
+        Err(Error::Format(_))
+    ));
+}